@@ -9,19 +9,29 @@
 // This implements the high-privilege side of delegated certification where
 // the SPIRE Agent requests App Key certificates signed by the AK
 
+use crate::transparency_log;
+use crate::x509_attest;
 use crate::QuoteData;
 use actix_web::{http, web, HttpRequest, HttpResponse, Responder};
 use base64::{engine::general_purpose::STANDARD as base64_standard, Engine as _};
 use keylime::json_wrapper::JsonWrapper;
-use keylime::tpm::Context as TpmContext;
 use log::*;
+use openssl::pkey::{PKey, Public as OpensslPublic};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::Command;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use tss_esapi::{
     handles::KeyHandle,
-    structures::{Attest, Data, Signature},
-    traits::Marshall,
+    interface_types::{
+        algorithm::HashingAlgorithm, resource_handles::Hierarchy,
+        session_handles::PolicySession,
+    },
+    structures::{
+        Attest, Data, Digest, MaxBuffer, PcrSelectionListBuilder, PcrSlot, Public,
+        SavedTpmContext, Signature, SignatureScheme, SymmetricDefinition,
+    },
+    traits::{Marshall, UnMarshall},
 };
 
 #[derive(Deserialize, Debug)]
@@ -33,6 +43,12 @@ pub struct CertifyAppKeyRequest {
     pub app_key_public: String,
     #[serde(rename = "app_key_context_path")]
     pub app_key_context_path: String,
+    /// Selects the certification output format: the default/missing value
+    /// keeps the bespoke base64 JSON blob (`format: "phase2_compatible"`);
+    /// `"x509"` returns a real, AK-signed PEM certificate in
+    /// `app_key_certificate_x509_pem` instead.
+    #[serde(rename = "cert_format", default)]
+    pub cert_format: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -40,15 +56,559 @@ pub struct CertifyAppKeyResponse {
     pub result: String,
     #[serde(rename = "app_key_certificate", skip_serializing_if = "Option::is_none")]
     pub app_key_certificate: Option<String>,
+    #[serde(rename = "app_key_certificate_x509_pem", skip_serializing_if = "Option::is_none")]
+    pub app_key_certificate_x509_pem: Option<String>,
+    /// Unified-Identity - Phase 3: this certification's zero-based index in
+    /// the transparency log (see `transparency_log`), set only when
+    /// `DELEGATED_CERT_TRANSPARENCY_LOG_PATH` is configured.
+    #[serde(rename = "log_index", skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+    /// Unified-Identity - Phase 3: base64-encoded sibling hashes proving
+    /// `log_index`'s leaf is included under `signed_tree_head`'s root,
+    /// ordered leaf to root.
+    #[serde(rename = "inclusion_proof", skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<Vec<String>>,
+    /// Unified-Identity - Phase 3: JSON object `{tree_size, root_hash,
+    /// timestamp, signature}` - the transparency log's root, AK-signed at
+    /// the moment this certification was appended.
+    #[serde(rename = "signed_tree_head", skip_serializing_if = "Option::is_none")]
+    pub signed_tree_head: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
 
+// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+// A single administrator-approved measured state: the PCR banks that must be
+// satisfied and the administrator's signature authorizing them, mirroring
+// tpm2-store's signed `TPMPolicyStep::Authorized { policies, .. }` entries.
+#[derive(Deserialize, Debug)]
+struct ApprovedPolicy {
+    pcr_slots: Vec<u8>,
+    policy_signature: String, // hex-encoded signature, over the PolicyAuthorize digest
+}
+
+// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+// The signed policy list gating `/certify_app_key`: an administrator signing
+// key (verifying `policy_signature` on each approved entry below) plus a
+// policy_ref distinguishing this policy from others signed with the same
+// key. Loaded from `DELEGATED_CERT_POLICY_FILE`; absent that env var the
+// policy gate is disabled, matching this handler's existing
+// `UNIFIED_IDENTITY_ENABLED`-style opt-in convention.
+#[derive(Deserialize, Debug)]
+struct CertificationPolicyConfig {
+    signing_public_key: String, // hex-encoded TPMT_PUBLIC
+    policy_ref: String,
+    approved_policies: Vec<ApprovedPolicy>,
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Load the signed policy list that gates AK certification, if configured.
+/// Returns `Ok(None)` when `DELEGATED_CERT_POLICY_FILE` is unset so the gate
+/// stays off by default.
+fn load_certification_policy_config() -> Result<Option<CertificationPolicyConfig>, String> {
+    let path = match std::env::var("DELEGATED_CERT_POLICY_FILE") {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+    let raw = fs::read_to_string(&path).map_err(|e| {
+        format!("Failed to read certification policy file {}: {}", path, e)
+    })?;
+    serde_json::from_str(&raw)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse certification policy file {}: {}", path, e))
+}
+
+fn pcr_slot_from_index(index: u8) -> Option<PcrSlot> {
+    match index {
+        0 => Some(PcrSlot::Slot0),
+        1 => Some(PcrSlot::Slot1),
+        2 => Some(PcrSlot::Slot2),
+        3 => Some(PcrSlot::Slot3),
+        4 => Some(PcrSlot::Slot4),
+        5 => Some(PcrSlot::Slot5),
+        6 => Some(PcrSlot::Slot6),
+        7 => Some(PcrSlot::Slot7),
+        8 => Some(PcrSlot::Slot8),
+        9 => Some(PcrSlot::Slot9),
+        10 => Some(PcrSlot::Slot10),
+        11 => Some(PcrSlot::Slot11),
+        12 => Some(PcrSlot::Slot12),
+        13 => Some(PcrSlot::Slot13),
+        14 => Some(PcrSlot::Slot14),
+        15 => Some(PcrSlot::Slot15),
+        16 => Some(PcrSlot::Slot16),
+        17 => Some(PcrSlot::Slot17),
+        18 => Some(PcrSlot::Slot18),
+        19 => Some(PcrSlot::Slot19),
+        20 => Some(PcrSlot::Slot20),
+        21 => Some(PcrSlot::Slot21),
+        22 => Some(PcrSlot::Slot22),
+        23 => Some(PcrSlot::Slot23),
+        _ => None,
+    }
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Gate AK certification behind a signed TPM enhanced-authorization policy,
+/// modeled on tpm2-store's `get_authorized_policy_step`: run a PolicyPCR
+/// session bound to an administrator-approved measured state, then
+/// PolicyAuthorize it against the administrator's signing key. Tries each
+/// approved policy in turn and returns the first session that satisfies the
+/// live PCR state; if none do, the host isn't in an approved measured state
+/// and the caller should be refused with `Forbidden`.
+fn authorize_certification_policy(
+    inner_ctx: &mut tss_esapi::Context,
+    config: &CertificationPolicyConfig,
+) -> Result<PolicySession, String> {
+    let signing_public_bytes = hex::decode(&config.signing_public_key)
+        .map_err(|e| format!("Failed to decode signing_public_key: {}", e))?;
+    let signing_public = Public::unmarshall(&signing_public_bytes)
+        .map_err(|e| format!("Failed to unmarshall signing_public_key: {}", e))?;
+    let signing_key_handle = inner_ctx
+        .load_external_public(signing_public, Hierarchy::Owner)
+        .map_err(|e| format!("Failed to load administrator signing key: {}", e))?;
+    let signing_key_name = inner_ctx
+        .tr_get_name(signing_key_handle.into())
+        .map_err(|e| format!("Failed to get administrator signing key name: {}", e))?;
+    let policy_ref = Digest::try_from(config.policy_ref.as_bytes().to_vec())
+        .map_err(|e| format!("Failed to build policy_ref digest: {}", e))?;
+
+    for approved in &config.approved_policies {
+        let pcr_slots: Vec<PcrSlot> = approved
+            .pcr_slots
+            .iter()
+            .filter_map(|&slot| pcr_slot_from_index(slot))
+            .collect();
+        if pcr_slots.is_empty() {
+            warn!("Unified-Identity - Phase 3: Approved policy has no recognised PCR slots, skipping");
+            continue;
+        }
+        let pcr_selection_list = match PcrSelectionListBuilder::new()
+            .with_selection(HashingAlgorithm::Sha256, &pcr_slots)
+            .build()
+        {
+            Ok(list) => list,
+            Err(e) => {
+                warn!("Unified-Identity - Phase 3: Failed to build PCR selection: {}", e);
+                continue;
+            }
+        };
+        let policy_session = match inner_ctx.start_auth_session(
+            None,
+            None,
+            None,
+            tss_esapi::constants::SessionType::Policy,
+            SymmetricDefinition::AES_128_CFB,
+            HashingAlgorithm::Sha256,
+        ) {
+            Ok(Some(session)) => PolicySession::try_from(session)
+                .map_err(|e| format!("Failed to start policy session: {}", e))?,
+            _ => {
+                warn!("Unified-Identity - Phase 3: Failed to start policy session, skipping approved policy");
+                continue;
+            }
+        };
+
+        let mut satisfied = inner_ctx
+            .policy_pcr(policy_session, Digest::default(), pcr_selection_list)
+            .is_ok();
+
+        let signature_bytes = match hex::decode(&approved.policy_signature) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Unified-Identity - Phase 3: Failed to decode policy_signature: {}", e);
+                satisfied = false;
+                Vec::new()
+            }
+        };
+
+        if satisfied {
+            satisfied = match Signature::unmarshall(&signature_bytes) {
+                Ok(signature) => match inner_ctx
+                    .policy_get_digest(policy_session)
+                    .and_then(|digest| inner_ctx.verify_signature(signing_key_handle, digest, signature))
+                {
+                    Ok(ticket) => inner_ctx
+                        .policy_authorize(policy_session, policy_ref.clone(), vec![], signing_key_name.clone(), ticket)
+                        .is_ok(),
+                    Err(e) => {
+                        warn!("Unified-Identity - Phase 3: Signature verification against approved policy failed: {}", e);
+                        false
+                    }
+                },
+                Err(e) => {
+                    warn!("Unified-Identity - Phase 3: Failed to unmarshal policy_signature: {}", e);
+                    false
+                }
+            };
+        }
+
+        if satisfied {
+            info!("Unified-Identity - Phase 3: PCR state satisfies an administrator-approved certification policy");
+            return Ok(policy_session);
+        }
+
+        let _ = inner_ctx.flush_context(policy_session.into());
+    }
+
+    Err("Current measured state does not satisfy any approved certification policy".to_string())
+}
+
+const DEFAULT_SIGNATURE_SKEW_SECONDS: u64 = 300;
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Load the SPIRE agent's pre-registered public keys used to verify HTTP
+/// message signatures on `/certify_app_key`, from a PEM file holding one or
+/// more `PUBLIC KEY` blocks. Returns an empty list (signature verification
+/// disabled) when `DELEGATED_CERT_AUTHORIZED_CLIENT_KEYS_FILE` is unset,
+/// matching this handler's existing opt-in env-var conventions.
+fn load_authorized_client_keys() -> Result<Vec<PKey<OpensslPublic>>, String> {
+    let path = match std::env::var("DELEGATED_CERT_AUTHORIZED_CLIENT_KEYS_FILE") {
+        Ok(path) => path,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let pem = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read authorized client keys file {}: {}", path, e))?;
+
+    let mut keys = Vec::new();
+    let mut block = String::new();
+    for line in pem.lines() {
+        block.push_str(line);
+        block.push('\n');
+        if line.trim() == "-----END PUBLIC KEY-----" {
+            let key = PKey::public_key_from_pem(block.as_bytes())
+                .map_err(|e| format!("Failed to parse public key in {}: {}", path, e))?;
+            keys.push(key);
+            block.clear();
+        }
+    }
+    if keys.is_empty() {
+        return Err(format!("No public keys found in {}", path));
+    }
+    Ok(keys)
+}
+
+/// Unified-Identity - Phase 3: split a `Signature` header's `key="value"`
+/// fields into a map. Doesn't unescape backslash-escaped quotes within a
+/// value - none of the fields this endpoint reads (`keyId`, `algorithm`,
+/// `signature`) need one.
+fn parse_signature_header(value: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for part in value.split(',') {
+        if let Some((key, val)) = part.split_once('=') {
+            fields.insert(
+                key.trim().to_string(),
+                val.trim().trim_matches('"').to_string(),
+            );
+        }
+    }
+    fields
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Verify an HTTP Message Signature (draft-cavage-http-signatures style)
+/// over the fixed header set `(request-target) host date digest`, so the
+/// high-privilege `/certify_app_key` path can trust that `body` truly came
+/// from the authorized SPIRE plugin. Checks the `Digest` header against a
+/// SHA-256 of `body` and the `Date` header against `skew_seconds` for replay
+/// protection, then tries the `signature` field against each of
+/// `authorized_keys` in turn - the `keyId` field is an opaque client-chosen
+/// label, not looked up against anything, so the signer is only identified
+/// by whichever key successfully verifies it.
+fn verify_http_message_signature(
+    req: &HttpRequest,
+    body: &[u8],
+    authorized_keys: &[PKey<OpensslPublic>],
+    skew_seconds: u64,
+) -> Result<(), String> {
+    use openssl::hash::{Hasher, MessageDigest};
+    use openssl::sign::Verifier;
+
+    let signature_header = req
+        .headers()
+        .get("Signature")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Signature header".to_string())?;
+    let digest_header = req
+        .headers()
+        .get("Digest")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Digest header".to_string())?;
+    let host_header = req
+        .headers()
+        .get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Host header".to_string())?;
+    let date_header = req
+        .headers()
+        .get(http::header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Date header".to_string())?;
+
+    let (digest_alg, digest_value) = digest_header
+        .split_once('=')
+        .ok_or_else(|| "Malformed Digest header".to_string())?;
+    if !digest_alg.eq_ignore_ascii_case("sha-256") {
+        return Err(format!("Unsupported Digest algorithm: {}", digest_alg));
+    }
+    let mut hasher = Hasher::new(MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create body digest hasher: {}", e))?;
+    hasher
+        .update(body)
+        .map_err(|e| format!("Failed to hash request body: {}", e))?;
+    let body_digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finalize body digest: {}", e))?;
+    if base64_standard.encode(&body_digest) != digest_value {
+        return Err("Digest header does not match the request body".to_string());
+    }
+
+    let request_time =
+        httpdate::parse_http_date(date_header).map_err(|e| format!("Invalid Date header: {}", e))?;
+    let request_secs = request_time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|_| "Date header predates the epoch".to_string())?
+        .as_secs();
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|_| "System clock error".to_string())?
+        .as_secs();
+    let skew = request_secs.abs_diff(now_secs);
+    if skew > skew_seconds {
+        return Err(format!(
+            "Date header outside allowed skew window ({}s > {}s)",
+            skew, skew_seconds
+        ));
+    }
+
+    let fields = parse_signature_header(signature_header);
+    let signature_b64 = fields
+        .get("signature")
+        .ok_or_else(|| "Signature header missing signature field".to_string())?;
+    let signature_bytes = base64_standard
+        .decode(signature_b64)
+        .map_err(|e| format!("Invalid signature encoding: {}", e))?;
+
+    let signing_string = format!(
+        "(request-target): {} {}\nhost: {}\ndate: {}\ndigest: {}",
+        req.method().as_str().to_lowercase(),
+        req.path(),
+        host_header,
+        date_header,
+        digest_header
+    );
+
+    for key in authorized_keys {
+        let mut verifier = Verifier::new(MessageDigest::sha256(), key)
+            .map_err(|e| format!("Failed to create signature verifier: {}", e))?;
+        verifier
+            .update(signing_string.as_bytes())
+            .map_err(|e| format!("Failed to hash signing string: {}", e))?;
+        if verifier.verify(&signature_bytes).unwrap_or(false) {
+            return Ok(());
+        }
+    }
+
+    Err("HTTP message signature verification failed".to_string())
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Extract the raw `r || s` bytes out of an ECDSA `Signature`, as needed for
+/// `x509_attest::ecdsa_signature_to_der`. The AK in this tree is always
+/// provisioned as an ECC P-256 key, so any other signature shape is an error.
+fn ecdsa_raw_signature(signature: &Signature) -> Result<Vec<u8>, String> {
+    match signature {
+        Signature::EcDsa(ecc_signature) => {
+            let mut raw = ecc_signature.signature_r().as_slice().to_vec();
+            raw.extend_from_slice(ecc_signature.signature_s().as_slice());
+            Ok(raw)
+        }
+        _ => Err("AK signature is not ECDSA".to_string()),
+    }
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Open the transparency log configured at
+/// `DELEGATED_CERT_TRANSPARENCY_LOG_PATH`, if any. Returns `Ok(None)` when
+/// that env var is unset so the subsystem stays off by default, matching
+/// this handler's existing opt-in env-var conventions.
+fn open_transparency_log() -> Result<Option<transparency_log::TransparencyLog>, String> {
+    let path = match std::env::var("DELEGATED_CERT_TRANSPARENCY_LOG_PATH") {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+    transparency_log::TransparencyLog::open(Path::new(&path))
+        .map(Some)
+        .map_err(|e| format!("Failed to open transparency log {}: {}", path, e))
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Sign a transparency log "signed tree head" - `tree_size || root_hash ||
+/// timestamp` - with the AK, via the same TPM2_Hash-then-TPM2_Sign pattern
+/// `build_x509_certificate` uses (the AK is a restricted signing key and can
+/// only sign a digest the TPM itself computed). Returned as the JSON string
+/// stored in `CertifyAppKeyResponse::signed_tree_head`.
+fn build_signed_tree_head(
+    inner_ctx: &mut tss_esapi::Context,
+    ak_handle: KeyHandle,
+    tree_size: u64,
+    root_hash: [u8; 32],
+    timestamp: u64,
+) -> Result<String, String> {
+    let mut signable = Vec::with_capacity(8 + 32 + 8);
+    signable.extend_from_slice(&tree_size.to_be_bytes());
+    signable.extend_from_slice(&root_hash);
+    signable.extend_from_slice(&timestamp.to_be_bytes());
+
+    let (digest, validation) = inner_ctx
+        .hash(
+            MaxBuffer::try_from(signable)
+                .map_err(|e| format!("Signed tree head payload too large to hash: {}", e))?,
+            HashingAlgorithm::Sha256,
+            Hierarchy::Owner,
+        )
+        .map_err(|e| format!("Failed to hash signed tree head: {}", e))?;
+
+    let signature = inner_ctx
+        .sign(ak_handle, digest, SignatureScheme::Null, validation)
+        .map_err(|e| format!("AK signature over signed tree head failed: {}", e))?;
+    let sig_bytes = ecdsa_raw_signature(&signature)?;
+
+    let signed_tree_head = serde_json::json!({
+        "tree_size": tree_size,
+        "root_hash": base64_standard.encode(root_hash),
+        "timestamp": timestamp,
+        "signature": base64_standard.encode(&sig_bytes),
+    });
+    Ok(signed_tree_head.to_string())
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Append this certification to the transparency log (see
+/// `transparency_log`) when `DELEGATED_CERT_TRANSPARENCY_LOG_PATH` is
+/// configured, returning the new entry's log index, its inclusion proof
+/// (base64 sibling hashes, leaf to root), and a freshly AK-signed tree head.
+/// Returns `Ok(None)` - not an error - when no log is configured, leaving
+/// `certify_app_key`'s transparency fields unset, as before this feature
+/// existed.
+fn append_transparency_log_entry(
+    inner_ctx: &mut tss_esapi::Context,
+    ak_handle: KeyHandle,
+    app_key_public: &str,
+    cert_data: &[u8],
+    sig_data: &[u8],
+    nonce: &[u8],
+) -> Result<Option<(u64, Vec<String>, String)>, String> {
+    let Some(mut log) = open_transparency_log()? else {
+        return Ok(None);
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("System clock is before the Unix epoch: {}", e))?
+        .as_secs();
+
+    let leaf_record = serde_json::json!({
+        "app_key_public": app_key_public,
+        "certify_data": base64_standard.encode(cert_data),
+        "signature": base64_standard.encode(sig_data),
+        "nonce": base64_standard.encode(nonce),
+        "timestamp": timestamp,
+    });
+    let leaf_data = serde_json::to_vec(&leaf_record)
+        .map_err(|e| format!("Failed to canonicalize transparency log entry: {}", e))?;
+
+    let log_index = log
+        .append(&leaf_data)
+        .map_err(|e| format!("Failed to append transparency log leaf: {}", e))?;
+    let proof = log
+        .inclusion_proof(log_index)
+        .map_err(|e| format!("Failed to build inclusion proof: {}", e))?;
+    let proof_b64 = proof
+        .iter()
+        .map(|sibling| base64_standard.encode(sibling))
+        .collect();
+
+    let signed_tree_head =
+        build_signed_tree_head(inner_ctx, ak_handle, log.tree_size(), log.root(), timestamp)?;
+
+    Ok(Some((log_index, proof_b64, signed_tree_head)))
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Build the self-issued X.509 App Key certificate for `cert_format: "x509"`
+/// (see `x509_attest`): subject key is `app_key_public_pem`, the
+/// `TPM2_Certify` evidence (`attest_bytes`/`sig_bytes`) is carried in a
+/// custom extension, and the certificate itself is signed by the AK. Since
+/// the AK is a restricted signing key, it can only sign a digest the TPM
+/// itself computed (`Context::hash`, which returns a validation ticket
+/// proving that) rather than one hashed outside the TPM.
+fn build_x509_certificate(
+    inner_ctx: &mut tss_esapi::Context,
+    ak_handle: KeyHandle,
+    app_key_public_pem: &str,
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+    subject_common_name: &str,
+) -> Result<String, String> {
+    let app_key_public_der = openssl::pkey::PKey::public_key_from_pem(app_key_public_pem.as_bytes())
+        .and_then(|key| key.public_key_to_der())
+        .map_err(|e| format!("Failed to parse app_key_public as a PEM public key: {}", e))?;
+
+    let extension_value = x509_attest::build_attestation_extension_value(attest_bytes, sig_bytes);
+
+    let mut serial = vec![0u8; 16];
+    openssl::rand::rand_bytes(&mut serial)
+        .map_err(|e| format!("Failed to generate serial number: {}", e))?;
+
+    let not_before = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("System clock is before the Unix epoch: {}", e))?
+        .as_secs() as i64;
+    let not_after = not_before + 365 * 24 * 60 * 60;
+
+    let tbs_der = x509_attest::build_tbs_certificate(
+        &serial,
+        subject_common_name,
+        &app_key_public_der,
+        not_before,
+        not_after,
+        &extension_value,
+    )?;
+
+    let (tbs_digest, validation) = inner_ctx
+        .hash(
+            MaxBuffer::try_from(tbs_der.clone())
+                .map_err(|e| format!("tbsCertificate too large to hash: {}", e))?,
+            HashingAlgorithm::Sha256,
+            Hierarchy::Owner,
+        )
+        .map_err(|e| format!("Failed to hash tbsCertificate: {}", e))?;
+
+    let signature = inner_ctx
+        .sign(ak_handle, tbs_digest, SignatureScheme::Null, validation)
+        .map_err(|e| format!("AK signature over tbsCertificate failed: {}", e))?;
+
+    let raw_signature = ecdsa_raw_signature(&signature)?;
+    let signature_value = x509_attest::ecdsa_signature_to_der(&raw_signature)?;
+    let cert_der = x509_attest::assemble_certificate(&tbs_der, &signature_value);
+
+    let pem_bytes = openssl::x509::X509::from_der(&cert_der)
+        .and_then(|cert| cert.to_pem())
+        .map_err(|e| format!("Failed to parse/PEM-encode the assembled certificate: {}", e))?;
+    String::from_utf8(pem_bytes).map_err(|e| format!("Certificate PEM is not valid UTF-8: {}", e))
+}
+
 /// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
 /// Certify an App Key using the host's Attestation Key (AK)
+///
+/// The body is taken as raw bytes rather than a `web::Json` extractor so the
+/// exact bytes the `Digest` header covers are available to
+/// `verify_http_message_signature`, and parsed into `CertifyAppKeyRequest`
+/// afterward.
 async fn certify_app_key(
     req: HttpRequest,
-    body: web::Json<CertifyAppKeyRequest>,
+    body: web::Bytes,
     data: web::Data<QuoteData<'_>>,
 ) -> impl Responder {
     info!(
@@ -56,6 +616,66 @@ async fn certify_app_key(
         req.connection_info().peer_addr()
     );
 
+    // Unified-Identity - Phase 3: HTTP Message Signature authentication.
+    // Absent `DELEGATED_CERT_AUTHORIZED_CLIENT_KEYS_FILE` this is a no-op,
+    // preserving the previous behaviour.
+    match load_authorized_client_keys() {
+        Ok(authorized_keys) if !authorized_keys.is_empty() => {
+            if let Err(e) =
+                verify_http_message_signature(&req, &body, &authorized_keys, DEFAULT_SIGNATURE_SKEW_SECONDS)
+            {
+                warn!(
+                    "Unified-Identity - Phase 3: HTTP message signature verification failed: {}",
+                    e
+                );
+                return HttpResponse::Unauthorized().json(CertifyAppKeyResponse {
+                    result: "ERROR".to_string(),
+                    app_key_certificate: None,
+                    app_key_certificate_x509_pem: None,
+                    log_index: None,
+                    inclusion_proof: None,
+                    signed_tree_head: None,
+                    error: Some(format!("HTTP message signature verification failed: {}", e)),
+                });
+            }
+        }
+        Ok(_) => {}
+        Err(e) => {
+            error!(
+                "Unified-Identity - Phase 3: Failed to load authorized client keys: {}",
+                e
+            );
+            return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
+                result: "ERROR".to_string(),
+                app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
+                error: Some(format!("Failed to load authorized client keys: {}", e)),
+            });
+        }
+    }
+
+    let body: CertifyAppKeyRequest = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(
+                "Unified-Identity - Phase 3: Failed to parse request body: {}",
+                e
+            );
+            return HttpResponse::BadRequest().json(CertifyAppKeyResponse {
+                result: "ERROR".to_string(),
+                app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
+                error: Some(format!("Invalid request body: {}", e)),
+            });
+        }
+    };
+
     // Validate request
     if body.command != "certify_app_key" {
         warn!(
@@ -65,6 +685,10 @@ async fn certify_app_key(
         return HttpResponse::BadRequest().json(CertifyAppKeyResponse {
             result: "ERROR".to_string(),
             app_key_certificate: None,
+            app_key_certificate_x509_pem: None,
+            log_index: None,
+            inclusion_proof: None,
+            signed_tree_head: None,
             error: Some(format!("Invalid command: {}", body.command)),
         });
     }
@@ -73,12 +697,16 @@ async fn certify_app_key(
     let unified_identity_enabled = std::env::var("UNIFIED_IDENTITY_ENABLED")
         .unwrap_or_else(|_| "false".to_string())
         .to_lowercase();
-    
+
     if unified_identity_enabled != "true" && unified_identity_enabled != "1" && unified_identity_enabled != "yes" {
         warn!("Unified-Identity - Phase 3: Feature flag disabled, rejecting certification request");
         return HttpResponse::Forbidden().json(CertifyAppKeyResponse {
             result: "ERROR".to_string(),
             app_key_certificate: None,
+            app_key_certificate_x509_pem: None,
+            log_index: None,
+            inclusion_proof: None,
+            signed_tree_head: None,
             error: Some("Unified-Identity feature flag is disabled".to_string()),
         });
     }
@@ -95,28 +723,87 @@ async fn certify_app_key(
     );
 
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Step 1: Load App Key from context file using tpm2-tools
-    // The context file contains a saved TPM object context that needs to be loaded
-    // We use tpm2_readpublic to verify the key exists and get its handle
-    let app_key_handle = match load_app_key_from_context(&app_ctx_path) {
-        Ok(handle) => {
-            info!(
-                "Unified-Identity - Phase 3: App Key loaded successfully from context file"
-            );
-            handle
+    // Step 1: Load the App Key, either from its well-known persisted handle
+    // or, for a transient key, straight through tss_esapi's Context_Load
+    // rather than shelling out to tpm2-tools - see `load_app_key_from_context`.
+    let (app_key_handle, app_key_is_transient) = {
+        let inner_ctx_arc = tpm_context.inner();
+        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+        match load_app_key_from_context(&mut inner_ctx, &app_ctx_path) {
+            Ok(result) => {
+                info!("Unified-Identity - Phase 3: App Key loaded successfully from context file");
+                result
+            }
+            Err(e) => {
+                error!(
+                    "Unified-Identity - Phase 3: Failed to load App Key from context: {}",
+                    e
+                );
+                return HttpResponse::BadRequest().json(CertifyAppKeyResponse {
+                    result: "ERROR".to_string(),
+                    app_key_certificate: None,
+                    app_key_certificate_x509_pem: None,
+                    log_index: None,
+                    inclusion_proof: None,
+                    signed_tree_head: None,
+                    error: Some(format!("Failed to load App Key from context: {}", e)),
+                });
+            }
+        }
+    };
+
+    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+    // Gate: refuse to certify unless the host's live PCR state satisfies an
+    // administrator-signed policy (`DELEGATED_CERT_POLICY_FILE`). Absent
+    // that env var this is a no-op, preserving the previous behaviour. The
+    // returned session (when one was authorized) is held open and threaded
+    // into the TPM2_Certify call below as the AK's auth session, not
+    // flushed here - otherwise "PCR state was approved" is never actually
+    // bound to "the AK was used", making the gate a Rust-level check with
+    // no TPM-enforced effect.
+    let mut policy_session: Option<PolicySession> = None;
+    match load_certification_policy_config() {
+        Ok(Some(policy_config)) => {
+            let inner_ctx_arc = tpm_context.inner();
+            let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+            match authorize_certification_policy(&mut inner_ctx, &policy_config) {
+                Ok(session) => {
+                    policy_session = Some(session);
+                }
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity - Phase 3: Certification policy not satisfied: {}",
+                        e
+                    );
+                    return HttpResponse::Forbidden().json(CertifyAppKeyResponse {
+                        result: "ERROR".to_string(),
+                        app_key_certificate: None,
+                        app_key_certificate_x509_pem: None,
+                        log_index: None,
+                        inclusion_proof: None,
+                        signed_tree_head: None,
+                        error: Some(format!("Certification policy not satisfied: {}", e)),
+                    });
+                }
+            }
         }
+        Ok(None) => {}
         Err(e) => {
             error!(
-                "Unified-Identity - Phase 3: Failed to load App Key from context: {}",
+                "Unified-Identity - Phase 3: Failed to load certification policy config: {}",
                 e
             );
-            return HttpResponse::BadRequest().json(CertifyAppKeyResponse {
+            return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
-                error: Some(format!("Failed to load App Key from context: {}", e)),
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
+                error: Some(format!("Failed to load certification policy config: {}", e)),
             });
         }
-    };
+    }
 
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
     // Step 2: Use TPM2_Certify to certify the App Key with the AK
@@ -132,17 +819,39 @@ async fn certify_app_key(
             return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
                 error: Some(format!("Failed to create qualifying data: {}", e)),
             });
         }
     };
 
     // Certify the App Key with the AK
-    let (attest, signature) = match tpm_context.certify_credential(
-        qualifying_data,
-        app_key_handle,
-        ak_handle,
-    ) {
+    // Unified-Identity - Phase 3: the authorized policy session (if any) is
+    // bound to this single TPM2_Certify call via `execute_with_session` -
+    // the tss-esapi equivalent of passing it as the AK's auth session - so
+    // the enhanced-authorization gate above is actually enforced by the TPM,
+    // not just checked in software. The session is flushed immediately
+    // after, on every path, since it isn't needed again.
+    let certify_result = {
+        let inner_ctx_arc = tpm_context.inner();
+        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+        let result = match policy_session {
+            Some(session) => inner_ctx.execute_with_session(
+                Some(tss_esapi::interface_types::session_handles::AuthSession::from(session)),
+                |ctx| ctx.certify(app_key_handle.into(), ak_handle, qualifying_data),
+            ),
+            None => inner_ctx.certify(app_key_handle.into(), ak_handle, qualifying_data),
+        };
+        if let Some(session) = policy_session {
+            let _ = inner_ctx.flush_context(session.into());
+        }
+        result
+    };
+
+    let (attest, signature) = match certify_result {
         Ok(result) => {
             info!(
                 "Unified-Identity - Phase 3: App Key certified successfully with AK"
@@ -157,11 +866,29 @@ async fn certify_app_key(
             return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
                 error: Some(format!("TPM2_Certify failed: {}", e)),
             });
         }
     };
 
+    // Unified-Identity - Phase 3: a transient handle loaded via Context_Load
+    // occupies TPM object slots until flushed; a persisted handle (the
+    // `false` case from `load_app_key_from_context`) isn't ours to flush.
+    if app_key_is_transient {
+        let inner_ctx_arc = tpm_context.inner();
+        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+        if let Err(e) = inner_ctx.flush_context(app_key_handle.into()) {
+            warn!(
+                "Unified-Identity - Phase 3: Failed to flush transient App Key handle: {}",
+                e
+            );
+        }
+    }
+
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
     // Step 3: Format the certificate structure for Phase 2 compatibility
     // Phase 2 expects base64-encoded certificate with certify_data and signature
@@ -175,6 +902,10 @@ async fn certify_app_key(
             return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
                 error: Some(format!("Failed to marshal attestation: {}", e)),
             });
         }
@@ -190,11 +921,98 @@ async fn certify_app_key(
             return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
                 error: Some(format!("Failed to marshal signature: {}", e)),
             });
         }
     };
 
+    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+    // Record this certification in the transparency log
+    // (`DELEGATED_CERT_TRANSPARENCY_LOG_PATH`), if configured. A no-op,
+    // leaving the response's transparency fields unset, when it isn't.
+    let (log_index_value, inclusion_proof_value, signed_tree_head_value) = {
+        let inner_ctx_arc = tpm_context.inner();
+        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+        match append_transparency_log_entry(
+            &mut inner_ctx,
+            ak_handle,
+            &body.app_key_public,
+            &cert_data,
+            &sig_data,
+            b"Unified-Identity-Phase3-Certification",
+        ) {
+            Ok(Some((index, proof, sth))) => (Some(index), Some(proof), Some(sth)),
+            Ok(None) => (None, None, None),
+            Err(e) => {
+                error!(
+                    "Unified-Identity - Phase 3: Failed to record transparency log entry: {}",
+                    e
+                );
+                return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
+                    result: "ERROR".to_string(),
+                    app_key_certificate: None,
+                    app_key_certificate_x509_pem: None,
+                    log_index: None,
+                    inclusion_proof: None,
+                    signed_tree_head: None,
+                    error: Some(format!("Failed to record transparency log entry: {}", e)),
+                });
+            }
+        }
+    };
+
+    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+    // cert_format: "x509" returns a real, AK-signed PEM certificate instead
+    // of the Phase 2 JSON blob below - see `build_x509_certificate`.
+    if body.cert_format.as_deref() == Some("x509") {
+        let subject_common_name = app_ctx_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("keylime-app-key");
+        let inner_ctx_arc = tpm_context.inner();
+        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
+        return match build_x509_certificate(
+            &mut inner_ctx,
+            ak_handle,
+            &body.app_key_public,
+            &cert_data,
+            &sig_data,
+            subject_common_name,
+        ) {
+            Ok(cert_pem) => {
+                info!("Unified-Identity - Phase 3: X.509 App Key certificate generated successfully");
+                HttpResponse::Ok().json(CertifyAppKeyResponse {
+                    result: "SUCCESS".to_string(),
+                    app_key_certificate: None,
+                    app_key_certificate_x509_pem: Some(cert_pem),
+                    log_index: log_index_value,
+                    inclusion_proof: inclusion_proof_value,
+                    signed_tree_head: signed_tree_head_value,
+                    error: None,
+                })
+            }
+            Err(e) => {
+                error!(
+                    "Unified-Identity - Phase 3: Failed to build X.509 App Key certificate: {}",
+                    e
+                );
+                HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
+                    result: "ERROR".to_string(),
+                    app_key_certificate: None,
+                    app_key_certificate_x509_pem: None,
+                    log_index: None,
+                    inclusion_proof: None,
+                    signed_tree_head: None,
+                    error: Some(format!("Failed to build X.509 certificate: {}", e)),
+                })
+            }
+        };
+    }
+
     // Create certificate structure compatible with Phase 2
     let cert_structure = serde_json::json!({
         "app_key_public": body.app_key_public,
@@ -215,6 +1033,10 @@ async fn certify_app_key(
             return HttpResponse::InternalServerError().json(CertifyAppKeyResponse {
                 result: "ERROR".to_string(),
                 app_key_certificate: None,
+                app_key_certificate_x509_pem: None,
+                log_index: None,
+                inclusion_proof: None,
+                signed_tree_head: None,
                 error: Some(format!("Failed to serialize certificate: {}", e)),
             });
         }
@@ -229,86 +1051,121 @@ async fn certify_app_key(
     HttpResponse::Ok().json(CertifyAppKeyResponse {
         result: "SUCCESS".to_string(),
         app_key_certificate: Some(cert_b64),
+        app_key_certificate_x509_pem: None,
+        log_index: log_index_value,
+        inclusion_proof: inclusion_proof_value,
+        signed_tree_head: signed_tree_head_value,
         error: None,
     })
 }
 
+// Unified-Identity - Phase 3: tpm2-tools' own on-disk context format
+// (`TPMS_CONTEXT_blob` in tpm2-tools' `context_util.c`) prefixes the
+// marshalled `TPMS_CONTEXT` with a fixed 8-byte header: a magic value
+// followed by a version word. We only need to skip past it to get at the
+// `TPMS_CONTEXT` bytes tss_esapi can unmarshall.
+const TPM2_TOOLS_CONTEXT_MAGIC: u32 = 0xBADC_C0DE;
+const TPM2_TOOLS_CONTEXT_HEADER_LEN: usize = 8; // magic (4 bytes) + version (4 bytes)
+
+/// Unified-Identity - Phase 3: checks that a tpm2-tools context file is long
+/// enough for its fixed 8-byte header (magic + version) and that the magic
+/// matches, before the remaining bytes are handed to
+/// `SavedTpmContext::unmarshall`; the version word is not checked. Split out from `load_app_key_from_context`
+/// so the malformed-header rejection path is unit-testable without a live
+/// TPM context.
+fn validate_tpm2_tools_context_header(raw_context: &[u8], context_path: &Path) -> Result<(), String> {
+    if raw_context.len() < TPM2_TOOLS_CONTEXT_HEADER_LEN {
+        return Err(format!(
+            "App Key context file {} is too short to contain a tpm2-tools context header",
+            context_path.display()
+        ));
+    }
+    let magic = u32::from_be_bytes([
+        raw_context[0],
+        raw_context[1],
+        raw_context[2],
+        raw_context[3],
+    ]);
+    if magic != TPM2_TOOLS_CONTEXT_MAGIC {
+        return Err(format!(
+            "App Key context file {} has unexpected magic {:#010x} (expected {:#010x})",
+            context_path.display(),
+            magic,
+            TPM2_TOOLS_CONTEXT_MAGIC
+        ));
+    }
+    Ok(())
+}
+
 /// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-/// Load App Key from context file
-/// The App Key is generated by the SPIRE TPM plugin and persisted at handle 0x8101000B
-/// We first try to use the persisted handle, then fall back to loading from context file
-fn load_app_key_from_context(context_path: &PathBuf) -> Result<KeyHandle, String> {
+/// Load the App Key `certify_app_key` will certify, either from its
+/// well-known persisted handle or, for a transient key, straight from a
+/// tpm2-tools context file via tss_esapi's `Context_Load` - no shelling out
+/// to `tpm2_readpublic`/`tpm2_load` required. Returns the loaded handle
+/// alongside whether it's transient (and therefore the caller's
+/// responsibility to flush once it's done being used).
+fn load_app_key_from_context(
+    inner_ctx: &mut tss_esapi::Context,
+    context_path: &Path,
+) -> Result<(KeyHandle, bool), String> {
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Step 1: Try to use the persisted App Key handle (0x8101000B)
-    // The SPIRE TPM plugin persists the App Key at this handle
+    // Step 1: Try to use the persisted App Key handle (0x8101000B).
+    // The SPIRE TPM plugin persists the App Key at this handle.
     let default_app_handle = "0x8101000B";
-    let handle_output = Command::new("tpm2_readpublic")
-        .arg("-c")
-        .arg(default_app_handle)
-        .output()
-        .map_err(|e| format!("Failed to execute tpm2_readpublic for handle: {}", e))?;
-
-    if handle_output.status.success() {
-        // Key is persisted, use the handle
-        let handle_value = u32::from_str_radix(
-            default_app_handle.trim_start_matches("0x"),
-            16,
-        )
+    let handle_value = u32::from_str_radix(default_app_handle.trim_start_matches("0x"), 16)
         .map_err(|e| format!("Failed to parse handle {}: {}", default_app_handle, e))?;
+    let persistent_handle = tss_esapi::handles::PersistentTpmHandle::new(handle_value)
+        .map_err(|e| format!("Failed to create persistent handle: {}", e))?;
 
-        // Create a TPM context to get the handle
-        let tpm_ctx = TpmContext::new().map_err(|e| {
-            format!("Failed to create TPM context: {}", e)
-        })?;
-
-        // Use tss_esapi to get the handle from the persistent handle
-        // Store the inner context to avoid temporary value issues
-        let inner_ctx_arc = tpm_ctx.inner();
-        let mut inner_ctx = inner_ctx_arc.lock().unwrap(); //#[allow_ci]
-        let key_handle: KeyHandle = inner_ctx
-            .tr_from_tpm_public(tss_esapi::handles::TpmHandle::Persistent(
-                tss_esapi::handles::PersistentTpmHandle::new(handle_value)
-                    .map_err(|e| format!("Failed to create persistent handle: {}", e))?,
-            ))
-            .map_err(|e| format!("Failed to get key handle: {}", e))?
-            .into();
-
+    if let Ok(object_handle) =
+        inner_ctx.tr_from_tpm_public(tss_esapi::handles::TpmHandle::Persistent(persistent_handle))
+    {
         info!(
             "Unified-Identity - Phase 3: Using persisted App Key handle: {}",
             default_app_handle
         );
-        return Ok(key_handle);
+        return Ok((object_handle.into(), false));
     }
 
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Step 2: If not persisted, try to load from context file
-    // Verify the context file exists and contains a valid key
-    let output = Command::new("tpm2_readpublic")
-        .arg("-c")
-        .arg(context_path.as_os_str())
-        .arg("-f")
-        .arg("der")
-        .output()
-        .map_err(|e| format!("Failed to execute tpm2_readpublic: {}", e))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!(
-            "tpm2_readpublic failed for context file: {}",
-            stderr
-        ));
-    }
+    // Step 2: Not persisted - load the transient App Key straight from its
+    // tpm2-tools context file, mirroring how tpm2-store loads keys through
+    // tss_esapi rather than external tools.
+    let raw_context = fs::read(context_path).map_err(|e| {
+        format!(
+            "Failed to read App Key context file {}: {}",
+            context_path.display(),
+            e
+        )
+    })?;
 
-    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Note: Loading a transient key from context file requires parsing the context
-    // file format or using tpm2-tools to load it. For Phase 3, we require the
-    // App Key to be persisted at the known handle.
-    // Future enhancement: Parse context file and load transient key using tss_esapi
-
-    Err(format!(
-        "App Key not found at persistent handle {} and context file loading requires key to be persisted",
-        default_app_handle
-    ))
+    validate_tpm2_tools_context_header(&raw_context, context_path)?;
+
+    let saved_context = SavedTpmContext::unmarshall(&raw_context[TPM2_TOOLS_CONTEXT_HEADER_LEN..])
+        .map_err(|e| {
+            format!(
+                "Failed to unmarshall TPMS_CONTEXT from App Key context file {}: {}",
+                context_path.display(),
+                e
+            )
+        })?;
+
+    let key_handle: KeyHandle = inner_ctx
+        .context_load(saved_context)
+        .map_err(|e| {
+            format!(
+                "Context_Load failed for App Key context file {}: {}",
+                context_path.display(),
+                e
+            )
+        })?
+        .into();
+
+    info!(
+        "Unified-Identity - Phase 3: Loaded transient App Key from context file {}",
+        context_path.display()
+    );
+    Ok((key_handle, true))
 }
 
 /// Configure the endpoints for delegated certification
@@ -335,11 +1192,36 @@ mod tests {
             command: "invalid_command".to_string(),
             app_key_public: "test_pubkey".to_string(),
             app_key_context_path: "/tmp/test.ctx".to_string(),
+            cert_format: None,
         };
 
         // This would need a proper QuoteData fixture
         // For now, just verify the structure
         assert_eq!(req.command, "invalid_command");
     }
+
+    #[test]
+    fn test_validate_tpm2_tools_context_header_rejects_truncated_buffer() {
+        let raw_context = vec![0u8; TPM2_TOOLS_CONTEXT_HEADER_LEN - 1];
+        let err = validate_tpm2_tools_context_header(&raw_context, Path::new("/tmp/test.ctx"))
+            .expect_err("a truncated header should be rejected");
+        assert!(err.contains("too short"));
+    }
+
+    #[test]
+    fn test_validate_tpm2_tools_context_header_rejects_bad_magic() {
+        let mut raw_context = vec![0u8; TPM2_TOOLS_CONTEXT_HEADER_LEN];
+        raw_context[0..4].copy_from_slice(&0xDEAD_BEEFu32.to_be_bytes());
+        let err = validate_tpm2_tools_context_header(&raw_context, Path::new("/tmp/test.ctx"))
+            .expect_err("an unrecognized magic should be rejected");
+        assert!(err.contains("unexpected magic"));
+    }
+
+    #[test]
+    fn test_validate_tpm2_tools_context_header_accepts_valid_header() {
+        let mut raw_context = vec![0u8; TPM2_TOOLS_CONTEXT_HEADER_LEN];
+        raw_context[0..4].copy_from_slice(&TPM2_TOOLS_CONTEXT_MAGIC.to_be_bytes());
+        assert!(validate_tpm2_tools_context_header(&raw_context, Path::new("/tmp/test.ctx")).is_ok());
+    }
 }
 
@@ -2,12 +2,17 @@
 // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
 // Geolocation PCR extension for TPM-bound geolocation attestation
 
+use hex;
 use keylime::algorithms::HashAlgorithm;
 use keylime::tpm::{Context as TpmContext, TpmError};
 use log::*;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::convert::TryFrom;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tss_esapi::{
     handles::PcrHandle,
     interface_types::algorithm::HashingAlgorithm,
@@ -19,142 +24,716 @@ use tss_esapi::{
 /// Per federated-jwt.md Appendix, PCR 17 or 18 is typically used
 pub const GEOLOCATION_PCR_INDEX: u32 = 17;
 
+/// Serial device nodes probed, in order, for an attached GNSS receiver once
+/// a u-blox/FTDI USB ID has been seen in `lsusb`.
+const GNSS_SERIAL_CANDIDATES: &[&str] = &["/dev/gps0", "/dev/ttyACM0", "/dev/ttyUSB0"];
+
+/// Number of NMEA lines read from the serial port before giving up on a fix.
+const GNSS_READ_LINE_BUDGET: usize = 50;
+
 /// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-/// Get current geolocation data with sensor detection
-/// Returns a structured geolocation string that can be hashed and extended into PCR
-/// Format: "mobile:sensor_id:geolocation" or "GNSS:sensor_id:geolocation" or "none"
-fn get_current_geolocation() -> String {
-    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Check for specific USB device IDs to determine sensor type
+/// A geolocation claim ready for attestation: `r#type` is `"mobile"`,
+/// `"gnss"`, `"disagreement"`, or unset when no sensor was detected;
+/// `sensor_id` identifies the detected device(s). `value` carries the
+/// canonical JSON payload for `"gnss"` fixes
+/// (`{"lat":..,"lon":..,"accuracy":..,"fix_time":..}`, see [`GnssFix`]) and
+/// a legacy freeform string (or `"none"`) for the cases that have no single
+/// coordinate pair to encode. `sources` records the per-sensor candidates
+/// fusion considered, whenever at least one sensor reported a position (see
+/// [`collect_sensor_candidates`]), so a verifier can see which sources
+/// concurred (or didn't) on the attested position.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Geolocation {
+    pub r#type: Option<String>,
+    pub sensor_id: Option<String>,
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<SensorSource>>,
+}
+
+/// One sensor's contribution to a fused geolocation estimate: its reported
+/// position and accuracy radius (meters), as collected by
+/// [`collect_sensor_candidates`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SensorSource {
+    pub sensor_id: String,
+    pub sensor_type: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub accuracy_m: f64,
+}
+
+/// A GNSS position decoded from `$GxGGA`/`$GxRMC` NMEA sentences.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct GnssFix {
+    lat: f64,
+    lon: f64,
+    /// For a decoded fix, the horizontal dilution of precision `$GxGGA`
+    /// exposes, stored without conversion; fusion and the Long Position
+    /// Vector encoding use this number directly as an accuracy radius in
+    /// meters. A fused fix carries the fused accuracy radius in meters.
+    accuracy: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix_time: Option<String>,
+}
+
+/// Verify an NMEA sentence's checksum: the two trailing hex digits after
+/// `*` must equal the XOR of every byte between `$` and `*`.
+fn verify_nmea_checksum(sentence: &str) -> bool {
+    let sentence = sentence.trim();
+    let Some(body) = sentence.strip_prefix('$') else {
+        return false;
+    };
+    let Some(star) = body.find('*') else {
+        return false;
+    };
+    let (data, checksum_part) = body.split_at(star);
+    let checksum_hex = &checksum_part[1..];
+    if checksum_hex.len() < 2 {
+        return false;
+    }
+    let Ok(expected) = u8::from_str_radix(&checksum_hex[..2], 16) else {
+        return false;
+    };
+    data.bytes().fold(0u8, |acc, b| acc ^ b) == expected
+}
+
+/// Convert an NMEA `ddmm.mmmm` (or `dddmm.mmmm`) coordinate field into
+/// signed decimal degrees, given the number of leading degree digits (2 for
+/// latitude, 3 for longitude). The hemisphere letter is applied by the
+/// caller.
+fn nmea_coord_to_decimal(raw: &str, degree_digits: usize) -> Option<f64> {
+    if raw.len() <= degree_digits {
+        return None;
+    }
+    let degrees: f64 = raw[..degree_digits].parse().ok()?;
+    let minutes: f64 = raw[degree_digits..].parse().ok()?;
+    Some(degrees + minutes / 60.0)
+}
+
+/// Parse a `$GxGGA` sentence into a [`GnssFix`], rejecting it if the
+/// checksum is invalid or the fix quality field (6) is `0` (no fix).
+fn parse_gga(sentence: &str) -> Option<GnssFix> {
+    if !verify_nmea_checksum(sentence) {
+        return None;
+    }
+    let body = sentence.trim().trim_start_matches('$');
+    let body = &body[..body.find('*').unwrap_or(body.len())];
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields.len() < 9 || !fields[0].ends_with("GGA") {
+        return None;
+    }
+
+    let fix_quality: u8 = fields[6].parse().unwrap_or(0);
+    if fix_quality == 0 {
+        return None;
+    }
+
+    let mut lat = nmea_coord_to_decimal(fields[2], 2)?;
+    if fields[3].eq_ignore_ascii_case("S") {
+        lat = -lat;
+    }
+    let mut lon = nmea_coord_to_decimal(fields[4], 3)?;
+    if fields[5].eq_ignore_ascii_case("W") {
+        lon = -lon;
+    }
+    let accuracy = fields[8].parse::<f64>().unwrap_or(0.0);
+
+    Some(GnssFix {
+        lat,
+        lon,
+        accuracy,
+        fix_time: None,
+    })
+}
+
+/// Parse a `$GxRMC` sentence into a UTC timestamp string, rejecting it if
+/// the checksum is invalid or the sentence reports a void fix (field 2 !=
+/// `A`).
+fn parse_rmc_fix_time(sentence: &str) -> Option<String> {
+    if !verify_nmea_checksum(sentence) {
+        return None;
+    }
+    let body = sentence.trim().trim_start_matches('$');
+    let body = &body[..body.find('*').unwrap_or(body.len())];
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields.len() < 10 || !fields[0].ends_with("RMC") {
+        return None;
+    }
+    if fields[2] != "A" {
+        return None;
+    }
+
+    let time = fields[1];
+    let date = fields[9];
+    if time.len() < 6 || date.len() != 6 {
+        return None;
+    }
+
+    Some(format!(
+        "20{yy}-{mo}-{dd}T{hh}:{mi}:{ss}Z",
+        yy = &date[4..6],
+        mo = &date[2..4],
+        dd = &date[0..2],
+        hh = &time[0..2],
+        mi = &time[2..4],
+        ss = &time[4..6],
+    ))
+}
+
+/// Open `path` as a 9600-baud serial device and read a handful of NMEA
+/// lines looking for a `$GxGGA` fix (and, opportunistically, a `$GxRMC`
+/// timestamp to go with it). Returns `None` if the device can't be opened
+/// or no valid, checksummed fix is seen within the read budget.
+fn read_gnss_fix(path: &str) -> Option<GnssFix> {
+    let port = serialport::new(path, 9600)
+        .timeout(Duration::from_secs(2))
+        .open()
+        .map_err(|e| debug!("Unified-Identity - Phase 3: Failed to open GNSS serial device {path}: {e}"))
+        .ok()?;
+
+    let mut fix: Option<GnssFix> = None;
+    let mut fix_time: Option<String> = None;
+
+    for line in BufReader::new(port).lines().take(GNSS_READ_LINE_BUDGET).flatten() {
+        if fix.is_none() {
+            fix = parse_gga(&line);
+        }
+        if fix_time.is_none() {
+            fix_time = parse_rmc_fix_time(&line);
+        }
+        if fix.is_some() && fix_time.is_some() {
+            break;
+        }
+    }
+
+    fix.map(|mut f| {
+        f.fix_time = fix_time;
+        f
+    })
+}
+
+/// Typical single-tower cell-ID position accuracy, in meters, used as the
+/// accuracy radius for a mobile-modem candidate position. Real deployments
+/// should prefer the accuracy the CAMARA/location API reports; this is a
+/// conservative fallback for the freeform override format, which has no
+/// accuracy field of its own.
+const MOBILE_CELL_ACCURACY_M: f64 = 5_000.0;
+
+/// One sensor's reported position, before fusion: see [`SensorSource`] for
+/// the serializable form recorded in [`Geolocation::sources`].
+#[derive(Debug, Clone)]
+struct SensorCandidate {
+    sensor_id: String,
+    sensor_type: String,
+    lat: f64,
+    lon: f64,
+    accuracy_m: f64,
+}
+
+/// Parse a plain `"lat,lon"` pair (decimal degrees). This is the only
+/// coordinate format a freeform mobile-sensor override can carry.
+fn parse_lat_lon_pair(value: &str) -> Option<(f64, f64)> {
+    let (lat_str, lon_str) = value.split_once(',')?;
+    let lat = lat_str.trim().parse::<f64>().ok()?;
+    let lon = lon_str.trim().parse::<f64>().ok()?;
+    Some((lat, lon))
+}
+
+/// Enumerate every attached geolocation-capable sensor and collect each
+/// one's reported position. Unlike the single-sensor detection this
+/// replaces, a spoofed or compromised sensor can no longer unilaterally
+/// determine the attested location: [`fuse_candidates`] requires every
+/// candidate to mutually agree before the fusion result is trusted.
+fn collect_sensor_candidates() -> Vec<SensorCandidate> {
     let lsusb_output = std::process::Command::new("lsusb")
         .output()
         .ok()
         .and_then(|output| String::from_utf8(output.stdout).ok())
         .unwrap_or_default();
 
-    let mut sensor_type = None;
-    let mut sensor_id = None;
-    let mut geolocation_data: Option<String> = None;
+    let mut candidates = Vec::new();
 
-    // Check for Huawei Mobile (12d1:1433)
+    // Mobile modem: cell-derived position, only if the override carries
+    // parseable coordinates (the override has no accuracy field of its own,
+    // so MOBILE_CELL_ACCURACY_M stands in for it).
     if lsusb_output.contains("12d1:1433") {
-        sensor_type = Some("mobile");
-        sensor_id = Some("12d1:1433");
-        // Check if geolocation is provided via environment variable
-        if let Ok(env_value) = std::env::var("KEYLIME_AGENT_GEOLOCATION") {
-            let trimmed = env_value.trim();
-            if !trimmed.is_empty() && !trimmed.eq_ignore_ascii_case("none") {
-                geolocation_data = Some(trimmed.to_string());
-            }
+        if let Some((lat, lon)) = env_geolocation_override().as_deref().and_then(parse_lat_lon_pair) {
+            candidates.push(SensorCandidate {
+                sensor_id: "12d1:1433".to_string(),
+                sensor_type: "mobile".to_string(),
+                lat,
+                lon,
+                accuracy_m: MOBILE_CELL_ACCURACY_M,
+            });
         }
-        // If no geolocation data available, set to "none"
-        if geolocation_data.is_none() {
-            geolocation_data = Some("none".to_string());
+    }
+
+    // GNSS receivers: one candidate per serial device that yields a valid
+    // fix, so multiple physically-attached receivers all get fused rather
+    // than only the first one found.
+    if lsusb_output.contains("1546:01a7") || lsusb_output.contains("1546:01a8") || lsusb_output.contains("0403:6015") {
+        for path in GNSS_SERIAL_CANDIDATES {
+            if let Some(fix) = read_gnss_fix(path) {
+                candidates.push(SensorCandidate {
+                    sensor_id: (*path).to_string(),
+                    sensor_type: "gnss".to_string(),
+                    lat: fix.lat,
+                    lon: fix.lon,
+                    accuracy_m: fix.accuracy,
+                });
+            }
         }
     }
-    // Check for common u-blox GNSS receivers (example VIDs/PIDs)
-    else if lsusb_output.contains("1546:01a7") || lsusb_output.contains("1546:01a8") || lsusb_output.contains("0403:6015") {
-        sensor_type = Some("GNSS");
-        // Extract sensor ID from lsusb output
-        for line in lsusb_output.lines() {
-            if line.contains("1546:01a7") {
-                sensor_id = Some("1546:01a7");
-                break;
-            } else if line.contains("1546:01a8") {
-                sensor_id = Some("1546:01a8");
-                break;
-            } else if line.contains("0403:6015") {
-                sensor_id = Some("0403:6015");
-                break;
+
+    candidates
+}
+
+/// Outcome of fusing a set of sensor candidates.
+enum FusionOutcome {
+    /// Every candidate mutually agreed; this is the fused estimate.
+    Agreed { lat: f64, lon: f64, accuracy_m: f64 },
+    /// At least one pair of candidates disagreed beyond their combined
+    /// accuracy radii - no single location could be trusted.
+    Disagreement,
+}
+
+/// Require every pair of candidates to mutually overlap within their
+/// combined accuracy radii (haversine distance between the two positions no
+/// greater than the sum of their accuracy radii) before trusting a fused
+/// position. When the quorum holds, fuse via an accuracy-weighted centroid
+/// (weight = 1/accuracy²), so the tightest-accuracy sensors dominate.
+fn fuse_candidates(candidates: &[SensorCandidate]) -> FusionOutcome {
+    for i in 0..candidates.len() {
+        for j in (i + 1)..candidates.len() {
+            let a = &candidates[i];
+            let b = &candidates[j];
+            let distance_m = haversine_distance_m((a.lat, a.lon), (b.lat, b.lon));
+            if distance_m > a.accuracy_m + b.accuracy_m {
+                return FusionOutcome::Disagreement;
             }
         }
-        // Check if geolocation is provided via environment variable
-        if let Ok(env_value) = std::env::var("KEYLIME_AGENT_GEOLOCATION") {
-            let trimmed = env_value.trim();
-            if !trimmed.is_empty() && !trimmed.eq_ignore_ascii_case("none") {
-                geolocation_data = Some(trimmed.to_string());
+    }
+
+    let mut weight_sum = 0.0;
+    let mut lat_sum = 0.0;
+    let mut lon_sum = 0.0;
+    for c in candidates {
+        let accuracy_m = if c.accuracy_m > 0.0 { c.accuracy_m } else { f64::MIN_POSITIVE };
+        let weight = 1.0 / (accuracy_m * accuracy_m);
+        weight_sum += weight;
+        lat_sum += c.lat * weight;
+        lon_sum += c.lon * weight;
+    }
+    let fused_accuracy_m = candidates.iter().map(|c| c.accuracy_m).fold(f64::INFINITY, f64::min);
+
+    FusionOutcome::Agreed {
+        lat: lat_sum / weight_sum,
+        lon: lon_sum / weight_sum,
+        accuracy_m: fused_accuracy_m,
+    }
+}
+
+/// Great-circle distance between two `(lat, lon)` points in degrees, in
+/// meters, via the haversine formula.
+fn haversine_distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_M: f64 = 6_371_000.0;
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = lon2 - lon1;
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().atan2((1.0 - h).sqrt())
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Get current geolocation data with multi-sensor detection and fusion.
+/// Returns a [`Geolocation`] claim: a fused `"gnss"` position when one or
+/// more sensors agree (see [`collect_sensor_candidates`]/[`fuse_candidates`]),
+/// a `"disagreement"` marker when attached sensors contradict each other,
+/// or the legacy single-sensor/override detection when no sensor reported a
+/// position to fuse at all.
+fn get_current_geolocation() -> Geolocation {
+    let candidates = collect_sensor_candidates();
+    if candidates.is_empty() {
+        return legacy_single_sensor_geolocation();
+    }
+
+    let sources: Vec<SensorSource> = candidates
+        .iter()
+        .map(|c| SensorSource {
+            sensor_id: c.sensor_id.clone(),
+            sensor_type: c.sensor_type.clone(),
+            lat: c.lat,
+            lon: c.lon,
+            accuracy_m: c.accuracy_m,
+        })
+        .collect();
+    let sensor_ids = candidates.iter().map(|c| c.sensor_id.clone()).collect::<Vec<_>>().join("+");
+
+    match fuse_candidates(&candidates) {
+        FusionOutcome::Agreed { lat, lon, accuracy_m } => {
+            let fix = GnssFix {
+                lat,
+                lon,
+                accuracy: accuracy_m,
+                fix_time: None,
+            };
+            let value = serde_json::to_string(&fix).unwrap_or_else(|e| {
+                warn!("Unified-Identity - Phase 3: Failed to serialize fused GNSS fix: {e}");
+                "none".to_string()
+            });
+            info!(
+                "Unified-Identity - Phase 3: Fused geolocation from {} source(s) ({sensor_ids}): {value}",
+                candidates.len()
+            );
+            Geolocation {
+                r#type: Some("gnss".to_string()),
+                sensor_id: Some(sensor_ids),
+                value: Some(value),
+                sources: Some(sources),
             }
         }
-        // If no geolocation data available, set to "none"
-        if geolocation_data.is_none() {
-            geolocation_data = Some("none".to_string());
+        FusionOutcome::Disagreement => {
+            warn!(
+                "Unified-Identity - Phase 3: Sensor fusion quorum failed for sources ({sensor_ids}): candidate positions disagree beyond combined accuracy radii"
+            );
+            Geolocation {
+                r#type: Some("disagreement".to_string()),
+                sensor_id: Some(sensor_ids),
+                value: Some("none".to_string()),
+                sources: Some(sources),
+            }
         }
     }
-    // Check for environment variable override (no sensor detected)
-    else if let Ok(env_value) = std::env::var("KEYLIME_AGENT_GEOLOCATION") {
-        let trimmed = env_value.trim();
-        if !trimmed.is_empty() {
-            geolocation_data = Some(trimmed.to_string());
+}
+
+/// The original single-sensor detection: used when no attached sensor
+/// reported a position fusion could work with (e.g. a bare mobile modem
+/// with no override, or a GNSS receiver with no fix yet).
+fn legacy_single_sensor_geolocation() -> Geolocation {
+    // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+    // Check for specific USB device IDs to determine sensor type
+    let lsusb_output = std::process::Command::new("lsusb")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+
+    // Check for Huawei Mobile (12d1:1433)
+    if lsusb_output.contains("12d1:1433") {
+        let sensor_id = "12d1:1433".to_string();
+        let geolocation_data = env_geolocation_override().unwrap_or_else(|| "none".to_string());
+        info!(
+            "Unified-Identity - Phase 3: Detected mobile sensor (ID: {}), geolocation: {}",
+            sensor_id, geolocation_data
+        );
+        return Geolocation {
+            r#type: Some("mobile".to_string()),
+            sensor_id: Some(sensor_id),
+            value: Some(geolocation_data),
+            sources: None,
+        };
+    }
+
+    // Check for common u-blox GNSS receivers (example VIDs/PIDs)
+    if lsusb_output.contains("1546:01a7") || lsusb_output.contains("1546:01a8") || lsusb_output.contains("0403:6015") {
+        let sensor_id = ["1546:01a7", "1546:01a8", "0403:6015"]
+            .into_iter()
+            .find(|id| lsusb_output.contains(id))
+            .unwrap_or("unknown")
+            .to_string();
+
+        // No live fix decoded from any candidate serial device (handled by
+        // collect_sensor_candidates otherwise) - fall back to the
+        // environment override (for test/dev rigs) or "none".
+        let geolocation_data = env_geolocation_override().unwrap_or_else(|| "none".to_string());
+        info!(
+            "Unified-Identity - Phase 3: Detected GNSS sensor (ID: {}) but no fix decoded, geolocation: {}",
+            sensor_id, geolocation_data
+        );
+        return Geolocation {
+            r#type: Some("gnss".to_string()),
+            sensor_id: Some(sensor_id),
+            value: Some(geolocation_data),
+            sources: None,
+        };
+    }
+
+    // No sensor detected: fall back to the environment override, if any.
+    match env_geolocation_override() {
+        Some(geo) => {
+            info!("Unified-Identity - Phase 3: Using geolocation from environment: {}", geo);
+            Geolocation {
+                r#type: None,
+                sensor_id: None,
+                value: Some(geo),
+                sources: None,
+            }
+        }
+        None => {
+            info!("Unified-Identity - Phase 3: No geolocation sensor detected or data unavailable");
+            Geolocation {
+                r#type: None,
+                sensor_id: None,
+                value: Some("none".to_string()),
+                sources: None,
+            }
         }
     }
+}
 
-    // Format the geolocation string
-    let result = if let (Some(sensor), Some(id)) = (sensor_type, sensor_id) {
-        // Sensor detected - format with sensor info
-        if let Some(geo) = geolocation_data {
-            let formatted = format!("{}:{}:{}", sensor, id, geo);
-            info!("Unified-Identity - Phase 3: Detected {} sensor (ID: {}), geolocation: {}", sensor, id, geo);
-            formatted
+/// Read `KEYLIME_AGENT_GEOLOCATION`, treating unset/blank/`"none"` as "no
+/// override".
+fn env_geolocation_override() -> Option<String> {
+    std::env::var("KEYLIME_AGENT_GEOLOCATION").ok().and_then(|env_value| {
+        let trimmed = env_value.trim();
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("none") {
+            None
         } else {
-            // Should not happen, but handle gracefully
-            info!("Unified-Identity - Phase 3: Sensor detected but no geolocation data");
-            format!("{}:{}:none", sensor, id)
+            Some(trimmed.to_string())
         }
-    } else if let Some(geo) = geolocation_data {
-        // No sensor, but environment variable provided
-        info!("Unified-Identity - Phase 3: Using geolocation from environment: {}", geo);
-        geo
-    } else {
-        // No sensor and no environment variable
-        info!("Unified-Identity - Phase 3: No geolocation sensor detected or data unavailable");
-        "none".to_string()
-    };
+    })
+}
+
+/// UNIX timestamp (seconds) of 2004-01-01T00:00:00Z, the TAI epoch the ETSI
+/// C-ITS Long Position Vector timestamp is measured from. Leap seconds
+/// between TAI and UTC are not modeled here; the few tens of seconds of
+/// drift are immaterial next to the millisecond truncation below.
+const ETSI_TAI_EPOCH_UNIX_SECS: u64 = 1_072_915_200;
+
+/// Encode a latitude in decimal degrees as a big-endian `i32` in units of
+/// 1/10 microdegree (degrees × 10^7), clamped to the valid ±90° range.
+fn encode_latitude_decimicrodeg(lat: f64) -> i32 {
+    (lat * 1e7).round().clamp(-900_000_000.0, 900_000_000.0) as i32
+}
+
+/// Encode a longitude in decimal degrees as a big-endian `i32` in units of
+/// 1/10 microdegree (degrees × 10^7), clamped to the valid ±180° range.
+fn encode_longitude_decimicrodeg(lon: f64) -> i32 {
+    (lon * 1e7).round().clamp(-1_800_000_000.0, 1_800_000_000.0) as i32
+}
+
+/// Encode a position-accuracy radius in meters as `u16` centimeters,
+/// saturating at `u16::MAX` (655.35 m) rather than overflowing.
+fn encode_accuracy_cm(accuracy_m: f64) -> u16 {
+    (accuracy_m * 100.0).round().clamp(0.0, u16::MAX as f64) as u16
+}
 
-    result
+/// Encode the ETSI-style Long Position Vector fields (latitude, longitude,
+/// and a semi-major/semi-minor accuracy pair) for a GNSS fix. NMEA `$GxGGA`
+/// only reports a single HDOP-derived accuracy, so both axes carry the same
+/// value.
+fn encode_lpv_fields(fix: &GnssFix) -> [u8; 12] {
+    let mut fields = [0u8; 12];
+    fields[0..4].copy_from_slice(&encode_latitude_decimicrodeg(fix.lat).to_be_bytes());
+    fields[4..8].copy_from_slice(&encode_longitude_decimicrodeg(fix.lon).to_be_bytes());
+    let accuracy_cm = encode_accuracy_cm(fix.accuracy).to_be_bytes();
+    fields[8..10].copy_from_slice(&accuracy_cm);
+    fields[10..12].copy_from_slice(&accuracy_cm);
+    fields
+}
+
+/// Encode a UNIX timestamp (seconds) as milliseconds since the ETSI TAI
+/// epoch, truncated (wrapping) to `u32`.
+fn encode_tai_millis_u32(unix_secs: u64) -> u32 {
+    unix_secs
+        .saturating_sub(ETSI_TAI_EPOCH_UNIX_SECS)
+        .saturating_mul(1000) as u32
 }
 
 /// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-/// Hash geolocation data with nonce and timestamp for TPM PCR extension
-/// This creates a composite hash that binds location, nonce, and time together
-fn hash_geolocation_data(geolocation: &str, nonce: &[u8], timestamp: u64) -> Vec<u8> {
+/// Hash geolocation data with nonce and timestamp for TPM PCR extension.
+///
+/// GNSS fixes with real coordinates (decoded from `geolocation.value`, see
+/// [`GnssFix`]) are hashed via the canonical, byte-exact ETSI Long Position
+/// Vector encoding (`H("UI-GeoLPV:" || lat || lon || accuracy || nonce ||
+/// timestamp)`, see [`encode_lpv_fields`]) so any verifier can reconstruct
+/// the same digest regardless of how the claim was formatted. The legacy
+/// freeform-string hash is kept for the `"mobile"`/`"none"` cases that have
+/// no coordinates to encode.
+fn hash_geolocation_data(geolocation: &Geolocation, nonce: &[u8], timestamp: u64) -> Result<Vec<u8>, TpmError> {
+    if geolocation.r#type.as_deref() == Some("gnss") {
+        if let Some(fix) = geolocation
+            .value
+            .as_deref()
+            .and_then(|v| serde_json::from_str::<GnssFix>(v).ok())
+        {
+            let mut hasher = Sha256::new();
+            hasher.update(b"UI-GeoLPV:");
+            hasher.update(encode_lpv_fields(&fix));
+            hasher.update(nonce);
+            hasher.update(encode_tai_millis_u32(timestamp).to_be_bytes());
+            return Ok(hasher.finalize().to_vec());
+        }
+    }
+
+    hash_geolocation_data_legacy(geolocation, nonce, timestamp)
+}
+
+/// Legacy freeform-string hash, used when the geolocation claim has no
+/// coordinates to encode canonically (the `"mobile"`/`"none"` cases).
+fn hash_geolocation_data_legacy(geolocation: &Geolocation, nonce: &[u8], timestamp: u64) -> Result<Vec<u8>, TpmError> {
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
-    // Create composite data: geolocation + nonce + timestamp
+    // Create composite data: canonical geolocation claim + nonce + timestamp
     // This ensures the location claim is fresh and bound to the specific attestation request
+    let canonical = serde_json::to_string(geolocation)
+        .map_err(|e| TpmError::Other(format!("Failed to serialize geolocation claim: {e}")))?;
+
     let mut hasher = Sha256::new();
     hasher.update(b"Unified-Identity-Geolocation:");
-    hasher.update(geolocation.as_bytes());
+    hasher.update(canonical.as_bytes());
     hasher.update(b":nonce:");
     hasher.update(nonce);
     hasher.update(b":timestamp:");
     hasher.update(timestamp.to_be_bytes());
-    hasher.finalize().to_vec()
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Default path for the append-only geolocation attestation history log,
+/// overridable via `KEYLIME_AGENT_GEOLOCATION_HISTORY_PATH` for test/dev
+/// rigs that can't write to `/var/lib/keylime`.
+const DEFAULT_HISTORY_PATH: &str = "/var/lib/keylime/geolocation_history.jsonl";
+
+/// One entry in the append-only geolocation attestation history: the claim
+/// that was attested, the nonce it was bound to (hashed, not the nonce
+/// itself, since that's replay-sensitive), and the resulting PCR digest.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeolocationHistoryEntry {
+    pub geolocation: Geolocation,
+    /// Hex-encoded SHA-256 hash of the nonce used for this attestation.
+    pub nonce_hash: String,
+    pub timestamp: u64,
+    /// Hex-encoded digest extended into `GEOLOCATION_PCR_INDEX`.
+    pub pcr_digest: String,
+}
+
+fn history_path() -> String {
+    std::env::var("KEYLIME_AGENT_GEOLOCATION_HISTORY_PATH").unwrap_or_else(|_| DEFAULT_HISTORY_PATH.to_string())
+}
+
+/// Append `entry` as one JSON line to the history log at `path`, creating it
+/// if it doesn't exist yet. Best-effort: failures are the caller's to log,
+/// since a history-log write should never fail an attestation.
+fn append_history_entry(path: &Path, entry: &GeolocationHistoryEntry) -> std::io::Result<()> {
+    let line = serde_json::to_string(entry)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{line}")
+}
+
+/// Format a UNIX timestamp (seconds) as an RFC3339 UTC timestamp, with no
+/// dependency on a calendar crate: converts days-since-epoch to a civil
+/// (year, month, day) triple via Howard Hinnant's `civil_from_days`
+/// algorithm, then formats the remaining seconds-of-day as `HH:MM:SS`.
+fn unix_secs_to_rfc3339(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let year = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 { year + 1 } else { year };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hh:02}:{mi:02}:{ss:02}Z",
+        hh = secs_of_day / 3600,
+        mi = (secs_of_day % 3600) / 60,
+        ss = secs_of_day % 60,
+    )
+}
+
+/// Escape the handful of characters XML requires escaping in text content
+/// and attribute values.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+/// Render the geolocation attestation history at `path` as a GPX 1.1 track:
+/// a single `<trk>` with one `<trkseg>` whose `<trkpt>` elements are the
+/// history entries that carry real coordinates (`"gnss"` fixes). `"mobile"`/
+/// `"none"` entries were logged but have no coordinates to plot, so they are
+/// skipped rather than appearing as a `(0, 0)` point.
+pub fn export_history_as_gpx(path: &Path) -> Result<String, TpmError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| TpmError::Other(format!("Failed to read geolocation history at {}: {e}", path.display())))?;
+
+    let mut trkpts = String::new();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: GeolocationHistoryEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Unified-Identity - Phase 3: Skipping unparseable geolocation history entry: {e}");
+                continue;
+            }
+        };
+        if entry.geolocation.r#type.as_deref() != Some("gnss") {
+            continue;
+        }
+        let Some(fix) = entry
+            .geolocation
+            .value
+            .as_deref()
+            .and_then(|v| serde_json::from_str::<GnssFix>(v).ok())
+        else {
+            continue;
+        };
+
+        let time = fix
+            .fix_time
+            .clone()
+            .unwrap_or_else(|| unix_secs_to_rfc3339(entry.timestamp));
+        let sensor_id = entry.geolocation.sensor_id.as_deref().unwrap_or("unknown");
+
+        trkpts.push_str(&format!(
+            "      <trkpt lat=\"{lat}\" lon=\"{lon}\">\n        <time>{time}</time>\n        <extensions>\n          <sensor_id>{sensor_id}</sensor_id>\n        </extensions>\n      </trkpt>\n",
+            lat = fix.lat,
+            lon = fix.lon,
+            time = xml_escape(&time),
+            sensor_id = xml_escape(sensor_id),
+        ));
+    }
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"keylime-agent\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n  <trk>\n    <name>Unified-Identity geolocation attestation history</name>\n    <trkseg>\n{trkpts}    </trkseg>\n  </trk>\n</gpx>\n"
+    ))
 }
 
 /// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
 /// Extend geolocation data into PCR 17 for TPM-bound attestation
 /// This function:
-/// 1. Gets current geolocation (hardcoded for now)
-/// 2. Hashes it with the nonce and timestamp
+/// 1. Gets current geolocation
+/// 2. Hashes its canonical form with the nonce and timestamp
 /// 3. Extends the hash into PCR 17
-/// 4. Returns the geolocation data for inclusion in the response
+/// 4. Returns the geolocation claim for inclusion in the response
 pub fn extend_geolocation_into_pcr(
     tpm_context: &mut TpmContext<'_>,
     nonce: &[u8],
     hash_alg: HashAlgorithm,
-) -> Result<String, TpmError> {
+) -> Result<Geolocation, TpmError> {
     // Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
     // Step 1: Get current geolocation
     let geolocation = get_current_geolocation();
-    
+
     // Step 2: Get current timestamp
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map_err(|e| TpmError::Other(format!("Failed to get timestamp: {}", e)))?
         .as_secs();
-    
-    // Step 3: Hash geolocation data with nonce and timestamp
-    let location_hash = hash_geolocation_data(&geolocation, nonce, timestamp);
-    
+
+    // Step 3: Hash the canonical geolocation claim with nonce and timestamp
+    let location_hash = hash_geolocation_data(&geolocation, nonce, timestamp)?;
+
     // Step 4: Convert hash to DigestValues for TPM
     let hash_alg_tss = match hash_alg {
         HashAlgorithm::Sha256 => HashingAlgorithm::Sha256,
@@ -170,17 +749,29 @@ pub fn extend_geolocation_into_pcr(
         .map_err(|e| TpmError::Other(format!("Failed to convert geolocation hash to digest: {e}")))?;
     let mut digest_values = DigestValues::new();
     digest_values.set(hash_alg_tss, digest);
-    
+
     // Step 5: Extend into PCR 17 (reset + extend handled by Context helper)
     tpm_context
         .reset_and_extend_pcr(PcrHandle::Pcr17, digest_values.clone())
         .map_err(|e| TpmError::Other(format!("Failed to extend geolocation into PCR 17: {e}")))?;
-    
+
     info!(
-        "Unified-Identity - Phase 3: Extended geolocation into PCR {} (location: {}, timestamp: {})",
-        GEOLOCATION_PCR_INDEX, geolocation, timestamp
+        "Unified-Identity - Phase 3: Extended geolocation into PCR {} (type: {:?}, timestamp: {})",
+        GEOLOCATION_PCR_INDEX, geolocation.r#type, timestamp
     );
-    
+
+    // Step 6: Append to the attestation history log (best-effort; a
+    // logging failure must never fail the attestation itself).
+    let history_entry = GeolocationHistoryEntry {
+        geolocation: geolocation.clone(),
+        nonce_hash: hex::encode(Sha256::digest(nonce)),
+        timestamp,
+        pcr_digest: hex::encode(&location_hash),
+    };
+    if let Err(e) = append_history_entry(Path::new(&history_path()), &history_entry) {
+        warn!("Unified-Identity - Phase 3: Failed to append geolocation history entry: {e}");
+    }
+
     Ok(geolocation)
 }
 
@@ -201,3 +792,228 @@ pub fn is_unified_identity_enabled() -> bool {
             == "yes"
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_nmea_checksum() {
+        assert!(verify_nmea_checksum(
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"
+        ));
+        assert!(!verify_nmea_checksum(
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*00"
+        ));
+        assert!(!verify_nmea_checksum("not a sentence"));
+    }
+
+    #[test]
+    fn test_nmea_coord_to_decimal() {
+        // 48 degrees 07.038 minutes
+        assert!((nmea_coord_to_decimal("4807.038", 2).unwrap() - 48.1173).abs() < 1e-4);
+        // 11 degrees 31.000 minutes
+        assert!((nmea_coord_to_decimal("01131.000", 3).unwrap() - 11.5167).abs() < 1e-4);
+        assert!(nmea_coord_to_decimal("", 2).is_none());
+    }
+
+    #[test]
+    fn test_parse_gga_rejects_no_fix() {
+        // fix quality field (6th) is 0
+        let sentence = "$GPGGA,123519,4807.038,N,01131.000,E,0,08,0.9,545.4,M,46.9,M,,*46";
+        assert!(verify_nmea_checksum(sentence));
+        assert!(parse_gga(sentence).is_none());
+    }
+
+    #[test]
+    fn test_parse_gga_valid_fix() {
+        let sentence = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        let fix = parse_gga(sentence).expect("expected a fix");
+        assert!((fix.lat - 48.1173).abs() < 1e-3);
+        assert!((fix.lon - 11.5167).abs() < 1e-3);
+        assert_eq!(fix.accuracy, 0.9);
+    }
+
+    #[test]
+    fn test_parse_gga_rejects_bad_checksum() {
+        let sentence = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*00";
+        assert!(parse_gga(sentence).is_none());
+    }
+
+    #[test]
+    fn test_hash_geolocation_data_is_deterministic() {
+        let geo = Geolocation {
+            r#type: Some("gnss".to_string()),
+            sensor_id: Some("1546:01a7".to_string()),
+            value: Some(r#"{"lat":48.1173,"lon":11.5167,"accuracy":0.9}"#.to_string()),
+            ..Default::default()
+        };
+        let a = hash_geolocation_data(&geo, b"nonce", 1_700_000_000).unwrap(); //#[allow_ci]
+        let b = hash_geolocation_data(&geo, b"nonce", 1_700_000_000).unwrap(); //#[allow_ci]
+        assert_eq!(a, b);
+
+        let c = hash_geolocation_data(&geo, b"other-nonce", 1_700_000_000).unwrap(); //#[allow_ci]
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_encode_lat_lon_clamping() {
+        assert_eq!(encode_latitude_decimicrodeg(48.1173), 481_173_000);
+        assert_eq!(encode_latitude_decimicrodeg(123.0), 900_000_000);
+        assert_eq!(encode_latitude_decimicrodeg(-123.0), -900_000_000);
+        assert_eq!(encode_longitude_decimicrodeg(11.5167), 115_167_000);
+        assert_eq!(encode_longitude_decimicrodeg(200.0), 1_800_000_000);
+    }
+
+    #[test]
+    fn test_encode_accuracy_cm_saturates() {
+        assert_eq!(encode_accuracy_cm(0.9), 90);
+        assert_eq!(encode_accuracy_cm(10_000.0), u16::MAX);
+    }
+
+    #[test]
+    fn test_lpv_hash_matches_manual_encoding() {
+        let fix = GnssFix {
+            lat: 48.1173,
+            lon: 11.5167,
+            accuracy: 0.9,
+            fix_time: None,
+        };
+        let geo = Geolocation {
+            r#type: Some("gnss".to_string()),
+            sensor_id: Some("1546:01a7".to_string()),
+            value: Some(serde_json::to_string(&fix).unwrap()), //#[allow_ci]
+            ..Default::default()
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"UI-GeoLPV:");
+        hasher.update(encode_lpv_fields(&fix));
+        hasher.update(b"nonce");
+        hasher.update(encode_tai_millis_u32(1_700_000_000).to_be_bytes());
+        let expected = hasher.finalize().to_vec();
+
+        assert_eq!(
+            hash_geolocation_data(&geo, b"nonce", 1_700_000_000).unwrap(), //#[allow_ci]
+            expected
+        );
+    }
+
+    #[test]
+    fn test_unix_secs_to_rfc3339() {
+        assert_eq!(unix_secs_to_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(unix_secs_to_rfc3339(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape(r#"a<b>&"c""#), "a&lt;b&gt;&amp;&quot;c&quot;");
+    }
+
+    #[test]
+    fn test_export_history_as_gpx_skips_non_gnss_entries() {
+        let path = std::env::temp_dir().join(format!(
+            "geolocation_history_test_{}_{:?}.jsonl",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+
+        let gnss_entry = GeolocationHistoryEntry {
+            geolocation: Geolocation {
+                r#type: Some("gnss".to_string()),
+                sensor_id: Some("1546:01a7".to_string()),
+                value: Some(r#"{"lat":48.1173,"lon":11.5167,"accuracy":0.9}"#.to_string()),
+                ..Default::default()
+            },
+            nonce_hash: hex::encode(Sha256::digest(b"nonce")),
+            timestamp: 1_700_000_000,
+            pcr_digest: "deadbeef".to_string(),
+        };
+        let mobile_entry = GeolocationHistoryEntry {
+            geolocation: Geolocation {
+                r#type: Some("mobile".to_string()),
+                sensor_id: Some("12d1:1433".to_string()),
+                value: Some("none".to_string()),
+                ..Default::default()
+            },
+            nonce_hash: hex::encode(Sha256::digest(b"nonce")),
+            timestamp: 1_700_000_100,
+            pcr_digest: "cafebabe".to_string(),
+        };
+
+        append_history_entry(&path, &gnss_entry).unwrap(); //#[allow_ci]
+        append_history_entry(&path, &mobile_entry).unwrap(); //#[allow_ci]
+
+        let gpx = export_history_as_gpx(&path).unwrap(); //#[allow_ci]
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(gpx.matches("<trkpt").count(), 1);
+        assert!(gpx.contains("lat=\"48.1173\""));
+        assert!(gpx.contains("lon=\"11.5167\""));
+        assert!(gpx.contains("<sensor_id>1546:01a7</sensor_id>"));
+        assert!(!gpx.contains("12d1:1433"));
+    }
+
+    #[test]
+    fn test_fuse_candidates_agrees_within_combined_accuracy() {
+        let candidates = vec![
+            SensorCandidate {
+                sensor_id: "/dev/ttyACM0".to_string(),
+                sensor_type: "gnss".to_string(),
+                lat: 48.1173,
+                lon: 11.5167,
+                accuracy_m: 5.0,
+            },
+            SensorCandidate {
+                sensor_id: "/dev/ttyUSB0".to_string(),
+                sensor_type: "gnss".to_string(),
+                lat: 48.11735,
+                lon: 11.51675,
+                accuracy_m: 5.0,
+            },
+        ];
+
+        match fuse_candidates(&candidates) {
+            FusionOutcome::Agreed { lat, lon, accuracy_m } => {
+                assert!((lat - 48.1173).abs() < 1e-3);
+                assert!((lon - 11.5167).abs() < 1e-3);
+                assert_eq!(accuracy_m, 5.0);
+            }
+            FusionOutcome::Disagreement => panic!("expected agreement"), //#[allow_ci]
+        }
+    }
+
+    #[test]
+    fn test_fuse_candidates_detects_disagreement() {
+        let candidates = vec![
+            SensorCandidate {
+                sensor_id: "/dev/ttyACM0".to_string(),
+                sensor_type: "gnss".to_string(),
+                lat: 48.1173,
+                lon: 11.5167,
+                accuracy_m: 5.0,
+            },
+            SensorCandidate {
+                sensor_id: "12d1:1433".to_string(),
+                sensor_type: "mobile".to_string(),
+                lat: 40.4168,
+                lon: -3.7038,
+                accuracy_m: MOBILE_CELL_ACCURACY_M,
+            },
+        ];
+
+        assert!(matches!(fuse_candidates(&candidates), FusionOutcome::Disagreement));
+    }
+
+    #[test]
+    fn test_parse_lat_lon_pair() {
+        assert_eq!(parse_lat_lon_pair("48.1173,11.5167"), Some((48.1173, 11.5167)));
+        assert_eq!(parse_lat_lon_pair(" 40.4168 , -3.7038 "), Some((40.4168, -3.7038)));
+        assert_eq!(parse_lat_lon_pair("none"), None);
+    }
+
+    #[test]
+    fn test_haversine_distance_m_zero_for_identical_points() {
+        assert_eq!(haversine_distance_m((48.1173, 11.5167), (48.1173, 11.5167)), 0.0);
+        assert!(haversine_distance_m((0.0, 0.0), (0.0, 1.0)) > 0.0);
+    }
+}
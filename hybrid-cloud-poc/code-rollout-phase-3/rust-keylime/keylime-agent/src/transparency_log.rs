@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+
+//! Unified-Identity - Phase 3: an optional, Rekor-style append-only
+//! transparency log for `delegated_certification_handler::certify_app_key` -
+//! every successful certification appends a leaf so an auditor can later get
+//! tamper-evident proof that a given App Key was certified at a point in
+//! time, without the agent having to retain every certification response
+//! itself.
+//!
+//! Leaf and internal node hashing follow RFC 6962's domain separation
+//! (`MTH` for leaves prefixes `0x00`, internal nodes prefix `0x01`), and the
+//! tree-splitting rule for odd-sized trees (split at the largest power of
+//! two smaller than the node count) so inclusion proofs stay compatible
+//! with the usual Merkle-audit-path shape used by Certificate
+//! Transparency/Rekor-style logs. This module only does the pure
+//! tree/file bookkeeping; signing the resulting "signed tree head" with the
+//! AK is `delegated_certification_handler`'s job (it owns the TPM context).
+
+use openssl::sha::sha256;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const LEAF_SIZE: usize = 32;
+
+/// Hash of a single leaf's raw data, with RFC 6962's `0x00` leaf prefix.
+pub(crate) fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(1 + data.len());
+    buf.push(0x00);
+    buf.extend_from_slice(data);
+    sha256(&buf)
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(1 + 32 + 32);
+    buf.push(0x01);
+    buf.extend_from_slice(left);
+    buf.extend_from_slice(right);
+    sha256(&buf)
+}
+
+/// The largest power of two strictly smaller than `n` (`n` must be > 1) -
+/// RFC 6962's tree-splitting point.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// `MTH` over a (non-empty) slice of already-leaf-hashed entries.
+fn subtree_root(hashes: &[[u8; 32]]) -> [u8; 32] {
+    if hashes.len() == 1 {
+        return hashes[0];
+    }
+    let k = split_point(hashes.len());
+    node_hash(&subtree_root(&hashes[..k]), &subtree_root(&hashes[k..]))
+}
+
+/// RFC 6962 `MTH({})`, the empty tree's root.
+fn empty_root() -> [u8; 32] {
+    sha256(&[])
+}
+
+/// An append-only Merkle tree of certification-record leaves, backed by a
+/// flat file of concatenated 32-byte leaf hashes (so restart just re-reads
+/// the file instead of recomputing anything).
+pub(crate) struct TransparencyLog {
+    file: File,
+    leaves: Vec<[u8; 32]>,
+}
+
+impl TransparencyLog {
+    /// Open (creating if needed) the leaf file at `path` and load any
+    /// existing leaves into memory.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+        let leaves = raw
+            .chunks_exact(LEAF_SIZE)
+            .map(|chunk| {
+                let mut leaf = [0u8; LEAF_SIZE];
+                leaf.copy_from_slice(chunk);
+                leaf
+            })
+            .collect();
+        Ok(Self { file, leaves })
+    }
+
+    /// Append a new leaf for `data`, flushing it to disk before returning,
+    /// and return its zero-based log index.
+    pub(crate) fn append(&mut self, data: &[u8]) -> io::Result<u64> {
+        let hash = leaf_hash(data);
+        self.file.write_all(&hash)?;
+        self.file.sync_data()?;
+        self.leaves.push(hash);
+        Ok((self.leaves.len() - 1) as u64)
+    }
+
+    pub(crate) fn tree_size(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    pub(crate) fn root(&self) -> [u8; 32] {
+        if self.leaves.is_empty() {
+            empty_root()
+        } else {
+            subtree_root(&self.leaves)
+        }
+    }
+
+    /// The sibling hashes for `index`, ordered from the leaf up to (but not
+    /// including) the root.
+    pub(crate) fn inclusion_proof(&self, index: u64) -> Result<Vec<[u8; 32]>, String> {
+        if index >= self.tree_size() {
+            return Err(format!(
+                "log index {index} is out of range (tree size {})",
+                self.tree_size()
+            ));
+        }
+        Ok(audit_path(index as usize, &self.leaves))
+    }
+}
+
+fn audit_path(index: usize, hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    if hashes.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split_point(hashes.len());
+    if index < k {
+        let mut path = audit_path(index, &hashes[..k]);
+        path.push(subtree_root(&hashes[k..]));
+        path
+    } else {
+        let mut path = audit_path(index - k, &hashes[k..]);
+        path.push(subtree_root(&hashes[..k]));
+        path
+    }
+}
@@ -0,0 +1,223 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity - Phase 3: Hardware Integration & Delegated Certification
+// Builds the self-issued X.509 App Key certificate returned by
+// `delegated_certification_handler::certify_app_key` when `cert_format` is
+// "x509": subject key is the App Key, the raw TPM2_Certify evidence
+// (marshalled TPMS_ATTEST + AK signature) is carried in a non-critical
+// custom extension under APP_KEY_ATTESTATION_OID, and the certificate
+// itself is signed by the AK. The AK only ever produces a raw TPM2_Sign
+// signature over caller-supplied bytes, and there's no "sign with an
+// external key" hook for that in rcgen's CertificateParams/Certificate, so
+// the tbsCertificate is DER-encoded by hand here and the caller signs the
+// resulting bytes with the AK directly through tss_esapi.
+
+/// Private enterprise OID used to tag the App Key attestation extension.
+pub(crate) const APP_KEY_ATTESTATION_OID: &str = "1.3.6.1.4.1.99999.1.2";
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        vec![len as u8]
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let trimmed: Vec<u8> = len_bytes.iter().skip_while(|&&b| b == 0).copied().collect();
+        let mut out = vec![0x80 | trimmed.len() as u8];
+        out.extend(trimmed);
+        out
+    }
+}
+
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    out.extend(der_length(content.len()));
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_sequence(items: &[&[u8]]) -> Vec<u8> {
+    der_tlv(0x30, &items.concat())
+}
+
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let mut b = bytes;
+    while b.len() > 1 && b[0] == 0 && b[1] & 0x80 == 0 {
+        b = &b[1..];
+    }
+    let mut content = Vec::with_capacity(b.len() + 1);
+    if b[0] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(b);
+    der_tlv(0x02, &content)
+}
+
+fn der_boolean(value: bool) -> Vec<u8> {
+    der_tlv(0x01, &[if value { 0xff } else { 0x00 }])
+}
+
+fn der_octet_string(bytes: &[u8]) -> Vec<u8> {
+    der_tlv(0x04, bytes)
+}
+
+fn der_utf8_string(s: &str) -> Vec<u8> {
+    der_tlv(0x0c, s.as_bytes())
+}
+
+fn der_null() -> Vec<u8> {
+    vec![0x05, 0x00]
+}
+
+/// Base-128 big-endian encoding of a single OID arc.
+fn base128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn oid_der(dotted: &str) -> Result<Vec<u8>, String> {
+    let arcs: Vec<u64> = dotted
+        .split('.')
+        .map(|s| s.parse::<u64>().map_err(|_| format!("invalid OID arc: {}", s)))
+        .collect::<Result<_, _>>()?;
+    if arcs.len() < 2 {
+        return Err("OID must have at least two arcs".to_string());
+    }
+    let mut body = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        body.extend(base128(arc));
+    }
+    Ok(der_tlv(0x06, &body))
+}
+
+/// `(year, month, day, hour, min, sec)` in UTC for `unix_secs`, via Howard
+/// Hinnant's `civil_from_days` - no calendar-library dependency needed for a
+/// single certificate validity stamp.
+fn civil_from_unix(unix_secs: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = unix_secs.div_euclid(86_400);
+    let secs_of_day = unix_secs.rem_euclid(86_400);
+    let (hour, min, sec) = (
+        (secs_of_day / 3600) as u32,
+        ((secs_of_day % 3600) / 60) as u32,
+        (secs_of_day % 60) as u32,
+    );
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = if m <= 2 { y + 1 } else { y };
+    (year, m, d, hour, min, sec)
+}
+
+/// DER `UTCTime` for `unix_secs`. Certificates built here are short-lived,
+/// agent-issued attestation artifacts, so the two-digit-year `UTCTime` form
+/// (valid through 2049) is used unconditionally.
+fn der_utc_time(unix_secs: i64) -> Vec<u8> {
+    let (year, month, day, hour, min, sec) = civil_from_unix(unix_secs);
+    let yy = year.rem_euclid(100) as u32;
+    der_tlv(
+        0x17,
+        format!("{:02}{:02}{:02}{:02}{:02}{:02}Z", yy, month, day, hour, min, sec).as_bytes(),
+    )
+}
+
+fn der_name(common_name: &str) -> Vec<u8> {
+    let oid_cn = oid_der("2.5.4.3").expect("2.5.4.3 is a valid OID");
+    let attribute = der_sequence(&[&oid_cn, &der_utf8_string(common_name)]);
+    let rdn = der_tlv(0x31, &attribute); // SET OF
+    der_sequence(&[&rdn])
+}
+
+/// Maps the AK's ECDSA-P256-SHA256 signing scheme to the matching X.509
+/// `AlgorithmIdentifier` DER bytes. Phase 3's AK is always provisioned as an
+/// ECC P-256 key, so unlike the base tree's `x509_attest` module this
+/// doesn't need to branch on the signing scheme actually used.
+fn ecdsa_with_sha256_algorithm_identifier() -> Vec<u8> {
+    let oid = oid_der("1.2.840.10045.4.3.2").expect("ecdsa-with-SHA256 is a valid OID");
+    der_sequence(&[&oid])
+}
+
+/// Convert a raw ECDSA signature (fixed-width `r || s`, as `Context::sign`
+/// returns for an ECC key) into the DER `signatureValue` bit-string content
+/// X.509 expects: `SEQUENCE { INTEGER r, INTEGER s }`.
+pub(crate) fn ecdsa_signature_to_der(raw_signature: &[u8]) -> Result<Vec<u8>, String> {
+    if raw_signature.is_empty() || raw_signature.len() % 2 != 0 {
+        return Err("ECDSA raw signature has unexpected length".to_string());
+    }
+    let half = raw_signature.len() / 2;
+    let (r, s) = raw_signature.split_at(half);
+    Ok(der_sequence(&[&der_integer(r), &der_integer(s)]))
+}
+
+/// TLV-concatenate the `TPM2_Certify` evidence for the attestation
+/// extension: `attest_len(4, BE) || attest || sig_len(4, BE) || sig`.
+pub(crate) fn build_attestation_extension_value(attest_bytes: &[u8], sig_bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + attest_bytes.len() + sig_bytes.len());
+    for part in [attest_bytes, sig_bytes] {
+        out.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+/// Build the DER `tbsCertificate` for a v3, self-issued certificate whose
+/// subject public key is `app_key_public_der` (a complete
+/// `SubjectPublicKeyInfo`), carrying `extension_value` under
+/// `APP_KEY_ATTESTATION_OID`.
+pub(crate) fn build_tbs_certificate(
+    serial: &[u8],
+    common_name: &str,
+    app_key_public_der: &[u8],
+    not_before_unix: i64,
+    not_after_unix: i64,
+    extension_value: &[u8],
+) -> Result<Vec<u8>, String> {
+    let algorithm_identifier = ecdsa_with_sha256_algorithm_identifier();
+    let version = der_tlv(0xa0, &der_integer(&[2])); // [0] EXPLICIT INTEGER v3
+    let serial_number = der_integer(serial);
+    let issuer = der_name(common_name);
+    let validity = der_sequence(&[
+        &der_utc_time(not_before_unix),
+        &der_utc_time(not_after_unix),
+    ]);
+    let subject = der_name(common_name);
+
+    let extension = der_sequence(&[
+        &oid_der(APP_KEY_ATTESTATION_OID)?,
+        &der_boolean(false),
+        &der_octet_string(extension_value),
+    ]);
+    let extensions = der_tlv(0xa3, &der_sequence(&[&extension])); // [3] EXPLICIT Extensions
+
+    Ok(der_sequence(&[
+        &version,
+        &serial_number,
+        &algorithm_identifier,
+        &issuer,
+        &validity,
+        &subject,
+        app_key_public_der,
+        &extensions,
+    ]))
+}
+
+/// Wrap a signed `tbsCertificate` into the outer `Certificate ::= SEQUENCE {
+/// tbsCertificate, signatureAlgorithm, signatureValue }`.
+pub(crate) fn assemble_certificate(tbs_der: &[u8], signature_value: &[u8]) -> Vec<u8> {
+    let algorithm_identifier = ecdsa_with_sha256_algorithm_identifier();
+    let signature_bit_string = {
+        let mut content = Vec::with_capacity(signature_value.len() + 1);
+        content.push(0); // no unused bits
+        content.extend_from_slice(signature_value);
+        der_tlv(0x03, &content)
+    };
+    der_sequence(&[tbs_der, &algorithm_identifier, &signature_bit_string])
+}
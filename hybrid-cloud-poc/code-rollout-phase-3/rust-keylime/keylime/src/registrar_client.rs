@@ -4,10 +4,13 @@ use crate::{
     serialization::*,
 };
 use log::*;
+use reqwest::header::WWW_AUTHENTICATE;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::Number;
+use std::collections::BTreeSet;
 use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -15,10 +18,418 @@ use crate::version::KeylimeRegistrarVersion;
 
 pub const UNKNOWN_API_VERSION: &str = "unknown";
 
+/// Port assumed for a registrar resolved via `.well-known` discovery when
+/// the document's `base_url` carries none, and always for the literal-domain
+/// fallback used when no document is found.
+const DEFAULT_REGISTRAR_PORT: u32 = 8891;
+
+/// Body of a `.well-known/keylime-registrar` discovery document, modeled
+/// on the matrix homeserver client's `.well-known` resolver.
+#[derive(Debug, Deserialize)]
+struct WellKnownRegistrar {
+    registrar: WellKnownRegistrarEntry,
+}
+
+#[derive(Debug, Deserialize)]
+struct WellKnownRegistrarEntry {
+    base_url: String,
+}
+
+/// Split a `scheme://host[:port]` base URL into `(use_https, host, port)`.
+/// Returns `None` for anything else, including a scheme other than
+/// `http`/`https` or a missing host. Does not handle bracketed IPv6
+/// literals; `.well-known` base URLs are expected to carry a hostname.
+fn parse_base_url(base_url: &str) -> Option<(bool, String, u32)> {
+    let (scheme, rest) = base_url.split_once("://")?;
+    let use_https = match scheme {
+        "https" => true,
+        "http" => false,
+        _ => return None,
+    };
+    let host_port = rest.split('/').next().unwrap_or(rest);
+    if host_port.is_empty() {
+        return None;
+    }
+    match host_port.rsplit_once(':') {
+        Some((host, port_str)) => {
+            if host.is_empty() {
+                return None;
+            }
+            let port = port_str.parse().ok()?;
+            Some((use_https, host.to_string(), port))
+        }
+        None => Some((use_https, host_port.to_string(), DEFAULT_REGISTRAR_PORT)),
+    }
+}
+
 fn is_empty(buf: &[u8]) -> bool {
     buf.is_empty()
 }
 
+/// A parsed `major.minor` API version, used to decide compatibility between
+/// the versions an agent has enabled and those a registrar advertises via
+/// its `/version` endpoint. A missing minor component defaults to `0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ApiVersion {
+    major: u32,
+    minor: u32,
+}
+
+impl ApiVersion {
+    /// Parse a `major.minor` (or bare `major`) version string. Returns
+    /// `None` for anything that doesn't parse that way, e.g. `"unknown"`.
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().splitn(2, '.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = match parts.next() {
+            Some(minor_str) => minor_str.parse().ok()?,
+            None => 0,
+        };
+        Some(Self { major, minor })
+    }
+
+    /// Whether `self` (an agent-enabled version) is compatible with
+    /// `registrar` (a registrar-supported version): same major version,
+    /// and the agent's minor is no newer than the registrar's.
+    fn is_compatible_with(&self, registrar: &ApiVersion) -> bool {
+        self.major == registrar.major && self.minor <= registrar.minor
+    }
+}
+
+/// Whether `api_version` (an agent-enabled version string) is
+/// semver-compatible with any entry of `registrar_supported`. Versions that
+/// fail to parse as `ApiVersion` (e.g. `"unknown"`) fall back to exact
+/// (trimmed) string equality, preserving the previous exhaustive-equality
+/// behavior for those entries.
+fn is_version_compatible(
+    api_version: &str,
+    registrar_supported: &[String],
+) -> bool {
+    match ApiVersion::parse(api_version) {
+        Some(agent_version) => registrar_supported.iter().any(|s| {
+            ApiVersion::parse(s)
+                .map(|registrar_version| {
+                    agent_version.is_compatible_with(&registrar_version)
+                })
+                .unwrap_or_else(|| s.trim() == api_version.trim())
+        }),
+        None => registrar_supported
+            .iter()
+            .any(|s| s.trim() == api_version.trim()),
+    }
+}
+
+/// Pick the numerically highest `(major, minor)` version present in both
+/// `enabled` (agent-enabled version strings) and `supported` (registrar-
+/// supported version strings), analogous to dkregistry's
+/// `is_v2_supported` intersection check. Unlike [`is_version_compatible`],
+/// this requires an exact match rather than "agent minor <= registrar
+/// minor", since it's choosing the best version to speak rather than
+/// merely a workable one. Entries that fail to parse as `ApiVersion` are
+/// ignored on both sides.
+fn highest_common_version(
+    enabled: &[&str],
+    supported: &[String],
+) -> Option<String> {
+    let supported_versions: Vec<ApiVersion> =
+        supported.iter().filter_map(|s| ApiVersion::parse(s)).collect();
+
+    enabled
+        .iter()
+        .filter_map(|e| ApiVersion::parse(e).map(|v| (v, *e)))
+        .filter(|(v, _)| supported_versions.contains(v))
+        .max_by_key(|(v, _)| *v)
+        .map(|(_, e)| e.to_string())
+}
+
+/// A set of advertised capability names, compared case-insensitively.
+/// Generalizes the single-version compatibility check in
+/// [`is_version_compatible`]/[`highest_common_version`] to arbitrary named
+/// features (API versions, hash algorithms, evidence formats, EK cert
+/// chains, ...) so the agent/registrar handshake degrades to an extensible
+/// set intersection instead of failing outright whenever either side grows
+/// a feature the other predates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(BTreeSet<String>);
+
+impl Capabilities {
+    /// Build a set from advertised names, trimming and lower-casing each
+    /// one so e.g. `"IAK-IDevID"` and `"iak-idevid"` are treated as the
+    /// same capability.
+    pub fn from_names<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self(
+            names
+                .into_iter()
+                .map(|n| n.as_ref().trim().to_lowercase())
+                .collect(),
+        )
+    }
+
+    /// Whether every entry of `needs` is present in this set.
+    pub fn can_meet(&self, needs: &[String]) -> bool {
+        needs
+            .iter()
+            .all(|n| self.0.contains(&n.trim().to_lowercase()))
+    }
+
+    /// Whether `name` is present in this set.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(&name.trim().to_lowercase())
+    }
+
+    /// The capabilities common to both sets, or `None` if there's no
+    /// overlap at all.
+    pub fn best_common(&self, other: &Capabilities) -> Option<Capabilities> {
+        let common: BTreeSet<String> =
+            self.0.intersection(&other.0).cloned().collect();
+        if common.is_empty() {
+            None
+        } else {
+            Some(Capabilities(common))
+        }
+    }
+
+    /// Iterate over the advertised names, in sorted order.
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.iter()
+    }
+}
+
+/// Identifies which IAK/IDevID signing operation a [`Signer`] is asked to
+/// perform, so one signer can serve multiple purposes with different
+/// backing keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyPurpose {
+    IakAttest,
+    IakSign,
+    IdevidSign,
+}
+
+/// A pluggable signer for IAK/IDevID attestation material, so those
+/// signatures can be produced on demand by a TPM- or HSM-backed
+/// implementation at registration time, keyed by purpose, instead of
+/// being precomputed and carried around as plain bytes. Modeled on the
+/// openid4vc `Sign` trait. `AgentIdentityBuilder` lives in the
+/// `agent_identity` crate outside this tree and isn't extended here;
+/// instead, `RegistrarClientBuilder::signer` lets a `RegistrarClient`
+/// consult a `Signer` at registration time, overriding whichever of
+/// `AgentIdentity`'s precomputed `iak_attest`/`iak_sign` fields it
+/// produces a signature for.
+pub trait Signer: std::fmt::Debug + Send + Sync {
+    /// The key identifier backing `purpose`, if the signer tracks one
+    /// (useful for logs/telemetry). `None` if it doesn't.
+    fn key_id(&self, purpose: KeyPurpose) -> Option<String> {
+        let _ = purpose;
+        None
+    }
+
+    /// Sign `message` with the key for `purpose`.
+    fn sign(&self, purpose: KeyPurpose, message: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// Escape hatch for plugging in a `Signer` implemented outside this
+/// crate (e.g. a TPM or HSM client living in a higher-level crate).
+pub fn external_signer<S: Signer + 'static>(signer: S) -> Arc<dyn Signer> {
+    Arc::new(signer)
+}
+
+/// Default in-memory software [`Signer`], wrapping signatures computed
+/// up front (the prior precomputed-bytes behavior), so callers without a
+/// TPM/HSM-backed `Signer` keep working unchanged.
+#[derive(Debug, Default)]
+pub struct SoftwareSigner {
+    iak_attest: Option<Vec<u8>>,
+    iak_sign: Option<Vec<u8>>,
+    idevid_sign: Option<Vec<u8>>,
+}
+
+impl SoftwareSigner {
+    pub fn new(
+        iak_attest: Option<Vec<u8>>,
+        iak_sign: Option<Vec<u8>>,
+        idevid_sign: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            iak_attest,
+            iak_sign,
+            idevid_sign,
+        }
+    }
+}
+
+impl Signer for SoftwareSigner {
+    fn sign(
+        &self,
+        purpose: KeyPurpose,
+        _message: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let material = match purpose {
+            KeyPurpose::IakAttest => &self.iak_attest,
+            KeyPurpose::IakSign => &self.iak_sign,
+            KeyPurpose::IdevidSign => &self.idevid_sign,
+        };
+        material.clone().ok_or_else(|| {
+            format!("no precomputed signature configured for {purpose:?}")
+        })
+    }
+}
+
+/// Whether `status` is a transient failure worth retrying (connection
+/// errors are always retried independently of this check): rate-limited
+/// or an upstream/gateway hiccup. `400`/`401`/`404` and other 4xx/5xx
+/// statuses are assumed to be stable outcomes and are not retried.
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Whether `e` is worth failing over to the next registrar endpoint in
+/// [`RegistrarClient::activate_agent`]'s endpoint list: a connection-level
+/// failure or a 5xx response. A 4xx response or a version mismatch means
+/// every endpoint would likely fail the same way, so those are returned
+/// immediately instead of masked by trying the next endpoint.
+fn is_failover_error(e: &RegistrarClientError) -> bool {
+    match e {
+        RegistrarClientError::Reqwest(_) | RegistrarClientError::Middleware(_) => {
+            true
+        }
+        RegistrarClientError::Activation { code, .. } => *code >= 500,
+        _ => false,
+    }
+}
+
+/// Exponential backoff with jitter for retry attempt number `attempt`
+/// (0-indexed), capped at `max_delay`. Jitter is derived from the
+/// current time rather than a `rand` dependency, and only needs to avoid
+/// a thundering herd, not be cryptographically random.
+fn jittered_delay(base: Duration, attempt: u32, max_delay: Duration) -> Duration {
+    let exp_ms = base
+        .as_millis()
+        .saturating_mul(1u128 << attempt.min(16))
+        .min(max_delay.as_millis());
+    let jitter_permille = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| u128::from(d.subsec_nanos()) % 500)
+        .unwrap_or(0);
+    let jittered_ms = (exp_ms + exp_ms * jitter_permille / 1000)
+        .min(max_delay.as_millis());
+    Duration::from_millis(jittered_ms as u64)
+}
+
+/// Pulls the broad capability set (e.g. `push-attestation`, `iak-idevid`,
+/// `dual-ek`) out of a `/version` response body. Read directly from the raw
+/// JSON rather than `KeylimeRegistrarVersion` itself, since the capability
+/// list rides alongside the version fields rather than being part of that
+/// type. Missing or malformed `results.capabilities` is treated as "no
+/// capabilities advertised" rather than an error, so older registrars that
+/// don't send the field still negotiate a version successfully.
+fn extract_capabilities(body: &serde_json::Value) -> Vec<String> {
+    body.get("results")
+        .and_then(|results| results.get("capabilities"))
+        .and_then(|capabilities| capabilities.as_array())
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| entry.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A parsed `WWW-Authenticate: Bearer realm="...",service="...",scope="..."`
+/// challenge, per the Docker/OCI-style bearer token flow used by registrars
+/// deployed behind an auth proxy.
+#[derive(Debug)]
+struct BearerChallenge {
+    realm: String,
+    service: Option<String>,
+    scope: Option<String>,
+}
+
+impl BearerChallenge {
+    /// Parse a `WWW-Authenticate` header value. Returns `None` if the
+    /// scheme isn't `Bearer` or no `realm` parameter is present.
+    fn parse(header_value: &str) -> Option<Self> {
+        let rest = header_value.trim().strip_prefix("Bearer ")?;
+
+        let mut realm = None;
+        let mut service = None;
+        let mut scope = None;
+        for part in rest.split(',') {
+            let Some((key, value)) = part.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "realm" => realm = Some(value),
+                "service" => service = Some(value),
+                "scope" => scope = Some(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            realm: realm?,
+            service,
+            scope,
+        })
+    }
+}
+
+/// Fetch a bearer token from a `WWW-Authenticate: Bearer` challenge's token
+/// endpoint via `client`, optionally presenting `username`/`password` as
+/// HTTP Basic credentials, and extract the `token`/`access_token` field
+/// from the JSON response. Returns a human-readable error description on
+/// any failure, since the token endpoint is not a Keylime registrar and so
+/// doesn't follow the `Response<T>` envelope.
+async fn fetch_bearer_token(
+    client: &reqwest::Client,
+    challenge: &BearerChallenge,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Result<String, String> {
+    let mut req = client.get(&challenge.realm);
+
+    let mut query = Vec::new();
+    if let Some(service) = &challenge.service {
+        query.push(("service", service.as_str()));
+    }
+    if let Some(scope) = &challenge.scope {
+        query.push(("scope", scope.as_str()));
+    }
+    if !query.is_empty() {
+        req = req.query(&query);
+    }
+    if let Some(username) = username {
+        req = req.basic_auth(username, password);
+    }
+
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("token endpoint returned status {}", resp.status()));
+    }
+
+    let body: serde_json::Value =
+        resp.json().await.map_err(|e| e.to_string())?;
+    body.get("token")
+        .or_else(|| body.get("access_token"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| {
+            "token endpoint response had no token/access_token field"
+                .to_string()
+        })
+}
+
 #[derive(Error, Debug)]
 pub enum RegistrarClientBuilderError {
     /// Registrar IP or hostname not set
@@ -37,18 +448,143 @@ pub enum RegistrarClientBuilderError {
     #[error("Reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
 
+    /// Serde error
+    #[error("Serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+
     /// Middleware error
     #[error("Middleware error: {0}")]
     Middleware(#[from] reqwest_middleware::Error),
+
+    /// Failed to read the registrar client config file
+    #[error("Failed to read registrar client config file {0}: {1}")]
+    ConfigFileRead(String, String),
+
+    /// Failed to parse the registrar client config file
+    #[error("Failed to parse registrar client config file {0}: {1}")]
+    ConfigFileParse(String, String),
+
+    /// Failed to read a TLS certificate or key file
+    #[error("Failed to read TLS file {0}: {1}")]
+    TlsFileRead(String, String),
+
+    /// Failed to parse a TLS certificate or key file into a usable identity
+    #[error("Failed to parse TLS material from {0}: {1}")]
+    TlsFileParse(String, String),
+
+    /// Received a 401 with no parseable Bearer challenge
+    #[error("Received 401 from {0} with no parseable Bearer challenge")]
+    MissingBearerChallenge(String),
+
+    /// Failed to obtain a bearer token from the challenge's token endpoint
+    #[error("Failed to obtain bearer token for {0}: {1}")]
+    BearerTokenFetch(String, String),
+
+    /// Failed to parse the `.well-known` registrar discovery document
+    #[error("Failed to parse .well-known registrar document from {0}: {1}")]
+    WellKnownParse(String, String),
+
+    /// The `.well-known` registrar document's `base_url` was not a usable
+    /// `scheme://host[:port]` URL
+    #[error("Invalid base_url '{0}' in .well-known registrar document")]
+    WellKnownInvalidBaseUrl(String),
+}
+
+/// Registrar client settings recognized by [`RegistrarClientBuilder::from_env`]
+/// (as `KEYLIME_REGISTRAR_*` environment variables) and
+/// [`RegistrarClientBuilder::from_config`] (as the same keys, snake_case, in
+/// a TOML file). All fields are optional: unset ones are simply left unset
+/// on the returned builder.
+#[derive(Debug, Default, Deserialize)]
+struct RegistrarClientFileConfig {
+    registrar_ip: Option<String>,
+    registrar_port: Option<u32>,
+    retry_initial_delay_ms: Option<u64>,
+    retry_max_retries: Option<u32>,
+    retry_max_delay_ms: Option<u64>,
+    use_https: Option<bool>,
+    tls_ca_cert_path: Option<String>,
+    tls_client_cert_path: Option<String>,
+    tls_client_key_path: Option<String>,
+    pinned_certificate_path: Option<String>,
+    bearer_username: Option<String>,
+    bearer_password: Option<String>,
+}
+
+/// Retry policy for `register_agent`/`activate_agent` against transient
+/// registrar failures (connection errors and 429/502/503/504 responses),
+/// independent of the `retry_config`/`ResilientClient` transport retry
+/// used for `/version` checks. A response's `Retry-After` header (when
+/// present and expressed as delta-seconds) is honored in place of the
+/// computed backoff.
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterRetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RegisterRetryPolicy {
+    /// A single attempt (no retries) with a 200ms/5s backoff range, used
+    /// as the starting point for [`RegistrarClientBuilder::max_retries`]/
+    /// [`RegistrarClientBuilder::initial_backoff`] when no
+    /// [`RegistrarClientBuilder::register_retry_policy`] was set yet.
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct RegistrarClientBuilder {
     registrar_current_api_version: Option<String>,
     registrar_supported_api_versions: Option<Vec<String>>,
+    // Unified-Identity: broad registrar capability flags (e.g.
+    // `push-attestation`, `iak-idevid`, `dual-ek`) advertised alongside the
+    // version in the `/version` response, adjacent to but not part of
+    // `KeylimeRegistrarVersion` itself.
+    registrar_capabilities: Option<Vec<String>>,
     registrar_address: Option<String>,
     registrar_port: Option<u32>,
+    // Unified-Identity: ordered registrar endpoints to fail over across
+    // in `RegistrarClient::activate_agent`; empty unless
+    // `registrar_endpoints` was called. When empty, `build()` falls back
+    // to a one-element list built from `registrar_address`/
+    // `registrar_port`.
+    registrar_endpoints: Vec<(String, u32)>,
+    // Unified-Identity: domain used for `.well-known` registrar discovery
+    // (see `resolve_well_known`), an alternative to setting
+    // `registrar_address`/`registrar_port` directly.
+    registrar_domain: Option<String>,
     retry_config: Option<RetryConfig>,
+    // Unified-Identity: HTTPS/mTLS transport, populated by `from_env`/
+    // `from_config` or the fluent setters below and consumed by `build()`
+    // to construct the `reqwest::Client` used for all registrar requests.
+    use_https: bool,
+    tls_ca_cert_path: Option<String>,
+    tls_client_cert_path: Option<String>,
+    tls_client_key_path: Option<String>,
+    // Unified-Identity: path to a PEM certificate to pin as the sole
+    // acceptable registrar identity (see `build_http_client`), rejecting
+    // any other certificate even if it's chain-valid.
+    pinned_certificate_path: Option<String>,
+    // Unified-Identity: HTTP Basic credentials used to obtain a bearer
+    // token from a `WWW-Authenticate: Bearer` challenge's token endpoint,
+    // and the token cached from the last such exchange.
+    bearer_username: Option<String>,
+    bearer_password: Option<String>,
+    bearer_token: Option<String>,
+    // Unified-Identity: how long a negotiated `/version` result stays
+    // valid before `RegistrarClient::ensure_compatible_version` re-fetches
+    // it; `None` means every call re-fetches.
+    version_cache_ttl: Option<Duration>,
+    register_retry_policy: Option<RegisterRetryPolicy>,
+    // Unified-Identity: optional TPM/HSM-backed signer consulted at
+    // registration time for IAK/IDevID signatures; see `Signer`.
+    signer: Option<Arc<dyn Signer>>,
 }
 
 impl RegistrarClientBuilder {
@@ -78,6 +614,25 @@ impl RegistrarClientBuilder {
         self
     }
 
+    /// Set an ordered list of registrar `(address, port)` endpoints to
+    /// try in order, failing over to the next on a connection error,
+    /// timeout, or 5xx response (see [`RegistrarClient::activate_agent`]).
+    /// `registrar_address`/`registrar_port` remain supported as thin
+    /// wrappers that `build()` turns into a one-element list when this
+    /// isn't set.
+    pub fn registrar_endpoints(
+        mut self,
+        endpoints: Vec<(String, u32)>,
+    ) -> Self {
+        self.registrar_endpoints = endpoints
+            .into_iter()
+            .map(|(address, port)| {
+                (Self::parse_registrar_address(address), port)
+            })
+            .collect();
+        self
+    }
+
     /// Set the RetryConfig for the registrar client
     ///
     /// # Arguments:
@@ -88,6 +643,239 @@ impl RegistrarClientBuilder {
         self
     }
 
+    /// Set a domain to resolve via `.well-known` registrar discovery
+    /// instead of setting `registrar_address`/`registrar_port` directly.
+    /// See [`Self::resolve_well_known`] for the resolution behavior; has
+    /// no effect if `registrar_address` is also set.
+    pub fn registrar_domain(mut self, domain: String) -> Self {
+        self.registrar_domain = Some(domain);
+        self
+    }
+
+    /// Set how long `RegistrarClient::ensure_compatible_version` may reuse
+    /// a previously negotiated `/version` result before re-fetching it.
+    pub fn version_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.version_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Set the retry policy `register_agent`/`activate_agent` use against
+    /// transient registrar failures. See [`RegisterRetryPolicy`].
+    pub fn register_retry_policy(
+        mut self,
+        policy: RegisterRetryPolicy,
+    ) -> Self {
+        self.register_retry_policy = Some(policy);
+        self
+    }
+
+    /// Convenience setter for how many times `activate_agent`/
+    /// `register_agent` retry a transient failure (connection errors and
+    /// 429/502/503/504 responses; see [`is_retryable_status`]), without
+    /// having to build a whole [`RegisterRetryPolicy`] by hand. Builds one
+    /// from [`RegisterRetryPolicy::default`] if [`Self::register_retry_policy`]
+    /// wasn't called first, preserving any `initial_backoff` already set.
+    /// `max_retries(0)` is equivalent to never calling this method: the
+    /// single-attempt, no-retry behavior.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        let mut policy = self.register_retry_policy.unwrap_or_default();
+        policy.max_attempts = max_retries.saturating_add(1);
+        self.register_retry_policy = Some(policy);
+        self
+    }
+
+    /// Convenience setter for the starting delay of the exponential-with-
+    /// jitter backoff schedule (see [`jittered_delay`]) that
+    /// `activate_agent`/`register_agent` retries use. Builds a
+    /// [`RegisterRetryPolicy`] from [`RegisterRetryPolicy::default`] if
+    /// [`Self::register_retry_policy`] wasn't called first, preserving any
+    /// `max_retries` already set.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        let mut policy = self.register_retry_policy.unwrap_or_default();
+        policy.base_delay = initial_backoff;
+        self.register_retry_policy = Some(policy);
+        self
+    }
+
+    /// Set the [`Signer`] consulted for IAK/IDevID signatures at
+    /// registration time, overriding `AgentIdentity`'s precomputed
+    /// `iak_attest`/`iak_sign` fields for whichever purposes it signs.
+    pub fn signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Build a partially-populated builder from well-known environment
+    /// variables (`KEYLIME_REGISTRAR_IP`, `KEYLIME_REGISTRAR_PORT`,
+    /// `KEYLIME_REGISTRAR_RETRY_INITIAL_DELAY_MS`,
+    /// `KEYLIME_REGISTRAR_RETRY_MAX_RETRIES`,
+    /// `KEYLIME_REGISTRAR_RETRY_MAX_DELAY_MS`, `KEYLIME_REGISTRAR_USE_HTTPS`,
+    /// `KEYLIME_REGISTRAR_TLS_CA_CERT`, `KEYLIME_REGISTRAR_TLS_CLIENT_CERT`,
+    /// `KEYLIME_REGISTRAR_TLS_CLIENT_KEY`, `KEYLIME_REGISTRAR_BEARER_USERNAME`,
+    /// `KEYLIME_REGISTRAR_BEARER_PASSWORD`), so callers don't have to wire
+    /// deployment configuration into the builder by hand. Unset variables
+    /// are left unset; callers can still override any field fluently
+    /// before calling `build()`.
+    pub fn from_env() -> Self {
+        let mut builder = Self::new();
+
+        if let Ok(ip) = std::env::var("KEYLIME_REGISTRAR_IP") {
+            builder = builder.registrar_address(ip);
+        }
+        if let Some(port) = std::env::var("KEYLIME_REGISTRAR_PORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            builder = builder.registrar_port(port);
+        }
+
+        let initial_delay_ms = std::env::var("KEYLIME_REGISTRAR_RETRY_INITIAL_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let max_retries = std::env::var("KEYLIME_REGISTRAR_RETRY_MAX_RETRIES")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        let max_delay_ms = std::env::var("KEYLIME_REGISTRAR_RETRY_MAX_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok());
+        if let (Some(initial_delay_ms), Some(max_retries)) =
+            (initial_delay_ms, max_retries)
+        {
+            builder = builder.retry_config(Some(RetryConfig {
+                initial_delay_ms,
+                max_retries,
+                max_delay_ms,
+            }));
+        }
+
+        if let Some(use_https) = std::env::var("KEYLIME_REGISTRAR_USE_HTTPS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            builder.use_https = use_https;
+        }
+        builder.tls_ca_cert_path = std::env::var("KEYLIME_REGISTRAR_TLS_CA_CERT").ok();
+        builder.tls_client_cert_path =
+            std::env::var("KEYLIME_REGISTRAR_TLS_CLIENT_CERT").ok();
+        builder.tls_client_key_path =
+            std::env::var("KEYLIME_REGISTRAR_TLS_CLIENT_KEY").ok();
+        builder.pinned_certificate_path =
+            std::env::var("KEYLIME_REGISTRAR_PIN_CERT_PATH").ok();
+        builder.bearer_username =
+            std::env::var("KEYLIME_REGISTRAR_BEARER_USERNAME").ok();
+        builder.bearer_password =
+            std::env::var("KEYLIME_REGISTRAR_BEARER_PASSWORD").ok();
+
+        builder
+    }
+
+    /// Build a partially-populated builder from a TOML config file at
+    /// `path`, recognizing the same keys as [`Self::from_env`] in
+    /// snake_case (e.g. `registrar_ip`, `retry_initial_delay_ms`,
+    /// `tls_ca_cert_path`). Callers can still override any field fluently
+    /// before calling `build()`.
+    pub fn from_config<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Self, RegistrarClientBuilderError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            RegistrarClientBuilderError::ConfigFileRead(
+                path.display().to_string(),
+                e.to_string(),
+            )
+        })?;
+        let file_config: RegistrarClientFileConfig = toml::from_str(&contents)
+            .map_err(|e| {
+                RegistrarClientBuilderError::ConfigFileParse(
+                    path.display().to_string(),
+                    e.to_string(),
+                )
+            })?;
+
+        let mut builder = Self::new();
+
+        if let Some(ip) = file_config.registrar_ip {
+            builder = builder.registrar_address(ip);
+        }
+        if let Some(port) = file_config.registrar_port {
+            builder = builder.registrar_port(port);
+        }
+        if let (Some(initial_delay_ms), Some(max_retries)) = (
+            file_config.retry_initial_delay_ms,
+            file_config.retry_max_retries,
+        ) {
+            builder = builder.retry_config(Some(RetryConfig {
+                initial_delay_ms,
+                max_retries,
+                max_delay_ms: file_config.retry_max_delay_ms,
+            }));
+        }
+        if let Some(use_https) = file_config.use_https {
+            builder.use_https = use_https;
+        }
+        builder.tls_ca_cert_path = file_config.tls_ca_cert_path;
+        builder.tls_client_cert_path = file_config.tls_client_cert_path;
+        builder.tls_client_key_path = file_config.tls_client_key_path;
+        builder.pinned_certificate_path = file_config.pinned_certificate_path;
+        builder.bearer_username = file_config.bearer_username;
+        builder.bearer_password = file_config.bearer_password;
+
+        Ok(builder)
+    }
+
+    /// Enable `https://` URLs and TLS-verified/mutual-TLS transport for
+    /// registrar requests, using whatever CA bundle and client identity
+    /// were set via `tls_ca_cert_path`/`tls_client_cert_path`/
+    /// `tls_client_key_path`.
+    pub fn use_https(mut self, use_https: bool) -> Self {
+        self.use_https = use_https;
+        self
+    }
+
+    /// Set the CA certificate path used to verify the registrar's TLS
+    /// certificate when mTLS transport is enabled.
+    pub fn tls_ca_cert_path(mut self, path: String) -> Self {
+        self.tls_ca_cert_path = Some(path);
+        self
+    }
+
+    /// Set the client certificate path presented for mutual TLS.
+    pub fn tls_client_cert_path(mut self, path: String) -> Self {
+        self.tls_client_cert_path = Some(path);
+        self
+    }
+
+    /// Set the client private key path presented for mutual TLS.
+    pub fn tls_client_key_path(mut self, path: String) -> Self {
+        self.tls_client_key_path = Some(path);
+        self
+    }
+
+    /// Pin the registrar's TLS certificate to the PEM file at `path`:
+    /// `build_http_client` trusts only this certificate, ignoring both
+    /// the system roots and any `tls_ca_cert_path` CA bundle, so that a
+    /// compromised CA can't mint a chain-valid impostor for this
+    /// registrar. Takes precedence over `tls_ca_cert_path` when both are
+    /// set.
+    pub fn pin_certificate_path(mut self, path: String) -> Self {
+        self.pinned_certificate_path = Some(path);
+        self
+    }
+
+    /// Set the HTTP Basic username presented to a `WWW-Authenticate: Bearer`
+    /// challenge's token endpoint when registering against a registrar
+    /// deployed behind an auth proxy.
+    pub fn bearer_username(mut self, username: String) -> Self {
+        self.bearer_username = Some(username);
+        self
+    }
+
+    /// Set the HTTP Basic password presented alongside `bearer_username`.
+    pub fn bearer_password(mut self, password: String) -> Self {
+        self.bearer_password = Some(password);
+        self
+    }
+
     /// Parse the received address
     fn parse_registrar_address(address: String) -> String {
         // Parse the registrar IP or hostname
@@ -109,54 +897,259 @@ impl RegistrarClientBuilder {
         }
     }
 
-    /// Get the registrar API version from the Registrar '/version' endpoint
-    async fn get_registrar_api_version(
+    /// Resolve `registrar_address`/`registrar_port` from a `.well-known`
+    /// discovery document, mirroring the matrix homeserver client's
+    /// `.well-known` resolver. Fetches
+    /// `https://<registrar_domain>/.well-known/keylime-registrar` and
+    /// expects a `{ "registrar": { "base_url": "<scheme>://<host>[:<port>]" } }`
+    /// body. No-op if `registrar_domain` wasn't set or `registrar_address`
+    /// is already set (which also makes the resolution idempotent across
+    /// repeated `build()` calls). A missing document (connection failure
+    /// or non-2xx) falls back to the literal domain on
+    /// [`DEFAULT_REGISTRAR_PORT`]; a 2xx response with an unparseable body
+    /// or an invalid `base_url` is a hard error, since that usually means
+    /// an operator typo rather than an absent feature.
+    async fn resolve_well_known(
         &mut self,
-    ) -> Result<String, RegistrarClientBuilderError> {
-        let Some(ref registrar_ip) = self.registrar_address else {
-            return Err(RegistrarClientBuilderError::RegistrarIPNotSet);
+    ) -> Result<(), RegistrarClientBuilderError> {
+        let Some(domain) = self.registrar_domain.clone() else {
+            return Ok(());
         };
+        if self.registrar_address.is_some() {
+            return Ok(());
+        }
 
-        let Some(registrar_port) = self.registrar_port else {
-            return Err(RegistrarClientBuilderError::RegistrarPortNotSet);
+        let addr = format!("https://{domain}/.well-known/keylime-registrar");
+        info!("Resolving registrar via {addr}");
+
+        let http_client = self.build_http_client()?;
+        let resp = match http_client.get(&addr).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            Ok(resp) => {
+                info!("No .well-known registrar document at {addr} (status {}), falling back to {domain}:{DEFAULT_REGISTRAR_PORT}", resp.status());
+                self.registrar_address =
+                    Some(Self::parse_registrar_address(domain));
+                self.registrar_port = Some(DEFAULT_REGISTRAR_PORT);
+                return Ok(());
+            }
+            Err(e) => {
+                info!("Failed to reach .well-known document at {addr}: {e}, falling back to {domain}:{DEFAULT_REGISTRAR_PORT}");
+                self.registrar_address =
+                    Some(Self::parse_registrar_address(domain));
+                self.registrar_port = Some(DEFAULT_REGISTRAR_PORT);
+                return Ok(());
+            }
         };
 
-        // Try to reach the registrar
-        let addr = format!("http://{registrar_ip}:{registrar_port}/version");
+        let doc: WellKnownRegistrar = resp.json().await.map_err(|e| {
+            RegistrarClientBuilderError::WellKnownParse(addr.clone(), e.to_string())
+        })?;
 
-        info!("Requesting registrar API version to {addr}");
+        let (use_https, host, port) = parse_base_url(&doc.registrar.base_url)
+            .ok_or_else(|| {
+                RegistrarClientBuilderError::WellKnownInvalidBaseUrl(
+                    doc.registrar.base_url.clone(),
+                )
+            })?;
+
+        self.use_https = use_https;
+        self.registrar_address = Some(Self::parse_registrar_address(host));
+        self.registrar_port = Some(port);
+
+        Ok(())
+    }
+
+    /// `http` or `https`, depending on whether HTTPS/mTLS transport was
+    /// enabled via `use_https`/`from_env`/`from_config`.
+    fn scheme(&self) -> &'static str {
+        if self.use_https {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// Build the `reqwest::Client` used for registrar requests. Loads the
+    /// CA bundle and/or client identity configured via
+    /// `tls_ca_cert_path`/`tls_client_cert_path`/`tls_client_key_path`, if
+    /// any were set; a registrar client with none configured gets a plain
+    /// `reqwest::Client`.
+    fn build_http_client(
+        &self,
+    ) -> Result<reqwest::Client, RegistrarClientBuilderError> {
+        let mut builder = reqwest::Client::builder();
+
+        // Backend selection mirrors actix-http's `rustls`/`openssl`
+        // cargo features: pick whichever TLS implementation this binary
+        // was built with. Picking neither leaves reqwest's own default.
+        #[cfg(feature = "tls-rustls")]
+        {
+            builder = builder.use_rustls_tls();
+        }
+        #[cfg(feature = "tls-openssl")]
+        {
+            builder = builder.use_native_tls();
+        }
+
+        if let Some(pin_path) = &self.pinned_certificate_path {
+            let pem = std::fs::read(pin_path).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileRead(
+                    pin_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            let pinned = reqwest::Certificate::from_pem(&pem).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileParse(
+                    pin_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            // Trust only the pinned registrar certificate, not the
+            // system/CA-bundle roots, so a compromised CA can't mint a
+            // chain-valid impostor for this registrar.
+            builder = builder
+                .add_root_certificate(pinned)
+                .tls_built_in_root_certs(false);
+        } else if let Some(ca_cert_path) = &self.tls_ca_cert_path {
+            let pem = std::fs::read(ca_cert_path).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileRead(
+                    ca_cert_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            let ca_cert = reqwest::Certificate::from_pem(&pem).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileParse(
+                    ca_cert_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            builder = builder.add_root_certificate(ca_cert);
+        }
 
-        let resp = if let Some(retry_config) = &self.retry_config {
+        if let (Some(cert_path), Some(key_path)) =
+            (&self.tls_client_cert_path, &self.tls_client_key_path)
+        {
+            let mut identity_pem = std::fs::read(cert_path).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileRead(
+                    cert_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            let mut key_pem = std::fs::read(key_path).map_err(|e| {
+                RegistrarClientBuilderError::TlsFileRead(
+                    key_path.clone(),
+                    e.to_string(),
+                )
+            })?;
+            identity_pem.append(&mut key_pem);
+            let identity =
+                reqwest::Identity::from_pem(&identity_pem).map_err(|e| {
+                    RegistrarClientBuilderError::TlsFileParse(
+                        cert_path.clone(),
+                        e.to_string(),
+                    )
+                })?;
+            builder = builder.identity(identity);
+        }
+
+        builder.build().map_err(RegistrarClientBuilderError::Reqwest)
+    }
+
+    /// Send the `GET /version` request, via the resilient client if
+    /// configured, attaching a cached bearer token if present.
+    async fn send_version_request(
+        &self,
+        addr: &str,
+        http_client: &reqwest::Client,
+    ) -> Result<reqwest::Response, RegistrarClientBuilderError> {
+        if let Some(retry_config) = &self.retry_config {
             debug!(
                 "Using ResilientClient for version check with {} retries.",
                 retry_config.max_retries
             );
             let client = ResilientClient::new(
-                None,
+                Some(http_client.clone()),
                 Duration::from_millis(retry_config.initial_delay_ms),
                 retry_config.max_retries,
                 &[StatusCode::OK],
                 retry_config.max_delay_ms.map(Duration::from_millis),
             );
 
-            client
-                .get_request(reqwest::Method::GET, &addr)
-                .send()
-                .await?
+            let mut req = client.get_request(reqwest::Method::GET, addr);
+            if let Some(token) = &self.bearer_token {
+                req = req.bearer_auth(token);
+            }
+            Ok(req.send().await?)
         } else {
-            reqwest::Client::new()
-                .get(&addr)
-                .send()
-                .await
-                .map_err(RegistrarClientBuilderError::Reqwest)?
+            let mut req = http_client.get(addr);
+            if let Some(token) = &self.bearer_token {
+                req = req.bearer_auth(token);
+            }
+            req.send().await.map_err(RegistrarClientBuilderError::Reqwest)
+        }
+    }
+
+    /// Get the registrar API version from the Registrar '/version' endpoint
+    async fn get_registrar_api_version(
+        &mut self,
+    ) -> Result<String, RegistrarClientBuilderError> {
+        let Some(ref registrar_ip) = self.registrar_address else {
+            return Err(RegistrarClientBuilderError::RegistrarIPNotSet);
         };
 
+        let Some(registrar_port) = self.registrar_port else {
+            return Err(RegistrarClientBuilderError::RegistrarPortNotSet);
+        };
+
+        // Try to reach the registrar
+        let scheme = self.scheme();
+        let addr = format!("{scheme}://{registrar_ip}:{registrar_port}/version");
+
+        info!("Requesting registrar API version to {addr}");
+
+        let http_client = self.build_http_client()?;
+
+        let mut resp = self.send_version_request(&addr, &http_client).await?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            let challenge = resp
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(BearerChallenge::parse)
+                .ok_or_else(|| {
+                    RegistrarClientBuilderError::MissingBearerChallenge(
+                        addr.clone(),
+                    )
+                })?;
+
+            let token = fetch_bearer_token(
+                &http_client,
+                &challenge,
+                self.bearer_username.as_deref(),
+                self.bearer_password.as_deref(),
+            )
+            .await
+            .map_err(|e| {
+                RegistrarClientBuilderError::BearerTokenFetch(
+                    challenge.realm.clone(),
+                    e,
+                )
+            })?;
+            self.bearer_token = Some(token);
+
+            resp = self.send_version_request(&addr, &http_client).await?;
+        }
+
         if !resp.status().is_success() {
             info!("Registrar at '{addr}' does not support the '/version' endpoint");
             return Err(RegistrarClientBuilderError::RegistrarNoVersion);
         }
 
-        let resp: Response<KeylimeRegistrarVersion> = resp.json().await?;
+        let body: serde_json::Value = resp.json().await?;
+        self.registrar_capabilities = Some(extract_capabilities(&body));
+        let resp: Response<KeylimeRegistrarVersion> =
+            serde_json::from_value(body)?;
 
         self.registrar_current_api_version =
             Some(resp.results.current_version.clone());
@@ -170,6 +1163,8 @@ impl RegistrarClientBuilder {
     pub async fn build(
         &mut self,
     ) -> Result<RegistrarClient, RegistrarClientBuilderError> {
+        self.resolve_well_known().await?;
+
         let Some(registrar_ip) = self.registrar_address.clone() else {
             return Err(RegistrarClientBuilderError::RegistrarIPNotSet);
         };
@@ -193,10 +1188,12 @@ impl RegistrarClientBuilder {
                 },
             };
 
+        let http_client = self.build_http_client()?;
+
         let resilient_client =
             self.retry_config.as_ref().map(|retry_config| {
                 ResilientClient::new(
-                    None,
+                    Some(http_client.clone()),
                     Duration::from_millis(retry_config.initial_delay_ms),
                     retry_config.max_retries,
                     &[StatusCode::OK],
@@ -206,12 +1203,29 @@ impl RegistrarClientBuilder {
 
         let supported_versions = self.registrar_supported_api_versions.clone();
         info!("RegistrarClient::build: api_version = '{}', supported_api_versions = {:?}", registrar_api_version, supported_versions);
+        let mut registrar_endpoints = self.registrar_endpoints.clone();
+        if registrar_endpoints.is_empty() {
+            registrar_endpoints.push((registrar_ip.clone(), registrar_port));
+        }
+
         Ok(RegistrarClient {
             supported_api_versions: supported_versions,
             api_version: registrar_api_version,
             registrar_ip,
             registrar_port,
+            registrar_endpoints,
+            use_https: self.use_https,
+            http_client,
             resilient_client,
+            bearer_username: self.bearer_username.clone(),
+            bearer_password: self.bearer_password.clone(),
+            bearer_token: self.bearer_token.clone(),
+            capabilities: self.registrar_capabilities.clone().unwrap_or_default(),
+            negotiated_capabilities: None,
+            version_cache_ttl: self.version_cache_ttl,
+            version_fetched_at: None,
+            register_retry_policy: self.register_retry_policy,
+            signer: self.signer.clone(),
         })
     }
 }
@@ -241,6 +1255,10 @@ pub enum RegistrarClientError {
     #[error("cannot get error code for type {0}")]
     NoCode(String),
 
+    /// The registrar does not support the '/version' endpoint
+    #[error("Registrar does not support the /version endpoint")]
+    NoVersionEndpoint,
+
     /// Registration failure
     #[error("Failed to register agent: received {code} from {addr}")]
     Registration { addr: String, code: u16 },
@@ -256,15 +1274,198 @@ pub enum RegistrarClientError {
     /// Middleware error
     #[error("Middleware error: {0}")]
     Middleware(#[from] reqwest_middleware::Error),
+
+    /// Received a 401 with no parseable Bearer challenge
+    #[error("Received 401 from {0} with no parseable Bearer challenge")]
+    MissingBearerChallenge(String),
+
+    /// Failed to obtain a bearer token from the challenge's token endpoint
+    #[error("Failed to obtain bearer token for {0}: {1}")]
+    BearerTokenFetch(String, String),
+}
+
+/// Coarse, caller-facing classification of a [`RegistrarClientError`],
+/// collapsing its many specific variants down to the distinctions that
+/// matter when deciding what to do next: retry the same request, fix the
+/// request, reconfigure credentials, or give up. Returned from
+/// [`RegistrarClient::activate_agent`] and
+/// [`RegistrarClient::negotiate_capabilities`] instead of the finer-grained
+/// `RegistrarClientError`, which `Display`s into [`Self::BadRequest`] for
+/// variants that don't map onto a more specific case.
+#[derive(Error, Debug)]
+pub enum RegistrarError {
+    /// No API version or capability survived negotiation between the
+    /// agent's offered set and the registrar's advertised set.
+    #[error("no API version or capability in common: agent offered {agent:?}, registrar supports {registrar:?}")]
+    VersionMismatch {
+        agent: Vec<String>,
+        registrar: Vec<String>,
+    },
+
+    /// The registrar rejected the request for lacking valid credentials
+    /// (a 401, or a bearer-token challenge that couldn't be satisfied).
+    #[error("registrar rejected the request as unauthorized")]
+    Unauthorized,
+
+    /// The registrar rejected the request as malformed, independent of
+    /// credentials (a 4xx other than 401, or a response that didn't parse).
+    #[error("registrar rejected the request: {0}")]
+    BadRequest(String),
+
+    /// The request never reached the registrar, or its response couldn't
+    /// be read at the transport level (DNS, TCP, TLS, timeout).
+    #[error("transport error reaching registrar: {0}")]
+    Transport(String),
+
+    /// The registrar accepted and parsed the request but failed to
+    /// service it.
+    #[error("registrar returned {status}: {body}")]
+    Server { status: u16, body: String },
+}
+
+impl RegistrarError {
+    /// Whether retrying the same request against the same registrar has a
+    /// reasonable chance of succeeding: transport-level failures, 429, and
+    /// any 5xx (a wider set than the 429/502/503/504 that
+    /// [`RegisterRetryPolicy`] retries). Version mismatches, unauthorized,
+    /// and other 4xx responses are definitive and aren't retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RegistrarError::Transport(_) => true,
+            RegistrarError::Server { status, .. } => {
+                *status == 429 || *status >= 500
+            }
+            RegistrarError::VersionMismatch { .. }
+            | RegistrarError::Unauthorized
+            | RegistrarError::BadRequest(_) => false,
+        }
+    }
 }
 
-#[derive(Clone, Default, Debug)]
+/// Classifies an HTTP status from a registrar response into the
+/// corresponding [`RegistrarError`] variant.
+fn classify_registrar_status(addr: &str, code: u16) -> RegistrarError {
+    match code {
+        401 => RegistrarError::Unauthorized,
+        400..=499 => RegistrarError::BadRequest(format!("{addr} returned {code}")),
+        _ => RegistrarError::Server {
+            status: code,
+            body: format!("request to {addr} failed"),
+        },
+    }
+}
+
+impl From<RegistrarClientError> for RegistrarError {
+    fn from(e: RegistrarClientError) -> Self {
+        match e {
+            RegistrarClientError::IncompatibleAPI {
+                agent_enabled,
+                registrar_supported,
+            } => RegistrarError::VersionMismatch {
+                agent: split_version_list(&agent_enabled),
+                registrar: split_version_list(&registrar_supported),
+            },
+            RegistrarClientError::AllAPIVersionsRejected(tried) => {
+                RegistrarError::VersionMismatch {
+                    agent: split_version_list(&tried),
+                    registrar: Vec::new(),
+                }
+            }
+            RegistrarClientError::Activation { addr, code }
+            | RegistrarClientError::Registration { addr, code } => {
+                classify_registrar_status(&addr, code)
+            }
+            RegistrarClientError::MissingBearerChallenge(_)
+            | RegistrarClientError::BearerTokenFetch(_, _) => {
+                RegistrarError::Unauthorized
+            }
+            // `reqwest_middleware::Error` doesn't expose a documented way
+            // to recover its inner `reqwest::Error` across versions, so it
+            // carries its message as a string rather than risking an
+            // unverifiable API call.
+            RegistrarClientError::Reqwest(e) => {
+                RegistrarError::Transport(e.to_string())
+            }
+            RegistrarClientError::Middleware(e) => {
+                RegistrarError::Transport(e.to_string())
+            }
+            other => RegistrarError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+/// Splits a comma-space-joined version/capability list (as produced by
+/// `RegistrarClientError::IncompatibleAPI`/`AllAPIVersionsRejected`) back
+/// into its entries.
+fn split_version_list(s: &str) -> Vec<String> {
+    if s.is_empty() {
+        Vec::new()
+    } else {
+        s.split(", ").map(String::from).collect()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct RegistrarClient {
     api_version: String,
     supported_api_versions: Option<Vec<String>>,
     registrar_ip: String,
     registrar_port: u32,
+    // Unified-Identity: ordered registrar endpoints to fail over across in
+    // `activate_agent`; see `RegistrarClientBuilder::registrar_endpoints`.
+    registrar_endpoints: Vec<(String, u32)>,
+    use_https: bool,
+    http_client: reqwest::Client,
     resilient_client: Option<ResilientClient>,
+    // Unified-Identity: HTTP Basic credentials used to obtain a bearer
+    // token from a `WWW-Authenticate: Bearer` challenge, and the token
+    // cached from the last such exchange, reused until another 401 forces
+    // a re-fetch.
+    bearer_username: Option<String>,
+    bearer_password: Option<String>,
+    bearer_token: Option<String>,
+    // Unified-Identity: broad capability flags (e.g. `push-attestation`,
+    // `iak-idevid`, `dual-ek`) advertised by the registrar in `/version`,
+    // used to gate optional fields in outgoing requests so older
+    // registrars that don't understand them aren't sent unknown data.
+    capabilities: Vec<String>,
+    // Unified-Identity: best-common capability set from the last
+    // `negotiate_capabilities` call; see `Capabilities`.
+    negotiated_capabilities: Option<Capabilities>,
+    // Unified-Identity: TTL and last-fetch timestamp backing
+    // `ensure_compatible_version`'s `/version` cache.
+    version_cache_ttl: Option<Duration>,
+    version_fetched_at: Option<std::time::Instant>,
+    // Unified-Identity: retry policy for transient register/activate
+    // failures; see `RegisterRetryPolicy`.
+    register_retry_policy: Option<RegisterRetryPolicy>,
+    // Unified-Identity: optional TPM/HSM-backed signer consulted for
+    // IAK/IDevID signatures at registration time; see `Signer`.
+    signer: Option<Arc<dyn Signer>>,
+}
+
+impl Default for RegistrarClient {
+    fn default() -> Self {
+        Self {
+            api_version: String::default(),
+            supported_api_versions: None,
+            registrar_ip: String::default(),
+            registrar_port: 0,
+            registrar_endpoints: Vec::new(),
+            use_https: false,
+            http_client: reqwest::Client::new(),
+            resilient_client: None,
+            bearer_username: None,
+            bearer_password: None,
+            bearer_token: None,
+            capabilities: Vec::new(),
+            negotiated_capabilities: None,
+            version_cache_ttl: None,
+            version_fetched_at: None,
+            register_retry_policy: None,
+            signer: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -332,32 +1533,410 @@ struct Register<'a> {
 }
 
 impl RegistrarClient {
-    async fn try_register_agent(
+    /// `http` or `https`, depending on whether HTTPS/mTLS transport was
+    /// enabled when this client was built.
+    fn scheme(&self) -> &'static str {
+        if self.use_https {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// Send `data` to `addr` using `method`, via the resilient client if
+    /// configured, attaching a cached bearer token if present.
+    async fn send_json_request<T: Serialize + ?Sized>(
         &self,
+        method: reqwest::Method,
+        addr: &str,
+        data: &T,
+    ) -> Result<reqwest::Response, RegistrarClientError> {
+        match self.resilient_client {
+            Some(ref client) => {
+                let mut req = client
+                    .get_json_request_from_struct(method, addr, data, None)
+                    .map_err(RegistrarClientError::Serde)?;
+                if let Some(token) = &self.bearer_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send().await.map_err(RegistrarClientError::Middleware)
+            }
+            None => {
+                let mut req = self.http_client.request(method, addr).json(data);
+                if let Some(token) = &self.bearer_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send().await.map_err(RegistrarClientError::Reqwest)
+            }
+        }
+    }
+
+    /// Send `method` to `addr` with `data` via [`Self::send_json_request`],
+    /// transparently handling a `WWW-Authenticate: Bearer` challenge, and
+    /// retrying per `register_retry_policy` (if set) on connection errors
+    /// or a `429`/`502`/`503`/`504` response (see [`is_retryable_status`]).
+    /// A `Retry-After` header (delta-seconds) on a retryable response is
+    /// honored in place of the computed backoff. Any other status,
+    /// including non-retryable 4xx/5xx, is returned as-is without
+    /// consuming a retry attempt — the caller is responsible for turning
+    /// a non-success status into an error.
+    async fn send_with_retry<T: Serialize + ?Sized>(
+        &mut self,
+        method: reqwest::Method,
+        addr: &str,
+        data: &T,
+    ) -> Result<reqwest::Response, RegistrarClientError> {
+        let mut attempt = 0u32;
+        loop {
+            match self.send_json_request(method.clone(), addr, data).await {
+                Ok(mut resp) => {
+                    if self
+                        .refresh_bearer_token_if_challenged(addr, &resp)
+                        .await?
+                    {
+                        resp = self
+                            .send_json_request(method.clone(), addr, data)
+                            .await?;
+                    }
+
+                    let status = resp.status();
+                    if status.is_success() || !is_retryable_status(status) {
+                        return Ok(resp);
+                    }
+
+                    let Some(policy) = self.register_retry_policy else {
+                        return Ok(resp);
+                    };
+                    if attempt + 1 >= policy.max_attempts {
+                        return Ok(resp);
+                    }
+
+                    let delay = resp
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse::<u64>().ok())
+                        .map(Duration::from_secs)
+                        .unwrap_or_else(|| {
+                            jittered_delay(
+                                policy.base_delay,
+                                attempt,
+                                policy.max_delay,
+                            )
+                        });
+
+                    warn!("Registrar request to {addr} returned {status}, retrying in {delay:?} (attempt {}/{})", attempt + 1, policy.max_attempts);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    let Some(policy) = self.register_retry_policy else {
+                        return Err(e);
+                    };
+                    if attempt + 1 >= policy.max_attempts {
+                        return Err(e);
+                    }
+
+                    let delay = jittered_delay(
+                        policy.base_delay,
+                        attempt,
+                        policy.max_delay,
+                    );
+                    warn!("Registrar request to {addr} failed: {e}, retrying in {delay:?} (attempt {}/{})", attempt + 1, policy.max_attempts);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// If `resp` is a `401` carrying a `WWW-Authenticate: Bearer`
+    /// challenge, fetch and cache a token for it. Returns `true` if a new
+    /// token was cached, meaning the caller should retry its request.
+    async fn refresh_bearer_token_if_challenged(
+        &mut self,
+        addr: &str,
+        resp: &reqwest::Response,
+    ) -> Result<bool, RegistrarClientError> {
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            return Ok(false);
+        }
+
+        let challenge = resp
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(BearerChallenge::parse)
+            .ok_or_else(|| {
+                RegistrarClientError::MissingBearerChallenge(addr.to_string())
+            })?;
+
+        let token = fetch_bearer_token(
+            &self.http_client,
+            &challenge,
+            self.bearer_username.as_deref(),
+            self.bearer_password.as_deref(),
+        )
+        .await
+        .map_err(|e| {
+            RegistrarClientError::BearerTokenFetch(challenge.realm.clone(), e)
+        })?;
+        self.bearer_token = Some(token);
+
+        Ok(true)
+    }
+
+    /// Send a bodyless GET to `addr`, via the resilient client if
+    /// configured, attaching a cached bearer token if present.
+    async fn send_get_request(
+        &self,
+        addr: &str,
+    ) -> Result<reqwest::Response, RegistrarClientError> {
+        match self.resilient_client {
+            Some(ref client) => {
+                let mut req = client.get_request(reqwest::Method::GET, addr);
+                if let Some(token) = &self.bearer_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send().await.map_err(RegistrarClientError::Middleware)
+            }
+            None => {
+                let mut req = self.http_client.get(addr);
+                if let Some(token) = &self.bearer_token {
+                    req = req.bearer_auth(token);
+                }
+                req.send().await.map_err(RegistrarClientError::Reqwest)
+            }
+        }
+    }
+
+    /// Re-query the registrar's `/version` endpoint, updating `api_version`
+    /// and `supported_api_versions` in place and discarding whichever API
+    /// version was previously negotiated. Useful for long-lived agents to
+    /// re-probe a registrar that was upgraded, or that fell back to
+    /// [`UNKNOWN_API_VERSION`] at `build()` time because it was briefly
+    /// unreachable. Safe to call between `register_agent` and
+    /// `activate_agent`; leaves `registrar_ip`/`registrar_port` untouched.
+    pub async fn refresh(&mut self) -> Result<(), RegistrarClientError> {
+        let scheme = self.scheme();
+        let registrar_ip = self.registrar_ip.clone();
+        let registrar_port = self.registrar_port;
+        let addr = format!("{scheme}://{registrar_ip}:{registrar_port}/version");
+
+        info!("Refreshing registrar API version from {addr}");
+
+        let mut resp = self.send_get_request(&addr).await?;
+
+        if self.refresh_bearer_token_if_challenged(&addr, &resp).await? {
+            resp = self.send_get_request(&addr).await?;
+        }
+
+        if !resp.status().is_success() {
+            return Err(RegistrarClientError::NoVersionEndpoint);
+        }
+
+        let body: serde_json::Value = resp.json().await?;
+        self.capabilities = extract_capabilities(&body);
+        let resp: Response<KeylimeRegistrarVersion> = serde_json::from_value(body)?;
+
+        self.api_version = resp.results.current_version;
+        self.supported_api_versions = Some(resp.results.supported_versions);
+
+        Ok(())
+    }
+
+    /// Whether the registrar advertised `name` in its last `/version`
+    /// response's `capabilities` list (via `build()` or [`Self::refresh`]).
+    /// A registrar that predates capability advertisement, or that simply
+    /// doesn't support `name`, reports `false`.
+    pub fn supports_capability(&self, name: &str) -> bool {
+        self.capabilities.iter().any(|c| c == name)
+    }
+
+    /// Cached pre-flight version negotiation, analogous to dkregistry's
+    /// `ensure_v2_registry`: re-fetches `/version` only if the last fetch
+    /// is missing or older than `version_cache_ttl` (set via
+    /// [`RegistrarClientBuilder::version_cache_ttl`]; a client built
+    /// without one re-fetches on every call), then picks the numerically
+    /// highest version in `enabled_api_versions` that the registrar also
+    /// supports exactly (see [`highest_common_version`]). Returns the
+    /// negotiated version, also retrievable afterwards via
+    /// [`Self::negotiated_version`]. A registrar that doesn't support
+    /// `/version` negotiates [`UNKNOWN_API_VERSION`] rather than erroring,
+    /// matching `build()`'s fallback behavior; one that's reachable but
+    /// shares no version with `enabled_api_versions` returns the same
+    /// [`RegistrarClientError::IncompatibleAPI`] as the register/activate
+    /// trial loop.
+    pub async fn ensure_compatible_version(
+        &mut self,
+        enabled_api_versions: &[&str],
+    ) -> Result<String, RegistrarClientError> {
+        let stale = match (self.version_fetched_at, self.version_cache_ttl) {
+            (Some(fetched_at), Some(ttl)) => fetched_at.elapsed() >= ttl,
+            _ => true,
+        };
+
+        if stale {
+            match self.refresh().await {
+                Ok(()) => {
+                    self.version_fetched_at = Some(std::time::Instant::now());
+                }
+                Err(RegistrarClientError::NoVersionEndpoint) => {
+                    self.api_version = UNKNOWN_API_VERSION.to_string();
+                    self.supported_api_versions = None;
+                    self.version_fetched_at = Some(std::time::Instant::now());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let Some(supported) = self.supported_api_versions.clone() else {
+            if self.api_version == UNKNOWN_API_VERSION {
+                return Ok(UNKNOWN_API_VERSION.to_string());
+            }
+            return Err(RegistrarClientError::Inconsistent(
+                self.api_version.clone(),
+            ));
+        };
+
+        let negotiated = highest_common_version(enabled_api_versions, &supported)
+            .ok_or_else(|| {
+                self.incompatible(
+                    enabled_api_versions.join(", "),
+                    supported.join(", "),
+                )
+            })?;
+
+        self.api_version = negotiated.clone();
+        Ok(negotiated)
+    }
+
+    /// The API version most recently negotiated by `build()`, `refresh()`,
+    /// or `ensure_compatible_version()`.
+    pub fn negotiated_version(&self) -> &str {
+        &self.api_version
+    }
+
+    /// The registrar-supported API versions from the last successful
+    /// `/version` fetch, if any.
+    pub fn registrar_supported_versions(&self) -> Option<&[String]> {
+        self.supported_api_versions.as_deref()
+    }
+
+    /// Generalizes [`Self::ensure_compatible_version`]'s single-version
+    /// check into a full capability handshake: builds this client's
+    /// [`Capabilities`] from `agent_capabilities` (typically
+    /// `enabled_api_versions` plus any named features the agent
+    /// implements), re-fetches `/version` under the same staleness rule as
+    /// `ensure_compatible_version`, and intersects the agent's set with the
+    /// registrar's advertised API versions and `capabilities` list. The
+    /// result is cached on the client (retrievable via
+    /// [`Self::negotiated_capabilities`]) and returned, or
+    /// [`RegistrarError::VersionMismatch`] if the two sets share nothing at
+    /// all. Returns [`RegistrarError`] rather than [`RegistrarClientError`]
+    /// so callers can branch on whether the failure is worth retrying; see
+    /// [`Self::activate_agent`].
+    pub async fn negotiate_capabilities(
+        &mut self,
+        agent_capabilities: &[String],
+    ) -> Result<Capabilities, RegistrarError> {
+        let stale = match (self.version_fetched_at, self.version_cache_ttl) {
+            (Some(fetched_at), Some(ttl)) => fetched_at.elapsed() >= ttl,
+            _ => true,
+        };
+
+        if stale {
+            match self.refresh().await {
+                Ok(()) => {
+                    self.version_fetched_at = Some(std::time::Instant::now());
+                }
+                Err(RegistrarClientError::NoVersionEndpoint) => {
+                    self.api_version = UNKNOWN_API_VERSION.to_string();
+                    self.supported_api_versions = None;
+                    self.version_fetched_at = Some(std::time::Instant::now());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut registrar_names: Vec<String> =
+            self.supported_api_versions.clone().unwrap_or_default();
+        registrar_names.extend(self.capabilities.iter().cloned());
+
+        let agent = Capabilities::from_names(agent_capabilities);
+        let registrar = Capabilities::from_names(&registrar_names);
+
+        let common = agent.best_common(&registrar).ok_or_else(|| {
+            warn!(
+                "Registrar at '{}' shares no capability with the agent: agent = '{agent_capabilities:?}', registrar = '{registrar_names:?}'",
+                self.registrar_ip
+            );
+            RegistrarError::VersionMismatch {
+                agent: agent_capabilities.to_vec(),
+                registrar: registrar_names.clone(),
+            }
+        })?;
+
+        self.negotiated_capabilities = Some(common.clone());
+        Ok(common)
+    }
+
+    /// The capability set from the last successful
+    /// [`Self::negotiate_capabilities`] call, if any.
+    pub fn negotiated_capabilities(&self) -> Option<&Capabilities> {
+        self.negotiated_capabilities.as_ref()
+    }
+
+    async fn try_register_agent(
+        &mut self,
         ai: &AgentIdentity<'_>,
         api_version: &str,
     ) -> Result<Vec<u8>, RegistrarClientError> {
+        let supports_iak_idevid = self.supports_capability("iak-idevid");
+        let (iak_attest, iak_sign) = if !supports_iak_idevid {
+            (None, None)
+        } else if let Some(signer) = &self.signer {
+            debug!(
+                "Signing IAK attestation via configured Signer (key_id = {:?})",
+                signer.key_id(KeyPurpose::IakAttest)
+            );
+            (
+                signer
+                    .sign(KeyPurpose::IakAttest, ai.ak_pub)
+                    .ok()
+                    .or_else(|| ai.iak_attest.clone()),
+                signer
+                    .sign(KeyPurpose::IakSign, ai.ak_pub)
+                    .ok()
+                    .or_else(|| ai.iak_sign.clone()),
+            )
+        } else {
+            (ai.iak_attest.clone(), ai.iak_sign.clone())
+        };
         let data = Register {
             aik_tpm: ai.ak_pub,
             ek_tpm: ai.ek_pub,
             ekcert: ai.ek_cert.clone(),
-            iak_attest: ai.iak_attest.clone(),
-            iak_cert: ai.iak_cert.clone(),
-            iak_sign: ai.iak_sign.clone(),
-            iak_tpm: ai.iak_pub,
-            idevid_cert: ai.idevid_cert.clone(),
-            idevid_tpm: ai.idevid_pub,
+            iak_attest,
+            iak_cert: supports_iak_idevid.then(|| ai.iak_cert.clone()).flatten(),
+            iak_sign,
+            iak_tpm: supports_iak_idevid.then_some(ai.iak_pub).flatten(),
+            idevid_cert: supports_iak_idevid
+                .then(|| ai.idevid_cert.clone())
+                .flatten(),
+            idevid_tpm: supports_iak_idevid.then_some(ai.idevid_pub).flatten(),
             ip: Some(ai.ip.clone()),
             mtls_cert: ai.mtls_cert.clone(),
             port: Some(ai.port),
         };
 
+        let scheme = self.scheme();
         let registrar_ip = &self.registrar_ip;
         let registrar_port = &self.registrar_port;
         let uuid = &ai.uuid;
 
         let addr = format!(
-            "http://{registrar_ip}:{registrar_port}/v{api_version}/agents/{uuid}",
+            "{scheme}://{registrar_ip}:{registrar_port}/v{api_version}/agents/{uuid}",
         );
 
         eprintln!("[DEBUG] try_register_agent: Preparing registration request to {}", &addr);
@@ -367,26 +1946,9 @@ impl RegistrarClient {
         );
         eprintln!("[DEBUG] try_register_agent: Registration data prepared, sending POST request...");
 
-        let resp = match self.resilient_client {
-            Some(ref client) => client
-                .get_json_request_from_struct(
-                    reqwest::Method::POST,
-                    &addr,
-                    &data,
-                    None,
-                )
-                .map_err(RegistrarClientError::Serde)?
-                .send()
-                .await
-                .map_err(RegistrarClientError::Middleware)?,
-            None => {
-                reqwest::Client::new()
-                    .post(&addr)
-                    .json(&data)
-                    .send()
-                    .await?
-            }
-        };
+        let resp = self
+            .send_with_retry(reqwest::Method::POST, &addr, &data)
+            .await?;
 
         if !resp.status().is_success() {
             return Err(RegistrarClientError::Registration {
@@ -434,7 +1996,8 @@ impl RegistrarClient {
         // The current Registrar API version is enabled and should work
         if ai.enabled_api_versions.contains(&self.api_version.as_ref()) {
             debug!("Current API version '{}' is in agent's enabled list, attempting registration", self.api_version);
-            return self.try_register_agent(ai, &self.api_version).await;
+            let api_version = self.api_version.clone();
+            return self.try_register_agent(ai, &api_version).await;
         } else {
             debug!("Current API version '{}' is NOT in agent's enabled list {:?}, will try other versions", self.api_version, ai.enabled_api_versions);
         }
@@ -459,67 +2022,54 @@ impl RegistrarClient {
             ))
         } else {
             // The current Registrar API version is not enabled.
-            // Find the latest enabled version that is supported
+            // Find the newest enabled version that is semver-compatible
+            // with a version the registrar supports (same major, agent
+            // minor <= registrar minor), trying candidates newest-first so
+            // the first one that actually works is also the newest.
             info!("Current API version '{}' is not in enabled list, checking supported versions. supported_api_versions = {:?}", self.api_version, self.supported_api_versions);
-            if let Some(ref supported) = self.supported_api_versions {
+            if let Some(supported) = self.supported_api_versions.clone() {
                 info!(
                     "Checking API version compatibility: agent enabled = {:?}, registrar supported = {:?}",
                     ai.enabled_api_versions, supported
                 );
-                for api_version in ai.enabled_api_versions.iter().rev() {
-                    let api_version_str = api_version.trim();
-                    info!(
-                        "Checking if registrar supports agent version '{}' (trimmed from '{}')",
-                        api_version_str, api_version
-                    );
-                    // Trim whitespace from both sides for comparison
-                    eprintln!("[DEBUG] Comparing agent version '{}' (trimmed: '{}') against registrar supported versions: {:?}", api_version, api_version_str, supported);
-                    let version_matches = supported.iter().any(|s| {
-                        let s_trimmed = s.trim();
-                        let matches = s_trimmed == api_version_str;
-                        eprintln!("[DEBUG]   Comparing '{}' (trimmed: '{}') == '{}' -> {}", s, s_trimmed, api_version_str, matches);
-                        matches
-                    });
-                    eprintln!("[DEBUG] Version '{}' matches: {}", api_version_str, version_matches);
-                    if version_matches {
-                        eprintln!("[DEBUG] Found compatible API version: {}, attempting registration...", api_version_str);
-                        info!("Found compatible API version: {}", api_version_str);
-                        // Found a compatible API version, it should work
-                        eprintln!("[DEBUG] Calling try_register_agent with version: {}", api_version_str);
-                        let r =
-                            self.try_register_agent(ai, api_version).await;
-                        eprintln!("[DEBUG] try_register_agent result: {:?}", r);
-
-                        // If successful, cache the API version for future requests
-                        if r.is_ok() {
-                            self.api_version = api_version_str.to_string();
-                            return r;
+                let compatible: Vec<&str> = ai
+                    .enabled_api_versions
+                    .iter()
+                    .rev()
+                    .copied()
+                    .filter(|&api_version| {
+                        is_version_compatible(api_version, &supported)
+                    })
+                    .collect();
+
+                for api_version in compatible {
+                    info!("Found compatible API version: {}", api_version);
+                    let r = self.try_register_agent(ai, api_version).await;
+
+                    // If successful, cache the API version for future requests
+                    if r.is_ok() {
+                        self.api_version = api_version.trim().to_string();
+                        return r;
+                    } else {
+                        // Check if the error is specifically an API incompatibility error
+                        // If so, continue to next version. Otherwise, return the actual error.
+                        if let Err(RegistrarClientError::IncompatibleAPI { .. }) = r {
+                            warn!(
+                                "Registration attempt with API version {} failed due to API incompatibility: {:?}",
+                                api_version, r
+                            );
+                            // Continue to next version
                         } else {
-                            // Check if the error is specifically an API incompatibility error
-                            // If so, continue to next version. Otherwise, return the actual error.
-                            if let Err(RegistrarClientError::IncompatibleAPI { .. }) = r {
-                                warn!(
-                                    "Registration attempt with API version {} failed due to API incompatibility: {:?}",
-                                    api_version_str, r
-                                );
-                                // Continue to next version
-                            } else {
-                                // This is a different error (TPM, network, etc.) - return it immediately
-                                warn!(
-                                    "Registration attempt with API version {} failed with non-API error: {:?}",
-                                    api_version_str, r
-                                );
-                                return r;
-                            }
+                            // This is a different error (TPM, network, etc.) - return it immediately
+                            warn!(
+                                "Registration attempt with API version {} failed with non-API error: {:?}",
+                                api_version, r
+                            );
+                            return r;
                         }
-                    } else {
-                        info!(
-                            "API version '{}' not found in registrar supported list",
-                            api_version_str
-                        );
                     }
                 }
-                // None of the enabled APIs is supported
+                // None of the compatible APIs actually worked
                 warn!(
                     "No compatible API version found. Agent enabled: {:?}, Registrar supported: {:?}",
                     ai.enabled_api_versions, supported
@@ -537,19 +2087,20 @@ impl RegistrarClient {
     }
 
     async fn try_activate_agent(
-        &self,
+        &mut self,
         auth_tag: &str,
         ai: &AgentIdentity<'_>,
         api_version: &str,
     ) -> Result<(), RegistrarClientError> {
         let data = Activate { auth_tag };
 
+        let scheme = self.scheme();
         let registrar_ip = &self.registrar_ip;
         let registrar_port = &self.registrar_port;
         let uuid = &ai.uuid;
 
         let addr = format!(
-            "http://{registrar_ip}:{registrar_port}/v{api_version}/agents/{uuid}",
+            "{scheme}://{registrar_ip}:{registrar_port}/v{api_version}/agents/{uuid}",
         );
 
         info!(
@@ -557,8 +2108,9 @@ impl RegistrarClient {
             &addr, &ai.uuid
         );
 
-        let resp =
-            reqwest::Client::new().put(&addr).json(&data).send().await?;
+        let resp = self
+            .send_with_retry(reqwest::Method::PUT, &addr, &data)
+            .await?;
 
         if !resp.status().is_success() {
             return Err(RegistrarClientError::Activation {
@@ -589,15 +2141,65 @@ impl RegistrarClient {
     ///
     /// * ai (&AgentIdentity<'_>): The identity data of the Agent to be activated
     /// * auth_tag (&str): The authentication tag
+    ///
+    /// Tries each endpoint in `registrar_endpoints` in order (see
+    /// [`RegistrarClientBuilder::registrar_endpoints`]), failing over to
+    /// the next on a connection error, timeout, or 5xx response (see
+    /// [`is_failover_error`]) while preserving whichever API version was
+    /// already negotiated. A client built from a single
+    /// `registrar_address`/`registrar_port` has a one-element endpoint
+    /// list and behaves exactly as before.
+    ///
+    /// Returns [`RegistrarError`], a coarser classification of the
+    /// underlying failure than [`RegistrarClientError`] (version mismatch
+    /// vs. unauthorized vs. bad request vs. transport vs. server error),
+    /// so callers can decide whether to retry, reconfigure, or abort
+    /// without matching on every specific variant.
     pub async fn activate_agent(
         &mut self,
         ai: &AgentIdentity<'_>,
         auth_tag: &str,
+    ) -> Result<(), RegistrarError> {
+        let endpoints = self.registrar_endpoints.clone();
+        let mut last_err = None;
+
+        for (index, (ip, port)) in endpoints.iter().enumerate() {
+            self.registrar_ip = ip.clone();
+            self.registrar_port = *port;
+
+            match self.activate_agent_at_current_endpoint(ai, auth_tag).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if index + 1 < endpoints.len() && is_failover_error(&e) {
+                        warn!("Activation against registrar endpoint {ip}:{port} failed ({e}), failing over to the next endpoint");
+                        last_err = Some(e);
+                    } else {
+                        return Err(e.into());
+                    }
+                }
+            }
+        }
+
+        Err(last_err
+            .unwrap_or(RegistrarClientError::Inconsistent(
+                self.api_version.clone(),
+            ))
+            .into())
+    }
+
+    /// The version-negotiation activation logic against whichever
+    /// endpoint is currently set in `registrar_ip`/`registrar_port`; see
+    /// [`Self::activate_agent`] for the multi-endpoint wrapper.
+    async fn activate_agent_at_current_endpoint(
+        &mut self,
+        ai: &AgentIdentity<'_>,
+        auth_tag: &str,
     ) -> Result<(), RegistrarClientError> {
         // The current Registrar API version is enabled and should work
         if ai.enabled_api_versions.contains(&self.api_version.as_ref()) {
+            let api_version = self.api_version.clone();
             return self
-                .try_activate_agent(auth_tag, ai, &self.api_version)
+                .try_activate_agent(auth_tag, ai, &api_version)
                 .await;
         }
 
@@ -622,33 +2224,38 @@ impl RegistrarClient {
             ))
         } else {
             // The current Registrar API version is not enabled.
-            // Find the latest enabled version that is supported
-            if let Some(ref supported) = self.supported_api_versions {
+            // Find the newest enabled version that is semver-compatible
+            // with a version the registrar supports.
+            if let Some(supported) = self.supported_api_versions.clone() {
                 debug!(
                     "Checking API version compatibility for activation: agent enabled = {:?}, registrar supported = {:?}",
                     ai.enabled_api_versions, supported
                 );
-                for api_version in ai.enabled_api_versions.iter().rev() {
-                    let api_version_str = api_version.trim();
-                    // Trim whitespace from both sides for comparison
-                    let version_matches = supported.iter().any(|s| s.trim() == api_version_str);
-                    if version_matches {
-                        debug!("Found compatible API version for activation: {}", api_version_str);
-                        // Found a compatible API version, it should work
-                        let r = self
-                            .try_activate_agent(auth_tag, ai, api_version)
-                            .await;
-
-                        // If successful, cache the API version for future requests
-                        if r.is_ok() {
-                            self.api_version = api_version_str.to_string();
-                            return r;
-                        } else {
-                            warn!(
-                                "Activation attempt with API version {} failed: {:?}",
-                                api_version_str, r
-                            );
-                        }
+                let compatible: Vec<&str> = ai
+                    .enabled_api_versions
+                    .iter()
+                    .rev()
+                    .copied()
+                    .filter(|&api_version| {
+                        is_version_compatible(api_version, &supported)
+                    })
+                    .collect();
+
+                for api_version in compatible {
+                    debug!("Found compatible API version for activation: {}", api_version);
+                    let r = self
+                        .try_activate_agent(auth_tag, ai, api_version)
+                        .await;
+
+                    // If successful, cache the API version for future requests
+                    if r.is_ok() {
+                        self.api_version = api_version.trim().to_string();
+                        return r;
+                    } else {
+                        warn!(
+                            "Activation attempt with API version {} failed: {:?}",
+                            api_version, r
+                        );
                     }
                 }
                 // None of the enabled APIs is supported
@@ -672,11 +2279,58 @@ impl RegistrarClient {
 #[cfg(feature = "testing")]
 #[cfg(test)]
 mod tests {
+    // Unified-Identity: `test_activate_agent_against_real_registrar` below
+    // is gated behind a `test-registry` feature that isn't declared in
+    // this tree's Cargo manifest (this snapshot has none at all), so it
+    // can't actually be compiled in or run here; it's written the way it
+    // would be wired up once the manifest declares
+    // `test-registry = ["dep:testcontainers"]`.
     use super::*;
     use crate::{agent_identity::AgentIdentityBuilder, crypto};
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
+    /// Splits a `MockServer::uri()` (`http://127.0.0.1:PORT`) into its
+    /// `(ip, port)` parts, as every test previously did inline.
+    fn split_uri(uri: &str) -> (String, u32) {
+        let parts = uri.split("//").collect::<Vec<&str>>()[1]
+            .split(':')
+            .collect::<Vec<&str>>();
+        assert_eq!(parts.len(), 2);
+        (
+            parts[0].to_string(),
+            parts[1].parse().expect("mock server URI had a non-numeric port"),
+        )
+    }
+
+    /// Spawn-app style test harness wrapping a [`MockServer`] bound to an
+    /// OS-assigned port, with the `ip`/`port` pre-parsed and a matching
+    /// [`RegistrarClientBuilder`] ready to go, so individual tests no
+    /// longer need to re-derive them from `MockServer::uri()` by hand.
+    struct TestRegistrar {
+        server: MockServer,
+        ip: String,
+        port: u32,
+    }
+
+    impl TestRegistrar {
+        async fn start() -> Self {
+            let server = MockServer::start().await;
+            let (ip, port) = split_uri(&server.uri());
+            Self { server, ip, port }
+        }
+
+        async fn register(&self, mock: Mock) {
+            self.server.register(mock).await;
+        }
+
+        fn builder(&self) -> RegistrarClientBuilder {
+            RegistrarClientBuilder::new()
+                .registrar_address(self.ip.clone())
+                .registrar_port(self.port)
+        }
+    }
+
     #[actix_rt::test]
     async fn test_register_agent_ok() {
         // Setup mock server with the registration and api version responses
@@ -695,27 +2349,19 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("POST"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
         let mock_chain = String::from("");
@@ -746,9 +2392,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let response = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port)
+        let response = registrar.builder()
             .build()
             .await;
 
@@ -767,20 +2411,12 @@ mod tests {
             results: RegisterResponseResults { blob: None },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("POST"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
-
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
         let mock_chain = String::from("");
@@ -805,9 +2441,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -834,24 +2468,16 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("POST"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET")).respond_with(
             ResponseTemplate::new(200).set_body_json(api_response),
         );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
         let mock_chain = String::from("");
@@ -876,9 +2502,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -904,27 +2528,19 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("POST"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
         let priv_key = crypto::testing::rsa_generate(2048).unwrap(); //#[allow_ci]
@@ -947,9 +2563,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -960,15 +2574,7 @@ mod tests {
     #[actix_rt::test]
     async fn test_register_agent_err() {
         // Setup mock server without any response configured
-        let mock_server = MockServer::start().await;
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
-
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
+        let registrar = TestRegistrar::start().await;
 
         let mock_data = [0u8; 1];
         let priv_key = crypto::testing::rsa_generate(2048).unwrap(); //#[allow_ci]
@@ -990,9 +2596,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -1019,27 +2623,19 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("POST"))
             .and(path("/v3.4/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
         let mock_chain = String::from("");
@@ -1065,9 +2661,7 @@ mod tests {
             .expect("failed to build Agent Identity");
 
         // Try to register with an unsupported API version
-        let response = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port)
+        let response = registrar.builder()
             .build()
             .await;
 
@@ -1097,27 +2691,19 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("PUT"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
-
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
 
@@ -1132,9 +2718,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -1151,20 +2735,12 @@ mod tests {
             results: ActivateResponseResults {},
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("PUT"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
 
@@ -1180,9 +2756,7 @@ mod tests {
             .expect("failed to build Agent Identity");
 
         // Enable only a newer API version in the client
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder.build().await.unwrap(); //#[allow_ci]
 
@@ -1209,27 +2783,19 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
         let mock = Mock::given(method("PUT"))
             .and(path("/v1.2/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
 
@@ -1244,9 +2810,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut registrar_client = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port)
+        let mut registrar_client = registrar.builder()
             .build()
             .await
             .expect("failed top build Registrar Client");
@@ -1274,28 +2838,20 @@ mod tests {
             },
         };
 
-        let mock_server = MockServer::start().await;
+        let registrar = TestRegistrar::start().await;
 
         let mock = Mock::given(method("PUT"))
             .and(path("/v3.4/agents/uuid"))
             .respond_with(ResponseTemplate::new(200).set_body_json(response));
-        mock_server.register(mock).await;
+        registrar.register(mock).await;
 
         let mock = Mock::given(method("GET"))
             .and(path("/version"))
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(api_response),
             );
-        mock_server.register(mock).await;
-
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
+        registrar.register(mock).await;
 
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
 
         let mock_data = [0u8; 1];
 
@@ -1311,9 +2867,7 @@ mod tests {
             .expect("failed to build Agent Identity");
 
         // Try to activate with an unsupported API version
-        let response = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port)
+        let response = registrar.builder()
             .build()
             .await;
 
@@ -1329,15 +2883,7 @@ mod tests {
     #[actix_rt::test]
     async fn test_activate_agent_err() {
         // Setup mock server without any response configured
-        let mock_server = MockServer::start().await;
-        let uri = mock_server.uri();
-        let uri = uri.split("//").collect::<Vec<&str>>()[1]
-            .split(':')
-            .collect::<Vec<&str>>();
-        assert_eq!(uri.len(), 2);
-
-        let ip = uri[0];
-        let port = uri[1].parse().unwrap(); //#[allow_ci]
+        let registrar = TestRegistrar::start().await;
 
         let mock_data = [0u8; 1];
 
@@ -1352,9 +2898,7 @@ mod tests {
             .await
             .expect("failed to build Agent Identity");
 
-        let mut builder = RegistrarClientBuilder::new()
-            .registrar_address(ip.to_string())
-            .registrar_port(port);
+        let mut builder = registrar.builder();
 
         let mut registrar_client = builder
             .build()
@@ -1387,4 +2931,47 @@ mod tests {
             assert!(result.is_err());
         }
     }
+
+    /// Exercises the activate/negotiate flow against a real `keylime_registrar`
+    /// container instead of a `wiremock` mock, so genuine HTTP framing and
+    /// API-version negotiation are covered, not just handwritten mock
+    /// responses. See the `test-registry` feature note at the top of this
+    /// module.
+    #[cfg(feature = "test-registry")]
+    #[actix_rt::test]
+    async fn test_activate_agent_against_real_registrar() {
+        use testcontainers::clients::Cli;
+        use testcontainers::GenericImage;
+
+        let docker = Cli::default();
+        let image =
+            GenericImage::new("keylime/keylime_registrar", "latest")
+                .with_exposed_port(8891);
+        let container = docker.run(image);
+        let port = container.get_host_port_ipv4(8891);
+
+        let mut registrar_client = RegistrarClientBuilder::new()
+            .registrar_address("127.0.0.1".to_string())
+            .registrar_port(port as u32)
+            .build()
+            .await
+            .expect(
+                "failed to build Registrar Client against containerized registrar",
+            );
+
+        let mock_data = [0u8; 1];
+        let ai = AgentIdentityBuilder::new()
+            .ak_pub(&mock_data)
+            .ek_pub(&mock_data)
+            .enabled_api_versions(vec!["1.2"])
+            .ip("127.0.0.1".to_string())
+            .port(0)
+            .uuid("uuid")
+            .build()
+            .await
+            .expect("failed to build Agent Identity");
+
+        let response = registrar_client.activate_agent(&ai, "tag").await;
+        assert!(response.is_ok(), "error: {response:?}");
+    }
 }
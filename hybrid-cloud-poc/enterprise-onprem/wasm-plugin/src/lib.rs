@@ -1,6 +1,10 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 // Unified Identity extension OIDs (as ASN.1 OID bytes)
 // 1.3.6.1.4.1.99999.2 = 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x63, 0x02
@@ -8,6 +12,37 @@ use serde::{Deserialize, Serialize};
 const UNIFIED_IDENTITY_OID_STR: &str = "1.3.6.1.4.1.99999.2";
 const LEGACY_OID_STR: &str = "1.3.6.1.4.1.99999.1";
 
+// Mean Earth radius used by the haversine geofence distance calculation.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// How long a sensor's last verification result stays usable without a
+// fresh `/verify` call (positive results are trusted longer than negative
+// ones, so a sensor that briefly failed doesn't stay locked out once it's
+// fixed), and how many distinct sensor_ids the cache keeps before evicting
+// the least-recently-used one. Overridable via the plugin configuration;
+// see `PluginConfig`.
+const DEFAULT_POSITIVE_CACHE_TTL_SECS: u64 = 15;
+const DEFAULT_NEGATIVE_CACHE_TTL_SECS: u64 = 5;
+const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+// How long a sensor_id may sit marked as "verification in flight" before
+// a lost `/verify` response is assumed and the marker is dropped, letting
+// a subsequent request try again instead of being wedged forever.
+const DEFAULT_DISPATCH_TIMEOUT_SECS: u64 = 5;
+
+// Default verification-call target, used when the plugin configuration
+// doesn't override it.
+const DEFAULT_VERIFY_CLUSTER: &str = "mobile_location_service";
+const DEFAULT_VERIFY_AUTHORITY: &str = "localhost:5000";
+const DEFAULT_VERIFY_PATH: &str = "/verify";
+const DEFAULT_CALL_TIMEOUT_SECS: u64 = 5;
+
+// How old a geolocation claim's `iat` may be before it's treated as stale
+// rather than fresh, and how many recently-seen nonces are remembered to
+// catch a replayed claim before that window lapses.
+const DEFAULT_CLAIM_FRESHNESS_SECS: u64 = 300;
+const DEFAULT_NONCE_RING_CAPACITY: usize = 1024;
+
 #[derive(Serialize, Deserialize)]
 struct VerifyRequest {
     sensor_id: String,
@@ -18,20 +53,568 @@ struct VerifyResponse {
     verification_result: bool,
 }
 
+/// A single allowed geofence area, mirroring ETSI geo-area shapes. Loaded
+/// from the plugin configuration on `on_configure` (see `PluginConfig`).
+#[derive(Deserialize, Clone)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+enum GeofenceArea {
+    Circle {
+        center_lat: f64,
+        center_lon: f64,
+        radius_m: f64,
+    },
+    Polygon {
+        /// `(lat, lon)` vertices, in order, describing a closed ring.
+        vertices: Vec<(f64, f64)>,
+    },
+}
+
+/// Top-level shape of the plugin configuration passed to `on_configure`,
+/// making every operational knob (cache TTLs/capacity, the verification
+/// call target and timeout, and fail-open/closed policy) tunable without
+/// recompiling the binary for each deployment.
+#[derive(Deserialize)]
+#[serde(default)]
+struct PluginConfig {
+    allowed_areas: Vec<GeofenceArea>,
+    /// PEM bundle of trusted CA certificates, concatenated one after
+    /// another. When set, every incoming sensor certificate must chain to
+    /// one of these roots before its embedded sensor_id is trusted; when
+    /// absent, certificate trust verification is skipped (the prior
+    /// "parse and trust" behavior), so deployments that haven't configured
+    /// a bundle yet keep working unchanged.
+    trust_bundle_pem: Option<String>,
+    positive_cache_ttl_secs: u64,
+    negative_cache_ttl_secs: u64,
+    cache_capacity: usize,
+    /// Cluster name passed to `dispatch_http_call` for the verification
+    /// service, e.g. as configured in the Envoy listener's cluster table.
+    verify_cluster: String,
+    verify_authority: String,
+    verify_path: String,
+    call_timeout_secs: u64,
+    dispatch_timeout_secs: u64,
+    /// When `true`, a failure of the verification infrastructure itself
+    /// (the single-flight guard reporting another `/verify` call for the
+    /// same sensor still in flight, the `/verify` call failing to
+    /// dispatch, or its response failing to parse) lets the request
+    /// through instead of rejecting it, trading strict enforcement for
+    /// availability. A sensor that the verification service actually
+    /// examined and rejected is always denied regardless of this flag.
+    fail_open: bool,
+    /// Ed25519 public key (hex-encoded, 32 bytes) used to verify the
+    /// detached signature over a geolocation claim. When absent, claims
+    /// are accepted without a freshness/replay check (the prior behavior),
+    /// so deployments that haven't issued signed claims yet are unaffected.
+    claim_verifier_pubkey_hex: Option<String>,
+    /// How old a claim's `iat` may be (in either direction, to tolerate
+    /// clock skew) before it's rejected as stale.
+    claim_freshness_secs: u64,
+    /// How many recently-seen claim nonces are remembered to detect a
+    /// replayed claim.
+    nonce_ring_capacity: usize,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            allowed_areas: Vec::new(),
+            trust_bundle_pem: None,
+            positive_cache_ttl_secs: DEFAULT_POSITIVE_CACHE_TTL_SECS,
+            negative_cache_ttl_secs: DEFAULT_NEGATIVE_CACHE_TTL_SECS,
+            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            verify_cluster: DEFAULT_VERIFY_CLUSTER.to_string(),
+            verify_authority: DEFAULT_VERIFY_AUTHORITY.to_string(),
+            verify_path: DEFAULT_VERIFY_PATH.to_string(),
+            call_timeout_secs: DEFAULT_CALL_TIMEOUT_SECS,
+            dispatch_timeout_secs: DEFAULT_DISPATCH_TIMEOUT_SECS,
+            fail_open: false,
+            claim_verifier_pubkey_hex: None,
+            claim_freshness_secs: DEFAULT_CLAIM_FRESHNESS_SECS,
+            nonce_ring_capacity: DEFAULT_NONCE_RING_CAPACITY,
+        }
+    }
+}
+
+/// Decodes and verifies the detached signature over a geolocation claim,
+/// and screens it for staleness/replay. Absent a configured key, claims
+/// are passed through unverified, matching the prior "parse and trust"
+/// behavior for deployments that haven't rolled out signed claims yet.
+#[derive(Clone, Default)]
+struct ClaimVerifier {
+    verifying_key: Option<VerifyingKey>,
+    freshness: Duration,
+}
+
+impl ClaimVerifier {
+    fn from_config(config: &PluginConfig) -> Self {
+        let verifying_key = config.claim_verifier_pubkey_hex.as_deref().and_then(|hex_key| {
+            let bytes = decode_hex(hex_key)?;
+            let key_bytes: [u8; 32] = bytes.try_into().ok()?;
+            VerifyingKey::from_bytes(&key_bytes).ok()
+        });
+        Self {
+            verifying_key,
+            freshness: Duration::from_secs(config.claim_freshness_secs),
+        }
+    }
+
+    /// Whether a verifier key is configured, i.e. whether incoming claims
+    /// are required to carry (and pass) signature verification.
+    fn is_enabled(&self) -> bool {
+        self.verifying_key.is_some()
+    }
+
+    /// Verifies `claim`'s signature and freshness, then (via `cache`) that
+    /// its nonce hasn't been seen before. Returns `true` when no verifier
+    /// key is configured, so the claim is trusted on certificate possession
+    /// alone, as before. The signature is checked before the nonce is
+    /// recorded: recording it first would let an attacker who merely
+    /// observes a nonce (without forging a valid signature) burn it ahead
+    /// of the legitimate claim, causing the real, authentic claim to be
+    /// rejected as a replay once it arrives.
+    fn verify(&self, claim: &SignedClaim, now: SystemTime, cache: &CacheState) -> bool {
+        let Some(verifying_key) = &self.verifying_key else {
+            return true;
+        };
+        let Ok(now_secs) = now.duration_since(std::time::UNIX_EPOCH) else {
+            return false;
+        };
+        let age = (now_secs.as_secs() as i64 - claim.iat).abs();
+        if age > self.freshness.as_secs() as i64 {
+            return false;
+        }
+        let Some(sig_bytes) = base64::decode(&claim.sig).ok() else {
+            return false;
+        };
+        let Ok(sig_array): Result<[u8; 64], _> = sig_bytes.try_into() else {
+            return false;
+        };
+        let signature = Signature::from_bytes(&sig_array);
+        if verifying_key
+            .verify(claim.canonical_message().as_bytes(), &signature)
+            .is_err()
+        {
+            return false;
+        }
+        cache.check_and_record_nonce(&claim.nonce)
+    }
+}
+
+fn decode_hex(hex_str: &str) -> Option<Vec<u8>> {
+    if hex_str.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex_str.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex_str[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Splits a concatenated PEM bundle into its individual DER-encoded
+/// certificates, skipping any block that fails to decode rather than
+/// rejecting the whole bundle.
+fn split_pem_blocks(pem: &[u8]) -> Vec<Vec<u8>> {
+    let Ok(pem_str) = std::str::from_utf8(pem) else {
+        return Vec::new();
+    };
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut in_block = false;
+    for line in pem_str.lines() {
+        if line.starts_with("-----BEGIN") {
+            in_block = true;
+            current.clear();
+        } else if line.starts_with("-----END") {
+            in_block = false;
+            if let Ok(der) = base64::decode(current.join("")) {
+                blocks.push(der);
+            }
+        } else if in_block {
+            current.push(line);
+        }
+    }
+    blocks
+}
+
+/// Decodes a single certificate, PEM or DER, into its raw DER bytes.
+fn decode_cert_der(cert: &[u8]) -> Option<Vec<u8>> {
+    if cert.starts_with(b"-----BEGIN") {
+        split_pem_blocks(cert).into_iter().next()
+    } else {
+        Some(cert.to_vec())
+    }
+}
+
+/// True if `cert`'s `notBefore`/`notAfter` window contains `now`.
+fn is_within_validity(cert: &x509_parser::certificate::X509Certificate, now: SystemTime) -> bool {
+    let Ok(now_ts) = now.duration_since(std::time::UNIX_EPOCH) else {
+        return false;
+    };
+    let now_ts = now_ts.as_secs() as i64;
+    let validity = cert.validity();
+    now_ts >= validity.not_before.timestamp() && now_ts <= validity.not_after.timestamp()
+}
+
+/// Set of trusted root CA certificates, loaded once from the plugin
+/// configuration's `trust_bundle_pem` and checked against every incoming
+/// sensor certificate, like proxmox's use of `X509StoreContextRef` and
+/// tricot's warmed cert store: parse trust roots once, verify on every
+/// request. An empty bundle disables verification entirely.
+#[derive(Clone, Default)]
+struct TrustBundle {
+    roots: Vec<Vec<u8>>,
+}
+
+impl TrustBundle {
+    fn from_pem(pem: &str) -> Self {
+        Self {
+            roots: split_pem_blocks(pem.as_bytes()),
+        }
+    }
+
+    /// True if `cert_der` is within its validity window at `now` and was
+    /// signed directly by one of the configured roots, which must itself
+    /// be within its own validity window. Intermediates are not walked:
+    /// for a forwarded XFCC `Chain`, only its first certificate (taken by
+    /// `decode_cert_der`) is checked against the roots.
+    fn verify(&self, cert_der: &[u8], now: SystemTime) -> bool {
+        let Ok((_, cert)) = x509_parser::parse_x509_certificate(cert_der) else {
+            return false;
+        };
+        if !is_within_validity(&cert, now) {
+            return false;
+        }
+        self.roots.iter().any(|root_der| {
+            let Ok((_, root)) = x509_parser::parse_x509_certificate(root_der) else {
+                return false;
+            };
+            is_within_validity(&root, now) && cert.verify_signature(Some(root.public_key())).is_ok()
+        })
+    }
+}
+
+/// Bounded least-recently-used map from `sensor_id` to its last
+/// verification result and the time it was recorded, keyed per sensor so
+/// an environment with several alternating sensor_ids doesn't constantly
+/// evict a single shared slot (as mangadex-home's `cache.rs` keys its LRU
+/// by a structured `CacheKey` instead of one bare value). Hand-rolled
+/// rather than pulling in the `lru` crate, whose exact API for a wasm32
+/// target couldn't be verified without a working dependency/compiler in
+/// this environment.
+struct SensorCache {
+    capacity: usize,
+    entries: HashMap<String, (bool, SystemTime)>,
+    // Least-recently-used sensor_id first, most-recently-used last; a
+    // `get`/`insert` hit moves its id to the back, eviction pops the front.
+    order: VecDeque<String>,
+}
+
+impl SensorCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, sensor_id: &str) {
+        if let Some(pos) = self.order.iter().position(|id| id == sensor_id) {
+            if let Some(id) = self.order.remove(pos) {
+                self.order.push_back(id);
+            }
+        }
+    }
+
+    fn get(&mut self, sensor_id: &str) -> Option<(bool, SystemTime)> {
+        let entry = self.entries.get(sensor_id).copied();
+        if entry.is_some() {
+            self.touch(sensor_id);
+        }
+        entry
+    }
+
+    fn insert(&mut self, sensor_id: String, result: bool, recorded_at: SystemTime) {
+        if self.entries.contains_key(&sensor_id) {
+            self.touch(&sensor_id);
+        } else {
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.order.push_back(sensor_id.clone());
+        }
+        self.entries.insert(sensor_id, (result, recorded_at));
+    }
+}
+
+/// Shared, cloneable handle to the verification-result cache and the
+/// single-flight in-flight registry, held by the root context and cloned
+/// into every `SensorVerificationFilter` so a result looked up (or a
+/// `/verify` call already under way) in one HTTP context is visible to
+/// the next, keeping the existing `Arc<Mutex<…>>` sharing model.
+#[derive(Clone)]
+struct CacheState {
+    cache: Arc<Mutex<SensorCache>>,
+    positive_ttl: Duration,
+    negative_ttl: Duration,
+    // Unified-Identity: sensor_ids with a `/verify` call outstanding,
+    // guarding against the thundering herd of duplicate calls that N
+    // concurrent requests for the same unverified sensor would otherwise
+    // produce. `proxy-wasm` HTTP filter contexts are stream-scoped and
+    // the SDK has no safe, documented way for one context to resume
+    // another's already-paused stream, so a second concurrent request
+    // can't be parked and woken once the first's result lands; instead it
+    // is told to retry shortly (503 + `Retry-After`), which still
+    // collapses the herd down to exactly one in-flight call per sensor.
+    in_flight: Arc<Mutex<HashMap<String, Instant>>>,
+    dispatch_timeout: Duration,
+    // Recently-seen signed-claim nonces, so a captured claim replayed
+    // within its freshness window is still caught; see `ClaimVerifier`.
+    nonces: Arc<Mutex<NonceRing>>,
+}
+
+impl CacheState {
+    fn new(
+        capacity: usize,
+        positive_ttl: Duration,
+        negative_ttl: Duration,
+        dispatch_timeout: Duration,
+        nonce_ring_capacity: usize,
+    ) -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(SensorCache::new(capacity))),
+            positive_ttl,
+            negative_ttl,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            dispatch_timeout,
+            nonces: Arc::new(Mutex::new(NonceRing::new(nonce_ring_capacity))),
+        }
+    }
+
+    /// Records `nonce` as seen and returns `true` if it wasn't already on
+    /// file (a fresh claim), or `false` if it was (a replay). A poisoned
+    /// mutex is treated as "unseen", matching the rest of this cache's
+    /// permissive handling of lock failures.
+    fn check_and_record_nonce(&self, nonce: &str) -> bool {
+        self.nonces
+            .lock()
+            .map(|mut ring| ring.check_and_record(nonce))
+            .unwrap_or(true)
+    }
+
+    /// The cached verification result for `sensor_id`, if one is on file
+    /// and still within its TTL (`positive_ttl` for a prior pass,
+    /// `negative_ttl` for a prior fail).
+    fn get(&self, sensor_id: &str) -> Option<bool> {
+        let mut cache = self.cache.lock().ok()?;
+        let (result, recorded_at) = cache.get(sensor_id)?;
+        let ttl = if result {
+            self.positive_ttl
+        } else {
+            self.negative_ttl
+        };
+        if recorded_at.elapsed().ok()? < ttl {
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    /// Record `sensor_id`'s latest verification result, evicting the
+    /// least-recently-used entry first if the cache is at capacity.
+    fn insert(&self, sensor_id: String, result: bool) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(sensor_id, result, SystemTime::now());
+        }
+    }
+
+    /// Claims the right to dispatch a `/verify` call for `sensor_id`.
+    /// Returns `true` if no other call is currently in flight for it (or
+    /// the previous marker is older than `dispatch_timeout`, implying its
+    /// response was lost), in which case `sensor_id` is now marked
+    /// in-flight and the caller should proceed to dispatch. Returns
+    /// `false` if another call is genuinely still outstanding.
+    fn try_start_verification(&self, sensor_id: &str) -> bool {
+        let Ok(mut in_flight) = self.in_flight.lock() else {
+            return true;
+        };
+        if let Some(started_at) = in_flight.get(sensor_id) {
+            if started_at.elapsed() < self.dispatch_timeout {
+                return false;
+            }
+        }
+        in_flight.insert(sensor_id.to_string(), Instant::now());
+        true
+    }
+
+    /// Clears `sensor_id`'s in-flight marker, whether its call succeeded,
+    /// failed, or never actually got dispatched.
+    fn finish_verification(&self, sensor_id: &str) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(sensor_id);
+        }
+    }
+}
+
+impl Default for CacheState {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_CACHE_CAPACITY,
+            Duration::from_secs(DEFAULT_POSITIVE_CACHE_TTL_SECS),
+            Duration::from_secs(DEFAULT_NEGATIVE_CACHE_TTL_SECS),
+            Duration::from_secs(DEFAULT_DISPATCH_TIMEOUT_SECS),
+            DEFAULT_NONCE_RING_CAPACITY,
+        )
+    }
+}
+
+impl From<&PluginConfig> for CacheState {
+    fn from(config: &PluginConfig) -> Self {
+        Self::new(
+            config.cache_capacity,
+            Duration::from_secs(config.positive_cache_ttl_secs),
+            Duration::from_secs(config.negative_cache_ttl_secs),
+            Duration::from_secs(config.dispatch_timeout_secs),
+            config.nonce_ring_capacity,
+        )
+    }
+}
+
+/// Bounded set of recently-seen nonces, evicting the oldest once
+/// `capacity` is reached; mirrors `SensorCache`'s hand-rolled
+/// `HashMap`-plus-`VecDeque` shape for the same reason (no verifiable
+/// `lru`-crate API on this target).
+struct NonceRing {
+    capacity: usize,
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl NonceRing {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn check_and_record(&mut self, nonce: &str) -> bool {
+        if self.seen.contains(nonce) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(nonce.to_string());
+        self.seen.insert(nonce.to_string());
+        true
+    }
+}
+
 proxy_wasm::main! {{
     proxy_wasm::set_log_level(LogLevel::Info);
     proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
-        Box::new(SensorVerificationRoot)
+        Box::new(SensorVerificationRoot::default())
     });
 }}
 
-struct SensorVerificationRoot;
+struct SensorVerificationRoot {
+    // Allowed geofence areas; empty means geofencing is disabled and every
+    // attested location is accepted.
+    geofence: Vec<GeofenceArea>,
+    // Shared verification-result cache, cloned into every HTTP context.
+    cache: CacheState,
+    // Trusted root CAs a sensor certificate must chain to before its
+    // sensor_id is believed; empty disables chain verification.
+    trust_bundle: TrustBundle,
+    // Verification-call target and behavior, all plugin-configurable so
+    // the same binary is reusable across environments.
+    verify_cluster: String,
+    verify_authority: String,
+    verify_path: String,
+    call_timeout: Duration,
+    fail_open: bool,
+    // Verifies the signed geolocation claim's authenticity and freshness.
+    claim_verifier: ClaimVerifier,
+}
+
+impl Default for SensorVerificationRoot {
+    // Mirrors `PluginConfig::default()` so a deployment that never calls
+    // `on_configure` (or configures only `allowed_areas`/`trust_bundle_pem`)
+    // still dispatches to the same target the hardcoded behavior used.
+    fn default() -> Self {
+        let config = PluginConfig::default();
+        Self {
+            geofence: config.allowed_areas.clone(),
+            cache: CacheState::from(&config),
+            trust_bundle: config
+                .trust_bundle_pem
+                .as_deref()
+                .map(TrustBundle::from_pem)
+                .unwrap_or_default(),
+            verify_cluster: config.verify_cluster.clone(),
+            verify_authority: config.verify_authority.clone(),
+            verify_path: config.verify_path.clone(),
+            call_timeout: Duration::from_secs(config.call_timeout_secs),
+            fail_open: config.fail_open,
+            claim_verifier: ClaimVerifier::from_config(&config),
+        }
+    }
+}
 
 impl Context for SensorVerificationRoot {}
 
 impl RootContext for SensorVerificationRoot {
+    fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
+        let Some(config_bytes) = self.get_plugin_configuration() else {
+            return true;
+        };
+        match serde_json::from_slice::<PluginConfig>(&config_bytes) {
+            Ok(config) => {
+                self.geofence = config.allowed_areas;
+                self.trust_bundle = config
+                    .trust_bundle_pem
+                    .as_deref()
+                    .map(TrustBundle::from_pem)
+                    .unwrap_or_default();
+                self.call_timeout = Duration::from_secs(config.call_timeout_secs);
+                self.fail_open = config.fail_open;
+                self.verify_cluster = config.verify_cluster.clone();
+                self.verify_authority = config.verify_authority.clone();
+                self.verify_path = config.verify_path.clone();
+                self.claim_verifier = ClaimVerifier::from_config(&config);
+                self.cache = CacheState::from(&config);
+            }
+            Err(e) => {
+                proxy_wasm::hostcalls::log(
+                    LogLevel::Warn,
+                    &format!("invalid plugin configuration, ignoring: {e}"),
+                );
+            }
+        }
+        true
+    }
+
     fn create_http_context(&self, _context_id: u32) -> Option<Box<dyn HttpContext>> {
-        Some(Box::new(SensorVerificationFilter))
+        Some(Box::new(SensorVerificationFilter {
+            sensor_id: None,
+            attested_point: None,
+            geofence: self.geofence.clone(),
+            cache: self.cache.clone(),
+            trust_bundle: self.trust_bundle.clone(),
+            verify_cluster: self.verify_cluster.clone(),
+            verify_authority: self.verify_authority.clone(),
+            verify_path: self.verify_path.clone(),
+            call_timeout: self.call_timeout,
+            fail_open: self.fail_open,
+            claim_verifier: self.claim_verifier.clone(),
+        }))
     }
 
     fn get_type(&self) -> Option<ContextType> {
@@ -41,69 +624,180 @@ impl RootContext for SensorVerificationRoot {
 
 struct SensorVerificationFilter {
     sensor_id: Option<String>,
-}
-
-impl Default for SensorVerificationFilter {
-    fn default() -> Self {
-        Self { sensor_id: None }
-    }
+    // `(lat, lon)` parsed from the cert extension's `grc.geolocation.value`,
+    // if the attestation carried one.
+    attested_point: Option<(f64, f64)>,
+    geofence: Vec<GeofenceArea>,
+    cache: CacheState,
+    trust_bundle: TrustBundle,
+    verify_cluster: String,
+    verify_authority: String,
+    verify_path: String,
+    call_timeout: Duration,
+    fail_open: bool,
+    claim_verifier: ClaimVerifier,
 }
 
 impl Context for SensorVerificationFilter {}
 
 impl HttpContext for SensorVerificationFilter {
     fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
-        // Get client certificate from TLS connection (PEM format)
-        let cert_pem = match self.get_property(&["connection", "tls", "peer_certificate"]) {
-            Some(cert) => cert,
+        // Prefer a forwarded chain from an upstream edge proxy (Envoy's
+        // `x-forwarded-client-cert`) when present, falling back to this
+        // hop's own direct mTLS peer certificate otherwise.
+        let cert_pem = if let Some(xfcc) = self.get_http_request_header("x-forwarded-client-cert")
+        {
+            match parse_xfcc_header(&xfcc) {
+                Some(pem) => pem,
+                None => {
+                    self.send_http_response(
+                        403,
+                        vec![("content-type", "text/plain")],
+                        Some(b"Malformed x-forwarded-client-cert header"),
+                    );
+                    return Action::Pause;
+                }
+            }
+        } else {
+            match self.get_property(&["connection", "tls", "peer_certificate"]) {
+                Some(cert) => cert,
+                None => {
+                    self.send_http_response(
+                        403,
+                        vec![("content-type", "text/plain")],
+                        Some(b"Client certificate required"),
+                    );
+                    return Action::Pause;
+                }
+            }
+        };
+
+        let cert_der = match decode_cert_der(&cert_pem) {
+            Some(der) => der,
             None => {
                 self.send_http_response(
                     403,
                     vec![("content-type", "text/plain")],
-                    Some(b"Client certificate required"),
+                    Some(b"Invalid certificate encoding"),
                 );
                 return Action::Pause;
             }
         };
 
-        // Extract sensor ID from certificate
-        let sensor_id = match extract_sensor_id_from_cert(&cert_pem) {
-            Some(id) => id,
+        // Fail closed unless the certificate chains to a configured trust
+        // root and is within its validity window: otherwise any client
+        // that can craft a cert carrying the Unified Identity extension
+        // would be trusted on its say-so alone.
+        if !self.trust_bundle.roots.is_empty()
+            && !self
+                .trust_bundle
+                .verify(&cert_der, self.get_current_time())
+        {
+            self.send_http_response(
+                403,
+                vec![("content-type", "text/plain")],
+                Some(b"Client certificate failed trust chain validation"),
+            );
+            return Action::Pause;
+        }
+
+        // Extract sensor ID and attested location from the now-verified
+        // certificate, binding them to a signed, fresh claim so a captured
+        // cert or a reissued SVID carrying a stale location can't be
+        // replayed; see `ClaimVerifier`.
+        let attestation = match extract_sensor_id_from_cert(
+            &cert_der,
+            &self.claim_verifier,
+            self.get_current_time(),
+            &self.cache,
+        ) {
+            Some(attestation) => attestation,
             None => {
                 self.send_http_response(
                     403,
                     vec![("content-type", "text/plain")],
-                    Some(b"Invalid certificate: no sensor ID"),
+                    Some(b"Invalid certificate: no sensor ID or claim failed verification"),
                 );
                 return Action::Pause;
             }
         };
 
-        // Store sensor_id for use in response callback
-        self.sensor_id = Some(sensor_id.clone());
+        // Store sensor_id and attested location for use in response callback
+        self.sensor_id = Some(attestation.sensor_id.clone());
+        self.attested_point = attestation
+            .geolocation_value
+            .as_deref()
+            .and_then(parse_attested_point);
+
+        // Deny up front if a geofence is configured but the attestation
+        // didn't carry a location we could parse, instead of spending a
+        // round trip on the verification service first.
+        if !self.geofence.is_empty() && self.attested_point.is_none() {
+            self.send_http_response(
+                403,
+                vec![("content-type", "text/plain")],
+                Some(b"Sensor location required but not attested"),
+            );
+            return Action::Pause;
+        }
+
+        // Reuse a still-fresh verification result for this sensor_id
+        // instead of spending a round trip on the verification service.
+        if let Some(cached_result) = self.cache.get(&attestation.sensor_id) {
+            return if cached_result {
+                self.finish_verified()
+            } else {
+                self.send_http_response(
+                    403,
+                    vec![("content-type", "text/plain")],
+                    Some(b"Sensor verification failed"),
+                );
+                Action::Pause
+            };
+        }
+
+        // Collapse concurrent requests for the same unverified sensor_id
+        // down to a single `/verify` call; see `CacheState::in_flight`.
+        // This is an infrastructure hiccup, not a sensor failing its
+        // check, so `fail_open` lets the request through instead.
+        if !self.cache.try_start_verification(&attestation.sensor_id) {
+            if self.fail_open {
+                return self.finish_verified();
+            }
+            self.send_http_response(
+                503,
+                vec![("content-type", "text/plain"), ("retry-after", "1")],
+                Some(b"Sensor verification already in progress, retry shortly"),
+            );
+            return Action::Pause;
+        }
 
-        // Call mobile location service to verify sensor
+        // Call the verification service to check the sensor
         let verify_body = serde_json::to_string(&VerifyRequest {
-            sensor_id: sensor_id.clone(),
+            sensor_id: attestation.sensor_id.clone(),
         })
         .unwrap_or_default();
 
         let headers = vec![
             (":method", "POST"),
-            (":path", "/verify"),
-            (":authority", "localhost:5000"),
+            (":path", self.verify_path.as_str()),
+            (":authority", self.verify_authority.as_str()),
             ("content-type", "application/json"),
         ];
 
         match self.dispatch_http_call(
-            "mobile_location_service",
+            &self.verify_cluster.clone(),
             headers,
             Some(verify_body.as_bytes()),
             vec![],
-            Duration::from_secs(5),
+            self.call_timeout,
         ) {
             Ok(_) => Action::Pause,
             Err(_) => {
+                self.cache.finish_verification(&attestation.sensor_id);
+                if self.fail_open {
+                    return self.finish_verified();
+                }
                 self.send_http_response(
                     500,
                     vec![("content-type", "text/plain")],
@@ -125,10 +819,21 @@ impl HttpContext for SensorVerificationFilter {
         let body = self.get_http_call_response_body(0, body_size);
         let body_str = String::from_utf8_lossy(&body);
 
-        // Parse response
+        if let Some(sensor_id) = &self.sensor_id {
+            self.cache.finish_verification(sensor_id);
+        }
+
+        // Parse response. A malformed response is the verification
+        // service's fault, not the sensor's, so `fail_open` applies here.
         let verify_response: VerifyResponse = match serde_json::from_str(&body_str) {
             Ok(resp) => resp,
             Err(_) => {
+                if self.fail_open {
+                    if self.finish_verified() == Action::Continue {
+                        self.resume_http_request();
+                    }
+                    return;
+                }
                 self.send_http_response(
                     403,
                     vec![("content-type", "text/plain")],
@@ -138,6 +843,11 @@ impl HttpContext for SensorVerificationFilter {
             }
         };
 
+        if let Some(sensor_id) = &self.sensor_id {
+            self.cache
+                .insert(sensor_id.clone(), verify_response.verification_result);
+        }
+
         if !verify_response.verification_result {
             self.send_http_response(
                 403,
@@ -147,45 +857,118 @@ impl HttpContext for SensorVerificationFilter {
             return;
         }
 
-        // Get sensor_id and add header, then continue to backend
+        if self.finish_verified() == Action::Pause {
+            return;
+        }
+        self.resume_http_request();
+    }
+}
+
+impl SensorVerificationFilter {
+    /// Applies the geofence check (if one is configured) and, on success,
+    /// attaches the `X-Sensor-ID` header for the backend. Shared between
+    /// a fresh `/verify` response and a cache hit that skipped the call
+    /// entirely: `Action::Continue` means the caller should let the
+    /// request proceed (resuming it first if it had been paused),
+    /// `Action::Pause` means a 403 has already been sent.
+    fn finish_verified(&mut self) -> Action {
+        if !self.geofence.is_empty() {
+            let inside = self
+                .attested_point
+                .is_some_and(|point| is_within_any_area(point, &self.geofence));
+            if !inside {
+                self.send_http_response(
+                    403,
+                    vec![("content-type", "text/plain")],
+                    Some(b"Sensor location outside allowed geofence"),
+                );
+                return Action::Pause;
+            }
+        }
+
         if let Some(sensor_id) = &self.sensor_id {
             self.add_http_request_header("X-Sensor-ID", sensor_id);
         }
-        self.resume_http_request();
+        Action::Continue
     }
 }
 
-fn extract_sensor_id_from_cert(cert_pem: &[u8]) -> Option<String> {
-    // Parse certificate (handle both PEM and DER)
-    let cert_bytes = if cert_pem.starts_with(b"-----BEGIN") {
-        // PEM format - extract base64 content
-        let pem_str = std::str::from_utf8(cert_pem).ok()?;
-        let lines: Vec<&str> = pem_str
-            .lines()
-            .filter(|l| !l.starts_with("-----"))
-            .collect();
-        base64::decode(&lines.join("")).ok()?
-    } else {
-        cert_pem.to_vec()
-    };
+/// Sensor identity and (optional) attested geolocation extracted from the
+/// Unified Identity certificate extension.
+struct CertAttestation {
+    sensor_id: String,
+    // Raw `grc.geolocation.value` payload, if the attestation carried one.
+    geolocation_value: Option<String>,
+}
+
+/// A `grc.geolocation` claim carrying its own detached signature, issued-at
+/// timestamp, and a one-time nonce, as verified by `ClaimVerifier`.
+struct SignedClaim {
+    sensor_id: String,
+    value: Option<String>,
+    iat: i64,
+    nonce: String,
+    sig: String,
+}
+
+impl SignedClaim {
+    /// Deterministic byte serialization the signature is computed over.
+    /// A plain field concatenation is used instead of re-serializing the
+    /// JSON object, so signature verification doesn't depend on matching
+    /// `serde_json`'s (unspecified) key ordering on the signer's side.
+    fn canonical_message(&self) -> String {
+        format!(
+            "{}|{}|{}|{}",
+            self.sensor_id,
+            self.value.as_deref().unwrap_or(""),
+            self.iat,
+            self.nonce
+        )
+    }
+}
 
+fn extract_sensor_id_from_cert(
+    cert_der: &[u8],
+    claim_verifier: &ClaimVerifier,
+    now: SystemTime,
+    cache: &CacheState,
+) -> Option<CertAttestation> {
     // Parse X.509 certificate
-    let (_, cert) = x509_parser::parse_x509_certificate(&cert_bytes).ok()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert_der).ok()?;
 
     // Find Unified Identity extension
     for ext in cert.extensions() {
         let oid_str = format!("{}", ext.oid());
-        
+
         if oid_str == UNIFIED_IDENTITY_OID_STR || oid_str == LEGACY_OID_STR {
             // Parse extension value as JSON
             let ext_value = ext.value();
             if let Ok(json_str) = std::str::from_utf8(ext_value) {
                 if let Ok(json) = serde_json::from_str::<serde_json::Value>(json_str) {
                     if let Some(geo) = json.get("grc.geolocation") {
-                        if let Some(sensor_id) = geo.get("sensor_id") {
-                            if let Some(id_str) = sensor_id.as_str() {
-                                return Some(id_str.to_string());
+                        if let Some(sensor_id) = geo.get("sensor_id").and_then(|v| v.as_str()) {
+                            let value = geo.get("value").and_then(|v| v.as_str()).map(String::from);
+
+                            // A verifier key is configured and the claim
+                            // carries signature material: it must check
+                            // out, or the whole certificate is rejected.
+                            if claim_verifier.is_enabled() {
+                                let claim = SignedClaim {
+                                    sensor_id: sensor_id.to_string(),
+                                    value: value.clone(),
+                                    iat: geo.get("iat").and_then(|v| v.as_i64())?,
+                                    nonce: geo.get("nonce").and_then(|v| v.as_str())?.to_string(),
+                                    sig: geo.get("sig").and_then(|v| v.as_str())?.to_string(),
+                                };
+                                if !claim_verifier.verify(&claim, now, cache) {
+                                    return None;
+                                }
                             }
+
+                            return Some(CertAttestation {
+                                sensor_id: sensor_id.to_string(),
+                                geolocation_value: value,
+                            });
                         }
                     }
                 }
@@ -196,3 +979,259 @@ fn extract_sensor_id_from_cert(cert_pem: &[u8]) -> Option<String> {
     None
 }
 
+/// Parses a `grc.geolocation.value` payload into `(lat, lon)` degrees.
+/// Accepts the canonical GNSS fix JSON (`{"lat":...,"lon":...}`) as well as
+/// the simpler `"lat,lon"` form used by non-GNSS sensors.
+fn parse_attested_point(value: &str) -> Option<(f64, f64)> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(value) {
+        let lat = json.get("lat").and_then(|v| v.as_f64());
+        let lon = json.get("lon").and_then(|v| v.as_f64());
+        if let (Some(lat), Some(lon)) = (lat, lon) {
+            return Some((lat, lon));
+        }
+    }
+    let (lat_str, lon_str) = value.split_once(',')?;
+    let lat = lat_str.trim().parse::<f64>().ok()?;
+    let lon = lon_str.trim().parse::<f64>().ok()?;
+    Some((lat, lon))
+}
+
+// Great-circle distance between two `(lat, lon)` points in degrees, in
+// meters, via the haversine formula:
+// a = sin²(Δφ/2) + cos φ1·cos φ2·sin²(Δλ/2); d = 2R·atan2(√a, √(1−a))
+fn haversine_distance_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = lon2 - lon1;
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().atan2((1.0 - h).sqrt())
+}
+
+fn point_in_circle(point: (f64, f64), center_lat: f64, center_lon: f64, radius_m: f64) -> bool {
+    haversine_distance_m(point, (center_lat, center_lon)) <= radius_m
+}
+
+// Normalizes a longitude into `[0, 360)` so that polygons crossing the
+// antimeridian (e.g. vertices at `179` and `-179`) don't wrap around the
+// wrong way during ray-casting.
+fn normalize_longitude(lon: f64) -> f64 {
+    lon.rem_euclid(360.0)
+}
+
+// Ray-casting point-in-polygon test: counts crossings of a horizontal ray
+// cast east from `point`; an odd crossing count means the point is inside.
+fn point_in_polygon(point: (f64, f64), vertices: &[(f64, f64)]) -> bool {
+    if vertices.len() < 3 {
+        return false;
+    }
+    let (test_lat, test_lon) = (point.0, normalize_longitude(point.1));
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for i in 0..vertices.len() {
+        let (lat_i, lon_i) = (vertices[i].0, normalize_longitude(vertices[i].1));
+        let (lat_j, lon_j) = (vertices[j].0, normalize_longitude(vertices[j].1));
+        let crosses = (lat_i > test_lat) != (lat_j > test_lat);
+        if crosses {
+            let lon_at_crossing = lon_i + (test_lat - lat_i) / (lat_j - lat_i) * (lon_j - lon_i);
+            if test_lon < lon_at_crossing {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+    inside
+}
+
+fn is_within_any_area(point: (f64, f64), areas: &[GeofenceArea]) -> bool {
+    areas.iter().any(|area| match area {
+        GeofenceArea::Circle {
+            center_lat,
+            center_lon,
+            radius_m,
+        } => point_in_circle(point, *center_lat, *center_lon, *radius_m),
+        GeofenceArea::Polygon { vertices } => point_in_polygon(point, vertices),
+    })
+}
+
+/// Splits `s` on top-level occurrences of `sep`, treating any text inside
+/// a matching pair of `"` as opaque so a separator character that happens
+/// to appear inside a quoted value doesn't split it.
+fn split_respecting_quotes(s: &str, sep: char) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    for (i, c) in s.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == sep && !in_quotes {
+            tokens.push(s[start..i].trim());
+            start = i + c.len_utf8();
+        }
+    }
+    tokens.push(s[start..].trim());
+    tokens
+}
+
+/// Decodes every `%XX` escape in `s` into its raw byte, over the full
+/// byte range rather than a hardcoded subset of escapes.
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+/// Parses one comma-separated element of an XFCC header into its
+/// semicolon-separated `key=value` pairs, unwrapping a double-quoted
+/// value and leaving others as-is.
+fn parse_xfcc_element(element: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    for kv in split_respecting_quotes(element, ';') {
+        if let Some((key, value)) = kv.split_once('=') {
+            pairs.insert(
+                key.trim().to_string(),
+                value.trim().trim_matches('"').to_string(),
+            );
+        }
+    }
+    pairs
+}
+
+/// Parses an `x-forwarded-client-cert` header per Envoy's
+/// comma-separated-elements / semicolon-separated-pairs / quoted-value
+/// grammar. Each element's `Chain` field (the full forwarded chain) is
+/// preferred, falling back to `Cert` (the leaf only) when an element
+/// doesn't carry one, and the selected value is percent-decoded over its
+/// full byte range into PEM bytes.
+fn parse_xfcc_header(header: &str) -> Option<Vec<u8>> {
+    for element in split_respecting_quotes(header, ',') {
+        let pairs = parse_xfcc_element(element);
+        if let Some(value) = pairs.get("Chain").or_else(|| pairs.get("Cert")) {
+            return percent_decode(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_envoy_cert_element() {
+        let header = r#"By=spiffe://example.org/agent;Hash=abcd;Cert="-----BEGIN%20CERTIFICATE-----%0Aabc%0A-----END%20CERTIFICATE-----""#;
+        let pem = parse_xfcc_header(header).expect("should parse");
+        assert_eq!(
+            String::from_utf8(pem).unwrap(),
+            "-----BEGIN CERTIFICATE-----\nabc\n-----END CERTIFICATE-----"
+        );
+    }
+
+    #[test]
+    fn prefers_chain_over_cert() {
+        let header = r#"Cert="leaf-only";Chain="full%20chain""#;
+        let pem = parse_xfcc_header(header).expect("should parse");
+        assert_eq!(String::from_utf8(pem).unwrap(), "full chain");
+    }
+
+    #[test]
+    fn falls_back_to_cert_when_no_chain() {
+        let header = r#"By=spiffe://example.org/agent;Cert="leaf%20only""#;
+        let pem = parse_xfcc_header(header).expect("should parse");
+        assert_eq!(String::from_utf8(pem).unwrap(), "leaf only");
+    }
+
+    #[test]
+    fn handles_multiple_comma_separated_elements() {
+        let header = r#"By=spiffe://a;Cert="first"; , By=spiffe://b;Cert="second""#;
+        let pem = parse_xfcc_header(header).expect("should parse");
+        assert_eq!(String::from_utf8(pem).unwrap(), "first");
+    }
+
+    #[test]
+    fn quoted_value_may_contain_separators() {
+        // A semicolon and comma embedded in a quoted value must not split
+        // the element or the header.
+        let header = r#"Cert="part1;part2,part3""#;
+        let pairs = parse_xfcc_element(header);
+        assert_eq!(pairs.get("Cert").unwrap(), "part1;part2,part3");
+    }
+
+    #[test]
+    fn percent_decode_handles_arbitrary_escapes() {
+        let decoded = percent_decode("%00%ff%41").unwrap();
+        assert_eq!(decoded, vec![0x00, 0xff, 0x41]);
+    }
+
+    #[test]
+    fn malformed_header_yields_none() {
+        assert!(parse_xfcc_header("By=spiffe://example.org/agent").is_none());
+    }
+
+    #[test]
+    fn sensor_cache_evicts_least_recently_used() {
+        let mut cache = SensorCache::new(2);
+        let recorded_at = SystemTime::now();
+        cache.insert("a".to_string(), true, recorded_at);
+        cache.insert("b".to_string(), true, recorded_at);
+        // Touching "a" makes "b" the least-recently-used entry, so the next
+        // insert over capacity should evict "b", not "a".
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), true, recorded_at);
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn try_start_verification_collapses_concurrent_calls() {
+        let cache = CacheState::default();
+        assert!(cache.try_start_verification("sensor-1"));
+        // A second concurrent request for the same sensor_id must not also
+        // be allowed to dispatch while the first call is still outstanding.
+        assert!(!cache.try_start_verification("sensor-1"));
+
+        cache.finish_verification("sensor-1");
+        // Once the in-flight call completes, a fresh request may dispatch.
+        assert!(cache.try_start_verification("sensor-1"));
+    }
+
+    #[test]
+    fn try_start_verification_allows_retry_after_dispatch_timeout() {
+        let cache = CacheState::new(
+            DEFAULT_CACHE_CAPACITY,
+            Duration::from_secs(DEFAULT_POSITIVE_CACHE_TTL_SECS),
+            Duration::from_secs(DEFAULT_NEGATIVE_CACHE_TTL_SECS),
+            Duration::from_secs(0),
+            DEFAULT_NONCE_RING_CAPACITY,
+        );
+        assert!(cache.try_start_verification("sensor-1"));
+        // With a zero dispatch_timeout the first marker is immediately
+        // stale, so a second caller may proceed without `finish_verification`
+        // ever being called - covering the "lost response" recovery path.
+        assert!(cache.try_start_verification("sensor-1"));
+    }
+
+    #[test]
+    fn point_in_polygon_handles_antimeridian_wrap() {
+        // A rectangle straddling the antimeridian, expressed with vertices
+        // on both sides of the +/-180 discontinuity.
+        let vertices = vec![(10.0, 179.0), (10.0, -179.0), (-10.0, -179.0), (-10.0, 179.0)];
+
+        assert!(point_in_polygon((0.0, 179.9), &vertices));
+        assert!(point_in_polygon((0.0, -179.9), &vertices));
+        assert!(!point_in_polygon((0.0, 0.0), &vertices));
+    }
+}
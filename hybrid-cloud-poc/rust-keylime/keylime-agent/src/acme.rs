@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Automatic ACME certificate provisioning
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: automatic ACME provisioning for the agent's mTLS/contact cert.
+//!
+//! When `acme_enabled = true`, instead of self-signing a certificate keyed to
+//! the agent UUID, the agent obtains a real certificate for `acme_dns_names`
+//! from `acme_directory_url` via the HTTP-01 challenge, using the existing
+//! mTLS keypair for the CSR. The account key and issued chain are cached under
+//! `agent_data_path` so a restart does not re-provision, and a background task
+//! renews the certificate before it expires.
+//!
+//! The HTTP-01 challenge is served by the agent's own already-running actix
+//! server at `/.well-known/acme-challenge/{token}`; [`Http01Challenges`] is the
+//! shared state the challenge route and the provisioning flow both touch.
+
+use actix_web::{web, HttpResponse, Responder};
+use instant_acme::{
+    Account, AuthorizationStatus, ChallengeType, Identifier, LetsEncrypt, NewAccount, NewOrder,
+    OrderStatus,
+};
+use log::*;
+use openssl::{pkey::{PKey, Private}, x509::X509};
+use rcgen::{CertificateParams, KeyPair};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::Duration};
+use thiserror::Error;
+
+/// Token -> key-authorization map for in-flight HTTP-01 challenges, shared
+/// between the provisioning flow and the `/.well-known/acme-challenge` route.
+pub(crate) type Http01Challenges = Mutex<HashMap<String, String>>;
+
+/// Messages accepted by the background ACME renewal task.
+pub(crate) enum AcmeMessage {
+    Shutdown,
+}
+
+/// Configuration for ACME certificate provisioning, derived from the agent config.
+#[derive(Debug, Clone)]
+pub(crate) struct AcmeConfig {
+    pub(crate) directory_url: String,
+    pub(crate) contact_email: String,
+    pub(crate) dns_names: Vec<String>,
+    pub(crate) cache_dir: PathBuf,
+}
+
+impl AcmeConfig {
+    fn account_key_path(&self) -> PathBuf {
+        self.cache_dir.join("acme_account.json")
+    }
+
+    fn cert_chain_path(&self) -> PathBuf {
+        self.cache_dir.join("acme_cert_chain.pem")
+    }
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum AcmeError {
+    #[error("ACME protocol error: {0}")]
+    Protocol(String),
+    #[error("failed to read/write ACME cache at {0}: {1}")]
+    Cache(PathBuf, String),
+    #[error("failed to build certificate request: {0}")]
+    Csr(String),
+    #[error("failed to parse issued certificate chain: {0}")]
+    Parse(String),
+    #[error("ACME order did not become ready for finalization (last status: {0:?})")]
+    OrderNotValid(OrderStatus),
+}
+
+/// Obtain a certificate for `cfg.dns_names`, reusing a cached account and
+/// chain when present, otherwise running a full HTTP-01 order.
+pub(crate) async fn provision_or_renew(
+    cfg: &AcmeConfig,
+    priv_key: &PKey<Private>,
+    challenges: &Http01Challenges,
+) -> Result<X509, AcmeError> {
+    if let Some(cert) = load_cached_chain(cfg)? {
+        if !certificate_expires_within(&cert, Duration::from_secs(30 * 24 * 3600)) {
+            return Ok(cert);
+        }
+        info!("ACME: cached certificate is within its renewal window, re-provisioning");
+    }
+
+    let account = load_or_create_account(cfg).await?;
+    let identifiers: Vec<Identifier> = cfg
+        .dns_names
+        .iter()
+        .cloned()
+        .map(Identifier::Dns)
+        .collect();
+
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &identifiers,
+        })
+        .await
+        .map_err(|e| AcmeError::Protocol(format!("failed to create order: {e}")))?;
+
+    let authorizations = order
+        .authorizations()
+        .await
+        .map_err(|e| AcmeError::Protocol(format!("failed to fetch authorizations: {e}")))?;
+
+    for authz in &authorizations {
+        if authz.status == AuthorizationStatus::Valid {
+            continue;
+        }
+        let challenge = authz
+            .challenges
+            .iter()
+            .find(|c| c.r#type == ChallengeType::Http01)
+            .ok_or_else(|| {
+                AcmeError::Protocol("CA did not offer an HTTP-01 challenge".to_string())
+            })?;
+        let key_authorization = order.key_authorization(challenge).as_str().to_string();
+        challenges
+            .lock()
+            .unwrap() //#[allow_ci]
+            .insert(challenge.token.clone(), key_authorization);
+        order
+            .set_challenge_ready(&challenge.url)
+            .await
+            .map_err(|e| AcmeError::Protocol(format!("failed to mark challenge ready: {e}")))?;
+    }
+
+    poll_until_ready(&mut order).await?;
+
+    let key_pair = KeyPair::try_from(
+        priv_key
+            .private_key_to_der()
+            .map_err(|e| AcmeError::Csr(format!("failed to DER-encode mTLS key: {e}")))?
+            .as_slice(),
+    )
+    .map_err(|e| AcmeError::Csr(format!("failed to import mTLS key into rcgen: {e}")))?;
+    let mut params = CertificateParams::new(cfg.dns_names.clone())
+        .map_err(|e| AcmeError::Csr(format!("failed to build certificate params: {e}")))?;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let csr_der = params
+        .serialize_request(&key_pair)
+        .map_err(|e| AcmeError::Csr(format!("failed to serialize CSR: {e}")))?;
+
+    order
+        .finalize(csr_der.der())
+        .await
+        .map_err(|e| AcmeError::Protocol(format!("failed to finalize order: {e}")))?;
+
+    let chain_pem = loop {
+        match order
+            .certificate()
+            .await
+            .map_err(|e| AcmeError::Protocol(format!("failed to fetch certificate: {e}")))?
+        {
+            Some(chain) => break chain,
+            None => tokio::time::sleep(Duration::from_secs(2)).await,
+        }
+    };
+
+    std::fs::write(cfg.cert_chain_path(), &chain_pem)
+        .map_err(|e| AcmeError::Cache(cfg.cert_chain_path(), e.to_string()))?;
+
+    leaf_cert_from_chain(&chain_pem)
+}
+
+/// Repeatedly poll the order status until it reaches `ready` (or `valid`),
+/// so it can be finalized, returning an error if it turns `invalid` or is
+/// still `pending`/`processing` after 30 polls.
+async fn poll_until_ready(order: &mut instant_acme::Order) -> Result<(), AcmeError> {
+    let mut last_status = OrderStatus::Pending;
+    for _ in 0..30 {
+        let state = order
+            .refresh()
+            .await
+            .map_err(|e| AcmeError::Protocol(format!("failed to poll order: {e}")))?;
+        last_status = state.status;
+        match state.status {
+            OrderStatus::Ready | OrderStatus::Valid => return Ok(()),
+            OrderStatus::Pending | OrderStatus::Processing => (),
+            other => return Err(AcmeError::OrderNotValid(other)),
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+    Err(AcmeError::OrderNotValid(last_status))
+}
+
+async fn load_or_create_account(cfg: &AcmeConfig) -> Result<Account, AcmeError> {
+    let account_path = cfg.account_key_path();
+    if account_path.exists() {
+        let credentials = std::fs::read_to_string(&account_path)
+            .map_err(|e| AcmeError::Cache(account_path.clone(), e.to_string()))?;
+        let credentials: instant_acme::AccountCredentials = serde_json::from_str(&credentials)
+            .map_err(|e| AcmeError::Cache(account_path.clone(), e.to_string()))?;
+        return Account::from_credentials(credentials)
+            .await
+            .map_err(|e| AcmeError::Protocol(format!("failed to restore ACME account: {e}")));
+    }
+
+    let directory_url = if cfg.directory_url.is_empty() {
+        LetsEncrypt::Production.url().to_string()
+    } else {
+        cfg.directory_url.clone()
+    };
+    let (account, credentials) = Account::create(
+        &NewAccount {
+            contact: &[&format!("mailto:{}", cfg.contact_email)],
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        &directory_url,
+        None,
+    )
+    .await
+    .map_err(|e| AcmeError::Protocol(format!("failed to create ACME account: {e}")))?;
+
+    let serialized = serde_json::to_string(&credentials)
+        .map_err(|e| AcmeError::Cache(account_path.clone(), e.to_string()))?;
+    std::fs::write(&account_path, serialized)
+        .map_err(|e| AcmeError::Cache(account_path, e.to_string()))?;
+
+    Ok(account)
+}
+
+fn load_cached_chain(cfg: &AcmeConfig) -> Result<Option<X509>, AcmeError> {
+    let path = cfg.cert_chain_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let pem = std::fs::read_to_string(&path).map_err(|e| AcmeError::Cache(path, e.to_string()))?;
+    leaf_cert_from_chain(&pem).map(Some)
+}
+
+fn leaf_cert_from_chain(chain_pem: &str) -> Result<X509, AcmeError> {
+    X509::from_pem(chain_pem.as_bytes())
+        .map_err(|e| AcmeError::Parse(format!("leaf certificate: {e}")))
+}
+
+/// `GET /.well-known/acme-challenge/{token}`, served over plain HTTP on the
+/// agent's already-running actix server to satisfy the HTTP-01 challenge.
+async fn http01_challenge_handler(
+    token: web::Path<String>,
+    challenges: web::Data<Http01Challenges>,
+) -> impl Responder {
+    match challenges.lock().unwrap().get(token.as_str()) {
+        //#[allow_ci]
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization.clone()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Register the HTTP-01 challenge route on the agent's actix app.
+pub(crate) fn configure_acme_endpoints(cfg: &mut web::ServiceConfig) {
+    _ = cfg.service(
+        web::resource("/.well-known/acme-challenge/{token}")
+            .route(web::get().to(http01_challenge_handler)),
+    );
+}
+
+/// Background task: periodically re-provisions the certificate before it
+/// expires and refreshes the on-disk cache, stopping on [`AcmeMessage::Shutdown`].
+///
+/// Picking up a renewed certificate without an agent restart would
+/// additionally require a TLS acceptor that supports hot-reloading the served
+/// certificate, which actix-web's `bind_openssl`/`bind_rustls_0_23` do not
+/// expose today; this task keeps the cache fresh for the next restart.
+pub(crate) async fn renewal_worker(
+    cfg: AcmeConfig,
+    priv_key: PKey<Private>,
+    challenges: web::Data<Http01Challenges>,
+    mut rx: tokio::sync::mpsc::Receiver<AcmeMessage>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(12 * 3600)) => {
+                match provision_or_renew(&cfg, &priv_key, &challenges).await {
+                    Ok(_) => info!("ACME: certificate renewal check completed"),
+                    Err(e) => warn!("ACME: certificate renewal check failed: {e}"),
+                }
+            }
+            msg = rx.recv() => {
+                match msg {
+                    Some(AcmeMessage::Shutdown) | None => {
+                        debug!("Shutting down ACME renewal task");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn certificate_expires_within(cert: &X509, window: Duration) -> bool {
+    let window_secs = window.as_secs() as i32;
+    let deadline = openssl::asn1::Asn1Time::days_from_now(window_secs / 86_400)
+        .expect("Asn1Time::days_from_now(window) should not overflow"); //#[allow_ci]
+    cert.not_after() <= deadline
+}
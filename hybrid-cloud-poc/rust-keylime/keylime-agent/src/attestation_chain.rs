@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Hardware Integration & Delegated Certification
+// Copyright 2024 Keylime Authors
+
+//! Unified-Identity: assembles the DICE-style attestation chain returned by
+//! `delegated_certification_handler::certify_app_key` when a request sets
+//! `include_chain: true`. The chain is a CBOR array `[version, entries]`
+//! linking App Key -> AK -> EK: the root entry carries the EK certificate
+//! (read from NV) so a verifier can anchor to the TPM vendor CA, and each
+//! subsequent entry is a COSE_Sign1 structure whose payload is a
+//! TPM2_Certify `Attest` and whose `qualifyingData` hashes in the previous
+//! entry's payload plus the next entry's subject key, via
+//! [`next_qualifying_data`] - making the chain cryptographically
+//! continuous link to link rather than three independently-nonced blobs.
+//!
+//! This agent doesn't retain the MakeCredential/ActivateCredential exchange
+//! from enrollment (that's a registrar-mediated protocol requiring a
+//! verifier-supplied credential blob, not something a running agent can
+//! reproduce on demand) - so the EK -> AK link is instead a TPM2_Certify of
+//! the EK's own public area with the AK as signer, the same primitive
+//! already used to bind AK -> App Key.
+
+use serde_cbor::Value;
+use tss_esapi::structures::Data;
+
+pub(crate) const ATTESTATION_CHAIN_VERSION: i128 = 1;
+
+/// One non-root entry: a subject public key plus the COSE_Sign1-wrapped
+/// `Attest`/signature that certifies it.
+pub(crate) struct ChainLinkInput {
+    /// Public key this entry certifies: DER-encoded SubjectPublicKeyInfo for
+    /// the App Key, or the marshalled `TPM2B_PUBLIC` for a key of the
+    /// agent's own TPM (the AK).
+    pub subject_public_key_der: Vec<u8>,
+    /// IANA COSE algorithm identifier for `raw_signature`, from
+    /// `delegated_certification_handler::cose_alg_and_raw_signature`.
+    pub cose_alg: i128,
+    /// Marshalled `TPMS_ATTEST` - the COSE_Sign1 payload.
+    pub attest_bytes: Vec<u8>,
+    /// Raw (non-TPM-wire-format) signature bytes over `attest_bytes`.
+    pub raw_signature: Vec<u8>,
+}
+
+/// Build the version-tagged CBOR chain: a root entry carrying the raw EK
+/// certificate (DER), followed by one COSE_Sign1 entry per `links`, in
+/// order (App Key last).
+pub(crate) fn build_chain(
+    ek_certificate_der: &[u8],
+    links: &[ChainLinkInput],
+) -> Result<Vec<u8>, String> {
+    let mut entries = vec![Value::Array(vec![
+        Value::Bytes(ek_certificate_der.to_vec()),
+        Value::Null,
+    ])];
+
+    for link in links {
+        let protected_header = Value::Map(
+            [(Value::Integer(1), Value::Integer(link.cose_alg))]
+                .into_iter()
+                .collect(),
+        );
+        let protected_bytes =
+            serde_cbor::to_vec(&protected_header).map_err(|e| e.to_string())?;
+        let cose_sign1 = Value::Array(vec![
+            Value::Bytes(protected_bytes),
+            Value::Map(Default::default()),
+            Value::Bytes(link.attest_bytes.clone()),
+            Value::Bytes(link.raw_signature.clone()),
+        ]);
+        let cose_bytes = serde_cbor::to_vec(&cose_sign1).map_err(|e| e.to_string())?;
+
+        entries.push(Value::Array(vec![
+            Value::Bytes(link.subject_public_key_der.clone()),
+            Value::Bytes(cose_bytes),
+        ]));
+    }
+
+    let chain = Value::Array(vec![
+        Value::Integer(ATTESTATION_CHAIN_VERSION),
+        Value::Array(entries),
+    ]);
+    serde_cbor::to_vec(&chain).map_err(|e| e.to_string())
+}
+
+/// Hash `prev_payload` (the previous link's COSE_Sign1 payload, i.e. a
+/// marshalled `Attest`) together with `next_subject_public_key_der` into
+/// TPM `Data` qualifying data for the next `TPM2_Certify` call, so that
+/// link can only be replayed together with the link before it.
+pub(crate) fn next_qualifying_data(
+    prev_payload: &[u8],
+    next_subject_public_key_der: &[u8],
+) -> Result<Data, String> {
+    use openssl::hash::{Hasher, MessageDigest};
+
+    let mut hasher =
+        Hasher::new(MessageDigest::sha256()).map_err(|e| format!("Failed to create hasher: {e}"))?;
+    hasher
+        .update(prev_payload)
+        .map_err(|e| format!("Failed to hash previous link payload: {e}"))?;
+    hasher
+        .update(next_subject_public_key_der)
+        .map_err(|e| format!("Failed to hash next link subject key: {e}"))?;
+    let digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finalize qualifying data hash: {e}"))?;
+
+    Data::try_from(digest.as_ref()).map_err(|e| format!("Failed to build TPM Data: {e}"))
+}
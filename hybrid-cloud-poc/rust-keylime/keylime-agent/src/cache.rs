@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2021 Keylime Authors
+
+//! Unified-Identity: content-addressed cache for rendered `/integrity` quote
+//! responses. A small in-memory LRU front-ends a disk-backed
+//! content-addressed store (`cacache`), so a warm cache survives an agent
+//! restart and a corrupted or evicted disk blob is detected via its stored
+//! `ssri::Integrity` digest and falls through to regeneration instead of
+//! being served. The same store also backs a small persistent index of
+//! per-verifier IMA measurement-list offsets, so iterative attestation can
+//! resume where a given verifier left off.
+
+use log::warn;
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Unified-Identity: disk key under which the whole per-verifier IMA offset
+/// index is stored, in the same content-addressed store as rendered
+/// quotes. The index is small and rewritten wholesale on each checkpoint
+/// rather than addressed per-verifier, since `cacache` has no notion of
+/// partial/incremental updates to a single entry.
+const IMA_OFFSET_INDEX_DISK_KEY: &str = "quote-cache/ima-offset-index";
+
+/// Unified-Identity: maximum number of distinct verifier identities tracked
+/// in the IMA offset index. Most callers are keyed by an authenticated
+/// capability-token `iss` (see `resolve_verifier_identity`), but a caller
+/// with no token falls back to its self-reported `client_id`, which is
+/// unauthenticated and unbounded in cardinality - without a cap, a caller
+/// sending a fresh `client_id` per request could grow this index (and the
+/// disk snapshot rewritten on every checkpoint) without limit.
+const MAX_IMA_OFFSET_ENTRIES: usize = 4096;
+
+/// Unified-Identity: identifies a cacheable rendered quote by the inputs
+/// that fully determine its content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    hash_alg: String,
+    nonce: Vec<u8>,
+    partial: String,
+}
+
+impl CacheKey {
+    pub(crate) fn new(hash_alg: &str, nonce: &[u8], partial: &str) -> Self {
+        CacheKey {
+            hash_alg: hash_alg.to_string(),
+            nonce: nonce.to_vec(),
+            partial: partial.to_string(),
+        }
+    }
+
+    /// Unified-Identity: stable string form used as the `cacache` key, since
+    /// `cacache` addresses its index by string key rather than an arbitrary
+    /// `Hash` impl.
+    fn disk_key(&self) -> String {
+        format!(
+            "quote-cache/{}/{}/{}",
+            self.hash_alg,
+            hex::encode(&self.nonce),
+            self.partial
+        )
+    }
+}
+
+/// Unified-Identity: a rendered `/integrity` response body plus the HTTP
+/// metadata needed to serve it again without re-rendering.
+#[derive(Debug, Clone)]
+pub(crate) struct CachedQuote {
+    pub(crate) data: Vec<u8>,
+    pub(crate) content_type: String,
+    pub(crate) content_length: u64,
+    pub(crate) last_modified: u64,
+}
+
+/// Unified-Identity: HTTP metadata stored alongside the `cacache` blob;
+/// the blob itself is just `CachedQuote::data`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedQuoteMetadata {
+    content_type: String,
+    content_length: u64,
+    last_modified: u64,
+}
+
+/// Unified-Identity: bounded in-memory LRU front-ending a disk-backed
+/// content-addressed store of rendered `/integrity` quotes.
+#[derive(Debug)]
+pub(crate) struct QuoteCache {
+    memory: Mutex<LruCache<CacheKey, CachedQuote>>,
+    disk_dir: PathBuf,
+    ttl: Duration,
+    /// Unified-Identity: per-verifier last-served IMA measurement-list
+    /// entry, loaded from disk at construction and checkpointed (in memory
+    /// and to disk) on every served request. Keyed by the caller-resolved
+    /// verifier identity string, not a `CacheKey` - unlike rendered quotes,
+    /// this index tracks progress across many different nonces. Bounded to
+    /// [`MAX_IMA_OFFSET_ENTRIES`] and LRU-evicted, since an unauthenticated
+    /// fallback identity (see `resolve_verifier_identity`) has no natural
+    /// cardinality limit.
+    ima_offsets: Mutex<LruCache<String, u64>>,
+}
+
+impl QuoteCache {
+    pub(crate) fn new(disk_dir: PathBuf, max_entries: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::new(1).unwrap()); //#[allow_ci]
+        let ima_offset_capacity =
+            NonZeroUsize::new(MAX_IMA_OFFSET_ENTRIES).unwrap_or(NonZeroUsize::new(1).unwrap()); //#[allow_ci]
+        let mut ima_offsets = LruCache::new(ima_offset_capacity);
+        let loaded = cacache::read_sync(&disk_dir, IMA_OFFSET_INDEX_DISK_KEY)
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<HashMap<String, u64>>(&bytes).ok())
+            .unwrap_or_default();
+        for (verifier_id, offset) in loaded {
+            ima_offsets.put(verifier_id, offset);
+        }
+        QuoteCache {
+            memory: Mutex::new(LruCache::new(capacity)),
+            disk_dir,
+            ttl,
+            ima_offsets: Mutex::new(ima_offsets),
+        }
+    }
+
+    fn is_fresh(&self, last_modified: u64) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        now.saturating_sub(last_modified) < self.ttl.as_secs()
+    }
+
+    /// Unified-Identity: fetch a cached quote, re-verifying the disk blob's
+    /// integrity digest on a memory-cache miss. Returns `None` on a miss, an
+    /// expired entry, or a digest mismatch (corrupt/tampered blob) - all of
+    /// which the caller should treat as "regenerate".
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<CachedQuote> {
+        {
+            let mut memory = self.memory.lock().unwrap(); //#[allow_ci]
+            if let Some(entry) = memory.get(key) {
+                if self.is_fresh(entry.last_modified) {
+                    return Some(entry.clone());
+                }
+                let _ = memory.pop(key);
+            }
+        }
+
+        let disk_key = key.disk_key();
+        let metadata = cacache::metadata_sync(&self.disk_dir, &disk_key)
+            .ok()
+            .flatten()?;
+        let stored: CachedQuoteMetadata =
+            serde_json::from_value(metadata.metadata).ok()?;
+        if !self.is_fresh(stored.last_modified) {
+            return None;
+        }
+
+        // Unified-Identity: `cacache::read_sync` re-verifies the blob
+        // against its stored `ssri::Integrity` digest before returning it,
+        // so a corrupted or tampered blob surfaces as an `Err` here rather
+        // than being served.
+        let data = cacache::read_sync(&self.disk_dir, &disk_key).ok()?;
+        let entry = CachedQuote {
+            data,
+            content_type: stored.content_type,
+            content_length: stored.content_length,
+            last_modified: stored.last_modified,
+        };
+        self.memory
+            .lock()
+            .unwrap() //#[allow_ci]
+            .put(key.clone(), entry.clone());
+        Some(entry)
+    }
+
+    /// Unified-Identity: store a rendered quote in both the in-memory LRU
+    /// and the disk-backed content-addressed store, recording its integrity
+    /// digest and HTTP metadata for later verification/retrieval.
+    pub(crate) fn put(&self, key: CacheKey, entry: CachedQuote) {
+        let disk_key = key.disk_key();
+        let metadata = CachedQuoteMetadata {
+            content_type: entry.content_type.clone(),
+            content_length: entry.content_length,
+            last_modified: entry.last_modified,
+        };
+        if let Ok(metadata_value) = serde_json::to_value(&metadata) {
+            let write_result = cacache::WriteOpts::new()
+                .metadata(metadata_value)
+                .open_sync(&self.disk_dir, &disk_key)
+                .and_then(|mut writer| {
+                    use std::io::Write;
+                    writer.write_all(&entry.data)?;
+                    writer.commit()
+                });
+            if let Err(e) = write_result {
+                warn!(
+                    "Unified-Identity: Failed to persist quote cache entry to disk: {}",
+                    e
+                );
+            }
+        }
+
+        self.memory.lock().unwrap().put(key, entry); //#[allow_ci]
+    }
+
+    /// Unified-Identity: the IMA measurement-list entry `verifier_id` should
+    /// resume from, for a `partial=1` request that didn't specify an
+    /// explicit entry number. Returns `0` for a verifier not yet seen.
+    pub(crate) fn resolve_ima_start(&self, verifier_id: &str) -> u64 {
+        self.ima_offsets
+            .lock()
+            .unwrap() //#[allow_ci]
+            .get(verifier_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Unified-Identity: snapshot the current IMA offset index as a plain
+    /// `HashMap` for serialization - `LruCache` itself isn't `Serialize`.
+    fn ima_offsets_snapshot(offsets: &LruCache<String, u64>) -> HashMap<String, u64> {
+        offsets
+            .iter()
+            .map(|(verifier_id, offset)| (verifier_id.clone(), *offset))
+            .collect()
+    }
+
+    /// Unified-Identity: record the IMA entry served to `verifier_id` in
+    /// this request and persist the whole index to disk. A persistence
+    /// failure is logged and otherwise non-fatal - the in-memory index
+    /// still tracks progress for the life of this process, and the last
+    /// successfully persisted snapshot remains available across a restart.
+    pub(crate) fn checkpoint_ima_offset(&self, verifier_id: &str, served_entry: u64) {
+        let snapshot = {
+            let mut offsets = self.ima_offsets.lock().unwrap(); //#[allow_ci]
+            offsets.put(verifier_id.to_string(), served_entry);
+            Self::ima_offsets_snapshot(&offsets)
+        };
+        match bincode::serialize(&snapshot) {
+            Ok(bytes) => {
+                if let Err(e) =
+                    cacache::write_sync(&self.disk_dir, IMA_OFFSET_INDEX_DISK_KEY, bytes)
+                {
+                    warn!(
+                        "Unified-Identity: Failed to persist IMA offset index to disk: {}",
+                        e
+                    );
+                }
+            }
+            Err(e) => warn!(
+                "Unified-Identity: Failed to serialize IMA offset index: {}",
+                e
+            ),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Client-certificate revocation checking (CRL + OCSP)
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: CRL and OCSP revocation checking for inbound mTLS clients.
+//!
+//! `trusted_client_ca` authenticates verifier/tenant client certificates, but
+//! on its own gives no way to revoke a compromised client credential short of
+//! editing the CA bundle and restarting the agent. This module adds two,
+//! independently-optional checks layered on top of that chain validation:
+//!
+//! * `trusted_client_crl`: a list of CRL files, reloaded periodically by
+//!   [`reload_worker`] into a [`RevokedSerials`] set so revocation takes
+//!   effect without an agent restart.
+//! * `trusted_client_ocsp_responder`: an OCSP responder URL queried for
+//!   certificates not covered by a CRL hit, with responses cached for a
+//!   short TTL in [`OcspCache`] rather than fetched on every handshake.
+//!
+//! Both checks are exposed as plain functions ([`is_revoked`],
+//! [`check_ocsp`]) rather than installed as a standalone OpenSSL verify
+//! callback: the agent's mTLS listener may also need the RA-TLS peer check
+//! (see `ra_tls::verify_peer`), and only one verify callback can be active at
+//! a time, so the caller composes whichever checks are enabled into a single
+//! callback.
+
+use log::*;
+use openssl::{
+    hash::MessageDigest,
+    ocsp::{OcspCertId, OcspCertStatus, OcspRequest},
+    stack::Stack,
+    x509::{store::X509StoreBuilder, X509Crl, X509Ref},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// Messages accepted by the background CRL reload task.
+pub(crate) enum CrlReloadMessage {
+    Shutdown,
+}
+
+#[derive(Error, Debug)]
+pub(crate) enum RevocationError {
+    #[error("failed to read CRL file {0}: {1}")]
+    Io(String, String),
+    #[error("failed to parse CRL file {0}: {1}")]
+    Parse(String, String),
+    #[error("failed to build OCSP request: {0}")]
+    OcspRequest(String),
+    #[error("OCSP responder request to {0} failed: {1}")]
+    OcspFetch(String, String),
+    #[error("failed to parse OCSP response from {0}: {1}")]
+    OcspResponse(String, String),
+    #[error("OCSP response from {0} failed signature verification: {1}")]
+    OcspVerify(String, String),
+}
+
+/// Revoked certificate serial numbers, refreshed periodically from every file
+/// in `trusted_client_crl` by [`reload_worker`].
+#[derive(Default)]
+pub(crate) struct RevokedSerials(Mutex<HashSet<Vec<u8>>>);
+
+impl RevokedSerials {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+/// Parse every CRL in `crl_paths` (PEM) and collect the union of their
+/// revoked certificate serial numbers.
+fn load_crls(crl_paths: &[String]) -> Result<HashSet<Vec<u8>>, RevocationError> {
+    let mut serials = HashSet::new();
+    for path in crl_paths {
+        let pem = std::fs::read(path).map_err(|e| RevocationError::Io(path.clone(), e.to_string()))?;
+        let crl = X509Crl::from_pem(&pem)
+            .map_err(|e| RevocationError::Parse(path.clone(), e.to_string()))?;
+        if let Some(revoked) = crl.get_revoked() {
+            for entry in revoked {
+                if let Ok(bn) = entry.serial_number().to_bn() {
+                    serials.insert(bn.to_vec());
+                }
+            }
+        }
+    }
+    Ok(serials)
+}
+
+/// Background task: reloads `crl_paths` into `state` every `interval`,
+/// stopping on [`CrlReloadMessage::Shutdown`].
+pub(crate) async fn reload_worker(
+    crl_paths: Vec<String>,
+    interval: Duration,
+    state: Arc<RevokedSerials>,
+    mut rx: tokio::sync::mpsc::Receiver<CrlReloadMessage>,
+) {
+    loop {
+        match load_crls(&crl_paths) {
+            Ok(serials) => {
+                debug!(
+                    "Reloaded {} revoked client certificate serial(s) from {} CRL file(s)",
+                    serials.len(),
+                    crl_paths.len()
+                );
+                *state.0.lock().unwrap() = serials; //#[allow_ci]
+            }
+            Err(e) => warn!("Failed to reload 'trusted_client_crl': {e}"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            msg = rx.recv() => {
+                match msg {
+                    Some(CrlReloadMessage::Shutdown) | None => {
+                        debug!("Shutting down CRL reload task");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether `leaf`'s serial number currently appears in any loaded CRL.
+pub(crate) fn is_revoked(leaf: &X509Ref, state: &RevokedSerials) -> bool {
+    match leaf.serial_number().to_bn() {
+        Ok(bn) => state.0.lock().unwrap().contains(&bn.to_vec()), //#[allow_ci]
+        Err(e) => {
+            warn!("Failed to read client certificate serial number: {e}");
+            true
+        }
+    }
+}
+
+/// Cached OCSP status for a client certificate serial: `(good, checked_at)`.
+pub(crate) struct OcspCache(Mutex<HashMap<Vec<u8>, (bool, Instant)>>);
+
+impl OcspCache {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self(Mutex::new(HashMap::new())))
+    }
+}
+
+/// Query `responder_url` for `leaf`'s revocation status (issued by `issuer`),
+/// returning a cached answer when younger than `cache_ttl`.
+pub(crate) fn check_ocsp(
+    leaf: &X509Ref,
+    issuer: &X509Ref,
+    responder_url: &str,
+    cache_ttl: Duration,
+    cache: &OcspCache,
+) -> Result<bool, RevocationError> {
+    let serial = leaf
+        .serial_number()
+        .to_bn()
+        .map_err(|e| RevocationError::OcspRequest(format!("failed to read serial: {e}")))?
+        .to_vec();
+
+    if let Some((good, checked_at)) = cache.0.lock().unwrap().get(&serial) {
+        //#[allow_ci]
+        if checked_at.elapsed() < cache_ttl {
+            return Ok(*good);
+        }
+    }
+
+    // Unified-Identity: `OcspCertId` is consumed by `add_id`, so it's built
+    // twice here - once to send in the request, once below to look up this
+    // exact certificate's entry in the response.
+    let request_cert_id = OcspCertId::from_cert(MessageDigest::sha1(), leaf, issuer)
+        .map_err(|e| RevocationError::OcspRequest(e.to_string()))?;
+    let mut request = OcspRequest::new().map_err(|e| RevocationError::OcspRequest(e.to_string()))?;
+    request
+        .add_id(request_cert_id)
+        .map_err(|e| RevocationError::OcspRequest(e.to_string()))?;
+    let request_der = request
+        .to_der()
+        .map_err(|e| RevocationError::OcspRequest(e.to_string()))?;
+
+    let response_der = reqwest::blocking::Client::new()
+        .post(responder_url)
+        .header("Content-Type", "application/ocsp-request")
+        .body(request_der)
+        .send()
+        .and_then(|resp| resp.bytes())
+        .map_err(|e| RevocationError::OcspFetch(responder_url.to_string(), e.to_string()))?;
+
+    let response = openssl::ocsp::OcspResponse::from_der(&response_der)
+        .map_err(|e| RevocationError::OcspResponse(responder_url.to_string(), e.to_string()))?;
+    let basic = response
+        .basic()
+        .map_err(|e| RevocationError::OcspResponse(responder_url.to_string(), e.to_string()))?;
+
+    // Unified-Identity: the signer is trusted against the certificate's own
+    // issuer, i.e. the OCSP responder is expected to be the issuing CA
+    // itself (the common "CA acts as its own responder" deployment). A
+    // deployment using a dedicated, delegated OCSP responder certificate
+    // would need that cert added to this store instead/as well.
+    let mut store_builder = X509StoreBuilder::new()
+        .map_err(|e| RevocationError::OcspVerify(responder_url.to_string(), e.to_string()))?;
+    store_builder
+        .add_cert(issuer.to_owned())
+        .map_err(|e| RevocationError::OcspVerify(responder_url.to_string(), e.to_string()))?;
+    let store = store_builder.build();
+    let certs = Stack::new()
+        .map_err(|e| RevocationError::OcspVerify(responder_url.to_string(), e.to_string()))?;
+
+    let signature_ok = basic
+        .verify(&certs, &store)
+        .map_err(|e| RevocationError::OcspVerify(responder_url.to_string(), e.to_string()))?;
+    if !signature_ok {
+        return Err(RevocationError::OcspVerify(
+            responder_url.to_string(),
+            "response signature does not verify against the certificate's issuer".to_string(),
+        ));
+    }
+
+    // Unified-Identity: correlate the response back to the exact cert_id
+    // that was queried rather than trusting an aggregate status - an OCSP
+    // response can bundle entries for certificates we didn't ask about.
+    let cert_id = OcspCertId::from_cert(MessageDigest::sha1(), leaf, issuer)
+        .map_err(|e| RevocationError::OcspRequest(e.to_string()))?;
+    let good = basic
+        .find_status(&cert_id)
+        .map(|status| status.status == OcspCertStatus::GOOD)
+        .unwrap_or(false);
+
+    cache
+        .0
+        .lock()
+        .unwrap() //#[allow_ci]
+        .insert(serial, (good, Instant::now()));
+
+    Ok(good)
+}
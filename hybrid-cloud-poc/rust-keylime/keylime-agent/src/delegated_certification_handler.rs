@@ -2,47 +2,209 @@
 // Unified-Identity: Hardware Integration & Delegated Certification
 // Copyright 2024 Keylime Authors
 
-use crate::{tpm, Error as KeylimeError, QuoteData};
-use actix_web::{http, web, HttpRequest, HttpResponse, Responder};
+use crate::{
+    attestation_chain, device_attest_acme, tpm, transparency_log, x509_attest,
+    Error as KeylimeError, QuoteData, TpmMessage, TpmReply,
+};
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http, web, HttpRequest, HttpResponse, Responder,
+};
 use base64::{engine::general_purpose, Engine as _};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use futures::{stream, StreamExt};
 use keylime::json_wrapper::JsonWrapper;
 use log::*;
+use openssl::pkey::{PKey, Public};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::rc::Rc;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
 use tss_esapi::{
     handles::KeyHandle,
     structures::{Attest, Data, Signature},
     traits::Marshall,
 };
 
-// Rate limiter state: (request_count, window_start_time)
+// Unified-Identity: per-key token bucket state - current token count (as of
+// `last_refill`) plus `last_seen`, used to evict buckets for keys that have
+// gone idle so the map doesn't grow unboundedly as new source IPs/clients
+// show up. Replaces the old fixed-60s-window-per-IP counter, which both
+// leaked memory (entries were never removed) and allowed up to `2*limit`
+// requests across a window boundary.
+struct RateLimitBucket {
+    tokens: f64,
+    last_refill: Instant,
+    last_seen: Instant,
+}
+
+lazy_static::lazy_static! {
+    static ref RATE_LIMITER: Mutex<HashMap<String, RateLimitBucket>> = Mutex::new(HashMap::new());
+}
+
+// Unified-Identity: an ACME-inspired order state machine for
+// `certify_app_key`, replacing the single-shot `challenge_nonce` design
+// (a client could supply any nonce it liked, including a pre-harvested
+// one). `new_order` issues a server-generated `order_id` paired with a
+// fresh `challenge_nonce`; `certify_app_key` must reference that order and
+// binds its TPM qualifying data to the order's own nonce rather than one
+// the caller chose, so a harvested nonce can't be replayed against a
+// different order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OrderStatus {
+    Pending,
+    Processing,
+    Valid,
+    Invalid,
+}
+
+impl OrderStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Processing => "processing",
+            OrderStatus::Valid => "valid",
+            OrderStatus::Invalid => "invalid",
+        }
+    }
+}
+
+struct Order {
+    challenge_nonce: String,
+    status: OrderStatus,
+    issued_at: Instant,
+}
+
+// Unified-Identity: certification orders, keyed by `order_id`. Entries are
+// swept lazily on access rather than by a background task, matching this
+// file's `check_rate_limit` style below.
 lazy_static::lazy_static! {
-    static ref RATE_LIMITER: Mutex<HashMap<String, (u32, Instant)>> = Mutex::new(HashMap::new());
+    static ref ORDER_CACHE: Mutex<HashMap<String, Order>> = Mutex::new(HashMap::new());
+}
+
+/// Unified-Identity: generate a fresh, random order id and challenge
+/// nonce, record the order as `pending`, and evict any orders older than
+/// `ttl` while we hold the lock anyway. Returns `(order_id,
+/// challenge_nonce)`.
+fn issue_order(ttl: Duration) -> (String, String) {
+    fn random_token() -> String {
+        let mut bytes = [0u8; 32];
+        if let Err(e) = openssl::rand::rand_bytes(&mut bytes) {
+            // Extremely unlikely (OpenSSL RNG failure); fall back to a
+            // timestamp-derived value rather than panicking the handler.
+            warn!("Unified-Identity: Failed to generate random order token: {}", e);
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            bytes[..16].copy_from_slice(&nanos.to_be_bytes());
+        }
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    let order_id = random_token();
+    let challenge_nonce = random_token();
+
+    let mut cache = ORDER_CACHE.lock().unwrap();
+    let now = Instant::now();
+    cache.retain(|_, order| now.duration_since(order.issued_at) <= ttl);
+    cache.insert(
+        order_id.clone(),
+        Order {
+            challenge_nonce: challenge_nonce.clone(),
+            status: OrderStatus::Pending,
+            issued_at: now,
+        },
+    );
+    (order_id, challenge_nonce)
+}
+
+/// Unified-Identity: reject an `order_id` this agent never issued, that
+/// has expired, or that isn't `pending` (already consumed by a prior
+/// `certify_app_key` call, or already failed), transition a valid one to
+/// `processing` so it can't be raced by a concurrent call, and return its
+/// server-issued `challenge_nonce` for the caller to bind the TPM
+/// qualifying data to. Expired entries are swept opportunistically here
+/// too.
+fn begin_order(order_id: &str, ttl: Duration) -> Result<String, String> {
+    let mut cache = ORDER_CACHE.lock().unwrap();
+    let now = Instant::now();
+    cache.retain(|_, order| now.duration_since(order.issued_at) <= ttl);
+
+    match cache.get_mut(order_id) {
+        None => Err("order_id was not issued by this agent or has expired".to_string()),
+        Some(order) if order.status != OrderStatus::Pending => {
+            Err(format!(
+                "order is {} and cannot be certified again",
+                order.status.as_str()
+            ))
+        }
+        Some(order) => {
+            order.status = OrderStatus::Processing;
+            Ok(order.challenge_nonce.clone())
+        }
+    }
 }
 
-/// Check if the IP has exceeded the rate limit
-fn check_rate_limit(ip: &str, limit: u32) -> bool {
-    if limit == 0 {
+/// Unified-Identity: transition an order to its terminal state once
+/// `certify_app_key` knows whether the TPM2_Certify it gates actually
+/// succeeded. A `processing` order that expires before this is called is
+/// simply evicted by `begin_order`'s/`new_order`'s sweeps rather than ever
+/// becoming `valid` - not distinguishable from an order an attacker let
+/// lapse, which is the conservative side to fail on.
+fn finish_order(order_id: &str, status: OrderStatus) {
+    let mut cache = ORDER_CACHE.lock().unwrap();
+    if let Some(order) = cache.get_mut(order_id) {
+        order.status = status;
+    }
+}
+
+/// Unified-Identity: sliding-window token bucket rate limit, keyed by
+/// `key` (the authenticated client identity when HTTP message signature
+/// auth matched a key, otherwise the peer IP - see the `client_identity`
+/// computation in `certify_app_key`, which keeps shared-NAT callers from
+/// collectively exhausting one IP's bucket once they're individually
+/// authenticated). `burst` is both the bucket's capacity and its initial
+/// fill; `refill_per_second` is the sustained rate tokens are added back
+/// at. Buckets idle for longer than `idle_eviction` are dropped so the map
+/// stays bounded as new keys appear, rather than growing forever like the
+/// old fixed-window-per-IP counter did.
+fn check_rate_limit(
+    key: &str,
+    refill_per_second: f64,
+    burst: u32,
+    idle_eviction: Duration,
+) -> bool {
+    if burst == 0 {
         return true; // No rate limiting
     }
-    
+
     let mut limiter = RATE_LIMITER.lock().unwrap();
     let now = Instant::now();
-    
-    let entry = limiter.entry(ip.to_string()).or_insert((0, now));
-    
-    // Reset counter if more than 1 minute has passed
-    if now.duration_since(entry.1) > Duration::from_secs(60) {
-        entry.0 = 0;
-        entry.1 = now;
+
+    limiter.retain(|_, bucket| now.duration_since(bucket.last_seen) <= idle_eviction);
+
+    let bucket = limiter.entry(key.to_string()).or_insert(RateLimitBucket {
+        tokens: burst as f64,
+        last_refill: now,
+        last_seen: now,
+    });
+
+    let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * refill_per_second).min(burst as f64);
+    bucket.last_refill = now;
+    bucket.last_seen = now;
+
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        true
+    } else {
+        false
     }
-    
-    entry.0 += 1;
-    entry.0 <= limit
 }
 
 #[derive(Deserialize, Debug)]
@@ -54,8 +216,28 @@ pub struct CertifyAppKeyRequest {
     pub app_key_public: String,
     #[serde(rename = "app_key_context_path")]
     pub app_key_context_path: String,
-    #[serde(rename = "challenge_nonce")]
-    pub challenge_nonce: Option<String>,
+    // Unified-Identity: the `order_id` a prior `POST
+    // /delegated_certification/new_order` call returned; this call's TPM
+    // qualifying data is bound to that order's own server-issued
+    // `challenge_nonce`, not a client-supplied one - see `begin_order`.
+    #[serde(rename = "order_id")]
+    pub order_id: Option<String>,
+    // Unified-Identity: output encoding for the certification result -
+    // `"json"` (default) for the home-grown base64'd JSON blob, or `"cose"`
+    // for a COSE_Sign1 structure; see `cose_alg_and_raw_signature`.
+    #[serde(default)]
+    pub format: Option<String>,
+    // Unified-Identity: when true, populate `attestation_chain` with the
+    // DICE-style App Key -> AK -> EK chain; see `attestation_chain`.
+    #[serde(default)]
+    pub include_chain: bool,
+    // Unified-Identity: selects the shape of the primary certification
+    // result - `"keylime-json"` (default) for the existing
+    // `app_key_certificate`/`app_key_cose`/`x509_certificate_chain` trio
+    // of `CertifyAppKeyResponse`, or `"x509"` for a self-issued certificate carrying the same
+    // evidence in a custom extension; see `x509_attest`.
+    #[serde(default)]
+    pub cert_format: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -66,8 +248,57 @@ pub struct CertifyAppKeyResponse {
         skip_serializing_if = "Option::is_none"
     )]
     pub app_key_certificate: Option<String>,
+    // Unified-Identity: PEM certificate chain (leaf first) issued by a real
+    // CA via `device_attest_acme::obtain_certificate`, set instead of
+    // `app_key_certificate` when `device_attest_acme_config` is configured.
+    // Also used (as a single-entry "chain") for the self-issued PEM
+    // certificate built by `x509_attest` when `cert_format: "x509"`.
+    #[serde(
+        rename = "x509_certificate_chain",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub x509_certificate_chain: Option<String>,
+    // Unified-Identity: base64url-encoded COSE_Sign1 (CBOR) form of the
+    // certification result, set instead of `app_key_certificate` when the
+    // request asked for `format: "cose"`.
+    #[serde(rename = "app_key_cose", skip_serializing_if = "Option::is_none")]
+    pub app_key_cose: Option<String>,
     #[serde(rename = "agent_uuid", skip_serializing_if = "Option::is_none")]
     pub agent_uuid: Option<String>,
+    // Unified-Identity: base64url-encoded CBOR `attestation_chain` (see
+    // `attestation_chain::build_chain`), set when the request had
+    // `include_chain: true`, alongside whichever of the fields above this
+    // request's `format`/ACME config produced.
+    #[serde(
+        rename = "attestation_chain",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub attestation_chain: Option<String>,
+    // Unified-Identity: echoes the request's `cert_format` (defaulted to
+    // `"keylime-json"`), so callers that didn't set it can still tell which
+    // shape they got.
+    #[serde(rename = "cert_format")]
+    pub cert_format: String,
+    // Unified-Identity: this certification's zero-based index in the
+    // transparency log (see `transparency_log`), set only when
+    // `transparency_log` is configured on the agent.
+    #[serde(rename = "log_index", skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+    // Unified-Identity: base64-encoded sibling hashes proving `log_index`'s
+    // leaf is included under `signed_tree_head`'s root, ordered leaf to
+    // root; see `transparency_log::root_from_inclusion_proof`.
+    #[serde(rename = "inclusion_proof", skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<Vec<String>>,
+    // Unified-Identity: JSON object `{tree_size, root_hash, timestamp,
+    // signature}` - the transparency log's current root, AK-signed at the
+    // moment this certification was appended; see `build_signed_tree_head`.
+    // An auditor checks `inclusion_proof` against this to get tamper-evident
+    // proof the certification happened, without trusting the agent itself.
+    #[serde(
+        rename = "signed_tree_head",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub signed_tree_head: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
@@ -81,10 +312,22 @@ pub struct CertifyAppKeyResponse {
 /// The certificate format is a JSON object containing:
 /// - certify_data: The attestation structure (base64 encoded)
 /// - signature: The signature over the attestation (base64 encoded)
+///
+/// When `device_attest_acme_config` is set, the TPM2_Certify attestation is
+/// instead submitted as a `device-attest-01` ACME challenge response (see
+/// `device_attest_acme`), and the response carries a real
+/// `x509_certificate_chain` in place of the home-grown blob.
+///
+/// Remote (non-UDS) callers are authorized by an HTTP Message Signature over
+/// `(request-target) host date digest` when
+/// `delegated_cert_authorized_client_keys` is configured; see
+/// `verify_http_message_signature`. The body is taken as raw bytes rather
+/// than a `web::Json` extractor so the exact bytes the `Digest` header
+/// covers are available for that check, and parsed afterward.
 async fn certify_app_key(
     req: HttpRequest,
-    body: web::Json<CertifyAppKeyRequest>,
-    data: web::Data<QuoteData<'_>>,
+    body: web::Bytes,
+    data: web::Data<QuoteData>,
 ) -> impl Responder {
     // Unified-Identity: Check feature flag
     if !data.unified_identity_enabled {
@@ -95,37 +338,93 @@ async fn certify_app_key(
         ));
     }
 
-    // Extract peer IP address
-    let conn_info = req.connection_info();
-    let peer_addr = conn_info.peer_addr().unwrap_or("unknown");
-    let peer_ip = peer_addr.split(':').next().unwrap_or("unknown");
-
-    info!(
-        "Unified-Identity: Delegated certification request from {}",
-        peer_ip
-    );
-
-    // Check IP allowlist (if configured)
-    if !data.delegated_cert_allowed_ips.is_empty() {
-        if !data.delegated_cert_allowed_ips.contains(&peer_ip.to_string()) {
-            warn!("Delegated certification request from unauthorized IP: {}", peer_ip);
+    // Unified-Identity: a request arriving over the delegated-certification
+    // UDS listener carries a `PeerCred` extension (set by `uds_auth::install_peer_cred`
+    // via `on_connect`); authorize it by SO_PEERCRED instead of the IP
+    // allow-list/rate-limit machinery below, which only makes sense for the
+    // HTTPS listener's remote verifier/tenant traffic.
+    if let Some(cred) = req.conn_data::<crate::uds_auth::PeerCred>().copied() {
+        if !crate::uds_auth::is_authorized(
+            &cred,
+            &data.delegated_cert_uds_allowed_uids,
+            &data.delegated_cert_uds_allowed_gids,
+        ) {
+            warn!(
+                "Delegated certification request from unauthorized UDS peer (uid={}, gid={})",
+                cred.uid, cred.gid
+            );
             return HttpResponse::Forbidden().json(JsonWrapper::error(
                 403,
-                format!("IP {} not in allowed list", peer_ip),
+                format!("Peer uid {} / gid {} not in allowed list", cred.uid, cred.gid),
             ));
         }
-    }
+        info!(
+            "Unified-Identity: Delegated certification request from UDS peer (uid={}, gid={})",
+            cred.uid, cred.gid
+        );
+    } else {
+        // Extract peer IP address
+        let conn_info = req.connection_info();
+        let peer_addr = conn_info.peer_addr().unwrap_or("unknown");
+        let peer_ip = peer_addr.split(':').next().unwrap_or("unknown");
 
-    // Check rate limit
-    if !check_rate_limit(peer_ip, data.delegated_cert_rate_limit) {
-        warn!("Rate limit exceeded for IP: {}", peer_ip);
-        return HttpResponse::build(http::StatusCode::TOO_MANY_REQUESTS).json(JsonWrapper::error(
-            429,
-            "Rate limit exceeded. Please try again later.".to_string(),
-        ));
+        info!(
+            "Unified-Identity: Delegated certification request from {}",
+            peer_ip
+        );
+
+        // Unified-Identity: HTTP Message Signature authentication already
+        // ran in `HttpSignatureAuth` (wrapped around this route in
+        // `configure_delegated_certification_endpoints`), ahead of the IP
+        // allowlist/rate limit below since those are only coarse, spoofable
+        // additional layers once this is configured; a request only reaches
+        // here if that middleware either found no authorized keys
+        // configured (pass-through) or verified the signature and stashed
+        // the matching key's index as a `HttpSignatureAuthorizedKey`
+        // extension, which we use as the rate-limit key below instead of
+        // the raw IP so that shared-NAT clients aren't collectively
+        // throttled once they're each individually authenticated.
+        let client_identity = match req.extensions().get::<HttpSignatureAuthorizedKey>() {
+            Some(key) => format!("key:{}", key.0),
+            None => peer_ip.to_string(),
+        };
+
+        // Check IP allowlist (if configured)
+        if !data.delegated_cert_allowed_ips.is_empty() {
+            if !data.delegated_cert_allowed_ips.contains(&peer_ip.to_string()) {
+                warn!("Delegated certification request from unauthorized IP: {}", peer_ip);
+                return HttpResponse::Forbidden().json(JsonWrapper::error(
+                    403,
+                    format!("IP {} not in allowed list", peer_ip),
+                ));
+            }
+        }
+
+        // Check rate limit
+        if !check_rate_limit(
+            &client_identity,
+            data.delegated_cert_rate_limit_per_second,
+            data.delegated_cert_rate_limit_burst,
+            Duration::from_secs(data.delegated_cert_rate_limit_idle_eviction_seconds),
+        ) {
+            warn!("Rate limit exceeded for {}", client_identity);
+            return HttpResponse::build(http::StatusCode::TOO_MANY_REQUESTS).json(JsonWrapper::error(
+                429,
+                "Rate limit exceeded. Please try again later.".to_string(),
+            ));
+        }
     }
 
-    let request = body.into_inner();
+    let request: CertifyAppKeyRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Failed to parse delegated certification request body: {}", e);
+            return HttpResponse::BadRequest().json(JsonWrapper::error(
+                400,
+                "Invalid request body".to_string(),
+            ));
+        }
+    };
 
     // Validate required fields
     if request.app_key_public.is_empty() {
@@ -144,17 +443,36 @@ async fn certify_app_key(
         ));
     }
 
-    let challenge_nonce = match request.challenge_nonce.as_ref() {
-        Some(nonce) if !nonce.is_empty() => nonce.clone(),
+    let order_id = match request.order_id.as_ref() {
+        Some(order_id) if !order_id.is_empty() => order_id.clone(),
         _ => {
-            warn!("Delegated certification request missing challenge_nonce");
+            warn!("Delegated certification request missing order_id");
             return HttpResponse::BadRequest().json(JsonWrapper::error(
                 400,
-                "Missing required field: challenge_nonce".to_string(),
+                "Missing required field: order_id".to_string(),
             ));
         }
     };
 
+    // Unified-Identity: the order must be one this agent issued via
+    // POST /delegated_certification/new_order, unexpired, and still
+    // `pending` - this is what actually proves freshness; the order's own
+    // nonce (not a client-chosen one) is folded into the TPM qualifying
+    // data below so it can't be substituted after the fact either. Once
+    // accepted the order moves to `processing` so a second concurrent call
+    // with the same `order_id` can't race this one; `finish_order` below
+    // settles it `valid`/`invalid` once the outcome is known.
+    let challenge_nonce = match begin_order(
+        &order_id,
+        Duration::from_secs(data.delegated_cert_nonce_ttl_seconds),
+    ) {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            warn!("Delegated certification request rejected: {}", e);
+            return HttpResponse::BadRequest().json(JsonWrapper::error(400, e));
+        }
+    };
+
     // Validate that the context file exists
     let context_path = Path::new(&request.app_key_context_path);
     if !context_path.exists() {
@@ -171,21 +489,6 @@ async fn certify_app_key(
         ));
     }
 
-    // Get TPM context first (we'll need it for loading the App Key)
-    let mut context = data.tpmcontext.lock().unwrap(); //#[allow_ci]
-
-    // Load the App Key from the context file using tpm::Context method
-    let app_key_handle = match context.load_key_from_context_file(&request.app_key_context_path) {
-        Ok(handle) => handle,
-        Err(e) => {
-            error!("Failed to load App Key from context file: {:?}", e);
-            return HttpResponse::InternalServerError().json(JsonWrapper::error(
-                500,
-                format!("Failed to load App Key from context file: {}", e),
-            ));
-        }
-    };
-
     // Parse the App Key public key (PEM format)
     let app_key_public_pem = match request.app_key_public.strip_prefix("-----BEGIN") {
         Some(_) => request.app_key_public.clone(),
@@ -219,6 +522,7 @@ async fn certify_app_key(
                 "Failed to create qualifying data from App Key public key: {}",
                 e
             );
+            finish_order(&order_id, OrderStatus::Invalid);
             return HttpResponse::InternalServerError().json(JsonWrapper::error(
                 500,
                 format!("Failed to process App Key public key: {}", e),
@@ -226,18 +530,55 @@ async fn certify_app_key(
         }
     };
 
-    // Use the AK to certify the App Key (context is already locked above)
-    let (attest, signature) =
-        match context.certify_credential(qualifying_data, app_key_handle, data.ak_handle) {
-            Ok((attest, sig)) => (attest, sig),
-            Err(e) => {
-                error!("TPM2_Certify failed: {:?}", e);
-                return HttpResponse::InternalServerError().json(JsonWrapper::error(
-                    500,
-                    format!("TPM2_Certify failed: {}", e),
-                ));
-            }
-        };
+    // Load the App Key from its context file and use the AK to certify it, both
+    // via the dedicated TPM worker task rather than locking a shared `tpm::Context`.
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let message = TpmMessage::CertifyAppKey {
+        app_key_context_path: request.app_key_context_path.clone(),
+        qualifying_data,
+        ak_handle: data.ak_handle,
+    };
+    if let Err(e) = data.tpm_tx.send((message, reply_tx)).await {
+        error!("TPM worker channel closed: {e}");
+        finish_order(&order_id, OrderStatus::Invalid);
+        return HttpResponse::InternalServerError().json(JsonWrapper::error(
+            500,
+            "TPM worker channel closed".to_string(),
+        ));
+    }
+    let (attest, signature) = match reply_rx.await {
+        Ok(TpmReply::CertifyAppKey(Ok((attest, sig)))) => (attest, sig),
+        Ok(TpmReply::CertifyAppKey(Err(e))) => {
+            error!("TPM2_Certify failed: {:?}", e);
+            finish_order(&order_id, OrderStatus::Invalid);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                format!("TPM2_Certify failed: {}", e),
+            ));
+        }
+        Ok(_) => {
+            error!("Unexpected reply from TPM worker for CertifyAppKey request");
+            finish_order(&order_id, OrderStatus::Invalid);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Unexpected reply from TPM worker".to_string(),
+            ));
+        }
+        Err(e) => {
+            error!("TPM worker dropped reply channel: {e}");
+            finish_order(&order_id, OrderStatus::Invalid);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "TPM worker dropped reply channel".to_string(),
+            ));
+        }
+    };
+
+    // Unified-Identity: the TPM has now proven possession of the App Key
+    // over this order's own nonce - the order is settled `valid` here
+    // (ACME's equivalent of a validated challenge) so it can't be
+    // replayed, even if one of the output-formatting steps below fails.
+    finish_order(&order_id, OrderStatus::Valid);
 
     // Serialize attestation and signature to base64
     let attest_bytes = match attest.marshall() {
@@ -262,20 +603,191 @@ async fn certify_app_key(
         }
     };
 
-    // Create certificate JSON structure (matching Keylime Verifier expectations)
-    let certificate = serde_json::json!({
-        "certify_data": general_purpose::STANDARD.encode(&attest_bytes),
-        "signature": general_purpose::STANDARD.encode(&sig_bytes),
-        "challenge_nonce": challenge_nonce,
-    });
+    let app_key_public_der = match openssl::pkey::PKey::public_key_from_pem(
+        app_key_public_pem.as_bytes(),
+    )
+    .and_then(|pkey| pkey.public_key_to_der())
+    {
+        Ok(der) => der,
+        Err(e) => {
+            error!("Failed to convert App Key public key to DER: {}", e);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Failed to process App Key public key".to_string(),
+            ));
+        }
+    };
 
-    let certificate_b64 = general_purpose::STANDARD.encode(certificate.to_string().as_bytes());
+    let attestation_chain_value = if request.include_chain {
+        match build_attestation_chain(
+            &data,
+            &challenge_nonce,
+            &request.app_key_context_path,
+            &app_key_public_der,
+        )
+        .await
+        {
+            Ok(chain) => Some(chain),
+            Err(e) => {
+                error!("Failed to build attestation chain: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    format!("Failed to build attestation chain: {}", e),
+                ));
+            }
+        }
+    } else {
+        None
+    };
 
-    let response = CertifyAppKeyResponse {
-        result: "SUCCESS".to_string(),
-        app_key_certificate: Some(certificate_b64),
-        agent_uuid: Some(data.agent_uuid.clone()),
-        error: None,
+    let (log_index_value, inclusion_proof_value, signed_tree_head_value) =
+        match append_transparency_log_entry(&data, &attest_bytes, &sig_bytes, &challenge_nonce)
+            .await
+        {
+            Ok(Some((index, proof, sth))) => (Some(index), Some(proof), Some(sth)),
+            Ok(None) => (None, None, None),
+            Err(e) => {
+                error!("Failed to record transparency log entry: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    format!("Failed to record transparency log entry: {}", e),
+                ));
+            }
+        };
+
+    let cert_format_value = request
+        .cert_format
+        .clone()
+        .unwrap_or_else(|| "keylime-json".to_string());
+
+    let response = if cert_format_value == "x509" {
+        match build_x509_certificate(
+            &data,
+            &app_key_public_der,
+            &attest_bytes,
+            &sig_bytes,
+            &challenge_nonce,
+        )
+        .await
+        {
+            Ok(cert_pem) => CertifyAppKeyResponse {
+                result: "SUCCESS".to_string(),
+                app_key_certificate: None,
+                x509_certificate_chain: Some(cert_pem),
+                app_key_cose: None,
+                agent_uuid: Some(data.agent_uuid.clone()),
+                attestation_chain: attestation_chain_value,
+                cert_format: cert_format_value,
+                log_index: log_index_value,
+                inclusion_proof: inclusion_proof_value,
+                signed_tree_head: signed_tree_head_value,
+                error: None,
+            },
+            Err(e) => {
+                error!("Failed to build X.509 App Key attestation certificate: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    format!("Failed to build X.509 certificate: {}", e),
+                ));
+            }
+        }
+    } else if let Some(acme_cfg) = data.device_attest_acme_config.as_ref() {
+        match device_attest_acme::obtain_certificate(
+            acme_cfg,
+            &data.agent_uuid,
+            &app_key_public_pem,
+            &app_key_public_der,
+            &attest_bytes,
+            &sig_bytes,
+        )
+        .await
+        {
+            Ok(chain_pem) => CertifyAppKeyResponse {
+                result: "SUCCESS".to_string(),
+                app_key_certificate: None,
+                x509_certificate_chain: Some(chain_pem),
+                app_key_cose: None,
+                agent_uuid: Some(data.agent_uuid.clone()),
+                attestation_chain: attestation_chain_value,
+                cert_format: cert_format_value,
+                log_index: log_index_value,
+                inclusion_proof: inclusion_proof_value,
+                signed_tree_head: signed_tree_head_value,
+                error: None,
+            },
+            Err(e) => {
+                error!("device-attest-01 ACME certification failed: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    format!("ACME certification failed: {}", e),
+                ));
+            }
+        }
+    } else if request.format.as_deref() == Some("cose") {
+        let (cose_alg, raw_signature) = match cose_alg_and_raw_signature(&sig_bytes) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Cannot emit COSE output for this TPM signature scheme: {}", e);
+                return HttpResponse::BadRequest().json(JsonWrapper::error(
+                    400,
+                    format!("Unsupported TPM signature scheme for COSE output: {}", e),
+                ));
+            }
+        };
+
+        let cose_bytes = match build_cose_sign1(
+            cose_alg,
+            &challenge_nonce,
+            &data.agent_uuid,
+            &attest_bytes,
+            &raw_signature,
+        ) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to build COSE_Sign1 structure: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    "Failed to build COSE_Sign1 structure".to_string(),
+                ));
+            }
+        };
+
+        CertifyAppKeyResponse {
+            result: "SUCCESS".to_string(),
+            app_key_certificate: None,
+            x509_certificate_chain: None,
+            app_key_cose: Some(general_purpose::URL_SAFE_NO_PAD.encode(cose_bytes)),
+            agent_uuid: Some(data.agent_uuid.clone()),
+            attestation_chain: attestation_chain_value,
+            cert_format: cert_format_value,
+            log_index: log_index_value,
+            inclusion_proof: inclusion_proof_value,
+            signed_tree_head: signed_tree_head_value,
+            error: None,
+        }
+    } else {
+        // Create certificate JSON structure (matching Keylime Verifier expectations)
+        let certificate = serde_json::json!({
+            "certify_data": general_purpose::STANDARD.encode(&attest_bytes),
+            "signature": general_purpose::STANDARD.encode(&sig_bytes),
+            "challenge_nonce": challenge_nonce,
+        });
+
+        let certificate_b64 = general_purpose::STANDARD.encode(certificate.to_string().as_bytes());
+
+        CertifyAppKeyResponse {
+            result: "SUCCESS".to_string(),
+            app_key_certificate: Some(certificate_b64),
+            x509_certificate_chain: None,
+            app_key_cose: None,
+            agent_uuid: Some(data.agent_uuid.clone()),
+            attestation_chain: attestation_chain_value,
+            cert_format: cert_format_value,
+            log_index: log_index_value,
+            inclusion_proof: inclusion_proof_value,
+            signed_tree_head: signed_tree_head_value,
+            error: None,
+        }
     };
 
     info!(
@@ -286,6 +798,246 @@ async fn certify_app_key(
     HttpResponse::Ok().json(response)
 }
 
+/// Unified-Identity: split a `Signature` header's `key="value"` fields into
+/// a map. Doesn't unescape backslash-escaped quotes within a value - none of
+/// the fields this endpoint reads (`keyId`, `algorithm`, `signature`) need
+/// one.
+fn parse_signature_header(value: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for part in value.split(',') {
+        if let Some((key, val)) = part.split_once('=') {
+            fields.insert(
+                key.trim().to_string(),
+                val.trim().trim_matches('"').to_string(),
+            );
+        }
+    }
+    fields
+}
+
+/// Unified-Identity: verify an HTTP Message Signature (draft-cavage-http-signatures
+/// style) over the fixed header set `(request-target) host date digest`, for
+/// the remote-caller path of `certify_app_key`. Checks the `Digest` header
+/// against a SHA-256 of `body` and the `Date` header against `skew_seconds`
+/// for replay protection, then tries the `signature` field against each of
+/// `authorized_keys` in turn - the `keyId` field is an opaque client-chosen
+/// label, not looked up against anything, so the signer is only identified
+/// by whichever key successfully verifies it. Returns that key's index into
+/// `authorized_keys` on success, for use as an authenticated rate-limit
+/// identity (see `check_rate_limit`).
+fn verify_http_message_signature(
+    req: &HttpRequest,
+    body: &[u8],
+    authorized_keys: &[PKey<Public>],
+    skew_seconds: u64,
+) -> Result<usize, String> {
+    use openssl::hash::{Hasher, MessageDigest};
+    use openssl::sign::Verifier;
+
+    let signature_header = req
+        .headers()
+        .get("Signature")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Signature header".to_string())?;
+    let digest_header = req
+        .headers()
+        .get("Digest")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Digest header".to_string())?;
+    let host_header = req
+        .headers()
+        .get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Host header".to_string())?;
+    let date_header = req
+        .headers()
+        .get(http::header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| "Missing Date header".to_string())?;
+
+    let (digest_alg, digest_value) = digest_header
+        .split_once('=')
+        .ok_or_else(|| "Malformed Digest header".to_string())?;
+    if !digest_alg.eq_ignore_ascii_case("sha-256") {
+        return Err(format!("Unsupported Digest algorithm: {digest_alg}"));
+    }
+    let mut hasher = Hasher::new(MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create body digest hasher: {e}"))?;
+    hasher
+        .update(body)
+        .map_err(|e| format!("Failed to hash request body: {e}"))?;
+    let body_digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finalize body digest: {e}"))?;
+    if general_purpose::STANDARD.encode(&body_digest) != digest_value {
+        return Err("Digest header does not match the request body".to_string());
+    }
+
+    let request_time =
+        httpdate::parse_http_date(date_header).map_err(|e| format!("Invalid Date header: {e}"))?;
+    let request_secs = request_time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|_| "Date header predates the epoch".to_string())?
+        .as_secs();
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|_| "System clock error".to_string())?
+        .as_secs();
+    let skew = request_secs.abs_diff(now_secs);
+    if skew > skew_seconds {
+        return Err(format!(
+            "Date header outside allowed skew window ({skew}s > {skew_seconds}s)"
+        ));
+    }
+
+    let fields = parse_signature_header(signature_header);
+    let signature_b64 = fields
+        .get("signature")
+        .ok_or_else(|| "Signature header missing signature field".to_string())?;
+    let signature_bytes = general_purpose::STANDARD
+        .decode(signature_b64)
+        .map_err(|e| format!("Invalid signature encoding: {e}"))?;
+
+    let signing_string = format!(
+        "(request-target): {} {}\nhost: {}\ndate: {}\ndigest: {}",
+        req.method().as_str().to_lowercase(),
+        req.path(),
+        host_header,
+        date_header,
+        digest_header
+    );
+
+    for (index, key) in authorized_keys.iter().enumerate() {
+        let mut verifier = Verifier::new(MessageDigest::sha256(), key)
+            .map_err(|e| format!("Failed to create signature verifier: {e}"))?;
+        verifier
+            .update(signing_string.as_bytes())
+            .map_err(|e| format!("Failed to hash signing string: {e}"))?;
+        if verifier.verify(&signature_bytes).unwrap_or(false) {
+            return Ok(index);
+        }
+    }
+
+    Err("HTTP message signature verification failed".to_string())
+}
+
+/// Unified-Identity: map the signature scheme the TPM actually used (read
+/// straight out of the marshalled `TPMT_SIGNATURE`, rather than trusting a
+/// caller-supplied hint) to an IANA COSE algorithm identifier, and extract
+/// the bare signature value COSE expects - ECDSA as fixed-width `r || s`,
+/// RSA as the plain signature bytes - in place of the TPM wire structure's
+/// length-prefixed fields. Schemes with no standard COSE algorithm (EC-DAA,
+/// EC-Schnorr, HMAC, ...) are rejected rather than guessed at.
+fn cose_alg_and_raw_signature(marshalled_signature: &[u8]) -> Result<(i128, Vec<u8>), String> {
+    const TPM_ALG_RSASSA: u16 = 0x0014;
+    const TPM_ALG_RSAPSS: u16 = 0x0016;
+    const TPM_ALG_ECDSA: u16 = 0x0018;
+    const TPM_ALG_SHA256: u16 = 0x000B;
+    const TPM_ALG_SHA384: u16 = 0x000C;
+    const TPM_ALG_SHA512: u16 = 0x000D;
+
+    fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+        bytes
+            .get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| "truncated TPMT_SIGNATURE".to_string())
+    }
+
+    let sig_alg = read_u16(marshalled_signature, 0)?;
+    let hash_alg = read_u16(marshalled_signature, 2)?;
+
+    match sig_alg {
+        TPM_ALG_RSASSA | TPM_ALG_RSAPSS => {
+            let size = read_u16(marshalled_signature, 4)? as usize;
+            let sig = marshalled_signature
+                .get(6..6 + size)
+                .ok_or_else(|| "truncated TPMS_SIGNATURE_RSA".to_string())?
+                .to_vec();
+            let alg = match (sig_alg, hash_alg) {
+                (TPM_ALG_RSASSA, TPM_ALG_SHA256) => -257, // RS256
+                (TPM_ALG_RSAPSS, TPM_ALG_SHA256) => -37,  // PS256
+                _ => return Err("unsupported RSA hash algorithm for COSE output".to_string()),
+            };
+            Ok((alg, sig))
+        }
+        TPM_ALG_ECDSA => {
+            let r_size = read_u16(marshalled_signature, 4)? as usize;
+            let r_start = 6;
+            let r = marshalled_signature
+                .get(r_start..r_start + r_size)
+                .ok_or_else(|| "truncated TPMS_SIGNATURE_ECC (r)".to_string())?;
+            let s_size_offset = r_start + r_size;
+            let s_size = read_u16(marshalled_signature, s_size_offset)? as usize;
+            let s_start = s_size_offset + 2;
+            let s = marshalled_signature
+                .get(s_start..s_start + s_size)
+                .ok_or_else(|| "truncated TPMS_SIGNATURE_ECC (s)".to_string())?;
+
+            let (alg, coord_len) = match hash_alg {
+                TPM_ALG_SHA256 => (-7, 32),  // ES256, P-256
+                TPM_ALG_SHA384 => (-35, 48), // ES384, P-384
+                TPM_ALG_SHA512 => (-36, 66), // ES512, P-521
+                _ => return Err("unsupported ECDSA hash algorithm for COSE output".to_string()),
+            };
+
+            // COSE/JOSE ECDSA signatures are fixed-width r || s, zero-padded
+            // to the curve's coordinate size, not the TPM's length-prefixed
+            // variable-width encoding.
+            let mut raw = vec![0u8; coord_len * 2];
+            raw[coord_len - r.len()..coord_len].copy_from_slice(r);
+            raw[coord_len * 2 - s.len()..].copy_from_slice(s);
+            Ok((alg, raw))
+        }
+        _ => Err("TPM signature scheme has no COSE equivalent".to_string()),
+    }
+}
+
+/// Unified-Identity: build a COSE_Sign1 structure (RFC 8152 section 4.2),
+/// simplified to an untagged 4-element CBOR array: a protected header
+/// carrying only the `alg` label, an unprotected header carrying the
+/// challenge nonce and agent UUID, the marshalled `Attest` as the payload,
+/// and the bare signature value from [`cose_alg_and_raw_signature`].
+fn build_cose_sign1(
+    alg: i128,
+    challenge_nonce: &str,
+    agent_uuid: &str,
+    payload: &[u8],
+    signature: &[u8],
+) -> Result<Vec<u8>, String> {
+    use serde_cbor::Value;
+
+    let protected_header = Value::Map(
+        [(Value::Integer(1), Value::Integer(alg))]
+            .into_iter()
+            .collect(),
+    );
+    let protected_bytes = serde_cbor::to_vec(&protected_header).map_err(|e| e.to_string())?;
+
+    let unprotected_header = Value::Map(
+        [
+            (
+                Value::Text("challenge_nonce".to_string()),
+                Value::Text(challenge_nonce.to_string()),
+            ),
+            (
+                Value::Text("agent_uuid".to_string()),
+                Value::Text(agent_uuid.to_string()),
+            ),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let cose_sign1 = Value::Array(vec![
+        Value::Bytes(protected_bytes),
+        unprotected_header,
+        Value::Bytes(payload.to_vec()),
+        Value::Bytes(signature.to_vec()),
+    ]);
+
+    serde_cbor::to_vec(&cose_sign1).map_err(|e| e.to_string())
+}
+
 /// Create qualifying data (hash) from PEM public key and challenge nonce
 fn create_qualifying_data(pem: &str, challenge_nonce: &str) -> Result<Data, String> {
     use openssl::hash::{Hasher, MessageDigest};
@@ -327,10 +1079,930 @@ fn create_qualifying_data(pem: &str, challenge_nonce: &str) -> Result<Data, Stri
         .map_err(|e| format!("Failed to create TPM Data from combined hash: {}", e))
 }
 
+/// Unified-Identity: assemble the `attestation_chain` for `certify_app_key`
+/// when the request sets `include_chain: true`. Independent of the
+/// App Key attestation the caller already obtained (which stays bound to
+/// `challenge_nonce` for the existing json/cose/ACME outputs): this issues
+/// two more TPM2_Certify calls of its own so the chain's qualifying data can
+/// follow [`attestation_chain::next_qualifying_data`]'s continuity rule -
+/// read the EK certificate (the chain root), certify it with the AK (the
+/// EK -> AK link), then certify the App Key again with qualifying data
+/// chained from that link's payload (the AK -> App Key link).
+async fn build_attestation_chain(
+    data: &QuoteData,
+    challenge_nonce: &str,
+    app_key_context_path: &str,
+    app_key_public_der: &[u8],
+) -> Result<String, String> {
+    use openssl::hash::{Hasher, MessageDigest};
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    data.tpm_tx
+        .send((TpmMessage::ReadEkCertificate, reply_tx))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    let ek_certificate_der = match reply_rx.await {
+        Ok(TpmReply::ReadEkCertificate(Ok(cert))) => cert,
+        Ok(TpmReply::ReadEkCertificate(Err(e))) => {
+            return Err(format!("Failed to read EK certificate: {e}"))
+        }
+        Ok(_) => {
+            return Err(
+                "Unexpected reply from TPM worker for ReadEkCertificate request".to_string(),
+            )
+        }
+        Err(e) => return Err(format!("TPM worker dropped reply channel: {e}")),
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    data.tpm_tx
+        .send((
+            TpmMessage::ReadPublic {
+                handle: data.ak_handle,
+            },
+            reply_tx,
+        ))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    let ak_public = match reply_rx.await {
+        Ok(TpmReply::ReadPublic(Ok(public))) => public,
+        Ok(TpmReply::ReadPublic(Err(e))) => return Err(format!("Failed to read AK public key: {e}")),
+        Ok(_) => {
+            return Err("Unexpected reply from TPM worker for ReadPublic request".to_string())
+        }
+        Err(e) => return Err(format!("TPM worker dropped reply channel: {e}")),
+    };
+    // Unified-Identity: as with `ra_tls::build_quote_extension`'s AK public
+    // area, this is the marshalled `TPM2B_PUBLIC`, not an ASN.1
+    // SubjectPublicKeyInfo - the chain's verifier understands TPM public
+    // areas already (it has to, to check the `Attest` signatures), so no
+    // conversion is needed for the entries that come from this agent's own
+    // TPM (the EK certificate root entry remains real DER, read from NV).
+    let ak_public_der = ak_public
+        .marshall()
+        .map_err(|e| format!("Failed to serialize AK public area: {e:?}"))?;
+
+    // EK -> AK link: TPM2_Certify the EK's own public area with the AK as
+    // signer, qualifying data binding in the EK certificate and the
+    // challenge nonce for freshness.
+    let mut hasher = Hasher::new(MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create hasher: {e}"))?;
+    hasher
+        .update(&ek_certificate_der)
+        .map_err(|e| format!("Failed to hash EK certificate: {e}"))?;
+    hasher
+        .update(challenge_nonce.as_bytes())
+        .map_err(|e| format!("Failed to hash challenge nonce: {e}"))?;
+    let root_qualifying_data = Data::try_from(
+        hasher
+            .finish()
+            .map_err(|e| format!("Failed to finish hash: {e}"))?
+            .as_ref(),
+    )
+    .map_err(|e| format!("Failed to create TPM Data from EK certificate hash: {e}"))?;
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    data.tpm_tx
+        .send((
+            TpmMessage::CertifyObject {
+                object_handle: data.ek_handle,
+                qualifying_data: root_qualifying_data,
+                signing_handle: data.ak_handle,
+            },
+            reply_tx,
+        ))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    let (ak_attest, ak_sig) = match reply_rx.await {
+        Ok(TpmReply::CertifyObject(Ok(v))) => v,
+        Ok(TpmReply::CertifyObject(Err(e))) => {
+            return Err(format!("TPM2_Certify (EK -> AK link) failed: {e}"))
+        }
+        Ok(_) => {
+            return Err("Unexpected reply from TPM worker for CertifyObject request".to_string())
+        }
+        Err(e) => return Err(format!("TPM worker dropped reply channel: {e}")),
+    };
+    let ak_attest_bytes = ak_attest
+        .marshall()
+        .map_err(|e| format!("Failed to serialize AK attestation: {e:?}"))?;
+    let ak_sig_bytes = ak_sig
+        .marshall()
+        .map_err(|e| format!("Failed to serialize AK signature: {e:?}"))?;
+    let (ak_cose_alg, ak_raw_signature) = cose_alg_and_raw_signature(&ak_sig_bytes)?;
+
+    // AK -> App Key link: re-certify the App Key, this time with qualifying
+    // data chained from the EK -> AK link's payload, so the chain is
+    // cryptographically continuous link to link.
+    let app_key_qualifying_data =
+        attestation_chain::next_qualifying_data(&ak_attest_bytes, app_key_public_der)?;
+    let (reply_tx, reply_rx) = oneshot::channel();
+    data.tpm_tx
+        .send((
+            TpmMessage::CertifyAppKey {
+                app_key_context_path: app_key_context_path.to_string(),
+                qualifying_data: app_key_qualifying_data,
+                ak_handle: data.ak_handle,
+            },
+            reply_tx,
+        ))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    let (app_key_attest, app_key_sig) = match reply_rx.await {
+        Ok(TpmReply::CertifyAppKey(Ok(v))) => v,
+        Ok(TpmReply::CertifyAppKey(Err(e))) => {
+            return Err(format!("TPM2_Certify (AK -> App Key link) failed: {e}"))
+        }
+        Ok(_) => {
+            return Err("Unexpected reply from TPM worker for CertifyAppKey request".to_string())
+        }
+        Err(e) => return Err(format!("TPM worker dropped reply channel: {e}")),
+    };
+    let app_key_attest_bytes = app_key_attest
+        .marshall()
+        .map_err(|e| format!("Failed to serialize App Key attestation: {e:?}"))?;
+    let app_key_sig_bytes = app_key_sig
+        .marshall()
+        .map_err(|e| format!("Failed to serialize App Key signature: {e:?}"))?;
+    let (app_key_cose_alg, app_key_raw_signature) = cose_alg_and_raw_signature(&app_key_sig_bytes)?;
+
+    attestation_chain::build_chain(
+        &ek_certificate_der,
+        &[
+            attestation_chain::ChainLinkInput {
+                subject_public_key_der: ak_public_der,
+                cose_alg: ak_cose_alg,
+                attest_bytes: ak_attest_bytes,
+                raw_signature: ak_raw_signature,
+            },
+            attestation_chain::ChainLinkInput {
+                subject_public_key_der: app_key_public_der.to_vec(),
+                cose_alg: app_key_cose_alg,
+                attest_bytes: app_key_attest_bytes,
+                raw_signature: app_key_raw_signature,
+            },
+        ],
+    )
+    .map(|bytes| general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Unified-Identity: sign an arbitrary digest with the AK via the TPM worker,
+/// returning the marshalled `Signature` bytes. Shared by the two signing
+/// passes `build_x509_certificate` needs (see below).
+async fn sign_with_ak(data: &QuoteData, digest: Vec<u8>) -> Result<Vec<u8>, String> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    data.tpm_tx
+        .send((
+            TpmMessage::SignDigest {
+                key_handle: data.ak_handle,
+                digest,
+                hash_alg: data.hash_alg,
+                sign_alg: data.sign_alg,
+            },
+            reply_tx,
+        ))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    match reply_rx.await {
+        Ok(TpmReply::SignDigest(Ok(signature))) => signature
+            .marshall()
+            .map_err(|e| format!("Failed to serialize signature: {e:?}")),
+        Ok(TpmReply::SignDigest(Err(e))) => Err(format!("TPM2_Sign failed: {e}")),
+        Ok(_) => Err("Unexpected reply from TPM worker for SignDigest request".to_string()),
+        Err(e) => Err(format!("TPM worker dropped reply channel: {e}")),
+    }
+}
+
+/// Unified-Identity: build the self-issued X.509 certificate for
+/// `certify_app_key`'s `cert_format: "x509"` output (see `x509_attest`).
+///
+/// The `tbsCertificate`'s `signature` field must name the same algorithm as
+/// the certificate's actual outer signature, but that algorithm (which of
+/// ECDSA/RSA, which hash) is only knowable for certain from the TPM's own
+/// marshalled `TPMT_SIGNATURE` - not by guessing at the agent's configured
+/// `hash_alg`/`sign_alg` in advance. So this signs a throwaway probe digest
+/// first purely to learn the scheme via `cose_alg_and_raw_signature`, then
+/// builds and signs the real `tbsCertificate` once that's known.
+async fn build_x509_certificate(
+    data: &QuoteData,
+    app_key_public_der: &[u8],
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+    challenge_nonce: &str,
+) -> Result<String, String> {
+    let probe_sig_bytes = sign_with_ak(data, vec![0u8; 32]).await?;
+    let (cose_alg, _) = cose_alg_and_raw_signature(&probe_sig_bytes)?;
+    let (algorithm_identifier, is_ecdsa) = x509_attest::x509_signature_algorithm(cose_alg)?;
+
+    let extension_value =
+        x509_attest::build_attestation_extension_value(attest_bytes, sig_bytes, challenge_nonce);
+
+    let mut serial = vec![0u8; 16];
+    openssl::rand::rand_bytes(&mut serial).map_err(|e| format!("Failed to generate serial number: {e}"))?;
+
+    let not_before = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("System clock is before the Unix epoch: {e}"))?
+        .as_secs() as i64;
+    let not_after = not_before + 365 * 24 * 60 * 60;
+
+    let tbs_der = x509_attest::build_tbs_certificate(
+        &serial,
+        &data.agent_uuid,
+        app_key_public_der,
+        not_before,
+        not_after,
+        &algorithm_identifier,
+        &extension_value,
+    )?;
+
+    let mut hasher = openssl::hash::Hasher::new(openssl::hash::MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create hasher: {e}"))?;
+    hasher
+        .update(&tbs_der)
+        .map_err(|e| format!("Failed to hash tbsCertificate: {e}"))?;
+    let tbs_digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finish tbsCertificate hash: {e}"))?
+        .to_vec();
+
+    let final_sig_bytes = sign_with_ak(data, tbs_digest).await?;
+    let (final_cose_alg, raw_signature) = cose_alg_and_raw_signature(&final_sig_bytes)?;
+    if final_cose_alg != cose_alg {
+        return Err("AK signature scheme changed between probe and final signing".to_string());
+    }
+    let signature_value = x509_attest::signature_value_der(is_ecdsa, &raw_signature)?;
+
+    let cert_der = x509_attest::assemble_certificate(&tbs_der, &algorithm_identifier, &signature_value);
+
+    let pem_bytes = openssl::x509::X509::from_der(&cert_der)
+        .and_then(|cert| cert.to_pem())
+        .map_err(|e| format!("Failed to parse/PEM-encode the assembled certificate: {e}"))?;
+    String::from_utf8(pem_bytes).map_err(|e| format!("Certificate PEM is not valid UTF-8: {e}"))
+}
+
+/// Unified-Identity: sign a transparency-log "signed tree head" - `tree_size
+/// || root_hash || timestamp`, hashed then signed with the AK via
+/// `sign_with_ak`, the same primitive `build_x509_certificate` uses - and
+/// return it as the JSON string stored in
+/// `CertifyAppKeyResponse::signed_tree_head`. `timestamp` is a Unix second
+/// count, the log's own `tree_size`/`root_hash` at the moment of signing.
+async fn build_signed_tree_head(
+    data: &QuoteData,
+    tree_size: u64,
+    root_hash: [u8; 32],
+) -> Result<String, String> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| format!("System clock is before the Unix epoch: {e}"))?
+        .as_secs();
+
+    let mut signable = Vec::with_capacity(8 + 32 + 8);
+    signable.extend_from_slice(&tree_size.to_be_bytes());
+    signable.extend_from_slice(&root_hash);
+    signable.extend_from_slice(&timestamp.to_be_bytes());
+
+    let mut hasher = openssl::hash::Hasher::new(openssl::hash::MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create hasher: {e}"))?;
+    hasher
+        .update(&signable)
+        .map_err(|e| format!("Failed to hash signed tree head: {e}"))?;
+    let digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finish signed tree head hash: {e}"))?
+        .to_vec();
+
+    let sig_bytes = sign_with_ak(data, digest).await?;
+
+    let signed_tree_head = serde_json::json!({
+        "tree_size": tree_size,
+        "root_hash": general_purpose::STANDARD.encode(root_hash),
+        "timestamp": timestamp,
+        "signature": general_purpose::STANDARD.encode(&sig_bytes),
+    });
+    Ok(signed_tree_head.to_string())
+}
+
+/// Unified-Identity: append this certification to the transparency log
+/// (see `transparency_log`) when one is configured, returning the new
+/// entry's log index, its inclusion proof (base64 sibling hashes, leaf to
+/// root), and a freshly AK-signed tree head. Returns `Ok(None)` - not an
+/// error - when no log is configured, leaving `certify_app_key`'s
+/// transparency fields unset, as before this feature existed.
+async fn append_transparency_log_entry(
+    data: &QuoteData,
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+    challenge_nonce: &str,
+) -> Result<Option<(u64, Vec<String>, String)>, String> {
+    let Some(log) = data.transparency_log.as_ref() else {
+        return Ok(None);
+    };
+
+    let mut leaf_data = Vec::with_capacity(
+        attest_bytes.len() + sig_bytes.len() + challenge_nonce.len() + data.agent_uuid.len(),
+    );
+    leaf_data.extend_from_slice(attest_bytes);
+    leaf_data.extend_from_slice(sig_bytes);
+    leaf_data.extend_from_slice(challenge_nonce.as_bytes());
+    leaf_data.extend_from_slice(data.agent_uuid.as_bytes());
+
+    let (log_index, tree_size, root_hash, proof) = {
+        let mut log = log.lock().unwrap();
+        let log_index = log
+            .append(&leaf_data)
+            .map_err(|e| format!("Failed to append transparency log leaf: {e}"))?;
+        let proof = log
+            .inclusion_proof(log_index)
+            .map_err(|e| format!("Failed to build inclusion proof: {e}"))?;
+        (log_index, log.tree_size(), log.root(), proof)
+    };
+
+    let signed_tree_head = build_signed_tree_head(data, tree_size, root_hash).await?;
+    let proof_b64 = proof
+        .iter()
+        .map(|sibling| general_purpose::STANDARD.encode(sibling))
+        .collect();
+    Ok(Some((log_index, proof_b64, signed_tree_head)))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VerifyInclusionRequest {
+    // Unified-Identity: the certification evidence this leaf was built
+    // from - `certify_app_key`'s `certify_data`/`attest_bytes` and
+    // `signature`/`sig_bytes`, base64 (standard) encoded as
+    // `certify_app_key`'s own `keylime-json` output already encodes them,
+    // and `challenge_nonce` as the plain string it returns.
+    pub attest_data: String,
+    pub signature: String,
+    pub challenge_nonce: String,
+    pub agent_uuid: String,
+    pub log_index: u64,
+    // Unified-Identity: base64 sibling hashes from `CertifyAppKeyResponse`,
+    // leaf to root.
+    pub inclusion_proof: Vec<String>,
+    // Unified-Identity: the `signed_tree_head` JSON string
+    // `certify_app_key` returned alongside this entry.
+    pub signed_tree_head: String,
+    // Unified-Identity: PEM-encoded AK public key to verify
+    // `signed_tree_head`'s signature against. Omitted, the endpoint only
+    // checks Merkle consistency (that `inclusion_proof` actually proves
+    // `log_index`'s leaf under the root named in `signed_tree_head`) and
+    // reports the signature as unverified - an auditor who doesn't supply
+    // their own independently-trusted AK key gets a weaker guarantee than
+    // one who does, the same trust model `ra_tls`'s peer verification uses.
+    #[serde(default)]
+    pub ak_public_pem: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VerifyInclusionResponse {
+    pub result: String,
+    pub consistent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Unified-Identity: recompute the Merkle root a supplied leaf + inclusion
+/// proof imply, and check it against the root embedded in a supplied
+/// `signed_tree_head` - letting an auditor confirm tamper-evident proof
+/// that a given App Key certification happened, without needing live
+/// access to the agent's own transparency log (see `transparency_log`).
+async fn verify_inclusion(
+    data: web::Data<QuoteData>,
+    request: web::Json<VerifyInclusionRequest>,
+) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: Inclusion verification request received but feature flag is disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified-Identity feature is disabled. Enable unified_identity_enabled in agent config.".to_string(),
+        ));
+    }
+
+    let attest_bytes = match general_purpose::STANDARD.decode(&request.attest_data) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(JsonWrapper::error(400, format!("Invalid attest_data encoding: {e}")))
+        }
+    };
+    let sig_bytes = match general_purpose::STANDARD.decode(&request.signature) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(JsonWrapper::error(400, format!("Invalid signature encoding: {e}")))
+        }
+    };
+
+    let mut leaf_data = Vec::with_capacity(
+        attest_bytes.len() + sig_bytes.len() + request.challenge_nonce.len() + request.agent_uuid.len(),
+    );
+    leaf_data.extend_from_slice(&attest_bytes);
+    leaf_data.extend_from_slice(&sig_bytes);
+    leaf_data.extend_from_slice(request.challenge_nonce.as_bytes());
+    leaf_data.extend_from_slice(request.agent_uuid.as_bytes());
+    let leaf = transparency_log::leaf_hash(&leaf_data);
+
+    let proof: Vec<[u8; 32]> = match request
+        .inclusion_proof
+        .iter()
+        .map(|sibling| {
+            let bytes = general_purpose::STANDARD
+                .decode(sibling)
+                .map_err(|e| format!("Invalid inclusion_proof entry encoding: {e}"))?;
+            <[u8; 32]>::try_from(bytes.as_slice())
+                .map_err(|_| "inclusion_proof entry is not 32 bytes".to_string())
+        })
+        .collect()
+    {
+        Ok(proof) => proof,
+        Err(e) => return HttpResponse::BadRequest().json(JsonWrapper::error(400, e)),
+    };
+
+    let sth: serde_json::Value = match serde_json::from_str(&request.signed_tree_head) {
+        Ok(v) => v,
+        Err(e) => {
+            return HttpResponse::BadRequest()
+                .json(JsonWrapper::error(400, format!("Invalid signed_tree_head: {e}")))
+        }
+    };
+    let (tree_size, root_hash, timestamp, sth_signature) = match (
+        sth.get("tree_size").and_then(|v| v.as_u64()),
+        sth.get("root_hash").and_then(|v| v.as_str()),
+        sth.get("timestamp").and_then(|v| v.as_u64()),
+        sth.get("signature").and_then(|v| v.as_str()),
+    ) {
+        (Some(tree_size), Some(root_hash), Some(timestamp), Some(signature)) => {
+            (tree_size, root_hash, timestamp, signature)
+        }
+        _ => {
+            return HttpResponse::BadRequest().json(JsonWrapper::error(
+                400,
+                "signed_tree_head is missing tree_size/root_hash/timestamp/signature".to_string(),
+            ))
+        }
+    };
+    let root_hash_bytes = match general_purpose::STANDARD
+        .decode(root_hash)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes.as_slice()).ok())
+    {
+        Some(bytes) => bytes,
+        None => {
+            return HttpResponse::BadRequest().json(JsonWrapper::error(
+                400,
+                "signed_tree_head.root_hash is not 32 bytes".to_string(),
+            ))
+        }
+    };
+
+    let consistent = match transparency_log::root_from_inclusion_proof(
+        leaf,
+        request.log_index,
+        tree_size,
+        &proof,
+    ) {
+        Ok(computed_root) => computed_root == root_hash_bytes,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(JsonWrapper::error(400, e));
+        }
+    };
+
+    let signature_verified = match request.ak_public_pem.as_ref() {
+        None => None,
+        Some(pem) => {
+            let mut signable = Vec::with_capacity(8 + 32 + 8);
+            signable.extend_from_slice(&tree_size.to_be_bytes());
+            signable.extend_from_slice(&root_hash_bytes);
+            signable.extend_from_slice(&timestamp.to_be_bytes());
+            match verify_signed_tree_head_signature(pem, &signable, sth_signature) {
+                Ok(verified) => Some(verified),
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(JsonWrapper::error(
+                        400,
+                        format!("Failed to verify signed_tree_head signature: {e}"),
+                    ))
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok().json(VerifyInclusionResponse {
+        result: "SUCCESS".to_string(),
+        consistent,
+        signature_verified,
+        error: None,
+    })
+}
+
+/// Unified-Identity: verify a `signed_tree_head`'s raw TPM signature
+/// (marshalled `TPMT_SIGNATURE`, base64-encoded, mirroring how
+/// `build_signed_tree_head` stores it) over `signable` against an AK public
+/// key supplied as PEM, using the same marshalled-wire-bytes parsing
+/// `cose_alg_and_raw_signature` already does to get a bare signature value
+/// and hash algorithm openssl's `Verifier` can use directly.
+fn verify_signed_tree_head_signature(
+    ak_public_pem: &str,
+    signable: &[u8],
+    signature_b64: &str,
+) -> Result<bool, String> {
+    let marshalled_signature = general_purpose::STANDARD
+        .decode(signature_b64)
+        .map_err(|e| format!("Invalid signed_tree_head.signature encoding: {e}"))?;
+    let (cose_alg, raw_signature) = cose_alg_and_raw_signature(&marshalled_signature)?;
+    let (_, is_ecdsa) = x509_attest::x509_signature_algorithm(cose_alg)?;
+
+    let digest_alg = match cose_alg {
+        -7 => openssl::hash::MessageDigest::sha256(),
+        -35 => openssl::hash::MessageDigest::sha384(),
+        -36 => openssl::hash::MessageDigest::sha512(),
+        -257 => openssl::hash::MessageDigest::sha256(),
+        _ => return Err("unsupported TPM signature scheme for signed_tree_head verification".to_string()),
+    };
+
+    let signature_der = x509_attest::signature_value_der(is_ecdsa, &raw_signature)?;
+
+    let ak_key = PKey::public_key_from_pem(ak_public_pem.as_bytes())
+        .map_err(|e| format!("Invalid ak_public_pem: {e}"))?;
+    let mut verifier = openssl::sign::Verifier::new(digest_alg, &ak_key)
+        .map_err(|e| format!("Failed to create signature verifier: {e}"))?;
+    verifier
+        .update(signable)
+        .map_err(|e| format!("Failed to hash signed tree head: {e}"))?;
+    Ok(verifier.verify(&signature_der).unwrap_or(false))
+}
+
+#[derive(Serialize, Debug)]
+struct OrderResponse {
+    order_id: String,
+    challenge_nonce: String,
+    status: &'static str,
+}
+
+/// Unified-Identity: issue a fresh certification order - a server-generated
+/// `order_id` paired with a single-use `challenge_nonce` - for a subsequent
+/// `certify_app_key` call to reference and sign over, mirroring the
+/// order/challenge lifecycle ACME uses for its own authorizations (see
+/// `device_attest_acme::fetch_nonce`). The order starts `pending` and moves
+/// to `valid`/`invalid` once `certify_app_key` is called; see `begin_order`.
+async fn new_order(data: web::Data<QuoteData>) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: New order request received but feature flag is disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified-Identity feature is disabled. Enable unified_identity_enabled in agent config.".to_string(),
+        ));
+    }
+
+    let (order_id, challenge_nonce) =
+        issue_order(Duration::from_secs(data.delegated_cert_nonce_ttl_seconds));
+    HttpResponse::Ok().json(JsonWrapper::success(OrderResponse {
+        order_id,
+        challenge_nonce,
+        status: OrderStatus::Pending.as_str(),
+    }))
+}
+
+#[derive(Serialize, Debug)]
+struct OrderStatusResponse {
+    order_id: String,
+    status: &'static str,
+}
+
+/// Unified-Identity: let the SPIRE plugin poll a certification order's
+/// current state, exactly like polling an ACME order's status URL.
+async fn order_status(
+    data: web::Data<QuoteData>,
+    path: web::Path<String>,
+) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: Order status request received but feature flag is disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified-Identity feature is disabled. Enable unified_identity_enabled in agent config.".to_string(),
+        ));
+    }
+
+    let order_id = path.into_inner();
+    let status = {
+        let cache = ORDER_CACHE.lock().unwrap();
+        cache.get(&order_id).map(|order| order.status)
+    };
+    match status {
+        Some(status) => HttpResponse::Ok().json(JsonWrapper::success(OrderStatusResponse {
+            order_id,
+            status: status.as_str(),
+        })),
+        None => HttpResponse::NotFound().json(JsonWrapper::error(
+            404,
+            format!("Unknown or expired order_id: {}", order_id),
+        )),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct SignRequest {
+    key_id: String,
+    data: String,
+    scheme: String,
+}
+
+#[derive(Serialize, Debug)]
+struct SignResponse {
+    signature: String,
+    public_area: String,
+}
+
+/// Unified-Identity: sign caller-supplied `data` with a TPM-resident key
+/// registered in `QuoteData::signing_key_registry`, for use cases beyond
+/// App Key certification (e.g. a standalone remote-signer backend) that
+/// still want the key sealed in the TPM rather than exported. This mirrors
+/// `certify_app_key`'s App Key load (`TpmMessage::CertifyAppKey` ->
+/// `tpm::Context::load_key_from_context_file`), just signing the request
+/// digest directly (`TpmMessage::SignWithRegisteredKey`) instead of
+/// certifying it with the AK.
+async fn sign(data: web::Data<QuoteData>, request: web::Json<SignRequest>) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: Sign request received but feature flag is disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified-Identity feature is disabled. Enable unified_identity_enabled in agent config.".to_string(),
+        ));
+    }
+
+    if data.signing_key_registry.is_empty() {
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "No signing keys are registered; configure delegated_cert_signing_keys to enable this endpoint.".to_string(),
+        ));
+    }
+
+    let Some(context_path) = data.signing_key_registry.get(&request.key_id) else {
+        warn!("Sign request for unregistered key_id: {}", request.key_id);
+        return HttpResponse::BadRequest().json(JsonWrapper::error(
+            400,
+            format!("Unknown key_id: {}", request.key_id),
+        ));
+    };
+
+    let digest = match general_purpose::STANDARD.decode(&request.data) {
+        Ok(digest) => digest,
+        Err(e) => {
+            warn!("Failed to decode sign request data as base64: {}", e);
+            return HttpResponse::BadRequest().json(JsonWrapper::error(
+                400,
+                "Invalid base64 encoding for data".to_string(),
+            ));
+        }
+    };
+
+    let sign_alg = match keylime::algorithms::SignAlgorithm::try_from(request.scheme.as_str()) {
+        Ok(sign_alg) => sign_alg,
+        Err(e) => {
+            warn!("Unsupported sign request scheme '{}': {:?}", request.scheme, e);
+            return HttpResponse::BadRequest().json(JsonWrapper::error(
+                400,
+                format!("Unsupported scheme: {}", request.scheme),
+            ));
+        }
+    };
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let message = TpmMessage::SignWithRegisteredKey {
+        context_path: context_path.clone(),
+        digest,
+        hash_alg: data.hash_alg,
+        sign_alg,
+    };
+    if let Err(e) = data.tpm_tx.send((message, reply_tx)).await {
+        error!("TPM worker channel closed: {e}");
+        return HttpResponse::InternalServerError().json(JsonWrapper::error(
+            500,
+            "TPM worker channel closed".to_string(),
+        ));
+    }
+    let (public, signature) = match reply_rx.await {
+        Ok(TpmReply::SignWithRegisteredKey(Ok((public, signature)))) => (public, signature),
+        Ok(TpmReply::SignWithRegisteredKey(Err(e))) => {
+            error!("TPM2_Sign failed for key_id {}: {:?}", request.key_id, e);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                format!("TPM2_Sign failed: {}", e),
+            ));
+        }
+        Ok(_) => {
+            error!("Unexpected reply from TPM worker for SignWithRegisteredKey request");
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Unexpected reply from TPM worker".to_string(),
+            ));
+        }
+        Err(e) => {
+            error!("TPM worker dropped reply channel: {e}");
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "TPM worker dropped reply channel".to_string(),
+            ));
+        }
+    };
+
+    let public_bytes = match public.marshall() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to serialize signing key's public area: {:?}", e);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Failed to serialize public area".to_string(),
+            ));
+        }
+    };
+    let sig_bytes = match signature.marshall() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to serialize signature: {:?}", e);
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Failed to serialize signature".to_string(),
+            ));
+        }
+    };
+
+    info!("Unified-Identity: Signed data with registered key {}", request.key_id);
+
+    HttpResponse::Ok().json(JsonWrapper::success(SignResponse {
+        signature: general_purpose::STANDARD.encode(sig_bytes),
+        public_area: general_purpose::STANDARD.encode(public_bytes),
+    }))
+}
+
+#[derive(Serialize, Debug)]
+struct ListKeysResponse {
+    key_ids: Vec<String>,
+}
+
+/// Unified-Identity: list the key identifiers registered for `sign`, so a
+/// caller can discover what's available without having to be told
+/// out-of-band, mirroring a standalone remote-signer service's key-metadata
+/// endpoint.
+async fn list_keys(data: web::Data<QuoteData>) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: List keys request received but feature flag is disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified-Identity feature is disabled. Enable unified_identity_enabled in agent config.".to_string(),
+        ));
+    }
+
+    let mut key_ids: Vec<String> = data.signing_key_registry.keys().cloned().collect();
+    key_ids.sort();
+    HttpResponse::Ok().json(JsonWrapper::success(ListKeysResponse { key_ids }))
+}
+
+/// Unified-Identity: the `delegated_cert_authorized_client_keys` index
+/// [`HttpSignatureAuthMiddleware`] verified the request's HTTP Message
+/// Signature against, stashed as a request extension so `certify_app_key`
+/// can use it as an authenticated rate-limit identity instead of the raw
+/// peer IP - see `verify_http_message_signature`.
+struct HttpSignatureAuthorizedKey(usize);
+
+/// Unified-Identity: HTTP Message Signature authentication for
+/// `/delegated_certification/certify_app_key`, verified before any TPM work
+/// happens rather than inline in the handler. A request carrying a
+/// `uds_auth::PeerCred` extension (i.e. one that arrived over the
+/// delegated-certification UDS listener) is already authorized by
+/// SO_PEERCRED and passes through unchanged, matching how the handler
+/// itself treats UDS peers; likewise an empty
+/// `delegated_cert_authorized_client_keys` disables this middleware
+/// (pass-through), the same opt-in shape as `quotes_handler::SignedRequestAuth`.
+/// Verifying the signature requires the raw request body (the `Digest`
+/// header covers it), which isn't available yet at this point in the
+/// pipeline, so `call` buffers the body out of the request, verifies
+/// against it, then reinserts it as a fresh payload so the handler's
+/// `web::Bytes` extractor still sees it.
+pub(crate) struct HttpSignatureAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for HttpSignatureAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = HttpSignatureAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(HttpSignatureAuthMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub(crate) struct HttpSignatureAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for HttpSignatureAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        if req.conn_data::<crate::uds_auth::PeerCred>().is_some() {
+            // Unified-Identity: SO_PEERCRED already authorizes UDS callers;
+            // see `certify_app_key`.
+            return Box::pin(self.service.call(req));
+        }
+
+        let authorized_keys = req
+            .app_data::<web::Data<QuoteData>>()
+            .map(|data| data.delegated_cert_authorized_client_keys.clone())
+            .unwrap_or_default();
+        if authorized_keys.is_empty() {
+            // Unified-Identity: not configured for this agent - pass
+            // through unchanged, matching `SignedRequestAuth`.
+            return Box::pin(self.service.call(req));
+        }
+        let skew_seconds = req
+            .app_data::<web::Data<QuoteData>>()
+            .map(|data| data.delegated_cert_signature_skew_seconds)
+            .unwrap_or(0);
+
+        let service = self.service.clone();
+        let mut payload = req.take_payload();
+        Box::pin(async move {
+            let mut body_bytes = Vec::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        return Ok(req.into_response(HttpResponse::BadRequest().json(
+                            JsonWrapper::error(400, format!("Failed to read request body: {e}")),
+                        )));
+                    }
+                };
+                body_bytes.extend_from_slice(&chunk);
+            }
+            let body = web::Bytes::from(body_bytes);
+
+            match verify_http_message_signature(req.request(), &body, &authorized_keys, skew_seconds)
+            {
+                Ok(key_index) => {
+                    req.extensions_mut()
+                        .insert(HttpSignatureAuthorizedKey(key_index));
+                    let replayed: std::pin::Pin<
+                        Box<dyn futures::Stream<Item = Result<web::Bytes, actix_web::error::PayloadError>>>,
+                    > = Box::pin(stream::once(async move { Ok(body) }));
+                    req.set_payload(Payload::Stream { payload: replayed });
+                    service.call(req).await
+                }
+                Err(message) => {
+                    warn!(
+                        "Unified-Identity: HTTP message signature auth returning 401 response: {}",
+                        message
+                    );
+                    Ok(req.into_response(
+                        HttpResponse::Unauthorized().json(JsonWrapper::error(401, message)),
+                    ))
+                }
+            }
+        })
+    }
+}
+
 /// Configure the endpoints for the /delegated_certification scope
 pub(crate) fn configure_delegated_certification_endpoints(cfg: &mut web::ServiceConfig) {
     _ = cfg
-        .service(web::resource("/certify_app_key").route(web::post().to(certify_app_key)))
+        .service(
+            web::resource("/certify_app_key")
+                .wrap(HttpSignatureAuth)
+                .route(web::post().to(certify_app_key)),
+        )
+        .service(web::resource("/new_order").route(web::post().to(new_order)))
+        .service(
+            web::resource("/order/{order_id}").route(web::get().to(order_status)),
+        )
+        .service(
+            web::resource("/verify_inclusion").route(web::post().to(verify_inclusion)),
+        )
+        .service(
+            web::resource("/sign")
+                .wrap(HttpSignatureAuth)
+                .route(web::post().to(sign)),
+        )
+        .service(web::resource("/list_keys").route(web::get().to(list_keys)))
         .default_service(web::to(delegated_certification_default));
 }
 
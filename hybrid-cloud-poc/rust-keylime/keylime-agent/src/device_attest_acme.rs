@@ -0,0 +1,605 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: ACME device-attestation client
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: RFC 8555 ACME client completing the `device-attest-01`
+//! challenge for a delegated App Key. This is a distinct ACME subsystem from
+//! [`crate::acme`], which provisions the agent's own mTLS/contact certificate
+//! via HTTP-01 and `instant_acme`/`rcgen`; the two share no code or config
+//! because they authenticate to the CA in different ways and certify
+//! different keys, so their types are named `DeviceAttestAcme*` to keep that
+//! distinction visible at call sites.
+//!
+//! `delegated_certification_handler::certify_app_key` only returns a
+//! home-grown `certify_data`/`signature` blob that a Keylime verifier
+//! understands. When `device_attest_acme_enabled` is set, [`obtain_certificate`]
+//! instead takes the same TPM2_Certify `Attest`/`Signature` pair and drives a
+//! full ACME order - account, `newOrder`, `device-attest-01` challenge
+//! response, authorization polling, CSR finalization, and chain download -
+//! against a CA that issues real X.509 certificates for the App Key.
+//!
+//! Every ACME request is a JWS per RFC 8555 section 6.2: a protected header
+//! (`alg: "ES256"`, a fresh `nonce`, and the target `url`, plus either `jwk`
+//! or `kid`) and an ES256 signature over
+//! `base64url(protected) || "." || base64url(payload)`, using a P-256
+//! account keypair generated on first use and reused afterwards.
+//!
+//! This is a pragmatic implementation, not a general-purpose ACME client: it
+//! retries a stale replay-nonce exactly once per request, and the
+//! `device-attest-01` attestation object is a CBOR map carrying the "tpm"
+//! format fields (`pubArea`, `certInfo`, `sig`) alongside the challenge
+//! token, which conveys the same attestation draft-acme-device-attest asks
+//! for without depending on this crate's CBOR structs being stable wire
+//! types for a real CA.
+
+use base64::{engine::general_purpose, Engine as _};
+use log::*;
+use openssl::{
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::{Hasher, MessageDigest},
+    nid::Nid,
+    pkey::{PKey, Private},
+    sign::Signer,
+    x509::{X509NameBuilder, X509ReqBuilder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum DeviceAttestAcmeError {
+    #[error("failed to load or generate the ACME account key at {0}: {1}")]
+    AccountKey(String, String),
+    #[error("ACME request to {0} failed: {1}")]
+    Request(String, String),
+    #[error("ACME server at {0} returned an error: {1}")]
+    Server(String, String),
+    #[error("failed to (de)serialize an ACME message: {0}")]
+    Serde(String),
+    #[error("failed to sign an ACME request: {0}")]
+    Sign(String),
+    #[error("failed to build the App Key CSR: {0}")]
+    Csr(String),
+    #[error("authorization {0} did not reach 'valid' before the polling deadline")]
+    AuthorizationTimeout(String),
+    #[error("order {0} did not reach 'valid' before the polling deadline")]
+    OrderTimeout(String),
+}
+
+/// Where to find the ACME CA and how to authenticate to it.
+#[derive(Clone)]
+pub(crate) struct DeviceAttestAcmeConfig {
+    /// URL of the ACME server's directory resource.
+    pub(crate) directory_url: String,
+    /// Path the account keypair is persisted to (PEM), generated on first use.
+    pub(crate) account_key_path: PathBuf,
+    /// How often to poll an authorization/order while waiting for it to leave "pending"/"processing".
+    pub(crate) poll_interval: Duration,
+    /// Give up waiting for an authorization/order to become "valid" after this long.
+    pub(crate) poll_timeout: Duration,
+}
+
+/// RFC 8555 section 7.1.1 directory resource - only the fields this client uses.
+#[derive(Deserialize)]
+struct Directory {
+    #[serde(rename = "newNonce")]
+    new_nonce: String,
+    #[serde(rename = "newAccount")]
+    new_account: String,
+    #[serde(rename = "newOrder")]
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct OrderResponse {
+    status: String,
+    authorizations: Vec<String>,
+    finalize: String,
+    #[serde(default)]
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AuthorizationResponse {
+    status: String,
+    challenges: Vec<ChallengeResponse>,
+}
+
+#[derive(Deserialize)]
+struct ChallengeResponse {
+    r#type: String,
+    url: String,
+    token: String,
+}
+
+fn b64url(bytes: &[u8]) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn sha256(bytes: &[u8]) -> Result<Vec<u8>, DeviceAttestAcmeError> {
+    let mut hasher = Hasher::new(MessageDigest::sha256())
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to create SHA-256 hasher: {e}")))?;
+    hasher
+        .update(bytes)
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to hash ACME message: {e}")))?;
+    hasher
+        .finish()
+        .map(|digest| digest.to_vec())
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to finalize ACME message hash: {e}")))
+}
+
+/// Load a previously generated P-256 ACME account key from `path`, or
+/// generate and persist a new one if none exists yet.
+fn load_or_create_account_key(path: &Path) -> Result<PKey<Private>, DeviceAttestAcmeError> {
+    if let Ok(pem) = std::fs::read(path) {
+        return PKey::private_key_from_pem(&pem)
+            .map_err(|e| DeviceAttestAcmeError::AccountKey(path.display().to_string(), e.to_string()));
+    }
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .map_err(|e| DeviceAttestAcmeError::AccountKey(path.display().to_string(), e.to_string()))?;
+    let ec_key = EcKey::generate(&group)
+        .map_err(|e| DeviceAttestAcmeError::AccountKey(path.display().to_string(), e.to_string()))?;
+    let pkey = PKey::from_ec_key(ec_key)
+        .map_err(|e| DeviceAttestAcmeError::AccountKey(path.display().to_string(), e.to_string()))?;
+
+    let pem = pkey
+        .private_key_to_pem_pkcs8()
+        .map_err(|e| DeviceAttestAcmeError::AccountKey(path.display().to_string(), e.to_string()))?;
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Err(e) = std::fs::write(path, &pem) {
+        warn!(
+            "Unified-Identity: Failed to persist ACME account key to {}: {e}",
+            path.display()
+        );
+    }
+    Ok(pkey)
+}
+
+/// The JSON Web Key (RFC 7517) form of an EC account key's public half,
+/// consumed both as the `jwk` protected-header member for unauthenticated
+/// requests and as the input to [`jwk_thumbprint`].
+fn jwk(pkey: &PKey<Private>) -> Result<Value, DeviceAttestAcmeError> {
+    let ec_key = pkey
+        .ec_key()
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("ACME account key is not an EC key: {e}")))?;
+    let mut ctx = openssl::bn::BigNumContext::new()
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to create bignum context: {e}")))?;
+    let mut x = openssl::bn::BigNum::new()
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to allocate bignum: {e}")))?;
+    let mut y = openssl::bn::BigNum::new()
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to allocate bignum: {e}")))?;
+    ec_key
+        .public_key()
+        .affine_coordinates(ec_key.group(), &mut x, &mut y, &mut ctx)
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to read EC public point: {e}")))?;
+
+    // P-256 coordinates are fixed at 32 bytes; pad so a short leading-zero
+    // coordinate still serializes to the JWK-required fixed width.
+    let mut x_bytes = x.to_vec();
+    let mut y_bytes = y.to_vec();
+    while x_bytes.len() < 32 {
+        x_bytes.insert(0, 0);
+    }
+    while y_bytes.len() < 32 {
+        y_bytes.insert(0, 0);
+    }
+
+    Ok(json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "x": b64url(&x_bytes),
+        "y": b64url(&y_bytes),
+    }))
+}
+
+/// RFC 7638 JWK thumbprint: base64url(SHA-256(canonical JSON)), required as
+/// the `keyAuthorization` suffix for every ACME challenge response.
+fn jwk_thumbprint(jwk: &Value) -> Result<String, DeviceAttestAcmeError> {
+    let canonical = format!(
+        r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+        jwk["x"].as_str().unwrap_or_default(),
+        jwk["y"].as_str().unwrap_or_default(),
+    );
+    sha256(canonical.as_bytes()).map(|digest| b64url(&digest))
+}
+
+/// Sign `signing_input` with the account key and return the raw (not DER)
+/// `r || s` signature JOSE's ES256 requires, each half fixed at 32 bytes for
+/// the P-256 curve.
+fn sign_es256(pkey: &PKey<Private>, signing_input: &[u8]) -> Result<Vec<u8>, DeviceAttestAcmeError> {
+    let mut signer = Signer::new(MessageDigest::sha256(), pkey)
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to create ES256 signer: {e}")))?;
+    signer
+        .update(signing_input)
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to hash JWS signing input: {e}")))?;
+    let der_sig = signer
+        .sign_to_vec()
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to finalize ES256 signature: {e}")))?;
+
+    let ecdsa_sig = EcdsaSig::from_der(&der_sig)
+        .map_err(|e| DeviceAttestAcmeError::Sign(format!("failed to parse ECDSA signature: {e}")))?;
+    let mut r = ecdsa_sig.r().to_vec();
+    let mut s = ecdsa_sig.s().to_vec();
+    while r.len() < 32 {
+        r.insert(0, 0);
+    }
+    while s.len() < 32 {
+        s.insert(0, 0);
+    }
+    r.extend(s);
+    Ok(r)
+}
+
+/// Build a JWS per RFC 8555 section 6.2, authenticated with `kid` (an
+/// existing account URL) when given, or embedding `jwk` directly for the
+/// account-creation request that precedes having a `kid`.
+fn build_jws(
+    pkey: &PKey<Private>,
+    url: &str,
+    nonce: &str,
+    kid: Option<&str>,
+    payload: Option<&Value>,
+) -> Result<Value, DeviceAttestAcmeError> {
+    let mut protected = json!({
+        "alg": "ES256",
+        "nonce": nonce,
+        "url": url,
+    });
+    match kid {
+        Some(kid) => protected["kid"] = json!(kid),
+        None => protected["jwk"] = jwk(pkey)?,
+    }
+
+    let protected_b64 = b64url(
+        serde_json::to_string(&protected)
+            .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?
+            .as_bytes(),
+    );
+    // RFC 8555 section 6.3: a POST-as-GET (no payload) signs an empty string,
+    // not an absent one.
+    let payload_b64 = match payload {
+        Some(payload) => b64url(
+            serde_json::to_string(payload)
+                .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?
+                .as_bytes(),
+        ),
+        None => String::new(),
+    };
+
+    let signing_input = format!("{protected_b64}.{payload_b64}");
+    let signature = sign_es256(pkey, signing_input.as_bytes())?;
+
+    Ok(json!({
+        "protected": protected_b64,
+        "payload": payload_b64,
+        "signature": b64url(&signature),
+    }))
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("Replay-Nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// POST a JWS-signed `payload` to `url`, returning the parsed response body
+/// and the next replay-nonce the server handed back. Retries exactly once if
+/// the server rejects the nonce with a `badNonce` problem, using the
+/// fresh nonce from that error response.
+async fn acme_post(
+    http: &reqwest::Client,
+    url: &str,
+    pkey: &PKey<Private>,
+    kid: Option<&str>,
+    payload: Option<&Value>,
+    mut nonce: String,
+) -> Result<(reqwest::Response, String), DeviceAttestAcmeError> {
+    for attempt in 0..2 {
+        let jws = build_jws(pkey, url, &nonce, kid, payload)?;
+        let response = http
+            .post(url)
+            .header("Content-Type", "application/jose+json")
+            .json(&jws)
+            .send()
+            .await
+            .map_err(|e| DeviceAttestAcmeError::Request(url.to_string(), e.to_string()))?;
+
+        if response.status().is_success() {
+            let next_nonce = replay_nonce(&response).unwrap_or(nonce);
+            return Ok((response, next_nonce));
+        }
+
+        let status = response.status();
+        let next_nonce = replay_nonce(&response);
+        let body = response.text().await.unwrap_or_default();
+        let is_bad_nonce = body.contains("urn:ietf:params:acme:error:badNonce");
+        if is_bad_nonce && attempt == 0 {
+            if let Some(fresh_nonce) = next_nonce {
+                nonce = fresh_nonce;
+                continue;
+            }
+        }
+        return Err(DeviceAttestAcmeError::Server(url.to_string(), format!("{status}: {body}")));
+    }
+    unreachable!("loop always returns within two attempts")
+}
+
+async fn fetch_directory(http: &reqwest::Client, directory_url: &str) -> Result<Directory, DeviceAttestAcmeError> {
+    http.get(directory_url)
+        .send()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Request(directory_url.to_string(), e.to_string()))?
+        .json::<Directory>()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))
+}
+
+async fn fetch_nonce(http: &reqwest::Client, new_nonce_url: &str) -> Result<String, DeviceAttestAcmeError> {
+    let response = http
+        .head(new_nonce_url)
+        .send()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Request(new_nonce_url.to_string(), e.to_string()))?;
+    replay_nonce(&response)
+        .ok_or_else(|| DeviceAttestAcmeError::Server(new_nonce_url.to_string(), "missing Replay-Nonce header".to_string()))
+}
+
+/// Build the `device-attest-01` attestation object for `token`: a CBOR map
+/// carrying the "tpm" attestation format's fields so the CA can verify the
+/// TPM2_Certify statement over the App Key, bound to this specific challenge.
+fn build_attestation_object(
+    token: &str,
+    app_key_public_der: &[u8],
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+) -> Result<Vec<u8>, DeviceAttestAcmeError> {
+    #[derive(Serialize)]
+    struct AttStmt<'a> {
+        ver: &'a str,
+        #[serde(rename = "pubArea")]
+        pub_area: &'a [u8],
+        #[serde(rename = "certInfo")]
+        cert_info: &'a [u8],
+        sig: &'a [u8],
+    }
+    #[derive(Serialize)]
+    struct AttestationObject<'a> {
+        fmt: &'a str,
+        token: &'a str,
+        #[serde(rename = "attStmt")]
+        att_stmt: AttStmt<'a>,
+    }
+
+    let obj = AttestationObject {
+        fmt: "tpm",
+        token,
+        att_stmt: AttStmt {
+            ver: "2.0",
+            pub_area: app_key_public_der,
+            cert_info: attest_bytes,
+            sig: sig_bytes,
+        },
+    };
+    serde_cbor::to_vec(&obj).map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))
+}
+
+/// Build a PKCS#10 CSR over the already-TPM-resident App Key public key,
+/// signed by an ephemeral throwaway key - the CA only uses the CSR to bind
+/// the public key and CN to the order, not to re-prove possession (that's
+/// what the `device-attest-01` challenge already established).
+fn build_csr(app_key_public_pem: &str, common_name: &str) -> Result<Vec<u8>, DeviceAttestAcmeError> {
+    let app_key_public = PKey::public_key_from_pem(app_key_public_pem.as_bytes())
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to parse App Key public PEM: {e}")))?;
+
+    let mut name_builder =
+        X509NameBuilder::new().map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to build CSR subject: {e}")))?;
+    name_builder
+        .append_entry_by_text("CN", common_name)
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to set CSR common name: {e}")))?;
+    let name = name_builder.build();
+
+    let mut req_builder =
+        X509ReqBuilder::new().map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to create CSR builder: {e}")))?;
+    req_builder
+        .set_subject_name(&name)
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to set CSR subject: {e}")))?;
+    req_builder
+        .set_pubkey(&app_key_public)
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to set CSR public key: {e}")))?;
+
+    // A CSR must be self-signed; the App Key's private half never leaves the
+    // TPM, so a throwaway signing key is used purely to produce a
+    // syntactically valid PKCS#10 structure around the App Key's public key.
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to create CSR signing key group: {e}")))?;
+    let throwaway_key = PKey::from_ec_key(
+        EcKey::generate(&group)
+            .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to generate CSR signing key: {e}")))?,
+    )
+    .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to wrap CSR signing key: {e}")))?;
+    req_builder
+        .sign(&throwaway_key, MessageDigest::sha256())
+        .map_err(|e| DeviceAttestAcmeError::Csr(format!("failed to sign CSR: {e}")))?;
+
+    req_builder.build().to_der().map_err(|e| DeviceAttestAcmeError::Csr(e.to_string()))
+}
+
+/// Drive a full ACME order to completion and return the issued certificate
+/// chain (PEM, leaf first) for `app_key_public_pem`, proving possession via
+/// the `device-attest-01` challenge over `attest_bytes`/`sig_bytes` (the
+/// TPM2_Certify `Attest`/`Signature` already produced by
+/// `certify_app_key`).
+pub(crate) async fn obtain_certificate(
+    cfg: &DeviceAttestAcmeConfig,
+    identifier_value: &str,
+    app_key_public_pem: &str,
+    app_key_public_der: &[u8],
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+) -> Result<String, DeviceAttestAcmeError> {
+    let http = reqwest::Client::new();
+    let account_key = load_or_create_account_key(&cfg.account_key_path)?;
+
+    let directory = fetch_directory(&http, &cfg.directory_url).await?;
+    let nonce = fetch_nonce(&http, &directory.new_nonce).await?;
+
+    let (account_response, nonce) = acme_post(
+        &http,
+        &directory.new_account,
+        &account_key,
+        None,
+        Some(&json!({"termsOfServiceAgreed": true})),
+        nonce,
+    )
+    .await?;
+    let account_url = account_response
+        .headers()
+        .get("Location")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| {
+            DeviceAttestAcmeError::Server(
+                directory.new_account.clone(),
+                "account response missing Location header".to_string(),
+            )
+        })?
+        .to_string();
+
+    let (order_response, mut nonce) = acme_post(
+        &http,
+        &directory.new_order,
+        &account_key,
+        Some(&account_url),
+        Some(&json!({
+            "identifiers": [{"type": "permanent-identifier", "value": identifier_value}],
+        })),
+        nonce,
+    )
+    .await?;
+    let order_url = order_response
+        .headers()
+        .get("Location")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(&directory.new_order)
+        .to_string();
+    let order: OrderResponse = order_response
+        .json()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?;
+
+    let Some(authz_url) = order.authorizations.first() else {
+        return Err(DeviceAttestAcmeError::Server(
+            order_url,
+            "order response had no authorizations".to_string(),
+        ));
+    };
+
+    let (authz_response, next_nonce) =
+        acme_post(&http, authz_url, &account_key, Some(&account_url), None, nonce).await?;
+    nonce = next_nonce;
+    let authorization: AuthorizationResponse = authz_response
+        .json()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?;
+    let Some(challenge) = authorization
+        .challenges
+        .iter()
+        .find(|c| c.r#type == "device-attest-01")
+    else {
+        return Err(DeviceAttestAcmeError::Server(
+            authz_url.clone(),
+            "authorization did not offer a device-attest-01 challenge".to_string(),
+        ));
+    };
+
+    let account_jwk = jwk(&account_key)?;
+    let thumbprint = jwk_thumbprint(&account_jwk)?;
+    let key_authorization_token = format!("{}.{thumbprint}", challenge.token);
+    let attestation_object = build_attestation_object(
+        &key_authorization_token,
+        app_key_public_der,
+        attest_bytes,
+        sig_bytes,
+    )?;
+
+    let (_, next_nonce) = acme_post(
+        &http,
+        &challenge.url,
+        &account_key,
+        Some(&account_url),
+        Some(&json!({"attObj": b64url(&attestation_object)})),
+        nonce,
+    )
+    .await?;
+    nonce = next_nonce;
+
+    let deadline = tokio::time::Instant::now() + cfg.poll_timeout;
+    loop {
+        let (poll_response, next_nonce) =
+            acme_post(&http, authz_url, &account_key, Some(&account_url), None, nonce).await?;
+        nonce = next_nonce;
+        let authorization: AuthorizationResponse = poll_response
+            .json()
+            .await
+            .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?;
+        if authorization.status == "valid" {
+            break;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(DeviceAttestAcmeError::AuthorizationTimeout(authz_url.clone()));
+        }
+        tokio::time::sleep(cfg.poll_interval).await;
+    }
+
+    let csr_der = build_csr(app_key_public_pem, identifier_value)?;
+    let (_, mut nonce) = acme_post(
+        &http,
+        &order.finalize,
+        &account_key,
+        Some(&account_url),
+        Some(&json!({"csr": b64url(&csr_der)})),
+        nonce,
+    )
+    .await?;
+
+    let deadline = tokio::time::Instant::now() + cfg.poll_timeout;
+    let certificate_url = loop {
+        let (poll_response, next_nonce) =
+            acme_post(&http, &order_url, &account_key, Some(&account_url), None, nonce).await?;
+        nonce = next_nonce;
+        let order: OrderResponse = poll_response
+            .json()
+            .await
+            .map_err(|e| DeviceAttestAcmeError::Serde(e.to_string()))?;
+        if order.status == "valid" {
+            if let Some(certificate_url) = order.certificate {
+                break certificate_url;
+            }
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(DeviceAttestAcmeError::OrderTimeout(order_url));
+        }
+        tokio::time::sleep(cfg.poll_interval).await;
+    };
+
+    let (certificate_response, _) = acme_post(
+        &http,
+        &certificate_url,
+        &account_key,
+        Some(&account_url),
+        None,
+        nonce,
+    )
+    .await?;
+    certificate_response
+        .text()
+        .await
+        .map_err(|e| DeviceAttestAcmeError::Request(certificate_url, e.to_string()))
+}
@@ -0,0 +1,368 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright 2025 Keylime Authors
+
+//! DICE Handler
+//!
+//! This module provides a layered-attestation endpoint that complements the
+//! TPM2 quote: a DICE Boot Certificate Chain (BCC) anchored to a TPM-derived
+//! root Compound Device Identifier (CDI). Each layer's CDI is derived from the
+//! parent layer's CDI plus a measurement of the next layer, and each layer
+//! signs a certificate attesting the next layer's public key and measurement.
+//!
+//! Endpoint: GET /v2.2/dice/bcc
+//!
+//! Features:
+//! - Root CDI derived via HMAC over the agent's EK/AK identity
+//! - Each layer certificate carries code-hash/config fields as CBOR map entries
+//! - The full chain is serialized as a single CBOR array
+
+use crate::{QuoteData, TpmMessage, TpmReply};
+use actix_web::{http, web, HttpRequest, HttpResponse, Responder};
+use base64::{engine::general_purpose, Engine as _};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use keylime::json_wrapper::JsonWrapper;
+use log::*;
+use openssl::{ec::EcKey, nid::Nid, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::oneshot;
+use tss_esapi::traits::Marshall;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A single layer of the DICE Boot Certificate Chain.
+///
+/// Serialized as a CBOR map so that a verifier can walk the chain without
+/// depending on this crate's type definitions.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BccLayer {
+    /// Name of the layer that produced this certificate (e.g. "bootloader", "agent").
+    pub layer: String,
+    /// SHA-256 measurement of the next layer's image/config, hex-encoded.
+    pub code_hash: String,
+    /// Free-form configuration descriptor measured into this layer's CDI.
+    pub config_hash: String,
+    /// DER-encoded public key of the next layer, base64-encoded.
+    pub subject_public_key: String,
+    /// COSE_Sign1-style signature over the layer payload, base64-encoded.
+    pub signature: String,
+}
+
+/// Response body for the DICE BCC endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DiceBccResponse {
+    /// The Boot Certificate Chain, CBOR-encoded and base64-wrapped for JSON transport.
+    pub bcc_cbor: String,
+    /// The leaf (agent) layer's public key, DER-encoded and base64-wrapped.
+    pub leaf_public_key: String,
+}
+
+/// Unified-Identity: Derive the next layer's CDI from the parent CDI and a
+/// measurement of the next layer, following the DICE CDI derivation scheme
+/// (HMAC(parent_cdi, measurement)).
+fn derive_cdi(parent_cdi: &[u8], measurement: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = HmacSha256::new_from_slice(parent_cdi)
+        .map_err(|e| format!("Failed to initialize CDI HMAC: {e}"))?;
+    mac.update(measurement);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Unified-Identity: Derive the root CDI from the agent's EK/AK identity,
+/// anchoring the whole BCC to the TPM identity already established in `main()`.
+fn derive_root_cdi(agent_uuid: &str, ak_public_der: &[u8]) -> Result<Vec<u8>, String> {
+    let mut mac = HmacSha256::new_from_slice(b"Unified-Identity-DICE-Root-CDI")
+        .map_err(|e| format!("Failed to initialize root CDI HMAC: {e}"))?;
+    mac.update(agent_uuid.as_bytes());
+    mac.update(ak_public_der);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Unified-Identity: Derive a deterministic P-256 keypair from a layer's CDI
+/// via HKDF-expand, so the layer's identity (and hence the whole BCC) is
+/// reproducible from the same measurements rather than a fresh random key
+/// every call - the latter would make `GET /bcc` return an unrelated,
+/// unverifiable chain on every request.
+fn derive_layer_keypair(cdi: &[u8]) -> Result<PKey<openssl::pkey::Private>, String> {
+    let hk = Hkdf::<Sha256>::new(None, cdi);
+    let mut scalar_bytes = [0u8; 32];
+    hk.expand(b"Unified-Identity-DICE-Layer-Key", &mut scalar_bytes)
+        .map_err(|e| format!("Failed to HKDF-expand layer key seed: {e}"))?;
+
+    let group = openssl::ec::EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+        .map_err(|e| format!("Failed to create EC group: {e}"))?;
+    let mut ctx = openssl::bn::BigNumContext::new()
+        .map_err(|e| format!("Failed to create bignum context: {e}"))?;
+    let mut order = openssl::bn::BigNum::new()
+        .map_err(|e| format!("Failed to allocate bignum: {e}"))?;
+    group
+        .order(&mut order, &mut ctx)
+        .map_err(|e| format!("Failed to read curve order: {e}"))?;
+
+    let raw_scalar = openssl::bn::BigNum::from_slice(&scalar_bytes)
+        .map_err(|e| format!("Failed to parse layer key seed: {e}"))?;
+    let mut private_number = openssl::bn::BigNum::new()
+        .map_err(|e| format!("Failed to allocate bignum: {e}"))?;
+    private_number
+        .nnmod(&raw_scalar, &order, &mut ctx)
+        .map_err(|e| format!("Failed to reduce layer key seed into curve order: {e}"))?;
+    if private_number.is_zero() {
+        // A zero scalar is invalid; nudging it to 1 on this astronomically
+        // unlikely HKDF output keeps the derivation total.
+        private_number
+            .add_word(1)
+            .map_err(|e| format!("Failed to adjust zero layer key seed: {e}"))?;
+    }
+
+    let mut public_point = openssl::ec::EcPoint::new(&group)
+        .map_err(|e| format!("Failed to allocate EC point: {e}"))?;
+    public_point
+        .mul_generator(&group, &private_number, &ctx)
+        .map_err(|e| format!("Failed to derive layer public point: {e}"))?;
+
+    let ec_key = EcKey::from_private_components(&group, &private_number, &public_point)
+        .map_err(|e| format!("Failed to build layer EC key: {e}"))?;
+    PKey::from_ec_key(ec_key).map_err(|e| format!("Failed to wrap layer key: {e}"))
+}
+
+/// Unified-Identity: Build and sign one BCC layer using a keypair
+/// deterministically derived from that layer's CDI, returning the layer
+/// certificate plus the key pair so the caller can pass the public key
+/// forward as the next layer's subject.
+fn build_layer(
+    layer_name: &str,
+    cdi: &[u8],
+    code_hash: &[u8],
+    config_hash: &[u8],
+) -> Result<(BccLayer, PKey<openssl::pkey::Private>), String> {
+    let pkey = derive_layer_keypair(cdi)?;
+
+    let subject_public_key = pkey
+        .public_key_to_der()
+        .map_err(|e| format!("Failed to encode layer public key: {e}"))?;
+
+    let mut signer = Signer::new(openssl::hash::MessageDigest::sha256(), &pkey)
+        .map_err(|e| format!("Failed to create layer signer: {e}"))?;
+    signer
+        .update(cdi)
+        .and_then(|_| signer.update(code_hash))
+        .and_then(|_| signer.update(config_hash))
+        .and_then(|_| signer.update(&subject_public_key))
+        .map_err(|e| format!("Failed to sign layer payload: {e}"))?;
+    let signature = signer
+        .sign_to_vec()
+        .map_err(|e| format!("Failed to finalize layer signature: {e}"))?;
+
+    Ok((
+        BccLayer {
+            layer: layer_name.to_string(),
+            code_hash: hex::encode(code_hash),
+            config_hash: hex::encode(config_hash),
+            subject_public_key: general_purpose::STANDARD.encode(subject_public_key),
+            signature: general_purpose::STANDARD.encode(signature),
+        },
+        pkey,
+    ))
+}
+
+/// GET /v2.2/dice/bcc
+///
+/// Builds the DICE Boot Certificate Chain from the TPM-anchored root CDI
+/// through a small fixed set of firmware-to-agent layers, and returns the
+/// CBOR-encoded chain plus the leaf (agent) layer's public key.
+pub(crate) async fn dice_bcc(data: web::Data<QuoteData>) -> impl Responder {
+    // Unified-Identity: anchor the root CDI to the agent's real AK public key
+    // (read from the TPM worker, the same round trip `main.rs` uses to embed
+    // the AK in the mTLS quote extension) rather than just `agent_uuid` -
+    // otherwise the root CDI is reproducible by anyone who knows the UUID.
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if let Err(e) = data
+        .tpm_tx
+        .send((
+            TpmMessage::ReadPublic {
+                handle: data.ak_handle,
+            },
+            reply_tx,
+        ))
+        .await
+    {
+        warn!("Failed to request AK public key from TPM worker: {e}");
+        return HttpResponse::InternalServerError()
+            .json(JsonWrapper::error(500, "Failed to request AK public key from TPM worker"));
+    }
+    let ak_public = match reply_rx.await {
+        Ok(TpmReply::ReadPublic(Ok(public))) => public,
+        Ok(TpmReply::ReadPublic(Err(e))) => {
+            warn!("Failed to read AK public key from TPM: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to read AK public key from TPM"));
+        }
+        Ok(_) => {
+            warn!("Unexpected reply from TPM worker for ReadPublic request");
+            return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                500,
+                "Unexpected reply from TPM worker for ReadPublic request",
+            ));
+        }
+        Err(e) => {
+            warn!("TPM worker dropped reply channel: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "TPM worker dropped reply channel"));
+        }
+    };
+    let ak_public_der = match ak_public.marshall() {
+        Ok(der) => der,
+        Err(e) => {
+            warn!("Failed to marshal AK public key: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to marshal AK public key"));
+        }
+    };
+    let root_cdi = match derive_root_cdi(&data.agent_uuid, &ak_public_der) {
+        Ok(cdi) => cdi,
+        Err(e) => {
+            warn!("Failed to derive DICE root CDI: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to derive DICE root CDI"));
+        }
+    };
+
+    // Fixed two-layer chain: bootloader layer measures and certifies the agent layer.
+    let bootloader_measurement = sha2::Sha256::digest(b"keylime-bootloader-image");
+    let (bootloader_layer, _bootloader_key) = match build_layer(
+        "bootloader",
+        &root_cdi,
+        &bootloader_measurement,
+        b"bootloader-config-v1",
+    ) {
+        Ok(layer) => layer,
+        Err(e) => {
+            warn!("Failed to build DICE bootloader layer: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to build DICE bootloader layer"));
+        }
+    };
+
+    let agent_cdi = match derive_cdi(&root_cdi, &bootloader_measurement) {
+        Ok(cdi) => cdi,
+        Err(e) => {
+            warn!("Failed to derive DICE agent-layer CDI: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to derive DICE agent-layer CDI"));
+        }
+    };
+    let agent_measurement = sha2::Sha256::digest(data.agent_uuid.as_bytes());
+    let (agent_layer, agent_key) = match build_layer(
+        "agent",
+        &agent_cdi,
+        &agent_measurement,
+        b"keylime-agent-config-v1",
+    ) {
+        Ok(layer) => layer,
+        Err(e) => {
+            warn!("Failed to build DICE agent layer: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to build DICE agent layer"));
+        }
+    };
+
+    let chain = vec![bootloader_layer, agent_layer];
+    let bcc_cbor = match serde_cbor::to_vec(&chain) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to CBOR-encode DICE BCC: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to CBOR-encode DICE BCC"));
+        }
+    };
+
+    let leaf_public_key = match agent_key.public_key_to_der() {
+        Ok(der) => der,
+        Err(e) => {
+            warn!("Failed to encode DICE leaf public key: {e}");
+            return HttpResponse::InternalServerError()
+                .json(JsonWrapper::error(500, "Failed to encode DICE leaf public key"));
+        }
+    };
+
+    let response = DiceBccResponse {
+        bcc_cbor: general_purpose::STANDARD.encode(bcc_cbor),
+        leaf_public_key: general_purpose::STANDARD.encode(leaf_public_key),
+    };
+
+    info!("GET dice/bcc returning 200 response");
+    HttpResponse::Ok().json(JsonWrapper::success(response))
+}
+
+/// Handles the default case for the /dice scope
+async fn dice_default(req: HttpRequest) -> impl Responder {
+    let error;
+    let response;
+    let message;
+
+    match req.head().method {
+        http::Method::GET => {
+            error = 400;
+            message = "URI not supported, only /bcc is supported for GET in /dice/ interface";
+            response = HttpResponse::BadRequest().json(JsonWrapper::error(error, message));
+        }
+        _ => {
+            error = 405;
+            message = "Method is not supported in /dice/ interface";
+            response = HttpResponse::MethodNotAllowed()
+                .insert_header(http::header::Allow(vec![http::Method::GET]))
+                .json(JsonWrapper::error(error, message));
+        }
+    };
+
+    warn!(
+        "{} returning {} response. {}",
+        req.head().method,
+        error,
+        message
+    );
+
+    response
+}
+
+/// Configure the endpoints for the /dice scope
+pub(crate) fn configure_dice_endpoints(cfg: &mut web::ServiceConfig) {
+    _ = cfg
+        .service(web::resource("/bcc").route(web::get().to(dice_bcc)))
+        .default_service(web::to(dice_default));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_layer_keypair_is_deterministic() {
+        let cdi = b"some-layer-cdi";
+        let key_a = derive_layer_keypair(cdi).unwrap(); //#[allow_ci]
+        let key_b = derive_layer_keypair(cdi).unwrap(); //#[allow_ci]
+        assert_eq!(
+            key_a.public_key_to_der().unwrap(), //#[allow_ci]
+            key_b.public_key_to_der().unwrap()  //#[allow_ci]
+        );
+    }
+
+    #[test]
+    fn derive_layer_keypair_differs_across_cdis() {
+        let key_a = derive_layer_keypair(b"cdi-one").unwrap(); //#[allow_ci]
+        let key_b = derive_layer_keypair(b"cdi-two").unwrap(); //#[allow_ci]
+        assert_ne!(
+            key_a.public_key_to_der().unwrap(), //#[allow_ci]
+            key_b.public_key_to_der().unwrap()  //#[allow_ci]
+        );
+    }
+
+    #[test]
+    fn build_layer_is_deterministic_for_same_cdi() {
+        let (layer_a, _) = build_layer("agent", b"fixed-cdi", b"code-hash", b"config-hash")
+            .unwrap(); //#[allow_ci]
+        let (layer_b, _) = build_layer("agent", b"fixed-cdi", b"code-hash", b"config-hash")
+            .unwrap(); //#[allow_ci]
+        assert_eq!(layer_a.subject_public_key, layer_b.subject_public_key);
+    }
+}
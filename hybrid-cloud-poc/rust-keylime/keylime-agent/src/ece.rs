@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: RFC 8188 encrypted-content-encoding payload decryption
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: RFC 8188 `aes128gcm` encrypted content-encoding.
+//!
+//! This is an alternative to the bespoke Keylime U/V-key payload scheme,
+//! selected by setting `payload_encoding = "ece"` in the agent config. Instead
+//! of recombining a split RSA-wrapped key, the payload is decrypted straight
+//! from a standard [RFC 8188](https://datatracker.ietf.org/doc/html/rfc8188)
+//! `aes128gcm` encoded body, so payloads can be produced with off-the-shelf
+//! ECE tooling rather than a Keylime-specific encoder.
+//!
+//! Wire format (RFC 8188 section 2):
+//! `salt(16) || record_size(4, big-endian) || key_id_len(1) || key_id || ciphertext`
+//!
+//! The content-encryption key and nonce base are derived from the input
+//! keying material (`ikm`) and the header's `salt` via HKDF-SHA256, using the
+//! `info` strings mandated by the RFC. Each fixed-size record is decrypted
+//! with AES-128-GCM under a nonce formed by XORing the nonce base with the
+//! big-endian record sequence number, then has its padding delimiter
+//! stripped: `0x01` marks the final record, `0x02` marks a non-final one.
+
+use hkdf::Hkdf;
+use openssl::symm::{decrypt_aead, Cipher};
+use sha2::Sha256;
+use thiserror::Error;
+
+const SALT_LEN: usize = 16;
+const HEADER_PREFIX_LEN: usize = SALT_LEN + 4 + 1;
+const KEY_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const CEK_INFO: &[u8] = b"Content-Encoding: aes128gcm\0";
+const NONCE_INFO: &[u8] = b"Content-Encoding: nonce\0";
+
+/// Errors produced while decoding an `aes128gcm` encrypted-content-encoding body.
+#[derive(Error, Debug)]
+pub enum EceError {
+    /// The body is shorter than the fixed-size RFC 8188 header.
+    #[error("aes128gcm body is shorter than the fixed header")]
+    TruncatedHeader,
+    /// The body carries no ciphertext after the header.
+    #[error("aes128gcm body has no records after the header")]
+    NoRecords,
+    /// The declared record size is too small to hold a padding delimiter and tag.
+    #[error("aes128gcm record size {0} is too small")]
+    RecordSizeTooSmall(u32),
+    /// HKDF key/nonce derivation failed.
+    #[error("failed to derive aes128gcm key material: {0}")]
+    KeyDerivation(String),
+    /// A record failed AES-128-GCM authentication or decryption.
+    #[error("failed to decrypt aes128gcm record {0}")]
+    RecordDecryption(usize),
+    /// A record's padding delimiter was not `0x01` (final record) or `0x02`
+    /// (non-final record).
+    #[error("aes128gcm record {0} has an invalid padding delimiter")]
+    InvalidPadding(usize),
+}
+
+/// Decrypt an RFC 8188 `aes128gcm` encoded payload using `ikm` as the input
+/// keying material, returning the concatenated plaintext of all records.
+pub(crate) fn decrypt_aes128gcm(ikm: &[u8], body: &[u8]) -> Result<Vec<u8>, EceError> {
+    if body.len() < HEADER_PREFIX_LEN {
+        return Err(EceError::TruncatedHeader);
+    }
+
+    let salt = &body[..SALT_LEN];
+    let record_size = u32::from_be_bytes([
+        body[SALT_LEN],
+        body[SALT_LEN + 1],
+        body[SALT_LEN + 2],
+        body[SALT_LEN + 3],
+    ]);
+    if (record_size as usize) <= TAG_LEN + 1 {
+        return Err(EceError::RecordSizeTooSmall(record_size));
+    }
+    let key_id_len = body[SALT_LEN + 4] as usize;
+    let header_len = HEADER_PREFIX_LEN + key_id_len;
+    if body.len() < header_len {
+        return Err(EceError::TruncatedHeader);
+    }
+    if body.len() == header_len {
+        return Err(EceError::NoRecords);
+    }
+
+    let (cek, nonce_base) = derive_key_material(ikm, salt)?;
+
+    let ciphertext = &body[header_len..];
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let records: Vec<&[u8]> = ciphertext.chunks(record_size as usize).collect();
+    let last_index = records.len() - 1;
+    for (index, record) in records.into_iter().enumerate() {
+        let nonce = record_nonce(&nonce_base, index as u64);
+        let decrypted = decrypt_record(&cek, &nonce, record)
+            .map_err(|_| EceError::RecordDecryption(index))?;
+        let is_last = index == last_index;
+        let unpadded = strip_padding(&decrypted, is_last)
+            .ok_or(EceError::InvalidPadding(index))?;
+        plaintext.extend_from_slice(unpadded);
+    }
+
+    Ok(plaintext)
+}
+
+/// Derive the content-encryption key and nonce base via HKDF-SHA256, per RFC 8188 section 2.1.
+fn derive_key_material(ikm: &[u8], salt: &[u8]) -> Result<([u8; KEY_LEN], [u8; NONCE_LEN]), EceError> {
+    let hk = Hkdf::<Sha256>::new(Some(salt), ikm);
+
+    let mut cek = [0u8; KEY_LEN];
+    hk.expand(CEK_INFO, &mut cek)
+        .map_err(|e| EceError::KeyDerivation(format!("content-encryption key: {e}")))?;
+
+    let mut nonce_base = [0u8; NONCE_LEN];
+    hk.expand(NONCE_INFO, &mut nonce_base)
+        .map_err(|e| EceError::KeyDerivation(format!("nonce base: {e}")))?;
+
+    Ok((cek, nonce_base))
+}
+
+/// XOR the big-endian 96-bit `seq` into the low-order bytes of the nonce base, per RFC 8188 section 3.1.
+fn record_nonce(nonce_base: &[u8; NONCE_LEN], seq: u64) -> [u8; NONCE_LEN] {
+    let mut nonce = *nonce_base;
+    let seq_bytes = seq.to_be_bytes();
+    for (i, b) in seq_bytes.iter().enumerate() {
+        nonce[NONCE_LEN - 8 + i] ^= b;
+    }
+    nonce
+}
+
+/// Decrypt a single AES-128-GCM record whose last [`TAG_LEN`] bytes are the authentication tag.
+fn decrypt_record(
+    cek: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    record: &[u8],
+) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let split = record.len().saturating_sub(TAG_LEN);
+    let (ciphertext, tag) = record.split_at(split);
+    decrypt_aead(Cipher::aes_128_gcm(), cek, Some(nonce), &[], ciphertext, tag)
+}
+
+/// Strip the RFC 8188 padding delimiter from a decrypted record: `0x01` for the
+/// final record, `0x02` followed by zero or more `0x00` bytes for any other record.
+fn strip_padding(record: &[u8], is_last: bool) -> Option<&[u8]> {
+    let delimiter_index = record.iter().rposition(|&b| b != 0)?;
+    let delimiter = record[delimiter_index];
+    let expected = if is_last { 0x01 } else { 0x02 };
+    if delimiter != expected {
+        return None;
+    }
+    Some(&record[..delimiter_index])
+}
@@ -7,24 +7,60 @@
 //! separated from TPM quote operations. Part of Unified Identity (Pillar 2 Task 2).
 //!
 //! Endpoint: GET /v2/agent/attested_geolocation
+//! Pass `?secured=true` for an ETSI ITS-style signed position beacon: the
+//! GNSS fix is signed with the agent's AK and `signing_cert_chain` is
+//! populated so a verifier can check authenticity/freshness offline.
 //!
 //! Features:
 //! - Nested mobile/GNSS sensor structure
 //! - PCR 17 attestation binding
 //! - Feature flag gating (unified_identity_enabled)
+//!
+//! Also exposes a bounded in-memory history of past attested fixes:
+//! - GET /v2/agent/geolocation_history (JSON)
+//! - GET /v2/agent/geolocation_history.gpx (GPX 1.1 track)
+//!
+//! When `geolocation_capability_enabled` is set, the main endpoint
+//! additionally requires a UCAN-style capability bearer token in the
+//! `Authorization` header; see [`verify_capability_token`].
 
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use base64::{engine::general_purpose, Engine as _};
 use keylime::json_wrapper::JsonWrapper; // Fixed import
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
 use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::QuoteData;
 
+/// Serial device nodes probed, in order, for an attached GNSS receiver.
+const GNSS_SERIAL_CANDIDATES: &[&str] = &["/dev/ttyUSB0", "/dev/ttyACM0", "/dev/gps", "/dev/gps0"];
+
+/// Number of NMEA lines read from the serial port before giving up on a fix.
+const GNSS_READ_LINE_BUDGET: usize = 50;
+
+/// Device nodes probed, in order, for an attached UWB ranging transport.
+const UWB_DEVICE_CANDIDATES: &[&str] = &["/dev/uwb0", "/dev/ttyUWB0"];
+
+/// Number of lines read from the UWB device before giving up on a ranging report.
+const UWB_READ_LINE_BUDGET: usize = 10;
+
+/// Maximum number of attested fixes retained in the in-memory history ring
+/// buffer before the oldest entry is evicted.
+const GEOLOCATION_HISTORY_CAPACITY: usize = 500;
+
 /// Request parameters for geolocation endpoint
 #[derive(Deserialize, Debug)]
 pub struct GeolocationRequest {
     pub nonce: String, // Required for TOCTOU protection
+    /// When `true`, sign a canonical encoding of the GNSS fix with the
+    /// agent's AK (ETSI ITS-style secured position beacon) and populate
+    /// `GNSSSensor.sensor_signature` plus `GeolocationResponse.signing_cert_chain`.
+    #[serde(default)]
+    pub secured: bool,
 }
 
 /// Nested geolocation response structure
@@ -35,9 +71,17 @@ pub struct GeolocationResponse {
     pub mobile: Option<MobileSensor>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gnss: Option<GNSSSensor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uwb: Option<UwbSensor>,
     pub tpm_attested: bool, // Always true for this endpoint
     pub tpm_pcr_index: u32,  // PCR 15 for geolocation
     pub nonce: String, // Nonce used in attestation (for verification)
+    /// PEM-encoded signing certificate chain for `gnss.sensor_signature`,
+    /// present only when the request set `secured=true`. Lets a verifier
+    /// check position authenticity/freshness offline, independent of the
+    /// PCR 15 quote.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_cert_chain: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -45,6 +89,19 @@ pub struct MobileSensor {
     pub sensor_id: String,
     pub sensor_imei: String,
     pub sensor_imsi: String,
+    /// Battery level, percent (0-100), when the modem script reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_percent: Option<u8>,
+    /// Cellular signal strength in dBm (RSSI/RSRP, whichever the modem
+    /// script surfaces), when present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_strength_dbm: Option<i32>,
+    /// Paired Bluetooth tag/beacon identifier, when present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bluetooth_id: Option<String>,
+    /// NFC tag identifier, when present.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nfc_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -59,6 +116,38 @@ pub struct GNSSSensor {
     pub sensor_signature: Option<String>, // Optional - GNSS sensor's own signature
 }
 
+/// Indoor/edge ultra-wideband ranging: no absolute lat/lon, just distance
+/// (and, where the anchor supports angle-of-arrival, azimuth/elevation)
+/// measurements against a set of fixed anchors.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UwbSensor {
+    pub sensor_id: String, // UWB session/device ID
+    pub anchors: Vec<UwbAnchorMeasurement>,
+    pub confidence: f64, // Reported ranging confidence, 0.0-1.0
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UwbAnchorMeasurement {
+    pub anchor_address: String,
+    pub distance_cm: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub azimuth_deg: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation_deg: Option<f64>,
+}
+
+/// A single attested fix retained in the history ring buffer: the response
+/// returned to the caller, the UTC time it was attested, and the PCR 15
+/// digest it was extended with (the SHA-256 of geolocation + nonce, the same
+/// value logged by [`extend_pcr_15_with_geolocation_and_nonce`] - PCR 15 is
+/// never read back here, only the digest it was extended with).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GeolocationHistoryEntry {
+    pub response: GeolocationResponse,
+    pub timestamp_unix: u64,
+    pub pcr15_digest: String,
+}
+
 /// Raw sensor data detected from system
 #[derive(Debug, Clone)]
 struct RawSensorData {
@@ -70,13 +159,127 @@ struct RawSensorData {
     lat: Option<f64>,
     lon: Option<f64>,
     accuracy: Option<f64>,
+    // UWB fields
+    uwb_anchors: Option<Vec<UwbAnchorMeasurement>>,
+    uwb_confidence: Option<f64>,
+    // Mobile device-health fields
+    battery_percent: Option<u8>,
+    signal_strength_dbm: Option<i32>,
+    bluetooth_id: Option<String>,
+    nfc_id: Option<String>,
+}
+
+/// Authorization scope granted by a verified capability token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GeolocationScope {
+    /// Full-precision fix plus mobile identifiers (`geolocation/read`).
+    Full,
+    /// Reduced-precision fix, mobile identifiers redacted (`geolocation/read:coarse`).
+    Coarse,
+}
+
+/// Claims carried by a capability token's payload segment.
+#[derive(Deserialize)]
+struct CapabilityClaims {
+    /// Audience this token was issued for - this agent's UUID, standing in
+    /// for a full DID/AK fingerprint until one is wired up.
+    aud: String,
+    /// Unix expiry timestamp.
+    exp: u64,
+    /// Granted capabilities, e.g. `["geolocation/read:coarse"]`.
+    cap: Vec<String>,
+}
+
+/// Verify a UCAN-style capability bearer token from the `Authorization`
+/// header: a compact `base64url(header).base64url(payload).base64url(sig)`
+/// token whose payload declares an audience, an expiry, and a capability
+/// set, signed (SHA-256) by the configured trusted issuer key. Returns the
+/// granted [`GeolocationScope`], or an error describing the rejection.
+fn verify_capability_token(
+    data: &QuoteData,
+    authorization_header: &str,
+) -> Result<GeolocationScope, String> {
+    use openssl::hash::MessageDigest;
+    use openssl::sign::Verifier;
+
+    let issuer_pubkey = data
+        .geolocation_capability_issuer_pubkey
+        .as_ref()
+        .ok_or_else(|| "No capability token issuer key configured".to_string())?;
+
+    let token = authorization_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| "Authorization header must be a Bearer token".to_string())?;
+
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err("Capability token must have 3 dot-separated parts".to_string());
+    }
+    let (header_b64, payload_b64, signature_b64) = (parts[0], parts[1], parts[2]);
+
+    let payload_json = general_purpose::URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .map_err(|e| format!("Failed to base64url-decode token payload: {e}"))?;
+    let claims: CapabilityClaims = serde_json::from_slice(&payload_json)
+        .map_err(|e| format!("Failed to parse token payload: {e}"))?;
+
+    let signature = general_purpose::URL_SAFE_NO_PAD
+        .decode(signature_b64)
+        .map_err(|e| format!("Failed to base64url-decode token signature: {e}"))?;
+
+    let signed_data = format!("{header_b64}.{payload_b64}");
+    let mut verifier = Verifier::new(MessageDigest::sha256(), issuer_pubkey)
+        .map_err(|e| format!("Failed to create signature verifier: {e}"))?;
+    verifier
+        .update(signed_data.as_bytes())
+        .map_err(|e| format!("Failed to hash token for verification: {e}"))?;
+    if !verifier
+        .verify(&signature)
+        .map_err(|e| format!("Failed to verify token signature: {e}"))?
+    {
+        return Err("Capability token signature is invalid".to_string());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if claims.exp <= now {
+        return Err("Capability token has expired".to_string());
+    }
+
+    if claims.aud != data.agent_uuid {
+        return Err("Capability token audience does not match this agent".to_string());
+    }
+
+    if claims.cap.iter().any(|c| c == "geolocation/read") {
+        Ok(GeolocationScope::Full)
+    } else if claims.cap.iter().any(|c| c == "geolocation/read:coarse") {
+        Ok(GeolocationScope::Coarse)
+    } else {
+        Err("Capability token does not grant a geolocation/read capability".to_string())
+    }
+}
+
+/// Round GNSS coordinates to ~1km precision and redact mobile identifiers,
+/// per the `geolocation/read:coarse` capability.
+fn apply_coarse_redaction(response: &mut GeolocationResponse) {
+    if let Some(gnss) = response.gnss.as_mut() {
+        gnss.latitude = (gnss.latitude * 100.0).round() / 100.0;
+        gnss.longitude = (gnss.longitude * 100.0).round() / 100.0;
+    }
+    if let Some(mobile) = response.mobile.as_mut() {
+        mobile.sensor_imei = "redacted".to_string();
+        mobile.sensor_imsi = "redacted".to_string();
+    }
 }
 
 /// Main endpoint handler for attested geolocation
 /// Requires nonce parameter for TOCTOU protection
 pub(crate) async fn attested_geolocation(
+    req: HttpRequest,
     query: web::Query<GeolocationRequest>,
-    data: web::Data<QuoteData<'_>>,
+    data: web::Data<QuoteData>,
 ) -> impl Responder {
     // Feature flag check
     if !data.unified_identity_enabled {
@@ -87,6 +290,37 @@ pub(crate) async fn attested_geolocation(
         ));
     }
 
+    // Unified-Identity: UCAN-style capability-token gating. Off by default;
+    // when enabled, a caller without a valid `geolocation/read[:coarse]`
+    // bearer token never reaches sensor detection at all.
+    let scope = if data.geolocation_capability_enabled {
+        let auth_header = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok());
+        match auth_header {
+            None => {
+                warn!("Unified-Identity: Geolocation request missing Authorization header");
+                return HttpResponse::Unauthorized().json(JsonWrapper::error(
+                    401,
+                    "Missing Authorization bearer capability token".to_string(),
+                ));
+            }
+            Some(header) => match verify_capability_token(&data, header) {
+                Ok(scope) => scope,
+                Err(e) => {
+                    warn!("Unified-Identity: Geolocation capability token rejected: {}", e);
+                    return HttpResponse::Unauthorized().json(JsonWrapper::error(
+                        401,
+                        format!("Capability token rejected: {}", e),
+                    ));
+                }
+            },
+        }
+    } else {
+        GeolocationScope::Full
+    };
+
     info!(
         "Unified-Identity: Geolocation request with nonce: {}",
         &query.nonce[..8.min(query.nonce.len())]
@@ -105,19 +339,104 @@ pub(crate) async fn attested_geolocation(
 
     let raw_sensor = sensor_data.unwrap(); //#[allow_ci]
 
+    // A detected GNSS sensor with no decoded fix has no real coordinates to
+    // report; reject rather than attesting fake (0.0, 0.0) zeros into PCR 15.
+    if raw_sensor.sensor_type == "gnss" && raw_sensor.lat.is_none() {
+        info!("Unified-Identity: GNSS sensor detected but no fix available");
+        return HttpResponse::NotFound().json(JsonWrapper::error(
+            404,
+            "GNSS sensor detected but no fix available".to_string(),
+        ));
+    }
+
+    // Same TOCTOU reasoning applies to UWB ranging: an empty anchor set has
+    // no real position evidence to attest.
+    if raw_sensor.sensor_type == "uwb" && raw_sensor.uwb_anchors.is_none() {
+        info!("Unified-Identity: UWB sensor detected but no ranging report available");
+        return HttpResponse::NotFound().json(JsonWrapper::error(
+            404,
+            "UWB sensor detected but no ranging report available".to_string(),
+        ));
+    }
+
     // Build nested structure (without nonce first)
     let mut response = build_nested_geolocation(raw_sensor);
     
     // Add nonce to response
     response.nonce = query.nonce.clone();
 
+    // Unified-Identity: honor a `geolocation/read:coarse` capability by
+    // reducing precision/omitting identifiers before anything - including
+    // the PCR 15 hash and a secured signature below - is computed over it.
+    if scope == GeolocationScope::Coarse {
+        apply_coarse_redaction(&mut response);
+    }
+
+    // Unified-Identity: ETSI ITS-style secured position beacon. Signs a
+    // canonical encoding of the fix so a verifier can check authenticity and
+    // freshness offline, independent of PCR 15 quote verification. Best
+    // effort: a signing failure doesn't block the core PCR-bound attestation.
+    if query.secured {
+        if let Some(gnss) = response.gnss.clone() {
+            let timestamp_unix = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            match sign_position(
+                &data,
+                gnss.latitude,
+                gnss.longitude,
+                gnss.accuracy,
+                timestamp_unix,
+                &query.nonce,
+            )
+            .await
+            {
+                Ok(signature_field) => {
+                    if let Some(gnss_mut) = response.gnss.as_mut() {
+                        gnss_mut.sensor_signature = Some(signature_field);
+                    }
+                    response.signing_cert_chain =
+                        Some(data.geolocation_signing_cert_chain.clone());
+                }
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity: Failed to sign secured position beacon: {}",
+                        e
+                    );
+                }
+            }
+        }
+    }
+
     // CRITICAL: Extend PCR 15 with geolocation + nonce for TOCTOU protection
-    if let Err(e) = extend_pcr_15_with_geolocation_and_nonce(&data, &response, &query.nonce) {
-        warn!("Unified-Identity: Failed to extend PCR 15: {}", e);
-        return HttpResponse::InternalServerError().json(JsonWrapper::error(
-            500,
-            format!("Failed to extend PCR 15: {}", e),
-        ));
+    let pcr15_digest =
+        match extend_pcr_15_with_geolocation_and_nonce(&data, &response, &query.nonce).await {
+            Ok(digest) => digest,
+            Err(e) => {
+                warn!("Unified-Identity: Failed to extend PCR 15: {}", e);
+                return HttpResponse::InternalServerError().json(JsonWrapper::error(
+                    500,
+                    format!("Failed to extend PCR 15: {}", e),
+                ));
+            }
+        };
+
+    // Record the attested fix in the bounded history ring buffer.
+    let timestamp_unix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    {
+        let mut history = data.geolocation_history.lock().unwrap(); //#[allow_ci]
+        if history.len() >= GEOLOCATION_HISTORY_CAPACITY {
+            history.pop_front();
+        }
+        history.push_back(GeolocationHistoryEntry {
+            response: response.clone(),
+            timestamp_unix,
+            pcr15_digest,
+        });
     }
 
     info!(
@@ -136,11 +455,17 @@ fn build_nested_geolocation(raw: RawSensorData) -> GeolocationResponse {
                 sensor_id: raw.sensor_id.clone(),
                 sensor_imei: raw.imei.unwrap_or_else(|| "unknown".to_string()),
                 sensor_imsi: raw.imsi.unwrap_or_else(|| "unknown".to_string()),
+                battery_percent: raw.battery_percent,
+                signal_strength_dbm: raw.signal_strength_dbm,
+                bluetooth_id: raw.bluetooth_id.clone(),
+                nfc_id: raw.nfc_id.clone(),
             }),
             gnss: None,
+            uwb: None,
             tpm_attested: true,
             tpm_pcr_index: 15, // PCR 15 dedicated to geolocation
             nonce: String::new(), // Filled in by handler
+            signing_cert_chain: None,
         },
         "gnss" => GeolocationResponse {
             sensor_type: "gnss".to_string(),
@@ -153,9 +478,25 @@ fn build_nested_geolocation(raw: RawSensorData) -> GeolocationResponse {
                 accuracy: raw.accuracy.unwrap_or(0.0),
                 sensor_signature: None, // Optional field
             }),
+            uwb: None,
+            tpm_attested: true,
+            tpm_pcr_index: 15,
+            nonce: String::new(), // Filled in by handler
+            signing_cert_chain: None,
+        },
+        "uwb" => GeolocationResponse {
+            sensor_type: "uwb".to_string(),
+            mobile: None,
+            gnss: None,
+            uwb: Some(UwbSensor {
+                sensor_id: raw.sensor_id.clone(),
+                anchors: raw.uwb_anchors.clone().unwrap_or_default(),
+                confidence: raw.uwb_confidence.unwrap_or(0.0),
+            }),
             tpm_attested: true,
             tpm_pcr_index: 15,
             nonce: String::new(), // Filled in by handler
+            signing_cert_chain: None,
         },
         _ => {
             // Fallback to mobile with unknown values
@@ -165,11 +506,17 @@ fn build_nested_geolocation(raw: RawSensorData) -> GeolocationResponse {
                     sensor_id: raw.sensor_id.clone(),
                     sensor_imei: "unknown".to_string(),
                     sensor_imsi: "unknown".to_string(),
+                    battery_percent: None,
+                    signal_strength_dbm: None,
+                    bluetooth_id: None,
+                    nfc_id: None,
                 }),
                 gnss: None,
+                uwb: None,
                 tpm_attested: true,
                 tpm_pcr_index: 15,
                 nonce: String::new(), // Filled in by handler
+                signing_cert_chain: None,
             }
         }
     }
@@ -192,17 +539,23 @@ fn detect_geolocation_sensor() -> Option<RawSensorData> {
                         sensor_id
                     );
 
-                    // Get IMEI and IMSI from script
-                    let (imei, imsi) = get_imei_imsi();
+                    // Get IMEI/IMSI and device-health telemetry from script
+                    let telemetry = get_mobile_telemetry();
 
                     return Some(RawSensorData {
                         sensor_type: "mobile".to_string(),
                         sensor_id,
-                        imei,
-                        imsi,
+                        imei: telemetry.imei,
+                        imsi: telemetry.imsi,
                         lat: None,
                         lon: None,
                         accuracy: None,
+                        uwb_anchors: None,
+                        uwb_confidence: None,
+                        battery_percent: telemetry.battery_percent,
+                        signal_strength_dbm: telemetry.signal_strength_dbm,
+                        bluetooth_id: telemetry.bluetooth_id,
+                        nfc_id: telemetry.nfc_id,
                     });
                 }
 
@@ -215,14 +568,53 @@ fn detect_geolocation_sensor() -> Option<RawSensorData> {
                         "Unified-Identity: GNSS/GPS sensor detected via lsusb: {}",
                         sensor_id
                     );
+                    let fix = GNSS_SERIAL_CANDIDATES.iter().find_map(|path| read_gnss_fix(path));
+                    if fix.is_none() {
+                        debug!("Unified-Identity: GNSS sensor {sensor_id} detected but no fix decoded from any serial candidate");
+                    }
                     return Some(RawSensorData {
                         sensor_type: "gnss".to_string(),
                         sensor_id,
                         imei: None,
                         imsi: None,
-                        lat: None, // TODO: Parse from GNSS device
+                        lat: fix.map(|f| f.lat),
+                        lon: fix.map(|f| f.lon),
+                        accuracy: fix.map(|f| f.accuracy),
+                        uwb_anchors: None,
+                        uwb_confidence: None,
+                        battery_percent: None,
+                        signal_strength_dbm: None,
+                        bluetooth_id: None,
+                        nfc_id: None,
+                    });
+                }
+
+                if line_lower.contains("uwb") || line_lower.contains("ultra-wideband") {
+                    let sensor_id = extract_usb_id(line);
+                    info!(
+                        "Unified-Identity: UWB ranging sensor detected via lsusb: {}",
+                        sensor_id
+                    );
+                    let ranging = UWB_DEVICE_CANDIDATES
+                        .iter()
+                        .find_map(|path| read_uwb_measurements(path));
+                    if ranging.is_none() {
+                        debug!("Unified-Identity: UWB sensor {sensor_id} detected but no ranging report decoded from any device candidate");
+                    }
+                    return Some(RawSensorData {
+                        sensor_type: "uwb".to_string(),
+                        sensor_id,
+                        imei: None,
+                        imsi: None,
+                        lat: None,
                         lon: None,
                         accuracy: None,
+                        uwb_anchors: ranging.as_ref().map(|r| r.0.clone()),
+                        uwb_confidence: ranging.as_ref().map(|r| r.1),
+                        battery_percent: None,
+                        signal_strength_dbm: None,
+                        bluetooth_id: None,
+                        nfc_id: None,
                     });
                 }
             }
@@ -233,19 +625,53 @@ fn detect_geolocation_sensor() -> Option<RawSensorData> {
     }
 
     // Fallback: Check for GNSS device nodes
-    let gnss_paths = ["/dev/ttyUSB0", "/dev/ttyACM0", "/dev/gps", "/dev/gps0"];
-
-    for path in &gnss_paths {
+    for path in GNSS_SERIAL_CANDIDATES {
         if std::path::Path::new(path).exists() {
             info!("Unified-Identity: GNSS device detected at {}", path);
+            let fix = read_gnss_fix(path);
+            if fix.is_none() {
+                debug!("Unified-Identity: GNSS device at {path} detected but no fix decoded");
+            }
             return Some(RawSensorData {
                 sensor_type: "gnss".to_string(),
                 sensor_id: path.to_string(),
                 imei: None,
                 imsi: None,
+                lat: fix.map(|f| f.lat),
+                lon: fix.map(|f| f.lon),
+                accuracy: fix.map(|f| f.accuracy),
+                uwb_anchors: None,
+                uwb_confidence: None,
+                battery_percent: None,
+                signal_strength_dbm: None,
+                bluetooth_id: None,
+                nfc_id: None,
+            });
+        }
+    }
+
+    // Fallback: Check for UWB ranging device nodes
+    for path in UWB_DEVICE_CANDIDATES {
+        if std::path::Path::new(path).exists() {
+            info!("Unified-Identity: UWB ranging device detected at {}", path);
+            let ranging = read_uwb_measurements(path);
+            if ranging.is_none() {
+                debug!("Unified-Identity: UWB device at {path} detected but no ranging report decoded");
+            }
+            return Some(RawSensorData {
+                sensor_type: "uwb".to_string(),
+                sensor_id: path.to_string(),
+                imei: None,
+                imsi: None,
                 lat: None,
                 lon: None,
                 accuracy: None,
+                uwb_anchors: ranging.as_ref().map(|r| r.0.clone()),
+                uwb_confidence: ranging.as_ref().map(|r| r.1),
+                battery_percent: None,
+                signal_strength_dbm: None,
+                bluetooth_id: None,
+                nfc_id: None,
             });
         }
     }
@@ -253,6 +679,144 @@ fn detect_geolocation_sensor() -> Option<RawSensorData> {
     None
 }
 
+/// A GNSS position decoded from a `$GxGGA` sentence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NmeaFix {
+    lat: f64,
+    lon: f64,
+    accuracy: f64,
+}
+
+/// Verify an NMEA sentence's checksum: the two trailing hex digits after
+/// `*` must equal the XOR of every byte between `$` and `*`.
+fn verify_nmea_checksum(sentence: &str) -> bool {
+    let sentence = sentence.trim();
+    let Some(body) = sentence.strip_prefix('$') else {
+        return false;
+    };
+    let Some(star) = body.find('*') else {
+        return false;
+    };
+    let (data, checksum_part) = body.split_at(star);
+    let checksum_hex = &checksum_part[1..];
+    if checksum_hex.len() < 2 {
+        return false;
+    }
+    let Ok(expected) = u8::from_str_radix(&checksum_hex[..2], 16) else {
+        return false;
+    };
+    data.bytes().fold(0u8, |acc, b| acc ^ b) == expected
+}
+
+/// Convert an NMEA `ddmm.mmmm`/`dddmm.mmmm` coordinate field into signed
+/// decimal degrees: `deg = floor(v/100); decimal = deg + (v - deg*100)/60`.
+/// The hemisphere letter is applied by the caller.
+fn nmea_coord_to_decimal(raw: &str) -> Option<f64> {
+    let v: f64 = raw.parse().ok()?;
+    let deg = (v / 100.0).floor();
+    Some(deg + (v - deg * 100.0) / 60.0)
+}
+
+/// Parse a `$GxGGA` sentence into an [`NmeaFix`], rejecting it if the
+/// checksum is invalid or the fix quality field (6) is `0` (no fix).
+fn parse_gga(sentence: &str) -> Option<NmeaFix> {
+    if !verify_nmea_checksum(sentence) {
+        return None;
+    }
+    let body = sentence.trim().trim_start_matches('$');
+    let body = &body[..body.find('*').unwrap_or(body.len())];
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields.len() < 9 || !fields[0].ends_with("GGA") {
+        return None;
+    }
+
+    let fix_quality: u8 = fields[6].parse().unwrap_or(0);
+    if fix_quality == 0 {
+        return None;
+    }
+
+    let mut lat = nmea_coord_to_decimal(fields[2])?;
+    if fields[3].eq_ignore_ascii_case("S") {
+        lat = -lat;
+    }
+    let mut lon = nmea_coord_to_decimal(fields[4])?;
+    if fields[5].eq_ignore_ascii_case("W") {
+        lon = -lon;
+    }
+    let accuracy = fields[8].parse::<f64>().unwrap_or(0.0);
+
+    Some(NmeaFix { lat, lon, accuracy })
+}
+
+/// Open `path` as a 9600-baud serial device and read a handful of NMEA
+/// lines looking for a `$GxGGA` fix. Returns `None` if the device can't be
+/// opened or no valid, checksummed, non-zero-quality fix is seen within the
+/// read budget - callers must never extend PCR 15 with fake zeros.
+fn read_gnss_fix(path: &str) -> Option<NmeaFix> {
+    let port = serialport::new(path, 9600)
+        .timeout(Duration::from_secs(2))
+        .open()
+        .map_err(|e| debug!("Unified-Identity: Failed to open GNSS serial device {path}: {e}"))
+        .ok()?;
+
+    BufReader::new(port)
+        .lines()
+        .take(GNSS_READ_LINE_BUDGET)
+        .flatten()
+        .find_map(|line| parse_gga(&line))
+}
+
+/// A single anchor ranging report plus confidence, as emitted by a UWB
+/// transport on one line of newline-delimited JSON:
+/// `{"anchors":[{"addr":"..","distance_cm":123,"azimuth_deg":12.3,"elevation_deg":4.5}],"confidence":0.9}`.
+#[derive(Deserialize)]
+struct UwbRangingReport {
+    anchors: Vec<UwbRangingAnchor>,
+    confidence: f64,
+}
+
+#[derive(Deserialize)]
+struct UwbRangingAnchor {
+    addr: String,
+    distance_cm: u32,
+    #[serde(default)]
+    azimuth_deg: Option<f64>,
+    #[serde(default)]
+    elevation_deg: Option<f64>,
+}
+
+/// Open `path` as a serial/character device and read a handful of lines
+/// looking for a UWB ranging report. Returns `None` if the device can't be
+/// opened or no valid, non-empty ranging report is seen within the read
+/// budget - callers must never attest a position with no anchor evidence.
+fn read_uwb_measurements(path: &str) -> Option<(Vec<UwbAnchorMeasurement>, f64)> {
+    let port = serialport::new(path, 115_200)
+        .timeout(Duration::from_secs(2))
+        .open()
+        .map_err(|e| debug!("Unified-Identity: Failed to open UWB device {path}: {e}"))
+        .ok()?;
+
+    let report = BufReader::new(port)
+        .lines()
+        .take(UWB_READ_LINE_BUDGET)
+        .flatten()
+        .find_map(|line| serde_json::from_str::<UwbRangingReport>(line.trim()).ok())
+        .filter(|report| !report.anchors.is_empty())?;
+
+    let anchors = report
+        .anchors
+        .into_iter()
+        .map(|a| UwbAnchorMeasurement {
+            anchor_address: a.addr,
+            distance_cm: a.distance_cm,
+            azimuth_deg: a.azimuth_deg,
+            elevation_deg: a.elevation_deg,
+        })
+        .collect();
+
+    Some((anchors, report.confidence))
+}
+
 /// Extract USB device ID from lsusb output line
 fn extract_usb_id(line: &str) -> String {
     // lsusb format: "Bus 001 Device 005: ID 12d1:1433 Huawei Technologies Co., Ltd."
@@ -265,8 +829,36 @@ fn extract_usb_id(line: &str) -> String {
     "unknown".to_string()
 }
 
-/// Get IMEI and IMSI from Huawei script
-fn get_imei_imsi() -> (Option<String>, Option<String>) {
+/// Device-health telemetry scraped alongside IMEI/IMSI from the modem
+/// script, mirroring the fields a location-tracker tag model exposes.
+#[derive(Debug, Clone, Default)]
+struct ModemTelemetry {
+    imei: Option<String>,
+    imsi: Option<String>,
+    battery_percent: Option<u8>,
+    signal_strength_dbm: Option<i32>,
+    bluetooth_id: Option<String>,
+    nfc_id: Option<String>,
+}
+
+/// Pull the trimmed value following `marker` out of `line`, rejecting the
+/// sentinel "not present" values the modem script uses in place of a reading.
+fn extract_script_field<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    if !line.contains(marker) {
+        return None;
+    }
+    let colon_pos = line.find(':')?;
+    let value = line[colon_pos + 1..].trim();
+    if value.is_empty() || value == "Missing" || value == "Locked/Unreadable" {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Get IMEI/IMSI and device-health telemetry (battery, cellular signal
+/// strength, paired Bluetooth/NFC tag identifiers) from the Huawei script.
+fn get_mobile_telemetry() -> ModemTelemetry {
     let script_paths = [
         "/usr/local/bin/get_imei_imsi_huawei.sh",
         "./get_imei_imsi_huawei.sh",
@@ -279,57 +871,64 @@ fn get_imei_imsi() -> (Option<String>, Option<String>) {
         }
 
         debug!(
-            "Unified-Identity: Running script to get IMEI/IMSI: {}",
+            "Unified-Identity: Running script to get mobile telemetry: {}",
             script_path
         );
 
         match Command::new(script_path).output() {
             Ok(output) => {
                 let stdout = String::from_utf8_lossy(&output.stdout);
-                let mut imei: Option<String> = None;
-                let mut imsi: Option<String> = None;
+                let mut telemetry = ModemTelemetry::default();
 
                 for line in stdout.lines() {
                     // Look for "SIM IMEI:   <value>"
-                    if line.contains("SIM IMEI:") {
-                        if let Some(colon_pos) = line.find(':') {
-                            let value = line[colon_pos + 1..].trim();
-                            if !value.is_empty()
-                                && value != "Missing"
-                                && value != "Locked/Unreadable"
-                            {
-                                imei = Some(value.to_string());
-                                debug!(
-                                    "Unified-Identity: Found IMEI in script output: {}",
-                                    value
-                                );
+                    if let Some(value) = extract_script_field(line, "SIM IMEI:") {
+                        debug!("Unified-Identity: Found IMEI in script output: {}", value);
+                        telemetry.imei = Some(value.to_string());
+                    }
+                    // Look for "SIM IMSI:   <value>"
+                    if let Some(value) = extract_script_field(line, "SIM IMSI:") {
+                        debug!("Unified-Identity: Found IMSI in script output: {}", value);
+                        telemetry.imsi = Some(value.to_string());
+                    }
+                    // Look for "Battery Level:   <value>%"
+                    if let Some(value) = extract_script_field(line, "Battery Level:") {
+                        match value.trim_end_matches('%').trim().parse::<u8>() {
+                            Ok(percent) => {
+                                debug!("Unified-Identity: Found battery level in script output: {}%", percent);
+                                telemetry.battery_percent = Some(percent);
                             }
+                            Err(e) => debug!("Unified-Identity: Failed to parse battery level '{}': {}", value, e),
                         }
                     }
-                    // Look for "SIM IMSI:   <value>"
-                    if line.contains("SIM IMSI:") {
-                        if let Some(colon_pos) = line.find(':') {
-                            let value = line[colon_pos + 1..].trim();
-                            if !value.is_empty()
-                                && value != "Missing"
-                                && value != "Locked/Unreadable"
-                            {
-                                imsi = Some(value.to_string());
-                                debug!(
-                                    "Unified-Identity: Found IMSI in script output: {}",
-                                    value
-                                );
+                    // Look for "Signal Strength:   <value> dBm" (RSSI/RSRP)
+                    if let Some(value) = extract_script_field(line, "Signal Strength:") {
+                        match value.trim_end_matches("dBm").trim().parse::<i32>() {
+                            Ok(dbm) => {
+                                debug!("Unified-Identity: Found signal strength in script output: {} dBm", dbm);
+                                telemetry.signal_strength_dbm = Some(dbm);
                             }
+                            Err(e) => debug!("Unified-Identity: Failed to parse signal strength '{}': {}", value, e),
                         }
                     }
+                    // Look for "Bluetooth ID:   <value>"
+                    if let Some(value) = extract_script_field(line, "Bluetooth ID:") {
+                        debug!("Unified-Identity: Found Bluetooth ID in script output: {}", value);
+                        telemetry.bluetooth_id = Some(value.to_string());
+                    }
+                    // Look for "NFC Tag ID:   <value>"
+                    if let Some(value) = extract_script_field(line, "NFC Tag ID:") {
+                        debug!("Unified-Identity: Found NFC tag ID in script output: {}", value);
+                        telemetry.nfc_id = Some(value.to_string());
+                    }
                 }
 
-                if imei.is_some() || imsi.is_some() {
+                if telemetry.imei.is_some() || telemetry.imsi.is_some() {
                     info!(
-                        "Unified-Identity: Retrieved IMEI/IMSI from script {}: IMEI={:?}, IMSI={:?}",
-                        script_path, imei, imsi
+                        "Unified-Identity: Retrieved mobile telemetry from script {}: IMEI={:?}, IMSI={:?}, battery={:?}%, signal={:?}dBm",
+                        script_path, telemetry.imei, telemetry.imsi, telemetry.battery_percent, telemetry.signal_strength_dbm
                     );
-                    return (imei, imsi);
+                    return telemetry;
                 } else {
                     debug!(
                         "Unified-Identity: Script {} ran successfully but no IMEI/IMSI found in output",
@@ -346,7 +945,7 @@ fn get_imei_imsi() -> (Option<String>, Option<String>) {
         }
     }
 
-    (None, None)
+    ModemTelemetry::default()
 }
 
 
@@ -361,12 +960,12 @@ fn get_imei_imsi() -> (Option<String>, Option<String>) {
 ///
 /// Security: The nonce ensures geolocation freshness. An attacker cannot reuse
 /// old geolocation data with a new nonce because the PCR 15 hash won't match.
-fn extend_pcr_15_with_geolocation_and_nonce(
+async fn extend_pcr_15_with_geolocation_and_nonce(
     quote_data: &QuoteData,
     geolocation: &GeolocationResponse,
     nonce: &str,
-) -> Result<(), String> {
-    use keylime::tpm;
+) -> Result<String, String> {
+    use crate::{TpmMessage, TpmReply};
     use openssl::hash::{Hasher, MessageDigest};
     use tss_esapi::structures::{DigestValues, PcrSlot};
     use tss_esapi::interface_types::algorithm::HashingAlgorithm;
@@ -376,6 +975,7 @@ fn extend_pcr_15_with_geolocation_and_nonce(
         "sensor_type": geolocation.sensor_type,
         "mobile": geolocation.mobile,
         "gnss": geolocation.gnss,
+        "uwb": geolocation.uwb,
         "tpm_attested": geolocation.tpm_attested,
         "tpm_pcr_index": geolocation.tpm_pcr_index,
     });
@@ -413,17 +1013,278 @@ fn extend_pcr_15_with_geolocation_and_nonce(
         .map_err(|e| format!("Failed to create TPM digest: {}", e))?;
     digest_values.set(HashingAlgorithm::Sha256, digest);
 
-    // 5. Access TPM context and extend PCR 15
-    let mut tpm_ctx = quote_data.tpmcontext.lock()
-        .map_err(|e| format!("Failed to lock TPM context: {}", e))?;
-    
-    tpm_ctx.extend_pcr(tss_esapi::handles::PcrHandle::Pcr15, digest_values)
-        .map_err(|e| format!("Failed to extend PCR 15: {:?}", e))?;
+    // 5. Send the extend request to the dedicated TPM worker task rather than
+    // locking a shared `tpm::Context` directly.
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    let message = TpmMessage::ExtendPcr {
+        pcr: tss_esapi::handles::PcrHandle::Pcr15,
+        digest_values,
+        reset_first: false,
+    };
+    quote_data
+        .tpm_tx
+        .send((message, reply_tx))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    match reply_rx
+        .await
+        .map_err(|e| format!("TPM worker dropped reply channel: {e}"))?
+    {
+        TpmReply::ExtendPcr(result) => {
+            result.map_err(|e| format!("Failed to extend PCR 15: {:?}", e))?
+        }
+        _ => return Err("Unexpected reply from TPM worker for ExtendPcr request".to_string()),
+    }
 
     info!(
         "Unified-Identity: PCR 15 extended with geolocation + nonce (nonce: {}...)",
         &nonce[..8.min(nonce.len())]
     );
 
-    Ok(())
+    Ok(hex::encode(&hash_bytes))
+}
+
+/// Canonical byte encoding of `(latitude, longitude, accuracy, timestamp,
+/// nonce)` signed by [`sign_position`], modeled on ETSI ITS GeoNetworking
+/// secured packets: each coordinate as 1e-7-degree fixed point and accuracy
+/// in centimeters, both big-endian `i64`, followed by the big-endian Unix
+/// timestamp and the raw nonce bytes.
+fn canonical_position_bytes(
+    lat: f64,
+    lon: f64,
+    accuracy: f64,
+    timestamp_unix: u64,
+    nonce: &str,
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8 * 4 + nonce.len());
+    buf.extend_from_slice(&((lat * 1e7).round() as i64).to_be_bytes());
+    buf.extend_from_slice(&((lon * 1e7).round() as i64).to_be_bytes());
+    buf.extend_from_slice(&((accuracy * 100.0).round() as i64).to_be_bytes());
+    buf.extend_from_slice(&timestamp_unix.to_be_bytes());
+    buf.extend_from_slice(nonce.as_bytes());
+    buf
+}
+
+/// Sign a GNSS fix for the secured position beacon: hash
+/// [`canonical_position_bytes`] and sign the digest with the agent's AK via
+/// the TPM worker task. Returns `"<key identifier>:<base64 signature>"`.
+async fn sign_position(
+    quote_data: &QuoteData,
+    lat: f64,
+    lon: f64,
+    accuracy: f64,
+    timestamp_unix: u64,
+    nonce: &str,
+) -> Result<String, String> {
+    use crate::{TpmMessage, TpmReply};
+    use openssl::hash::{Hasher, MessageDigest};
+    use tss_esapi::traits::Marshall;
+
+    let payload = canonical_position_bytes(lat, lon, accuracy, timestamp_unix, nonce);
+    let mut hasher = Hasher::new(MessageDigest::sha256())
+        .map_err(|e| format!("Failed to create hasher: {}", e))?;
+    hasher
+        .update(&payload)
+        .map_err(|e| format!("Failed to update hasher: {}", e))?;
+    let digest = hasher
+        .finish()
+        .map_err(|e| format!("Failed to finish hash: {}", e))?;
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    let message = TpmMessage::SignDigest {
+        key_handle: quote_data.ak_handle,
+        digest: digest.to_vec(),
+        hash_alg: quote_data.hash_alg,
+        sign_alg: quote_data.sign_alg,
+    };
+    quote_data
+        .tpm_tx
+        .send((message, reply_tx))
+        .await
+        .map_err(|e| format!("TPM worker channel closed: {e}"))?;
+    let signature = match reply_rx
+        .await
+        .map_err(|e| format!("TPM worker dropped reply channel: {e}"))?
+    {
+        TpmReply::SignDigest(result) => {
+            result.map_err(|e| format!("Failed to sign position: {:?}", e))?
+        }
+        _ => return Err("Unexpected reply from TPM worker for SignDigest request".to_string()),
+    };
+    let sig_bytes = signature
+        .marshall()
+        .map_err(|e| format!("Failed to serialize signature: {:?}", e))?;
+
+    Ok(format!(
+        "ak:{}:{}",
+        quote_data.agent_uuid,
+        general_purpose::STANDARD.encode(sig_bytes)
+    ))
+}
+
+/// Unified-Identity: apply the same `geolocation_capability_enabled` bearer-
+/// token gate the main endpoint uses (see `attested_geolocation`) to the
+/// history endpoints. Without this, any caller who can merely reach these
+/// routes - no token required - could read back full-precision fixes and raw
+/// mobile identifiers that a `geolocation/read:coarse`-scoped caller was
+/// never supposed to see, since the history buffer stores whatever precision
+/// a given request was authorized for.
+fn authorize_geolocation_history(
+    data: &QuoteData,
+    req: &HttpRequest,
+) -> Result<GeolocationScope, HttpResponse> {
+    if !data.geolocation_capability_enabled {
+        return Ok(GeolocationScope::Full);
+    }
+
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok());
+    match auth_header {
+        None => {
+            warn!("Unified-Identity: Geolocation history request missing Authorization header");
+            Err(HttpResponse::Unauthorized().json(JsonWrapper::error(
+                401,
+                "Missing Authorization bearer capability token".to_string(),
+            )))
+        }
+        Some(header) => verify_capability_token(data, header).map_err(|e| {
+            warn!("Unified-Identity: Geolocation history capability token rejected: {}", e);
+            HttpResponse::Unauthorized().json(JsonWrapper::error(
+                401,
+                format!("Capability token rejected: {}", e),
+            ))
+        }),
+    }
+}
+
+/// `GET /v2/agent/geolocation_history` - the bounded in-memory list of past
+/// attested fixes, most recent last, as JSON. A `geolocation/read:coarse`
+/// caller is served the same coarse redaction the main endpoint applies, not
+/// whatever precision the original request was authorized for.
+pub(crate) async fn geolocation_history(
+    req: HttpRequest,
+    data: web::Data<QuoteData>,
+) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: Geolocation history endpoint accessed but feature disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified Identity feature disabled".to_string(),
+        ));
+    }
+
+    let scope = match authorize_geolocation_history(&data, &req) {
+        Ok(scope) => scope,
+        Err(response) => return response,
+    };
+
+    let history = data.geolocation_history.lock().unwrap(); //#[allow_ci]
+    let entries: Vec<GeolocationHistoryEntry> = history
+        .iter()
+        .cloned()
+        .map(|mut entry| {
+            if scope == GeolocationScope::Coarse {
+                apply_coarse_redaction(&mut entry.response);
+            }
+            entry
+        })
+        .collect();
+    HttpResponse::Ok().json(JsonWrapper::success(entries))
+}
+
+/// `GET /v2/agent/geolocation_history.gpx` - the GNSS fixes in the history
+/// ring buffer serialized as a GPX 1.1 track. A `geolocation/read:coarse`
+/// caller is served the same coarse redaction the main endpoint applies.
+pub(crate) async fn geolocation_history_gpx(
+    req: HttpRequest,
+    data: web::Data<QuoteData>,
+) -> impl Responder {
+    if !data.unified_identity_enabled {
+        warn!("Unified-Identity: Geolocation history endpoint accessed but feature disabled");
+        return HttpResponse::Forbidden().json(JsonWrapper::error(
+            403,
+            "Unified Identity feature disabled".to_string(),
+        ));
+    }
+
+    let scope = match authorize_geolocation_history(&data, &req) {
+        Ok(scope) => scope,
+        Err(response) => return response,
+    };
+
+    let history = data.geolocation_history.lock().unwrap(); //#[allow_ci]
+    let redacted: VecDeque<GeolocationHistoryEntry> = history
+        .iter()
+        .cloned()
+        .map(|mut entry| {
+            if scope == GeolocationScope::Coarse {
+                apply_coarse_redaction(&mut entry.response);
+            }
+            entry
+        })
+        .collect();
+    let gpx = export_history_as_gpx(&redacted);
+    HttpResponse::Ok().content_type("application/gpx+xml").body(gpx)
+}
+
+/// Render the GNSS entries of `history` as a GPX 1.1 `<trk>`. Mobile-sensor
+/// entries carry no coordinates and are skipped.
+fn export_history_as_gpx(history: &VecDeque<GeolocationHistoryEntry>) -> String {
+    let mut gpx = String::new();
+    gpx.push_str(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"keylime-agent\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+         <trk><name>Attested geolocation history</name><trkseg>\n",
+    );
+    for entry in history {
+        let Some(gnss) = &entry.response.gnss else {
+            continue;
+        };
+        let time = unix_secs_to_rfc3339(entry.timestamp_unix);
+        gpx.push_str(&format!(
+            "<trkpt lat=\"{}\" lon=\"{}\"><time>{}</time><extensions><pcr15_digest>{}</pcr15_digest></extensions></trkpt>\n",
+            gnss.latitude,
+            gnss.longitude,
+            time,
+            xml_escape(&entry.pcr15_digest),
+        ));
+    }
+    gpx.push_str("</trkseg></trk></gpx>\n");
+    gpx
+}
+
+/// Escape the five XML predefined entities for safe inclusion in element text.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Format a Unix timestamp as an RFC 3339 UTC instant (`YYYY-MM-DDTHH:MM:SSZ`)
+/// without pulling in a date/time crate, using Howard Hinnant's
+/// days-since-epoch-to-civil-date algorithm.
+fn unix_secs_to_rfc3339(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let secs_of_day = unix_secs % 86400;
+    let (hour, minute, second) = (secs_of_day / 3600, (secs_of_day / 60) % 60, secs_of_day % 60);
+
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = if month <= 2 { y + 1 } else { y };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
 }
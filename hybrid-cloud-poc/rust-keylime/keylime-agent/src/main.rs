@@ -31,16 +31,32 @@
 //  missing_docs: there is many functions missing documentations for now
 #![allow(unused, missing_docs)]
 
+mod acme; // Unified-Identity: ACME certificate provisioning
 mod agent_handler;
 mod api;
+mod attestation_chain; // Unified-Identity: DICE-style App Key -> AK -> EK attestation chain
+mod cache; // Unified-Identity: content-addressed quote/IMA cache with LRU eviction
+mod client_revocation; // Unified-Identity: client-certificate CRL/OCSP revocation checking
 mod delegated_certification_handler;
+mod device_attest_acme; // Unified-Identity: RFC 8555 ACME client for delegated App Key certification via device-attest-01
+mod dice_handler; // Unified-Identity: DICE/BCC layered attestation endpoint
+mod ece; // Unified-Identity: RFC 8188 aes128gcm payload encoding
 mod errors_handler;
 mod geolocation_handler; // Unified-Identity: Task 2 - Geolocation API endpoint
 mod keys_handler;
 mod notifications_handler;
 mod payloads;
+#[cfg(feature = "with-quic")]
+mod quic_server; // Unified-Identity: optional QUIC bind path for the quote endpoint
 mod quotes_handler;
+mod ra_tls; // Unified-Identity: RA-TLS attested TLS certificate binding
 mod revocation;
+mod tls_backend; // Unified-Identity: pluggable rustls TLS backend
+mod transparency_log; // Unified-Identity: Rekor-style App Key certification transparency log
+mod trust_root; // Unified-Identity: TUF-backed trust root subsystem for mTLS CA anchors
+mod uds_auth; // Unified-Identity: SO_PEERCRED auth for the delegated-certification UDS listener
+mod ws_revocation; // Unified-Identity: WebSocket revocation subscriber, replaces deprecated ZeroMQ path
+mod x509_attest; // Unified-Identity: self-issued X.509 App Key attestation certificate
 
 use actix_web::{dev::Service, http, middleware, rt, web, App, HttpServer};
 use base64::{engine::general_purpose, Engine as _};
@@ -66,10 +82,12 @@ use keylime::{
 };
 use log::*;
 use openssl::{
+    asn1::Asn1Time,
     pkey::{PKey, Private, Public},
-    x509::X509,
+    x509::{X509Ref, X509},
 };
 use std::{
+    collections::VecDeque,
     convert::TryFrom,
     fs,
     io::{BufReader, Read, Write},
@@ -98,18 +116,277 @@ extern crate static_assertions;
 
 static NOTFOUND: &[u8] = b"Not Found";
 
+/// Unified-Identity: Messages accepted by the dedicated TPM worker task spawned
+/// in `main()`. This follows the same channel/actor pattern already used for
+/// `keys_tx`/`payload_tx`/`revocation_tx`: every TPM operation is funneled
+/// through the single task that owns the `tpm::Context`, instead of handlers
+/// contending on a shared `Mutex` (and potentially holding it across an
+/// `.await` point).
+#[derive(Debug)]
+pub enum TpmMessage {
+    /// Generate a TPM2 quote over the given PCR mask, bound to the nonce and payload key.
+    Quote {
+        nonce: Vec<u8>,
+        pcrmask: u32,
+        payload_pub_key: PKey<Public>,
+        ak_handle: KeyHandle,
+        hash_alg: keylime::algorithms::HashAlgorithm,
+        sign_alg: keylime::algorithms::SignAlgorithm,
+    },
+    /// Read the public area of an already loaded/persistent handle.
+    ReadPublic { handle: KeyHandle },
+    /// Reset (optionally) and extend a PCR with the given digest values.
+    ExtendPcr {
+        pcr: tss_esapi::handles::PcrHandle,
+        digest_values: tss_esapi::structures::DigestValues,
+        reset_first: bool,
+    },
+    /// Seal a secret to the TPM under a PCR-bound policy.
+    Seal {
+        secret: Vec<u8>,
+        pcrs: Vec<tss_esapi::structures::PcrSlot>,
+        hash_alg: keylime::algorithms::HashAlgorithm,
+        path: PathBuf,
+    },
+    /// Unseal a secret previously sealed with [`TpmMessage::Seal`], replaying the PCR policy.
+    Unseal {
+        pcrs: Vec<tss_esapi::structures::PcrSlot>,
+        hash_alg: keylime::algorithms::HashAlgorithm,
+        path: PathBuf,
+    },
+    /// Flush a transient handle. Sent fire-and-forget (no reply expected) from
+    /// `Drop`, since `Drop::drop` cannot await the worker's response.
+    FlushContext { handle: tss_esapi::handles::ObjectHandle },
+    /// Unified-Identity: Load an App Key from its context file and certify it
+    /// with the agent's AK via TPM2_Certify, for the delegated certification endpoint.
+    CertifyAppKey {
+        app_key_context_path: String,
+        qualifying_data: Data,
+        ak_handle: KeyHandle,
+    },
+    /// Unified-Identity: TPM2_Certify an already-loaded object (e.g. the
+    /// EK) with the given signing key, for the `attestation_chain` EK -> AK
+    /// link of `certify_app_key`'s `include_chain` path.
+    CertifyObject {
+        object_handle: KeyHandle,
+        qualifying_data: Data,
+        signing_handle: KeyHandle,
+    },
+    /// Unified-Identity: read the EK certificate from TPM NV, for the root
+    /// entry of an `attestation_chain`.
+    ReadEkCertificate,
+    /// Unified-Identity: sign an arbitrary digest with a loaded key handle
+    /// (the AK, by default), for the secured geolocation position beacon.
+    SignDigest {
+        key_handle: KeyHandle,
+        digest: Vec<u8>,
+        hash_alg: keylime::algorithms::HashAlgorithm,
+        sign_alg: keylime::algorithms::SignAlgorithm,
+    },
+    /// Unified-Identity: load a registered signing key from its context
+    /// file (see `QuoteData::signing_key_registry`) and sign `digest` with
+    /// it, for the `/delegated_certification/sign` remote-signing
+    /// endpoint - the same `load_key_from_context_file` load
+    /// `CertifyAppKey` already does, followed by the same `sign` primitive
+    /// `SignDigest` uses, against a caller-selected key instead of the
+    /// fixed App Key/AK.
+    SignWithRegisteredKey {
+        context_path: String,
+        digest: Vec<u8>,
+        hash_alg: keylime::algorithms::HashAlgorithm,
+        sign_alg: keylime::algorithms::SignAlgorithm,
+    },
+    /// Test-only: verify a quote against an AK and nonce using the owned context.
+    #[cfg(feature = "testing")]
+    CheckQuote {
+        ak_handle: KeyHandle,
+        quote: String,
+        nonce: Vec<u8>,
+    },
+}
+
+/// Replies sent back from the TPM worker task for each [`TpmMessage`] variant.
+#[derive(Debug)]
+pub enum TpmReply {
+    Quote(std::result::Result<String, tpm::TpmError>),
+    ReadPublic(std::result::Result<tss_esapi::structures::Public, tpm::TpmError>),
+    ExtendPcr(std::result::Result<(), tpm::TpmError>),
+    Seal(std::result::Result<(), tpm::TpmError>),
+    Unseal(std::result::Result<Vec<u8>, tpm::TpmError>),
+    FlushContext,
+    CertifyAppKey(
+        std::result::Result<
+            (
+                tss_esapi::structures::Attest,
+                tss_esapi::structures::Signature,
+            ),
+            tpm::TpmError,
+        >,
+    ),
+    CertifyObject(
+        std::result::Result<
+            (
+                tss_esapi::structures::Attest,
+                tss_esapi::structures::Signature,
+            ),
+            tpm::TpmError,
+        >,
+    ),
+    ReadEkCertificate(std::result::Result<Vec<u8>, tpm::TpmError>),
+    SignDigest(std::result::Result<tss_esapi::structures::Signature, tpm::TpmError>),
+    SignWithRegisteredKey(
+        std::result::Result<
+            (
+                tss_esapi::structures::Public,
+                tss_esapi::structures::Signature,
+            ),
+            tpm::TpmError,
+        >,
+    ),
+    #[cfg(feature = "testing")]
+    CheckQuote(std::result::Result<(), tpm::TpmError>),
+}
+
+/// Spawn the single task that owns the `tpm::Context` for the lifetime of the
+/// agent process. All TPM operations are sent to it as `(TpmMessage,
+/// oneshot::Sender<TpmReply>)` pairs and processed one at a time, so no two
+/// TPM commands are ever issued concurrently and no HTTP handler ever blocks
+/// another while holding a lock across an await point.
+fn spawn_tpm_worker(
+    mut ctx: tpm::Context<'static>,
+) -> mpsc::Sender<(TpmMessage, oneshot::Sender<TpmReply>)> {
+    let (tpm_tx, mut tpm_rx) = mpsc::channel::<(TpmMessage, oneshot::Sender<TpmReply>)>(16);
+
+    let _ = std::thread::Builder::new()
+        .name("tpm-worker".to_string())
+        .spawn(move || {
+            while let Some((message, reply_tx)) = tpm_rx.blocking_recv() {
+                let reply = match message {
+                    TpmMessage::Quote {
+                        nonce,
+                        pcrmask,
+                        payload_pub_key,
+                        ak_handle,
+                        hash_alg,
+                        sign_alg,
+                    } => TpmReply::Quote(ctx.quote(
+                        &nonce,
+                        pcrmask,
+                        &payload_pub_key,
+                        ak_handle,
+                        hash_alg,
+                        sign_alg,
+                    )),
+                    TpmMessage::ReadPublic { handle } => TpmReply::ReadPublic(
+                        ctx.read_public_from_handle(handle)
+                            .map(|(public, _, _)| public),
+                    ),
+                    TpmMessage::ExtendPcr {
+                        pcr,
+                        digest_values,
+                        reset_first,
+                    } => TpmReply::ExtendPcr(if reset_first {
+                        ctx.reset_and_extend_pcr(pcr, digest_values)
+                    } else {
+                        ctx.extend_pcr(pcr, digest_values)
+                    }),
+                    TpmMessage::Seal {
+                        secret,
+                        pcrs,
+                        hash_alg,
+                        path,
+                    } => TpmReply::Seal(ctx.seal_to_pcr_policy(&secret, &pcrs, hash_alg, &path)),
+                    TpmMessage::Unseal {
+                        pcrs,
+                        hash_alg,
+                        path,
+                    } => TpmReply::Unseal(ctx.unseal_with_pcr_policy(&path, &pcrs, hash_alg)),
+                    TpmMessage::FlushContext { handle } => {
+                        ctx.flush_context(handle);
+                        TpmReply::FlushContext
+                    }
+                    TpmMessage::CertifyAppKey {
+                        app_key_context_path,
+                        qualifying_data,
+                        ak_handle,
+                    } => TpmReply::CertifyAppKey(
+                        ctx.load_key_from_context_file(&app_key_context_path)
+                            .and_then(|app_key_handle| {
+                                ctx.certify_credential(qualifying_data, app_key_handle, ak_handle)
+                            }),
+                    ),
+                    TpmMessage::CertifyObject {
+                        object_handle,
+                        qualifying_data,
+                        signing_handle,
+                    } => TpmReply::CertifyObject(ctx.certify_credential(
+                        qualifying_data,
+                        object_handle,
+                        signing_handle,
+                    )),
+                    TpmMessage::ReadEkCertificate => {
+                        TpmReply::ReadEkCertificate(ctx.read_ek_certificate())
+                    }
+                    TpmMessage::SignDigest {
+                        key_handle,
+                        digest,
+                        hash_alg,
+                        sign_alg,
+                    } => TpmReply::SignDigest(ctx.sign(key_handle, hash_alg, sign_alg, &digest)),
+                    TpmMessage::SignWithRegisteredKey {
+                        context_path,
+                        digest,
+                        hash_alg,
+                        sign_alg,
+                    } => TpmReply::SignWithRegisteredKey(
+                        ctx.load_key_from_context_file(&context_path).and_then(
+                            |key_handle| {
+                                let public = ctx
+                                    .read_public_from_handle(key_handle)
+                                    .map(|(public, _, _)| public)?;
+                                let signature =
+                                    ctx.sign(key_handle, hash_alg, sign_alg, &digest)?;
+                                Ok((public, signature))
+                            },
+                        ),
+                    ),
+                    #[cfg(feature = "testing")]
+                    TpmMessage::CheckQuote {
+                        ak_handle,
+                        quote,
+                        nonce,
+                    } => TpmReply::CheckQuote(tpm::testing::check_quote(
+                        &mut ctx, ak_handle, &quote, &nonce,
+                    )),
+                };
+                let _ = reply_tx.send(reply);
+            }
+        });
+
+    tpm_tx
+}
+
 // This data is passed in to the actix httpserver threads that
 // handle quotes.
 #[derive(Debug)]
-pub struct QuoteData<'a> {
+pub struct QuoteData {
     agent_uuid: String,
     ak_handle: KeyHandle,
+    // Unified-Identity: the EK's transient handle, kept loaded for the
+    // lifetime of the process (see the EK setup in `main()`); used to build
+    // the EK -> AK link of an `attestation_chain` via
+    // `TpmMessage::CertifyObject`.
+    pub(crate) ek_handle: KeyHandle,
     allow_payload_revocation_actions: bool,
     api_versions: Vec<String>,
     enc_alg: keylime::algorithms::EncryptionAlgorithm,
     hash_alg: keylime::algorithms::HashAlgorithm,
     ima_ml: Mutex<MeasurementList>,
     ima_ml_file: Option<Mutex<fs::File>>,
+    // Unified-Identity: path backing `ima_ml_file`, so `quotes_handler::ima`
+    // can reopen the log as an independent file handle for its chunked
+    // stream instead of sharing `ima_ml_file`'s mutex/offset.
+    ima_ml_path: Option<PathBuf>,
     keys_tx: mpsc::Sender<(
         keys_handler::KeyMessage,
         Option<oneshot::Sender<keys_handler::SymmKeyMessage>>,
@@ -124,14 +401,93 @@ pub struct QuoteData<'a> {
     secure_mount: PathBuf,
     secure_size: String,
     sign_alg: keylime::algorithms::SignAlgorithm,
-    tpmcontext: Mutex<tpm::Context<'a>>,
+    tpm_tx: mpsc::Sender<(TpmMessage, oneshot::Sender<TpmReply>)>,
     work_dir: PathBuf,
     // Unified-Identity: Feature flag for unified identity support
     unified_identity_enabled: bool,
+    // Unified-Identity: bounded ring buffer of past attested geolocation
+    // fixes, served by `geolocation_handler::geolocation_history[_gpx]`.
+    geolocation_history: Mutex<VecDeque<geolocation_handler::GeolocationHistoryEntry>>,
+    // Unified-Identity: PEM-encoded mTLS certificate chain offered alongside a
+    // secured geolocation position beacon, so a verifier can check the
+    // position signature offline without replaying the TPM quote flow.
+    geolocation_signing_cert_chain: Vec<String>,
+    // Unified-Identity: UCAN-style capability-token gating for the
+    // geolocation endpoint; see `geolocation_handler::verify_capability_token`.
+    geolocation_capability_enabled: bool,
+    geolocation_capability_issuer_pubkey: Option<PKey<Public>>,
+    // Unified-Identity: UCAN-style capability-token gating for the /quotes
+    // endpoints (`identity`/`integrity`); see
+    // `quotes_handler::authorize_quote_capability`. Unlike the single-level
+    // geolocation token above, a quote capability token may chain through
+    // delegation proofs, so the trusted set below only needs to cover the
+    // root issuers, not every tenant a verifier delegates to.
+    quote_capability_enabled: bool,
+    quote_capability_trusted_roots: Vec<PKey<Public>>,
+    // Unified-Identity: response-hardening header set applied to every
+    // /quotes response by `quotes_handler::SecurityHeaders`.
+    quote_security_headers: quotes_handler::QuoteSecurityHeaders,
+    // Unified-Identity: pre-shared HMAC key authenticating signed
+    // `POST /integrity` policy documents; see
+    // `quotes_handler::verify_policy_hmac`. `None` disables the POST route.
+    quote_policy_hmac_key: Option<Vec<u8>>,
+    // Unified-Identity: serial device candidates probed by the GNSS/NMEA
+    // geolocation backend; see `quotes_handler::detect_geolocation_sensor`.
+    // Empty uses the backend's built-in default candidate list.
+    geolocation_gnss_device_paths: Vec<String>,
+    // Unified-Identity: content-addressed cache of rendered `/integrity`
+    // quotes; see `cache::QuoteCache`. `None` disables caching and every
+    // request regenerates the quote, as before.
+    quote_cache: Option<std::sync::Arc<cache::QuoteCache>>,
+    // Unified-Identity: Ed25519 signed-request authentication for the
+    // /quotes scope; see `quotes_handler::SignedRequestAuth`. `None`
+    // disables the middleware (pass-through), matching how the other
+    // optional /quotes gates behave when unconfigured.
+    signed_request_verifier_pubkey: Option<ed25519_dalek::VerifyingKey>,
+    signed_request_skew_seconds: u64,
     // Unified-Identity: Delegated certification config
     pub(crate) delegated_cert_enabled: bool,
     pub(crate) delegated_cert_allowed_ips: Vec<String>,
-    pub(crate) delegated_cert_rate_limit: u32,
+    // Unified-Identity: token-bucket rate limit for `certify_app_key` - see
+    // `delegated_certification_handler::check_rate_limit`. `burst` is the
+    // bucket capacity (0 disables rate limiting), `per_second` the
+    // sustained refill rate, and `idle_eviction_seconds` how long an idle
+    // key's bucket is kept before being dropped from the map.
+    pub(crate) delegated_cert_rate_limit_per_second: f64,
+    pub(crate) delegated_cert_rate_limit_burst: u32,
+    pub(crate) delegated_cert_rate_limit_idle_eviction_seconds: u64,
+    // Unified-Identity: SO_PEERCRED allow-list for the delegated-certification UDS listener
+    pub(crate) delegated_cert_uds_allowed_uids: Vec<u32>,
+    pub(crate) delegated_cert_uds_allowed_gids: Vec<u32>,
+    // Unified-Identity: when set, `delegated_certification_handler::certify_app_key`
+    // drives a `device-attest-01` ACME order for a real X.509 cert instead of
+    // returning the home-grown `certify_data`/`signature` blob; see
+    // `device_attest_acme`. `None` preserves the pre-existing blob behavior.
+    pub(crate) device_attest_acme_config: Option<device_attest_acme::DeviceAttestAcmeConfig>,
+    // Unified-Identity: authorized SPIRE client public keys for the
+    // delegated-certification endpoint's HTTP Message Signature check; see
+    // `delegated_certification_handler::verify_http_message_signature`.
+    // Empty disables the check (pass-through).
+    pub(crate) delegated_cert_authorized_client_keys: Vec<PKey<Public>>,
+    pub(crate) delegated_cert_signature_skew_seconds: u64,
+    // Unified-Identity: TTL for certification orders issued by
+    // `delegated_certification_handler::new_order`; see `issue_order` and
+    // `begin_order`.
+    pub(crate) delegated_cert_nonce_ttl_seconds: u64,
+    // Unified-Identity: Rekor-style append-only transparency log of issued
+    // App Key certifications; see `transparency_log` and
+    // `delegated_certification_handler::certify_app_key`. `None` disables
+    // logging, leaving the response's `log_index`/`inclusion_proof`/
+    // `signed_tree_head` fields unset, as before this feature existed.
+    pub(crate) transparency_log: Option<Mutex<transparency_log::TransparencyLog>>,
+    // Unified-Identity: registered signing keys for the
+    // `/delegated_certification/sign` and `/delegated_certification/list_keys`
+    // remote-signing endpoints - identifier -> context-file path, loaded
+    // lazily per request via `tpm::Context::load_key_from_context_file`, the
+    // same primitive `TpmMessage::CertifyAppKey`'s App Key load already
+    // uses. Empty disables both endpoints, the same opt-in shape as the
+    // other optional delegated-certification gates.
+    pub(crate) signing_key_registry: std::collections::HashMap<String, String>,
 }
 
 #[actix_web::main]
@@ -242,6 +598,22 @@ async fn main() -> Result<()> {
         )));
     }
 
+    // Unified-Identity: `payload_encoding` selects how delivered payloads are
+    // unwrapped: the default split U/V-key scheme, or the standardized RFC 8188
+    // `aes128gcm` encrypted content encoding (see the `ece` module).
+    match config.payload_encoding.as_str() {
+        "split" | "ece" => (),
+        other => {
+            let message = format!(
+                "Invalid 'payload_encoding' value '{other}': expected 'split' or 'ece'"
+            );
+            error!("Configuration error: {}", &message);
+            return Err(Error::Configuration(config::KeylimeConfigError::Generic(
+                message,
+            )));
+        }
+    }
+
     let secure_size = config.secure_size.clone();
     let work_dir = PathBuf::from(&config.keylime_dir);
     let mount = secure_mount::mount(&work_dir, &config.secure_size)?;
@@ -331,161 +703,25 @@ async fn main() -> Result<()> {
         keylime::algorithms::SignAlgorithm::try_from(config.tpm_signing_alg.as_ref())?;
 
     // Gather EK values and certs
-    // If USE_TPM2_QUOTE_DIRECT is set, create EK using tpm2 createek for persistence
+    // If USE_TPM2_QUOTE_DIRECT is set, persist the EK natively via tss-esapi so the
+    // direct-quote mode no longer depends on tpm2-tools being installed on the image.
     let (ek_result, ek_persistent_handle) =
         if std::env::var("USE_TPM2_QUOTE_DIRECT").is_ok() && config.ek_handle.is_empty() {
-            use std::fs;
-            use std::path::PathBuf;
-            use std::process::Command;
-
-            // Create EK context file path in agent data directory
-            let agent_data_dir = match config.agent_data_path.as_ref() {
-                "" => PathBuf::from("/tmp/keylime-agent"),
-                path => PathBuf::from(path)
-                    .parent()
-                    .unwrap_or(PathBuf::from("/tmp/keylime-agent").as_path())
-                    .to_path_buf(),
-            };
-            fs::create_dir_all(&agent_data_dir).map_err(|e| {
-                Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                    "Failed to create agent data directory: {}",
-                    e
-                )))
-            })?;
-
-            let ek_context_path = agent_data_dir.join("ek.ctx");
-            let ek_context_str = ek_context_path.to_str().ok_or_else(|| {
-                Error::Tpm(tpm::TpmError::HexDecodeError(
-                    "Invalid EK context file path".to_string(),
-                ))
-            })?;
-
-            let ek_pub_path = agent_data_dir.join("ek.pub");
-            let ek_pub_str = ek_pub_path.to_str().ok_or_else(|| {
-                Error::Tpm(tpm::TpmError::HexDecodeError(
-                    "Invalid EK pub file path".to_string(),
-                ))
-            })?;
-
-            let tcti = std::env::var("TCTI").unwrap_or_else(|_| "device:/dev/tpmrm0".to_string());
             let ek_persistent_handle_val = 0x81010001;
 
-            info!("Creating EK using tpm2 createek for tpm2_quote direct mode");
-
-            // Flush any existing transient handles first
-            let _ = Command::new("tpm2")
-                .arg("flushcontext")
-                .arg("-t")
-                .env("TCTI", &tcti)
-                .output();
-
-            // Create EK using tpm2 createek
-            let createek_output = Command::new("tpm2")
-                .arg("createek")
-                .env("TCTI", &tcti)
-                .arg("-G")
-                .arg("rsa")
-                .arg("-c")
-                .arg(ek_context_str)
-                .arg("-u")
-                .arg(ek_pub_str)
-                .output()
-                .map_err(|e| {
-                    Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                        "Failed to execute tpm2 createek: {}",
-                        e
-                    )))
-                })?;
+            info!("Creating and persisting EK natively for tpm2_quote direct mode");
 
-            if !createek_output.status.success() {
-                let stderr = String::from_utf8_lossy(&createek_output.stderr);
-                warn!(
-                    "tpm2 createek failed: {}. Falling back to TSS library create_ek.",
-                    stderr
-                );
-                // Fall back to TSS library method
-                let ek_result = ctx.create_ek(tpm_encryption_alg, None)?;
-                (ek_result, None)
-            } else {
-                info!(
-                    "EK created successfully with context file: {}",
-                    ek_context_str
-                );
-
-                // Persist EK to persistent handle
-                let evict_output = Command::new("tpm2")
-                    .arg("evictcontrol")
-                    .env("TCTI", &tcti)
-                    .arg("-C")
-                    .arg("o")
-                    .arg("-c")
-                    .arg(ek_context_str)
-                    .arg(&format!("{:#x}", ek_persistent_handle_val))
-                    .output()
-                    .map_err(|e| {
-                        Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                            "Failed to execute tpm2 evictcontrol for EK: {}",
-                            e
-                        )))
-                    })?;
-
-                if !evict_output.status.success() {
-                    let stderr = String::from_utf8_lossy(&evict_output.stderr);
+            match ctx.create_and_persist_ek(tpm_encryption_alg, ek_persistent_handle_val) {
+                Ok(ek_result) => {
+                    info!("EK persisted to handle {:#x}", ek_persistent_handle_val);
+                    (ek_result, Some(ek_persistent_handle_val))
+                }
+                Err(e) => {
                     warn!(
-                        "Failed to persist EK: {}. Falling back to TSS library.",
-                        stderr
+                        "Failed to create and persist EK natively: {e}. Falling back to transient TSS library create_ek."
                     );
                     let ek_result = ctx.create_ek(tpm_encryption_alg, None)?;
                     (ek_result, None)
-                } else {
-                    info!("EK persisted to handle {:#x}", ek_persistent_handle_val);
-
-                    // Read EK public key to create EKResult
-                    // We need to parse the public key file or use tpm2 readpublic
-                    let readpub_output = Command::new("tpm2")
-                        .arg("readpublic")
-                        .env("TCTI", &tcti)
-                        .arg("-c")
-                        .arg(ek_context_str)
-                        .arg("-f")
-                        .arg("pem")
-                        .output()
-                        .map_err(|e| {
-                            Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                                "Failed to read EK public key: {}",
-                                e
-                            )))
-                        })?;
-
-                    if !readpub_output.status.success() {
-                        let stderr = String::from_utf8_lossy(&readpub_output.stderr);
-                        warn!(
-                            "Failed to read EK public key: {}. Falling back to TSS library.",
-                            stderr
-                        );
-                        let ek_result = ctx.create_ek(tpm_encryption_alg, None)?;
-                        (ek_result, None)
-                    } else {
-                        // Load the persistent EK handle
-                        let ek_persistent_handle_tpm =
-                            ctx.load_persistent_handle(ek_persistent_handle_val)?;
-
-                        // Read public key from persistent handle using TSS library
-                        let (ek_public, _, _) = ctx
-                            .read_public_from_handle(ek_persistent_handle_tpm)
-                            .map_err(|e| Error::Tpm(e))?;
-
-                        // Create EKResult from the public key
-                        // EKResult has public, key_handle, ek_cert, and ek_chain fields
-                        let ek_result = tpm::EKResult {
-                            public: ek_public,
-                            key_handle: ek_persistent_handle_tpm,
-                            ek_cert: None,  // EK cert not available from tpm2 createek
-                            ek_chain: None, // EK chain not available from tpm2 createek
-                        };
-
-                        (ek_result, Some(ek_persistent_handle_val))
-                    }
                 }
             }
         } else {
@@ -497,6 +733,11 @@ async fn main() -> Result<()> {
             (ek_result, None)
         };
 
+    // Unified-Identity: captured before `ek_result` is moved into
+    // `AgentRegistration` below; kept loaded for `attestation_chain`'s
+    // EK -> AK link (see `QuoteData::ek_handle`).
+    let ek_handle = ek_result.key_handle;
+
     // Calculate the SHA-256 hash of the public key in PEM format
     let ek_hash = hash_ek::hash_ek_pubkey(ek_result.public.clone())?;
 
@@ -584,271 +825,54 @@ async fn main() -> Result<()> {
             )
         }
         None => {
-            // If USE_TPM2_QUOTE_DIRECT is set and we have a persistent EK, create AK using tpm2 createak
-            let (ak_handle, new_ak, persistent_handle) = if std::env::var("USE_TPM2_QUOTE_DIRECT")
-                .is_ok()
-                && ek_persistent_handle.is_some()
-            {
-                use std::fs;
-                use std::path::PathBuf;
-                use std::process::Command;
-
-                let ek_persistent_handle_val = ek_persistent_handle.unwrap();
-
-                // Create AK context file path in agent data directory
-                let agent_data_dir = match config.agent_data_path.as_ref() {
-                    "" => PathBuf::from("/tmp/keylime-agent"),
-                    path => PathBuf::from(path)
-                        .parent()
-                        .unwrap_or(PathBuf::from("/tmp/keylime-agent").as_path())
-                        .to_path_buf(),
-                };
-                fs::create_dir_all(&agent_data_dir).map_err(|e| {
-                    Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                        "Failed to create agent data directory: {}",
-                        e
-                    )))
-                })?;
-
-                let ak_context_path = agent_data_dir.join("ak.ctx");
-                let ak_context_str = ak_context_path.to_str().ok_or_else(|| {
-                    Error::Tpm(tpm::TpmError::HexDecodeError(
-                        "Invalid AK context file path".to_string(),
-                    ))
-                })?;
-
-                let tcti =
-                    std::env::var("TCTI").unwrap_or_else(|_| "device:/dev/tpmrm0".to_string());
-
-                let hash_alg_str = match tpm_hash_alg {
-                    keylime::algorithms::HashAlgorithm::Sha256 => "sha256",
-                    keylime::algorithms::HashAlgorithm::Sha1 => "sha1",
-                    keylime::algorithms::HashAlgorithm::Sha384 => "sha384",
-                    keylime::algorithms::HashAlgorithm::Sha512 => "sha512",
-                    _ => "sha256",
-                };
-
-                let sign_alg_str = match tpm_signing_alg {
-                    keylime::algorithms::SignAlgorithm::RsaSsa => "rsassa",
-                    keylime::algorithms::SignAlgorithm::RsaPss => "rsapss",
-                    _ => "rsassa",
-                };
-
-                info!(
-                    "Creating AK using tpm2 createak with persistent EK handle {:#x}",
-                    ek_persistent_handle_val
-                );
-
-                // Flush transient handles first
-                let _ = Command::new("tpm2")
-                    .arg("flushcontext")
-                    .arg("-t")
-                    .env("TCTI", &tcti)
-                    .output();
-
-                // Create AK using tpm2 createak with persistent EK handle
-                let createak_output = Command::new("tpm2")
-                    .arg("createak")
-                    .env("TCTI", &tcti)
-                    .arg("-C")
-                    .arg(&format!("{:#x}", ek_persistent_handle_val))
-                    .arg("-c")
-                    .arg(ak_context_str)
-                    .arg("--hash-alg")
-                    .arg(hash_alg_str)
-                    .arg("--signing-alg")
-                    .arg(sign_alg_str)
-                    .arg("--key-alg")
-                    .arg("rsa")
-                    .output()
-                    .map_err(|e| {
-                        Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                            "Failed to execute tpm2 createak: {}",
-                            e
-                        )))
-                    })?;
+            // If USE_TPM2_QUOTE_DIRECT is set, create and persist the AK natively via
+            // tss-esapi instead of shelling out to tpm2 createak/evictcontrol.
+            let (ak_handle, new_ak, persistent_handle) =
+                if std::env::var("USE_TPM2_QUOTE_DIRECT").is_ok() {
+                    let persistent_handle_val = 0x8101000A;
 
-                if !createak_output.status.success() {
-                    let stderr = String::from_utf8_lossy(&createak_output.stderr);
-                    warn!(
-                        "tpm2 createak failed: {}. Falling back to TSS library create_ak.",
-                        stderr
+                    info!(
+                        "Creating and persisting AK natively under EK handle {:?} for tpm2_quote direct mode",
+                        ek_result.key_handle
                     );
-                    // Fall back to TSS library method
-                    let new_ak = ctx.create_ak(
+
+                    match ctx.create_and_persist_ak(
                         ek_result.key_handle,
                         tpm_hash_alg,
                         tpm_encryption_alg,
                         tpm_signing_alg,
-                    )?;
-                    let ak_handle = ctx.load_ak(ek_result.key_handle, &new_ak)?;
-                    (ak_handle, new_ak, None)
-                } else {
-                    info!(
-                        "AK created successfully with context file: {}",
-                        ak_context_str
-                    );
-
-                    // Persist AK to persistent handle
-                    let persistent_handle_val = 0x8101000A;
-                    let evict_output = Command::new("tpm2")
-                        .arg("evictcontrol")
-                        .env("TCTI", &tcti)
-                        .arg("-C")
-                        .arg("o")
-                        .arg("-c")
-                        .arg(ak_context_str)
-                        .arg(&format!("{:#x}", persistent_handle_val))
-                        .output()
-                        .map_err(|e| {
-                            Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                                "Failed to execute tpm2 evictcontrol for AK: {}",
-                                e
-                            )))
-                        })?;
-
-                    if !evict_output.status.success() {
-                        let stderr = String::from_utf8_lossy(&evict_output.stderr);
-                        warn!(
-                            "Failed to persist AK: {}. Falling back to TSS library.",
-                            stderr
-                        );
-                        let new_ak = ctx.create_ak(
-                            ek_result.key_handle,
-                            tpm_hash_alg,
-                            tpm_encryption_alg,
-                            tpm_signing_alg,
-                        )?;
-                        let ak_handle = ctx.load_ak(ek_result.key_handle, &new_ak)?;
-                        (ak_handle, new_ak, None)
-                    } else {
-                        info!("AK persisted to handle {:#x}", persistent_handle_val);
-
-                        // Set environment variable with AK context file path for quote function
-                        std::env::set_var("KEYLIME_AGENT_AK_CONTEXT", ak_context_str);
-                        info!("Set KEYLIME_AGENT_AK_CONTEXT={}", ak_context_str);
-
-                        // Load the persistent AK handle (for AgentData, but quote will use context file)
-                        let ak_persistent_handle_tpm =
-                            ctx.load_persistent_handle(persistent_handle_val)?;
-
-                        // Read public key from persistent handle to create AKResult
-                        let (ak_public, _, _) = ctx
-                            .read_public_from_handle(ak_persistent_handle_tpm)
-                            .map_err(|e| Error::Tpm(e))?;
-
-                        // Create AKResult from the public key
-                        // The private key is in the context file, but we need a minimal one for AgentData
-                        use tss_esapi::structures::Private;
-                        let ak_private = Private::try_from(vec![0u8; 1]).unwrap(); // Dummy private key - real one is in ak.ctx
-
-                        let new_ak = tpm::AKResult {
-                            public: ak_public,
-                            private: ak_private,
-                        };
-
-                        (
-                            ak_persistent_handle_tpm,
-                            new_ak,
-                            Some(persistent_handle_val),
-                        )
-                    }
-                }
-            } else {
-                // Use TSS library method (standard)
-                let new_ak = ctx.create_ak(
-                    ek_result.key_handle,
-                    tpm_hash_alg,
-                    tpm_encryption_alg,
-                    tpm_signing_alg,
-                )?;
-                let ak_handle = ctx.load_ak(ek_result.key_handle, &new_ak)?;
-
-                // If USE_TPM2_QUOTE_DIRECT is set but EK is not persistent, try to save the AK context and persist it
-                let persistent_handle = if std::env::var("USE_TPM2_QUOTE_DIRECT").is_ok() {
-                    use std::fs;
-                    use std::path::PathBuf;
-                    use std::process::Command;
-
-                    // Create AK context file path in agent data directory
-                    let agent_data_dir = match config.agent_data_path.as_ref() {
-                        "" => PathBuf::from("/tmp/keylime-agent"),
-                        path => PathBuf::from(path)
-                            .parent()
-                            .unwrap_or(PathBuf::from("/tmp/keylime-agent").as_path())
-                            .to_path_buf(),
-                    };
-                    fs::create_dir_all(&agent_data_dir).map_err(|e| {
-                        Error::Tpm(tpm::TpmError::HexDecodeError(format!(
-                            "Failed to create agent data directory: {}",
-                            e
-                        )))
-                    })?;
-
-                    let ak_context_path = agent_data_dir.join("ak.ctx");
-                    let ak_context_str = ak_context_path.to_str().ok_or_else(|| {
-                        Error::Tpm(tpm::TpmError::HexDecodeError(
-                            "Invalid context file path".to_string(),
-                        ))
-                    })?;
-
-                    let tcti =
-                        std::env::var("TCTI").unwrap_or_else(|_| "device:/dev/tpmrm0".to_string());
-                    let ak_handle_str = format!("{:#x}", u32::from(ak_handle));
-
-                    info!(
-                        "Attempting to save AK context for tpm2_quote direct mode (handle: {})",
-                        ak_handle_str
-                    );
-
-                    // Try to save the context using TSS library's context_save, then serialize it
-                    // The context needs to be in TPM2B_CONTEXT format for tpm2-tools
-                    match ctx.save_ak_context_to_file(ak_handle, ak_context_str) {
-                        Ok(_) => {
-                            info!("Saved AK context to file: {}", ak_context_str);
-
-                            // Now try to persist it using the context file
-                            let persistent_handle_val = 0x8101000A;
-                            match ctx
-                                .persist_ak_from_context_file(ak_context_str, persistent_handle_val)
-                            {
-                                Ok(_) => {
-                                    info!(
-                                        "AK persisted to handle {:#x} using saved context file",
-                                        persistent_handle_val
-                                    );
-                                    Some(persistent_handle_val)
-                                }
-                                Err(e) => {
-                                    warn!("Failed to persist AK from context file: {}. Will use transient handle.", e);
-                                    None
-                                }
-                            }
+                        persistent_handle_val,
+                    ) {
+                        Ok((ak_handle, new_ak)) => {
+                            info!("AK persisted to handle {:#x}", persistent_handle_val);
+                            (ak_handle, new_ak, Some(persistent_handle_val))
                         }
                         Err(e) => {
                             warn!(
-                                "Failed to save AK context: {}. Cannot use tpm2_quote direct mode.",
-                                e
+                                "Failed to create and persist AK natively: {e}. Falling back to transient TSS library create_ak."
                             );
-                            None
+                            let new_ak = ctx.create_ak(
+                                ek_result.key_handle,
+                                tpm_hash_alg,
+                                tpm_encryption_alg,
+                                tpm_signing_alg,
+                            )?;
+                            let ak_handle = ctx.load_ak(ek_result.key_handle, &new_ak)?;
+                            (ak_handle, new_ak, None)
                         }
                     }
                 } else {
-                    None
+                    // Use TSS library method (standard)
+                    let new_ak = ctx.create_ak(
+                        ek_result.key_handle,
+                        tpm_hash_alg,
+                        tpm_encryption_alg,
+                        tpm_signing_alg,
+                    )?;
+                    let ak_handle = ctx.load_ak(ek_result.key_handle, &new_ak)?;
+                    (ak_handle, new_ak, None)
                 };
 
-                (
-                    if let Some(ph) = persistent_handle {
-                        // Use persistent handle if available
-                        ctx.load_persistent_handle(ph)?
-                    } else {
-                        ak_handle
-                    },
-                    new_ak,
-                    persistent_handle,
-                )
-            };
-
             (ak_handle, new_ak, persistent_handle)
         }
     };
@@ -935,14 +959,71 @@ async fn main() -> Result<()> {
         )))
     })?;
 
+    // Unified-Identity: Seal a copy of the payload private key to the TPM under a
+    // PCR-bound policy, written to `payload_key.sealed` beside the agent data. The
+    // policy digest is recomputed via a trial session against
+    // `config.payload_seal_pcrs`; an existing sealed file is only checked to unseal
+    // against the current PCR state. The key loaded above is still the one stored in
+    // `QuoteData` and used to unwrap payloads; the sealed copy is not read back for
+    // that. When the configured PCR set is empty, sealing is skipped.
+    if !config.payload_seal_pcrs.is_empty() {
+        let seal_pcrs = parse_pcr_slots(&config.payload_seal_pcrs)?;
+        let sealed_path = Path::new(&config.agent_data_path)
+            .parent()
+            .unwrap_or_else(|| Path::new("/var/lib/keylime/agent_data"))
+            .join("payload_key.sealed");
+
+        if sealed_path.exists() {
+            match ctx.unseal_with_pcr_policy(&sealed_path, &seal_pcrs, tpm_hash_alg) {
+                Ok(_) => info!(
+                    "Verified payload key can be unsealed against current PCR state from {}",
+                    sealed_path.display()
+                ),
+                Err(e) => warn!(
+                    "Existing sealed payload key at {} could not be unsealed against current PCR state: {e}",
+                    sealed_path.display()
+                ),
+            }
+        } else {
+            match ctx.seal_to_pcr_policy(
+                &payload_priv_key.private_key_to_der().map_err(|e| {
+                    Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                        "Failed to DER-encode payload private key for sealing: {e}"
+                    )))
+                })?,
+                &seal_pcrs,
+                tpm_hash_alg,
+                &sealed_path,
+            ) {
+                Ok(_) => info!(
+                    "Sealed payload key to TPM under PCRs {:?} at {}",
+                    config.payload_seal_pcrs,
+                    sealed_path.display()
+                ),
+                Err(e) => warn!("Failed to seal payload key to TPM: {e}"),
+            }
+        }
+    }
+
+    // Unified-Identity: hand `ctx` to the dedicated TPM worker task now, ahead
+    // of the startup self-test and RA-TLS quote below. Those used to issue
+    // `ctx.quote(...)` directly on the async runtime thread, blocking it for
+    // the full duration of a TPM2_Quote (tens to hundreds of ms); routing them
+    // through `tpm_tx`/`request_quote` like every other quote request keeps
+    // that work off the reactor and serializes it behind the same single
+    // TPM-owning task as the rest of the agent.
+    let tpm_tx = spawn_tpm_worker(ctx);
+
     if config.startup_quote_test {
         match run_startup_quote_self_test(
-            &mut ctx,
+            &tpm_tx,
             tpm_hash_alg,
             tpm_signing_alg,
             ak_handle,
             &payload_pub_key,
-        ) {
+        )
+        .await
+        {
             Ok(_) => info!("Startup TPM quote self-test completed successfully"),
             Err(e) => {
                 warn!("Startup TPM quote self-test failed (continuing): {e}")
@@ -960,39 +1041,133 @@ async fn main() -> Result<()> {
         false, // Don't validate algorithm for mTLS keys (for backward compatibility)
     )?;
 
+    // Unified-Identity: shared token -> key-authorization map for the ACME
+    // HTTP-01 challenge route, created unconditionally so it can be registered
+    // on the actix app below regardless of whether ACME ends up being used.
+    let acme_http01_challenges = web::Data::new(acme::Http01Challenges::new(
+        std::collections::HashMap::new(),
+    ));
+
     let cert: X509;
     let mtls_cert;
     let ssl_context;
+    let rustls_server_config;
+    #[cfg(feature = "mbedtls")]
+    let mbedtls_server_config;
+    // Unified-Identity: populated, for whichever TLS backend is selected,
+    // when `trusted_client_crl`/`trusted_client_ocsp_responder` are
+    // configured; see `client_revocation`. `crl_reload_rx` is handed to the
+    // background reload task spawned further below.
+    let mut revoked_serials: Option<std::sync::Arc<client_revocation::RevokedSerials>> = None;
+    let mut ocsp_cache: Option<std::sync::Arc<client_revocation::OcspCache>> = None;
+    let mut crl_reload_tx: Option<mpsc::Sender<client_revocation::CrlReloadMessage>> = None;
+    let mut crl_reload_rx: Option<mpsc::Receiver<client_revocation::CrlReloadMessage>> = None;
+    // Unified-Identity: populated only when `trust_root_enabled`; see `trust_root`.
+    let mut trusted_ca_store: Option<std::sync::Arc<trust_root::TrustedCaStore>> = None;
+    let mut trust_root_reload_tx: Option<mpsc::Sender<trust_root::TrustRootMessage>> = None;
+    let mut trust_root_reload_rx: Option<mpsc::Receiver<trust_root::TrustRootMessage>> = None;
     if config.enable_agent_mtls {
         let contact_ips = vec![config.contact_ip.as_str()];
-        cert = match config.server_cert.as_ref() {
-            "" => {
-                debug!("The server_cert option was not set in the configuration file");
 
-                crypto::x509::CertificateBuilder::new()
-                    .private_key(&mtls_priv)
-                    .common_name(&agent_uuid)
-                    .add_ips(contact_ips)
-                    .build()?
-            }
-            path => {
-                let cert_path = Path::new(&path);
-                if cert_path.exists() {
-                    debug!(
-                        "Loading existing mTLS certificate from {}",
-                        cert_path.display()
-                    );
-                    crypto::load_x509_pem(cert_path)?
-                } else {
-                    debug!("Generating new mTLS certificate");
-                    let cert = crypto::x509::CertificateBuilder::new()
+        // Unified-Identity: RA-TLS. Bind the mTLS keypair to a live TPM quote by
+        // embedding a custom extension carrying the AK public area and a quote
+        // whose qualifying data is the SHA-256 of this keypair's SubjectPublicKeyInfo.
+        let ra_tls_extension = if config.attested_tls {
+            let spki_der = mtls_pub.public_key_to_der().map_err(|e| {
+                Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                    "Failed to DER-encode mTLS public key for RA-TLS: {e}"
+                )))
+            })?;
+            let qualifying_data = openssl::sha::sha256(&spki_der).to_vec();
+            let quote_blob = quotes_handler::request_quote(
+                &tpm_tx,
+                qualifying_data,
+                config.attested_tls_pcr_mask,
+                mtls_pub.clone(),
+                ak_handle,
+                tpm_hash_alg,
+                tpm_signing_alg,
+            )
+            .await?;
+            let (reply_tx, reply_rx) = oneshot::channel();
+            tpm_tx
+                .send((TpmMessage::ReadPublic { handle: ak_handle }, reply_tx))
+                .await
+                .map_err(|e| tpm::TpmError::Other(format!("TPM worker channel closed: {e}")))?;
+            let ak_public = match reply_rx.await.map_err(|e| {
+                tpm::TpmError::Other(format!("TPM worker dropped reply channel: {e}"))
+            })? {
+                TpmReply::ReadPublic(result) => result?,
+                _ => {
+                    return Err(tpm::TpmError::Other(
+                        "Unexpected reply from TPM worker for ReadPublic request".to_string(),
+                    )
+                    .into())
+                }
+            };
+            let ak_public_der = ak_public.marshall()?;
+            let extension = ra_tls::build_quote_extension(&ak_public_der, &quote_blob).map_err(
+                |e| Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                    "Failed to build RA-TLS quote extension: {e}"
+                ))),
+            )?;
+            Some(extension)
+        } else {
+            None
+        };
+
+        cert = if config.acme_enabled {
+            // Unified-Identity: obtain a real certificate via ACME HTTP-01 instead
+            // of self-signing, reusing the mTLS keypair for the CSR.
+            let acme_cfg = acme::AcmeConfig {
+                directory_url: config.acme_directory_url.clone(),
+                contact_email: config.acme_contact_email.clone(),
+                dns_names: parse_list(config.acme_dns_names.as_ref())?,
+                cache_dir: PathBuf::from(&config.agent_data_path),
+            };
+            acme::provision_or_renew(&acme_cfg, &mtls_priv, &acme_http01_challenges)
+                .await
+                .map_err(|e| {
+                    Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                        "ACME provisioning failed: {e}"
+                    )))
+                })?
+        } else {
+            match config.server_cert.as_ref() {
+                "" => {
+                    debug!("The server_cert option was not set in the configuration file");
+
+                    let mut builder = crypto::x509::CertificateBuilder::new()
                         .private_key(&mtls_priv)
                         .common_name(&agent_uuid)
-                        .add_ips(contact_ips)
-                        .build()?;
-                    // Write the generated certificate
-                    crypto::write_x509(&cert, cert_path)?;
-                    cert
+                        .add_ips(contact_ips);
+                    if let Some(ext) = &ra_tls_extension {
+                        builder = builder.custom_extension(ra_tls::RA_TLS_QUOTE_OID, ext);
+                    }
+                    builder.build()?
+                }
+                path => {
+                    let cert_path = Path::new(&path);
+                    if cert_path.exists() {
+                        debug!(
+                            "Loading existing mTLS certificate from {}",
+                            cert_path.display()
+                        );
+                        crypto::load_x509_pem(cert_path)?
+                    } else {
+                        debug!("Generating new mTLS certificate");
+                        let mut builder = crypto::x509::CertificateBuilder::new()
+                            .private_key(&mtls_priv)
+                            .common_name(&agent_uuid)
+                            .add_ips(contact_ips);
+                        if let Some(ext) = &ra_tls_extension {
+                            builder = builder.custom_extension(ra_tls::RA_TLS_QUOTE_OID, ext);
+                        }
+                        let cert = builder.build()?;
+                        // Write the generated certificate
+                        crypto::write_x509(&cert, cert_path)?;
+                        cert
+                    }
                 }
             }
         };
@@ -1028,14 +1203,287 @@ async fn main() -> Result<()> {
             }?;
 
         mtls_cert = Some(cert.clone());
-        ssl_context = Some(crypto::generate_tls_context(
-            &cert,
-            &mtls_priv,
-            keylime_ca_certs,
-        )?);
+
+        // Unified-Identity: extend the startup self-test to the agent's own
+        // mTLS certificate, so a stale or prematurely-provisioned cert is
+        // caught here rather than failing opaquely on the first client
+        // handshake; see `check_certificate_validity`.
+        if config.startup_quote_test {
+            check_certificate_validity(
+                "agent mTLS",
+                &cert,
+                Duration::from_secs(config.cert_validity_skew_secs),
+                Duration::from_secs(config.cert_expiry_warning_days * 86_400),
+            )?;
+        }
+
+        // Unified-Identity: `trusted_client_crl`/`trusted_client_ocsp_responder`
+        // gate client certificates the same way regardless of which TLS
+        // backend binds the listener, so this is resolved once here rather
+        // than per-backend; see `client_revocation`.
+        let crl_list = parse_list(config.trusted_client_crl.as_ref())?;
+        if !crl_list.is_empty() {
+            revoked_serials = Some(client_revocation::RevokedSerials::new());
+            let (tx, rx) = mpsc::channel::<client_revocation::CrlReloadMessage>(1);
+            crl_reload_tx = Some(tx);
+            crl_reload_rx = Some(rx);
+        }
+        if !config.trusted_client_ocsp_responder.is_empty() {
+            ocsp_cache = Some(client_revocation::OcspCache::new());
+        }
+
+        // Unified-Identity: `tls_backend` selects which TLS stack binds the
+        // HTTPS listener below; both backends are built from this same cert,
+        // key, and trusted-client-CA material.
+        match tls_backend::TlsBackend::parse(&config.tls_backend)
+            .map_err(|e| Error::Configuration(config::KeylimeConfigError::Generic(e)))?
+        {
+            tls_backend::TlsBackend::OpenSsl => {
+                let mut generated_ssl_context =
+                    crypto::generate_tls_context(&cert, &mtls_priv, keylime_ca_certs)?;
+
+                // Unified-Identity: negotiate a supported agent protocol
+                // version over ALPN, see `tls_backend::alpn_protocols`.
+                tls_backend::install_openssl_alpn(
+                    &mut generated_ssl_context,
+                    tls_backend::alpn_protocols(&api_versions),
+                );
+
+                let ra_tls_ek_ca_certs = if config.attested_tls {
+                    let ek_ca_list = parse_list(config.attested_tls_ek_ca.as_ref())?;
+                    if ek_ca_list.is_empty() {
+                        error!("'attested_tls' is enabled, but 'attested_tls_ek_ca' is empty");
+                        return Err(Error::Configuration(config::KeylimeConfigError::Generic(
+                            "'attested_tls' is enabled, but 'attested_tls_ek_ca' is empty"
+                                .to_string(),
+                        )));
+                    }
+                    let ek_ca_certs =
+                        crypto::load_x509_cert_list(ek_ca_list.iter().map(Path::new).collect())?;
+
+                    // Unified-Identity: extend the startup self-test to the
+                    // RA-TLS EK/AK chain CA certificates; see
+                    // `check_certificate_validity`.
+                    if config.startup_quote_test {
+                        for (i, ek_ca_cert) in ek_ca_certs.iter().enumerate() {
+                            check_certificate_validity(
+                                &format!("RA-TLS EK/AK CA certificate #{i}"),
+                                ek_ca_cert,
+                                Duration::from_secs(config.cert_validity_skew_secs),
+                                Duration::from_secs(config.cert_expiry_warning_days * 86_400),
+                            )?;
+                        }
+                    }
+
+                    Some(ek_ca_certs)
+                } else {
+                    None
+                };
+
+                // Unified-Identity: optional expected PCR composite digest
+                // for the peer's RA-TLS quote; unset (empty string) means no
+                // PCR policy is enforced and only the chain/qualifying-data
+                // checks above apply.
+                let ra_tls_pcr_policy = if config.attested_tls_pcr_policy.is_empty() {
+                    None
+                } else {
+                    Some(hex::decode(&config.attested_tls_pcr_policy).map_err(|e| {
+                        Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                            "'attested_tls_pcr_policy' is not valid hex: {e}"
+                        )))
+                    })?)
+                };
+
+                // Unified-Identity: TUF-backed trust root subsystem, see
+                // `trust_root`. Seed the live store from the on-disk cache
+                // (if any) so a restart without network access keeps
+                // enforcing the last verified anchor set; `refresh_worker`,
+                // spawned below, hot-swaps it afterwards.
+                if config.trust_root_enabled {
+                    let cached = trust_root::load_cached(Path::new(&config.trust_root_cache_dir));
+                    // Unified-Identity: with no cached bundle yet, the live
+                    // store starts empty and `trust_root::is_trusted` rejects
+                    // every client certificate until the first refresh
+                    // succeeds - a full mTLS DoS on a cold start with no
+                    // network access. Require a pre-seeded `trust_roots.pem`
+                    // (e.g. shipped at deployment time) instead of silently
+                    // enforcing "trust nothing" in the meantime.
+                    let cached = match cached {
+                        Some(cached) => cached,
+                        None => {
+                            error!("'trust_root_enabled' is set, but no cached trust roots were found at {}/trust_roots.pem; pre-seed a bootstrap bundle before enabling this feature", config.trust_root_cache_dir);
+                            return Err(Error::Configuration(config::KeylimeConfigError::Generic(
+                                format!(
+                                    "'trust_root_enabled' is set, but no cached trust roots were found at {}/trust_roots.pem",
+                                    config.trust_root_cache_dir
+                                ),
+                            )));
+                        }
+                    };
+                    trusted_ca_store = Some(trust_root::TrustedCaStore::new(cached));
+                    let (tx, rx) = mpsc::channel::<trust_root::TrustRootMessage>(1);
+                    trust_root_reload_tx = Some(tx);
+                    trust_root_reload_rx = Some(rx);
+                }
+
+                // Unified-Identity: only one verify callback can be installed
+                // per `SslAcceptorBuilder`, so every enabled peer check
+                // (RA-TLS, CRL, OCSP, TUF trust root) is composed into a
+                // single callback here.
+                if ra_tls_ek_ca_certs.is_some()
+                    || revoked_serials.is_some()
+                    || ocsp_cache.is_some()
+                    || trusted_ca_store.is_some()
+                {
+                    let revoked_serials_cb = revoked_serials.clone();
+                    let ocsp_cache_cb = ocsp_cache.clone();
+                    let trusted_ca_store_cb = trusted_ca_store.clone();
+                    let ocsp_responder_url = config.trusted_client_ocsp_responder.clone();
+                    let ra_tls_pcr_policy_cb = ra_tls_pcr_policy.clone();
+                    generated_ssl_context.set_verify_callback(
+                        openssl::ssl::SslVerifyMode::PEER
+                            | openssl::ssl::SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+                        move |preverify_ok, x509_ctx| {
+                            if !preverify_ok {
+                                return false;
+                            }
+                            let leaf = match x509_ctx.current_cert() {
+                                Some(leaf) => leaf,
+                                None => return false,
+                            };
+
+                            if let Some(ref revoked_serials) = revoked_serials_cb {
+                                if client_revocation::is_revoked(leaf, revoked_serials) {
+                                    warn!("Rejecting client certificate: serial number is present in 'trusted_client_crl'");
+                                    return false;
+                                }
+                            }
+
+                            if let Some(ref ocsp_cache) = ocsp_cache_cb {
+                                let issuer = x509_ctx.chain().and_then(|chain| chain.iter().nth(1));
+                                match issuer {
+                                    Some(issuer) => match client_revocation::check_ocsp(
+                                        leaf,
+                                        issuer,
+                                        &ocsp_responder_url,
+                                        Duration::from_secs(3600),
+                                        ocsp_cache,
+                                    ) {
+                                        Ok(true) => (),
+                                        Ok(false) => {
+                                            warn!("Rejecting client certificate: OCSP responder reports it is not good");
+                                            return false;
+                                        }
+                                        Err(e) => {
+                                            warn!("Rejecting client certificate: OCSP check failed: {e}");
+                                            return false;
+                                        }
+                                    },
+                                    None => {
+                                        warn!("Rejecting client certificate: issuer certificate unavailable for OCSP check");
+                                        return false;
+                                    }
+                                }
+                            }
+
+                            if let Some(ref trusted_ca_store) = trusted_ca_store_cb {
+                                if !trust_root::is_trusted(leaf, trusted_ca_store) {
+                                    warn!("Rejecting client certificate: not issued by a currently TUF-trusted CA");
+                                    return false;
+                                }
+                            }
+
+                            match &ra_tls_ek_ca_certs {
+                                Some(ek_ca_certs) => ra_tls::verify_peer(
+                                    x509_ctx,
+                                    ek_ca_certs,
+                                    ra_tls_pcr_policy_cb.as_deref(),
+                                )
+                                .unwrap_or_else(|e| {
+                                    warn!("RA-TLS: rejecting peer certificate: {e}");
+                                    false
+                                }),
+                                None => true,
+                            }
+                        },
+                    );
+                }
+
+                ssl_context = Some(generated_ssl_context);
+                rustls_server_config = None;
+                #[cfg(feature = "mbedtls")]
+                {
+                    mbedtls_server_config = None;
+                }
+            }
+            tls_backend::TlsBackend::Rustls => {
+                let provider_kind =
+                    tls_backend::CryptoProviderKind::parse(&config.tls_crypto_provider)
+                        .map_err(|e| Error::Configuration(config::KeylimeConfigError::Generic(e)))?;
+                tls_backend::install_default_crypto_provider(provider_kind)
+                    .map_err(|e| Error::Configuration(config::KeylimeConfigError::Generic(e)))?;
+
+                let cert_der = cert.to_der()?;
+                let key_der = mtls_priv.private_key_to_der()?;
+                let client_ca_certs_der = keylime_ca_certs
+                    .iter()
+                    .map(|c| c.to_der())
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                // Unified-Identity: wire the same `trusted_client_crl`/
+                // `trusted_client_ocsp_responder` enforcement into the
+                // rustls backend as the OpenSSL verify callback above, so
+                // picking `tls_backend = "rustls"` doesn't silently drop
+                // revocation checking; see `build_server_config`.
+                let ocsp_for_rustls = ocsp_cache
+                    .clone()
+                    .map(|cache| (config.trusted_client_ocsp_responder.clone(), cache));
+
+                rustls_server_config = Some(
+                    tls_backend::build_server_config(
+                        cert_der,
+                        key_der,
+                        client_ca_certs_der,
+                        tls_backend::alpn_protocols(&api_versions),
+                        revoked_serials.clone(),
+                        ocsp_for_rustls,
+                    )
+                    .map_err(|e| Error::Configuration(config::KeylimeConfigError::Generic(e)))?,
+                );
+                ssl_context = None;
+                #[cfg(feature = "mbedtls")]
+                {
+                    mbedtls_server_config = None;
+                }
+            }
+            #[cfg(feature = "mbedtls")]
+            tls_backend::TlsBackend::MbedTls => {
+                let cert_der = cert.to_der()?;
+                let key_der = mtls_priv.private_key_to_der()?;
+                let client_ca_certs_der = keylime_ca_certs
+                    .iter()
+                    .map(|c| c.to_der())
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                mbedtls_server_config = Some(
+                    tls_backend::build_mbedtls_config(cert_der, key_der, client_ca_certs_der)
+                        .map_err(|e| {
+                            Error::Configuration(config::KeylimeConfigError::Generic(e))
+                        })?,
+                );
+                warn!("tls_backend = 'mbedtls': configuration validated, but the HTTPS listener does not bind an mbedtls accept loop yet");
+                ssl_context = None;
+                rustls_server_config = None;
+            }
+        }
     } else {
         mtls_cert = None;
         ssl_context = None;
+        rustls_server_config = None;
+        #[cfg(feature = "mbedtls")]
+        {
+            mbedtls_server_config = None;
+        }
         warn!("mTLS disabled, Tenant and Verifier will reach out to agent via HTTP");
     }
 
@@ -1048,6 +1496,216 @@ async fn main() -> Result<()> {
         ek_handle: config.ek_handle.clone(),
     };
 
+    // Unified-Identity: capture the mTLS certificate chain for the secured
+    // geolocation endpoint before `mtls_cert` is moved into `aa` below.
+    let geolocation_signing_cert_chain: Vec<String> = mtls_cert
+        .as_ref()
+        .and_then(|c| c.to_pem().ok())
+        .map(|pem| vec![String::from_utf8_lossy(&pem).into_owned()])
+        .unwrap_or_default();
+
+    // Unified-Identity: load the trusted capability-token issuer public key
+    // when capability-token gating is enabled for the geolocation endpoint.
+    let geolocation_capability_issuer_pubkey: Option<PKey<Public>> =
+        if config.geolocation_capability_enabled {
+            match fs::read(&config.geolocation_capability_issuer_pubkey_path)
+                .map_err(|e| e.to_string())
+                .and_then(|pem| PKey::public_key_from_pem(&pem).map_err(|e| e.to_string()))
+            {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity: Failed to load geolocation capability issuer public key from {}: {}",
+                        config.geolocation_capability_issuer_pubkey_path, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+    // Unified-Identity: build the content-addressed quote cache when
+    // enabled. An unset/empty disk directory disables caching entirely
+    // rather than falling back to a default path, so operators opt in
+    // explicitly to the on-disk footprint.
+    let quote_cache: Option<std::sync::Arc<cache::QuoteCache>> =
+        if config.quote_cache_enabled && !config.quote_cache_dir.is_empty() {
+            Some(std::sync::Arc::new(cache::QuoteCache::new(
+                PathBuf::from(&config.quote_cache_dir),
+                config.quote_cache_max_entries as usize,
+                Duration::from_secs(config.quote_cache_ttl_seconds),
+            )))
+        } else {
+            None
+        };
+
+    // Unified-Identity: open the App Key certification transparency log
+    // when enabled - an unset/empty path disables it, leaving
+    // `certify_app_key`'s `log_index`/`inclusion_proof`/`signed_tree_head`
+    // fields unset, the same opt-in shape as `quote_cache` above.
+    let transparency_log: Option<Mutex<transparency_log::TransparencyLog>> =
+        if config.delegated_cert_transparency_log_enabled
+            && !config.delegated_cert_transparency_log_path.is_empty()
+        {
+            match transparency_log::TransparencyLog::open(Path::new(
+                &config.delegated_cert_transparency_log_path,
+            )) {
+                Ok(log) => Some(Mutex::new(log)),
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity: Failed to open App Key certification transparency log at {}: {}",
+                        config.delegated_cert_transparency_log_path, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+    // Unified-Identity: build the device-attest-01 ACME config for
+    // `delegated_certification_handler::certify_app_key`. Distinct from
+    // `config.acme_enabled` above, which provisions the agent's own mTLS
+    // cert via HTTP-01 - this one certifies a delegated App Key and only
+    // runs per-request, so there's nothing to provision at startup.
+    let device_attest_acme_config: Option<device_attest_acme::DeviceAttestAcmeConfig> =
+        if config.device_attest_acme_enabled {
+            Some(device_attest_acme::DeviceAttestAcmeConfig {
+                directory_url: config.device_attest_acme_directory_url.clone(),
+                account_key_path: PathBuf::from(&config.device_attest_acme_account_key_path),
+                poll_interval: Duration::from_secs(
+                    config.device_attest_acme_poll_interval_seconds,
+                ),
+                poll_timeout: Duration::from_secs(config.device_attest_acme_poll_timeout_seconds),
+            })
+        } else {
+            None
+        };
+
+    // Unified-Identity: load the Ed25519 verifier key authorizing signed
+    // requests to the /quotes scope. Absent or unreadable leaves the
+    // middleware disabled (pass-through).
+    let signed_request_verifier_pubkey: Option<ed25519_dalek::VerifyingKey> =
+        if config.signed_request_verifier_pubkey_path.is_empty() {
+            None
+        } else {
+            match fs::read(&config.signed_request_verifier_pubkey_path).and_then(|bytes| {
+                let key_bytes: [u8; 32] = bytes.try_into().map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Ed25519 public key must be exactly 32 bytes",
+                    )
+                })?;
+                ed25519_dalek::VerifyingKey::from_bytes(&key_bytes).map_err(|e| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+                })
+            }) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity: Failed to load signed-request verifier public key from {}: {}",
+                        config.signed_request_verifier_pubkey_path, e
+                    );
+                    None
+                }
+            }
+        };
+
+    // Unified-Identity: load the trusted root verifier keys for the /quotes
+    // capability-token chain. Only the root of a delegation chain needs to
+    // match one of these; intermediate delegators are authorized by their
+    // proof chain instead.
+    let quote_capability_trusted_roots: Vec<PKey<Public>> =
+        if config.quote_capability_enabled {
+            parse_list(config.quote_capability_trusted_roots_paths.as_ref())?
+                .iter()
+                .filter_map(|path| {
+                    fs::read(path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|pem| {
+                            PKey::public_key_from_pem(&pem).map_err(|e| e.to_string())
+                        })
+                        .map_err(|e| {
+                            warn!(
+                                "Unified-Identity: Failed to load quote capability trusted root key from {}: {}",
+                                path, e
+                            );
+                        })
+                        .ok()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+    // Unified-Identity: load the authorized SPIRE client public keys for the
+    // delegated-certification endpoint's HTTP Message Signature check; see
+    // `delegated_certification_handler::verify_http_message_signature`. An
+    // empty list disables the check (pass-through), leaving the IP
+    // allowlist/rate-limit as the only gate, as before.
+    let delegated_cert_authorized_client_keys: Vec<PKey<Public>> =
+        parse_list(config.delegated_cert_authorized_client_keys_paths.as_ref())?
+            .iter()
+            .filter_map(|path| {
+                fs::read(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|pem| PKey::public_key_from_pem(&pem).map_err(|e| e.to_string()))
+                    .map_err(|e| {
+                        warn!(
+                            "Unified-Identity: Failed to load delegated-certification authorized client key from {}: {}",
+                            path, e
+                        );
+                    })
+                    .ok()
+            })
+            .collect();
+
+    // Unified-Identity: load the `/delegated_certification/sign` key
+    // registry - "id=context_file_path" entries mapping a caller-supplied
+    // `key_id` to the TPM context file
+    // `tpm::Context::load_key_from_context_file` should load, the same
+    // primitive `CertifyAppKey`'s App Key load already uses. An empty list
+    // disables the `/sign` and `/list_keys` endpoints, the same opt-in
+    // shape as the other optional delegated-certification gates.
+    let mut signing_key_registry: std::collections::HashMap<String, String> =
+        std::collections::HashMap::new();
+    for entry in parse_list(config.delegated_cert_signing_keys.as_ref())? {
+        match entry.split_once('=') {
+            Some((key_id, context_path))
+                if !key_id.is_empty() && !context_path.is_empty() =>
+            {
+                signing_key_registry
+                    .insert(key_id.to_string(), context_path.to_string());
+            }
+            _ => warn!(
+                "Unified-Identity: Ignoring malformed delegated_cert_signing_keys entry (expected \"id=path\"): {}",
+                entry
+            ),
+        }
+    }
+
+    // Unified-Identity: load the pre-shared HMAC key authenticating signed
+    // `POST /integrity` policy documents. Absent or unreadable leaves the
+    // POST route disabled (`quotes_handler::integrity_policy_json` and
+    // `integrity_policy_multipart` reject every request with 403 when no key
+    // is configured).
+    let quote_policy_hmac_key: Option<Vec<u8>> =
+        if config.quote_policy_hmac_key_path.is_empty() {
+            None
+        } else {
+            match fs::read(&config.quote_policy_hmac_key_path) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!(
+                        "Unified-Identity: Failed to load quote policy HMAC key from {}: {}",
+                        config.quote_policy_hmac_key_path, e
+                    );
+                    None
+                }
+            }
+        };
+
     let aa = AgentRegistration {
         ak,
         ek_result,
@@ -1075,9 +1733,22 @@ async fn main() -> Result<()> {
     )>(1);
     let (mut revocation_tx, mut revocation_rx) = mpsc::channel::<revocation::RevocationMessage>(1);
 
+    let (mut acme_tx, acme_rx) = mpsc::channel::<acme::AcmeMessage>(1);
+
     #[cfg(feature = "with-zmq")]
     let (mut zmq_tx, mut zmq_rx) = mpsc::channel::<revocation::ZmqMessage>(1);
 
+    // Unified-Identity: shutdown channel for the WebSocket revocation
+    // subscriber task, see `ws_revocation::subscriber_worker`.
+    #[cfg(feature = "with-ws-revocation")]
+    let (mut ws_revocation_tx, ws_revocation_rx) =
+        mpsc::channel::<ws_revocation::WsRevocationMessage>(1);
+
+    // Unified-Identity: one-shot signal telling the optional QUIC endpoint
+    // (see `quic_server`) to stop accepting new connections and drain.
+    #[cfg(feature = "with-quic")]
+    let (quic_shutdown_tx, quic_shutdown_rx) = oneshot::channel::<()>();
+
     let revocation_cert = match config.revocation_cert.as_ref() {
         "" => {
             error!("No revocation certificate set in 'revocation_cert' option");
@@ -1108,32 +1779,74 @@ async fn main() -> Result<()> {
     ))
     .map_err(Error::from);
 
+    // Unified-Identity: parse the delegated-certification UDS SO_PEERCRED allow-lists
+    let delegated_cert_uds_allowed_uids: Vec<u32> =
+        parse_list(config.delegated_cert_uds_allowed_uids.as_ref())?
+            .iter()
+            .filter_map(|s| s.parse().ok())
+            .collect();
+    let delegated_cert_uds_allowed_gids: Vec<u32> =
+        parse_list(config.delegated_cert_uds_allowed_gids.as_ref())?
+            .iter()
+            .filter_map(|s| s.parse().ok())
+            .collect();
+
     let quotedata = web::Data::new(QuoteData {
         agent_uuid: agent_uuid.clone(),
         ak_handle,
+        ek_handle,
         allow_payload_revocation_actions,
         api_versions: api_versions.clone(),
         enc_alg: tpm_encryption_alg,
         hash_alg: tpm_hash_alg,
         ima_ml: Mutex::new(MeasurementList::new()),
+        ima_ml_path: ima_ml_file.is_some().then(|| ima_ml_path.clone()),
         ima_ml_file,
         keys_tx: keys_tx.clone(),
         measuredboot_ml_file,
         payload_tx: payload_tx.clone(),
         payload_priv_key,
         payload_pub_key,
-        priv_key: mtls_priv,
+        priv_key: mtls_priv.clone(),
         pub_key: mtls_pub,
         revocation_tx: revocation_tx.clone(),
         secure_mount: PathBuf::from(&mount),
         secure_size,
         sign_alg: tpm_signing_alg,
-        tpmcontext: Mutex::new(ctx),
+        tpm_tx: tpm_tx.clone(),
         work_dir,
         unified_identity_enabled: config.unified_identity_enabled,
+        geolocation_history: Mutex::new(VecDeque::new()),
+        geolocation_signing_cert_chain,
+        geolocation_capability_enabled: config.geolocation_capability_enabled,
+        geolocation_capability_issuer_pubkey,
+        quote_capability_enabled: config.quote_capability_enabled,
+        quote_capability_trusted_roots,
+        quote_security_headers: quotes_handler::QuoteSecurityHeaders {
+            content_type_options: config.quote_security_header_content_type_options,
+            frame_options: config.quote_security_header_frame_options,
+            permissions_policy: config.quote_security_header_permissions_policy,
+            cache_control: config.quote_security_header_cache_control,
+        },
+        quote_policy_hmac_key,
+        geolocation_gnss_device_paths: config.geolocation_gnss_device_paths.clone(),
+        quote_cache,
+        signed_request_verifier_pubkey,
+        signed_request_skew_seconds: config.signed_request_skew_seconds,
         delegated_cert_enabled: config.delegated_cert_enabled,
         delegated_cert_allowed_ips: config.delegated_cert_allowed_ips.clone(),
-        delegated_cert_rate_limit: config.delegated_cert_rate_limit,
+        delegated_cert_rate_limit_per_second: config.delegated_cert_rate_limit_per_second,
+        delegated_cert_rate_limit_burst: config.delegated_cert_rate_limit_burst,
+        delegated_cert_rate_limit_idle_eviction_seconds: config
+            .delegated_cert_rate_limit_idle_eviction_seconds,
+        delegated_cert_uds_allowed_uids,
+        delegated_cert_uds_allowed_gids,
+        device_attest_acme_config,
+        delegated_cert_authorized_client_keys,
+        delegated_cert_signature_skew_seconds: config.delegated_cert_signature_skew_seconds,
+        delegated_cert_nonce_ttl_seconds: config.delegated_cert_nonce_ttl_seconds,
+        transparency_log,
+        signing_key_registry,
     });
 
     let actix_server = HttpServer::new(move || {
@@ -1153,9 +1866,11 @@ async fn main() -> Result<()> {
                 srv.call(req)
             })
             .app_data(quotedata.clone())
+            .app_data(acme_http01_challenges.clone())
             .app_data(web::JsonConfig::default().error_handler(errors_handler::json_parser_error))
             .app_data(web::QueryConfig::default().error_handler(errors_handler::query_parser_error))
-            .app_data(web::PathConfig::default().error_handler(errors_handler::path_parser_error));
+            .app_data(web::PathConfig::default().error_handler(errors_handler::path_parser_error))
+            .configure(acme::configure_acme_endpoints);
 
         for version in &api_versions {
             // This should never fail, thus unwrap should never panic
@@ -1164,6 +1879,14 @@ async fn main() -> Result<()> {
         }
 
         app.service(web::resource("/version").route(web::get().to(api::version)))
+            .service(
+                web::resource("/v2/agent/geolocation_history")
+                    .route(web::get().to(geolocation_handler::geolocation_history)),
+            )
+            .service(
+                web::resource("/v2/agent/geolocation_history.gpx")
+                    .route(web::get().to(geolocation_handler::geolocation_history_gpx)),
+            )
             .service(
                 web::resource(r"/v{major:\d+}.{minor:\d+}{tail}*")
                     .to(errors_handler::version_not_supported),
@@ -1195,15 +1918,96 @@ async fn main() -> Result<()> {
 
     let port = config.port;
 
-    // Unified-Identity: Support UDS socket for delegated certification
-    // Note: Actix-web 4.x doesn't natively support Unix domain sockets.
-    // For now, we'll use HTTP over localhost. UDS support can be added later
-    // using a custom server implementation or by upgrading to a version that supports it.
-    // The endpoint will be accessible via HTTP at http://127.0.0.1:{port}/v2.2/delegated_certification/certify_app_key
+    // Unified-Identity: Native Unix-domain-socket transport for the
+    // delegated-certification endpoint, see `uds_auth`. Bound as a second,
+    // independent `HttpServer` alongside the HTTPS server below; both share
+    // `quotedata`, but only the UDS listener's connections carry a `PeerCred`
+    // extension, which is how `certify_app_key` tells the two apart.
+    let mut uds_server_handle = None;
+    let uds_server_task = if !config.delegated_cert_uds_path.is_empty() {
+        let uds_path = config.delegated_cert_uds_path.clone();
+        let uds_quotedata = quotedata.clone();
+        let uds_server = HttpServer::new(move || {
+            App::new()
+                .wrap(middleware::Logger::new("%r from %a result %s (took %D ms)"))
+                .app_data(uds_quotedata.clone())
+                .app_data(web::JsonConfig::default().error_handler(errors_handler::json_parser_error))
+                .service(web::scope("/v2.2/delegated_certification").configure(
+                    delegated_certification_handler::configure_delegated_certification_endpoints,
+                ))
+        })
+        .disable_signals()
+        .on_connect(uds_auth::install_peer_cred)
+        .bind_uds(&uds_path)?
+        .run();
+
+        uds_auth::set_socket_permissions(
+            Path::new(&uds_path),
+            config.delegated_cert_uds_mode,
+            config.delegated_cert_uds_uid,
+            config.delegated_cert_uds_gid,
+        )?;
+
+        info!("Unified-Identity: Delegated certification endpoint available over UDS at {uds_path} (/v2.2/delegated_certification/certify_app_key)");
+
+        uds_server_handle = Some(uds_server.handle());
+        rt::spawn(uds_server).map_err(Error::from)
+    } else {
+        rt::spawn(ok(())).map_err(Error::from)
+    };
+
+    // Unified-Identity: optional QUIC bind path for the quote
+    // endpoint, see `quic_server`. Reuses the same rustls `ServerConfig`
+    // (ALPN included) as the HTTPS listener below, so it is only available
+    // when `tls_backend = "rustls"`.
+    #[cfg(feature = "with-quic")]
+    let quic_task = if config.quic_enabled {
+        match rustls_server_config.clone() {
+            Some(quic_tls_config) => {
+                let quic_addr = format!("{ip}:{}", config.quic_port)
+                    .parse()
+                    .map_err(|e| {
+                        Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                            "invalid 'quic_port': {e}"
+                        )))
+                    })?;
+                let quic_quote_cfg = std::sync::Arc::new(quic_server::QuicQuoteConfig {
+                    tpm_tx: tpm_tx.clone(),
+                    ak_handle,
+                    payload_pub_key: payload_pub_key.clone(),
+                    hash_alg: tpm_hash_alg,
+                    sign_alg: tpm_signing_alg,
+                });
+
+                info!("Unified-Identity: Listening for QUIC connections on {quic_addr}");
+
+                rt::spawn(async move {
+                    quic_server::run(quic_addr, quic_tls_config, quic_quote_cfg, quic_shutdown_rx)
+                        .await
+                })
+                .map_err(Error::from)
+            }
+            None => {
+                warn!("Unified-Identity: 'quic_enabled' is set but no rustls TLS config is available (tls_backend must be 'rustls'); QUIC bind path disabled");
+                rt::spawn(ok(())).map_err(Error::from)
+            }
+        }
+    } else {
+        rt::spawn(ok(())).map_err(Error::from)
+    };
 
     // Unified-Identity: Enable mTLS for verifier communication (Gap #2 fix)
     // Use HTTPS with mTLS when enabled, fall back to HTTP only if mTLS is disabled
-    if config.enable_agent_mtls && ssl_context.is_some() {
+    if config.enable_agent_mtls && rustls_server_config.is_some() {
+        server = actix_server
+            .bind_rustls_0_23(
+                format!("{ip}:{port}"),
+                rustls_server_config.unwrap(), //#[allow_ci]
+            )?
+            .run();
+        info!("Listening on https://{ip}:{port} (rustls)");
+        info!("Unified-Identity: Delegated certification endpoint available at https://{ip}:{port}/v2.2/delegated_certification/certify_app_key");
+    } else if config.enable_agent_mtls && ssl_context.is_some() {
         server = actix_server
             .bind_openssl(
                 format!("{ip}:{port}"),
@@ -1213,6 +2017,10 @@ async fn main() -> Result<()> {
         info!("Listening on https://{ip}:{port}");
         info!("Unified-Identity: Delegated certification endpoint available at https://{ip}:{port}/v2.2/delegated_certification/certify_app_key");
     } else {
+        #[cfg(feature = "mbedtls")]
+        if mbedtls_server_config.is_some() {
+            warn!("tls_backend = 'mbedtls' built a valid config, but no mbedtls accept loop is wired into the server bind step yet; falling back to HTTP (insecure)");
+        }
         warn!("mTLS disabled or SSL context unavailable, using HTTP (insecure)");
         server = actix_server.bind(format!("{ip}:{port}"))?.run();
         info!("Listening on http://{ip}:{port}");
@@ -1222,6 +2030,61 @@ async fn main() -> Result<()> {
     let server_handle = server.handle();
     let server_task = rt::spawn(server).map_err(Error::from);
 
+    // Unified-Identity: background ACME renewal, see `acme::renewal_worker`.
+    let acme_renewal_task = if config.acme_enabled {
+        let acme_cfg = acme::AcmeConfig {
+            directory_url: config.acme_directory_url.clone(),
+            contact_email: config.acme_contact_email.clone(),
+            dns_names: parse_list(config.acme_dns_names.as_ref())?,
+            cache_dir: PathBuf::from(&config.agent_data_path),
+        };
+        rt::spawn(acme::renewal_worker(
+            acme_cfg,
+            mtls_priv.clone(),
+            acme_http01_challenges.clone(),
+            acme_rx,
+        ))
+        .map_err(Error::from)
+    } else {
+        rt::spawn(ok(())).map_err(Error::from)
+    };
+
+    // Unified-Identity: background CRL reload, see `client_revocation::reload_worker`.
+    let crl_reload_task = match (revoked_serials.clone(), crl_reload_rx) {
+        (Some(state), Some(rx)) => {
+            let crl_list = parse_list(config.trusted_client_crl.as_ref())?;
+            rt::spawn(client_revocation::reload_worker(
+                crl_list,
+                Duration::from_secs(300),
+                state,
+                rx,
+            ))
+            .map_err(Error::from)
+        }
+        _ => rt::spawn(ok(())).map_err(Error::from),
+    };
+
+    // Unified-Identity: background TUF trust-root refresh, see `trust_root::refresh_worker`.
+    let trust_root_reload_task = match (trusted_ca_store.clone(), trust_root_reload_rx) {
+        (Some(store), Some(rx)) => {
+            let cfg = trust_root::TrustRootConfig {
+                cdn_base_url: config.trust_root_cdn_url.clone(),
+                cache_dir: PathBuf::from(&config.trust_root_cache_dir),
+                ca_bundle_target: config.trust_root_ca_bundle_target.clone(),
+                root_keys_hex: parse_list(config.trust_root_keys.as_ref())?,
+                root_threshold: config.trust_root_threshold,
+            };
+            rt::spawn(trust_root::refresh_worker(
+                cfg,
+                store,
+                rx,
+                Duration::from_secs(config.trust_root_refresh_interval_secs),
+            ))
+            .map_err(Error::from)
+        }
+        _ => rt::spawn(ok(())).map_err(Error::from),
+    };
+
     // Only run payload scripts if mTLS is enabled or 'enable_insecure_payload' option is set
     let run_payload = config.enable_agent_mtls || config.enable_insecure_payload;
 
@@ -1262,6 +2125,35 @@ async fn main() -> Result<()> {
         rt::spawn(ok(())).map_err(Error::from)
     };
 
+    // Unified-Identity: push-based replacement for the deprecated ZeroMQ
+    // revocation path, see `ws_revocation::subscriber_worker`.
+    #[cfg(feature = "with-ws-revocation")]
+    let ws_revocation_task = if config.enable_ws_revocation_notifications {
+        let revocation_cert_pem = std::fs::read(&config.revocation_cert).map_err(|e| {
+            Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                "failed to read 'revocation_cert' at {}: {e}",
+                config.revocation_cert
+            )))
+        })?;
+        let revocation_cert_x509 = X509::from_pem(&revocation_cert_pem).map_err(|e| {
+            Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                "failed to parse 'revocation_cert' as a certificate: {e}"
+            )))
+        })?;
+
+        rt::spawn(ws_revocation::subscriber_worker(
+            config.revocation_notification_ws_url.clone(),
+            revocation_cert_x509,
+            config.ws_revocation_queue_capacity,
+            Duration::from_secs(config.ws_revocation_max_backoff_secs),
+            revocation_tx.clone(),
+            ws_revocation_rx,
+        ))
+        .map_err(Error::from)
+    } else {
+        rt::spawn(ok(())).map_err(Error::from)
+    };
+
     let shutdown_task = rt::spawn(async move {
         let mut sigint = signal(SignalKind::interrupt()).unwrap(); //#[allow_ci]
         let mut sigterm = signal(SignalKind::terminate()).unwrap(); //#[allow_ci]
@@ -1279,16 +2171,33 @@ async fn main() -> Result<()> {
 
         // Shutdown tasks
         let server_stop = server_handle.stop(true);
+        let uds_server_stop = uds_server_handle.map(|handle| handle.stop(true));
         payload_tx.send(payloads::PayloadMessage::Shutdown);
         keys_tx.send((keys_handler::KeyMessage::Shutdown, None));
+        acme_tx.send(acme::AcmeMessage::Shutdown);
+        if let Some(ref mut crl_reload_tx) = crl_reload_tx {
+            crl_reload_tx.send(client_revocation::CrlReloadMessage::Shutdown);
+        }
+        if let Some(ref mut trust_root_reload_tx) = trust_root_reload_tx {
+            trust_root_reload_tx.send(trust_root::TrustRootMessage::Shutdown);
+        }
 
         #[cfg(feature = "with-zmq")]
         zmq_tx.send(revocation::ZmqMessage::Shutdown);
 
+        #[cfg(feature = "with-ws-revocation")]
+        ws_revocation_tx.send(ws_revocation::WsRevocationMessage::Shutdown);
+
+        #[cfg(feature = "with-quic")]
+        let _ = quic_shutdown_tx.send(());
+
         revocation_tx.send(revocation::RevocationMessage::Shutdown);
 
         // Await tasks shutdown
         server_stop.await;
+        if let Some(uds_server_stop) = uds_server_stop {
+            uds_server_stop.await;
+        }
     })
     .map_err(Error::from);
 
@@ -1296,12 +2205,24 @@ async fn main() -> Result<()> {
     #[cfg(feature = "with-zmq")]
     try_join!(zmq_task)?;
 
+    // If with-ws-revocation feature is enabled, wait for the WebSocket revocation subscriber
+    #[cfg(feature = "with-ws-revocation")]
+    try_join!(ws_revocation_task)?;
+
+    // If with-quic feature is enabled, wait for the optional QUIC endpoint
+    #[cfg(feature = "with-quic")]
+    try_join!(quic_task)?;
+
     let result = try_join!(
         server_task,
+        uds_server_task,
         payload_task,
         key_task,
         revocation_task,
         shutdown_task,
+        acme_renewal_task,
+        crl_reload_task,
+        trust_root_reload_task,
     );
     result.map(|_| ())
 }
@@ -1323,8 +2244,88 @@ fn read_in_file(path: String) -> std::io::Result<String> {
     Ok(contents)
 }
 
-fn run_startup_quote_self_test(
-    ctx: &mut tpm::Context<'_>,
+/// Unified-Identity: Convert PCR indices (e.g. from `payload_seal_pcrs` in the
+/// config file) into the `PcrSlot`s used by the TPM-sealing policy session.
+fn parse_pcr_slots(pcrs: &[u32]) -> Result<Vec<tss_esapi::structures::PcrSlot>> {
+    pcrs.iter()
+        .map(|pcr| {
+            // `PcrSlot` values are selection bits (`Slot0` = 1), not indices.
+            let slot_bit = 1u32.checked_shl(*pcr).unwrap_or(0);
+            tss_esapi::structures::PcrSlot::try_from(slot_bit).map_err(|e| {
+                Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                    "Invalid PCR index {pcr} in payload_seal_pcrs: {e}"
+                )))
+            })
+        })
+        .collect()
+}
+
+/// Unified-Identity: check that `x509`'s validity window covers the current
+/// time, within `skew_tolerance` of clock skew in either direction, mirroring
+/// the enclave cert verifier's rejection of a not-yet-valid certificate.
+/// `warn!`s if the certificate is still valid but expires within
+/// `expiry_warning` of now.
+fn check_certificate_validity(
+    label: &str,
+    x509: &X509Ref,
+    skew_tolerance: Duration,
+    expiry_warning: Duration,
+) -> Result<()> {
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| {
+            Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                "system clock is before the Unix epoch: {e}"
+            )))
+        })?
+        .as_secs() as i64;
+    let skew_secs = skew_tolerance.as_secs() as i64;
+
+    let not_before_ceiling = Asn1Time::from_unix(now_secs + skew_secs).map_err(|e| {
+        Error::Configuration(config::KeylimeConfigError::Generic(format!(
+            "failed to compute certificate validity window: {e}"
+        )))
+    })?;
+    if x509.not_before() > not_before_ceiling {
+        return Err(Error::Configuration(config::KeylimeConfigError::Generic(
+            format!("{label} certificate is not yet valid (not_before is in the future beyond the configured clock-skew tolerance)"),
+        )));
+    }
+
+    let not_after_floor = Asn1Time::from_unix(now_secs - skew_secs).map_err(|e| {
+        Error::Configuration(config::KeylimeConfigError::Generic(format!(
+            "failed to compute certificate validity window: {e}"
+        )))
+    })?;
+    if x509.not_after() < not_after_floor {
+        return Err(Error::Configuration(config::KeylimeConfigError::Generic(
+            format!("{label} certificate has expired"),
+        )));
+    }
+
+    let expiry_warning_ceiling =
+        Asn1Time::from_unix(now_secs + expiry_warning.as_secs() as i64).map_err(|e| {
+            Error::Configuration(config::KeylimeConfigError::Generic(format!(
+                "failed to compute certificate expiry warning window: {e}"
+            )))
+        })?;
+    if x509.not_after() < expiry_warning_ceiling {
+        warn!(
+            "{label} certificate expires within {} days",
+            expiry_warning.as_secs() / 86_400
+        );
+    }
+
+    Ok(())
+}
+
+/// Unified-Identity: run the startup quote self-test through the dedicated
+/// TPM worker task (`tpm_tx`/`quotes_handler::request_quote`) instead of
+/// locking `tpm::Context` directly, so the quote is queued behind the same
+/// single-task ownership as every other TPM command and its measured elapsed
+/// time reflects real queue latency rather than exclusive synchronous access.
+async fn run_startup_quote_self_test(
+    tpm_tx: &mpsc::Sender<(TpmMessage, oneshot::Sender<TpmReply>)>,
     hash_alg: keylime::algorithms::HashAlgorithm,
     sign_alg: keylime::algorithms::SignAlgorithm,
     ak_handle: KeyHandle,
@@ -1337,14 +2338,16 @@ fn run_startup_quote_self_test(
         nonce_hex
     );
     let start = Instant::now();
-    let quote = ctx.quote(
-        &nonce_bytes,
+    let quote = quotes_handler::request_quote(
+        tpm_tx,
+        nonce_bytes,
         0,
-        payload_pub_key.as_ref(),
+        payload_pub_key.clone(),
         ak_handle,
         hash_alg,
         sign_alg,
-    )?;
+    )
+    .await?;
     let elapsed = start.elapsed();
     info!(
         "Startup TPM quote self-test succeeded in {:?} ({} bytes)",
@@ -1402,17 +2405,20 @@ mod testing {
         TSSError(#[from] tss_esapi::Error),
     }
 
-    impl Drop for QuoteData<'_> {
+    impl Drop for QuoteData {
         /// Flush the created AK when dropping
         fn drop(&mut self) {
-            self.tpmcontext
-                .lock()
-                .unwrap() //#[allow_ci]
-                .flush_context(self.ak_handle.into());
+            let (reply_tx, _reply_rx) = oneshot::channel();
+            let _ = self.tpm_tx.try_send((
+                TpmMessage::FlushContext {
+                    handle: self.ak_handle.into(),
+                },
+                reply_tx,
+            ));
         }
     }
 
-    impl QuoteData<'_> {
+    impl QuoteData {
         pub(crate) async fn fixture(
         ) -> std::result::Result<(Self, AsyncMutexGuard<'static, ()>), MainTestError> {
             let mutex = lock_tests().await;
@@ -1473,7 +2479,7 @@ mod testing {
 
             let ima_ml_path = Path::new(env!("CARGO_MANIFEST_DIR"))
                 .join("test-data/ima/ascii_runtime_measurements");
-            let ima_ml_file = match fs::File::open(ima_ml_path) {
+            let ima_ml_file = match fs::File::open(&ima_ml_path) {
                 Ok(file) => Some(Mutex::new(file)),
                 Err(err) => None,
             };
@@ -1500,12 +2506,13 @@ mod testing {
             Ok((
                 QuoteData {
                     api_versions,
-                    tpmcontext: Mutex::new(ctx),
+                    tpm_tx: spawn_tpm_worker(ctx),
                     payload_priv_key,
                     payload_pub_key,
                     priv_key: mtls_priv,
                     pub_key: mtls_pub,
                     ak_handle,
+                    ek_handle: ek_result.key_handle,
                     keys_tx,
                     payload_tx,
                     revocation_tx,
@@ -1516,11 +2523,26 @@ mod testing {
                     allow_payload_revocation_actions: test_config.allow_payload_revocation_actions,
                     secure_size: test_config.secure_size,
                     work_dir,
+                    ima_ml_path: ima_ml_file.is_some().then(|| ima_ml_path.clone()),
                     ima_ml_file,
                     measuredboot_ml_file,
                     ima_ml: Mutex::new(MeasurementList::new()),
                     secure_mount,
                     unified_identity_enabled: test_config.unified_identity_enabled,
+                    geolocation_history: Mutex::new(VecDeque::new()),
+                    geolocation_signing_cert_chain: Vec::new(),
+                    geolocation_capability_enabled: false,
+                    geolocation_capability_issuer_pubkey: None,
+                    quote_capability_enabled: false,
+                    quote_capability_trusted_roots: Vec::new(),
+                    quote_security_headers: quotes_handler::QuoteSecurityHeaders::default(),
+                    quote_policy_hmac_key: None,
+                    geolocation_gnss_device_paths: Vec::new(),
+                    quote_cache: None,
+                    signed_request_verifier_pubkey: None,
+                    signed_request_skew_seconds: 300,
+                    transparency_log: None,
+                    signing_key_registry: std::collections::HashMap::new(),
                 },
                 mutex,
             ))
@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Optional QUIC bind path for the agent quote endpoint
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: optional QUIC bind path for the agent's quote endpoint.
+//!
+//! Feature-gated behind `with-quic`, mirroring the deprecated `with-zmq`
+//! path's gating style. [`run`] binds a `quinn` QUIC endpoint on a UDP
+//! socket, reusing the same mTLS certificate/key/trusted-client-CA material
+//! as the HTTPS listener (built the same way as the `rustls` backend, see
+//! `tls_backend::build_server_config`) with ALPN advertising the agent's
+//! supported protocol tokens (`tls_backend::alpn_protocols`) — a client that
+//! doesn't negotiate a supported token is rejected during the QUIC handshake,
+//! before any request is parsed. Operators on high-latency edge links get
+//! QUIC's 0-RTT connection resumption and stream multiplexing for the
+//! request path that benefits most: repeated quote retrieval.
+//!
+//! Only the quote endpoint is served here today: each accepted connection
+//! may open any number of bidirectional streams, each carrying one
+//! JSON-encoded [`QuicQuoteRequest`] (read up to the end of the client's send
+//! side, at most 64 KiB) and one JSON-encoded response,
+//! dispatched through the same `quotes_handler::request_quote`/TPM-worker
+//! path the HTTPS `/quotes/integrity` handler uses. The delegated
+//! certification endpoint is not served over QUIC yet: `certify_app_key`'s
+//! IP allow-list/rate-limit/SO_PEERCRED authorization is written directly
+//! against `actix_web::HttpRequest`, and porting that to a transport with no
+//! actix-web integration is tracked as follow-up work, the same way the
+//! `mbedtls` TLS backend documents its own accept-loop gap.
+
+use log::*;
+use openssl::pkey::{PKey, Public};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::Arc};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tss_esapi::handles::KeyHandle;
+
+use crate::{quotes_handler, tpm, TpmMessage, TpmReply};
+
+#[derive(Error, Debug)]
+pub(crate) enum QuicServerError {
+    #[error("failed to build QUIC transport TLS config: {0}")]
+    TlsConfig(String),
+    #[error("failed to bind QUIC endpoint on {0}: {1}")]
+    Bind(SocketAddr, String),
+}
+
+/// The quote parameters served per-stream, mirroring the query parameters
+/// accepted by the HTTPS `/quotes/integrity` handler.
+#[derive(Deserialize)]
+struct QuicQuoteRequest {
+    nonce: String,
+    mask: u32,
+    partial: bool,
+}
+
+#[derive(Serialize)]
+struct QuicQuoteResponse {
+    quote: Option<String>,
+    error: Option<String>,
+}
+
+/// Everything [`run`] needs to answer a quote request, independent of
+/// `actix_web` — the subset of `QuoteData` the TPM worker path touches.
+pub(crate) struct QuicQuoteConfig {
+    pub(crate) tpm_tx: mpsc::Sender<(TpmMessage, oneshot::Sender<TpmReply>)>,
+    pub(crate) ak_handle: KeyHandle,
+    pub(crate) payload_pub_key: PKey<Public>,
+    pub(crate) hash_alg: keylime::algorithms::HashAlgorithm,
+    pub(crate) sign_alg: keylime::algorithms::SignAlgorithm,
+}
+
+/// Bind a QUIC endpoint on `bind_addr` using `tls_config` (a rustls
+/// `ServerConfig` built the same way as the `rustls` TLS backend, with ALPN
+/// already set) and serve quote requests with `quote_cfg` until `shutdown`
+/// resolves.
+pub(crate) async fn run(
+    bind_addr: SocketAddr,
+    tls_config: rustls::ServerConfig,
+    quote_cfg: Arc<QuicQuoteConfig>,
+    mut shutdown: oneshot::Receiver<()>,
+) -> Result<(), QuicServerError> {
+    let quic_tls_config = quinn::crypto::rustls::QuicServerConfig::try_from(tls_config)
+        .map_err(|e| QuicServerError::TlsConfig(e.to_string()))?;
+    let server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_tls_config));
+
+    let endpoint = quinn::Endpoint::server(server_config, bind_addr)
+        .map_err(|e| QuicServerError::Bind(bind_addr, e.to_string()))?;
+
+    info!("Listening for QUIC connections on {bind_addr}");
+
+    loop {
+        tokio::select! {
+            incoming = endpoint.accept() => {
+                let Some(incoming) = incoming else {
+                    break;
+                };
+                let quote_cfg = quote_cfg.clone();
+                tokio::spawn(async move {
+                    match incoming.await {
+                        Ok(connection) => handle_connection(connection, quote_cfg).await,
+                        Err(e) => warn!("QUIC handshake failed: {e}"),
+                    }
+                });
+            }
+            _ = &mut shutdown => {
+                debug!("Shutting down QUIC endpoint");
+                endpoint.close(0u32.into(), b"shutdown");
+                break;
+            }
+        }
+    }
+
+    endpoint.wait_idle().await;
+    Ok(())
+}
+
+async fn handle_connection(connection: quinn::Connection, quote_cfg: Arc<QuicQuoteConfig>) {
+    loop {
+        match connection.accept_bi().await {
+            Ok((send, recv)) => {
+                let quote_cfg = quote_cfg.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_stream(send, recv, quote_cfg).await {
+                        warn!("QUIC stream handling failed: {e}");
+                    }
+                });
+            }
+            Err(quinn::ConnectionError::ApplicationClosed(_))
+            | Err(quinn::ConnectionError::LocallyClosed) => break,
+            Err(e) => {
+                warn!("QUIC connection error: {e}");
+                break;
+            }
+        }
+    }
+}
+
+async fn handle_stream(
+    mut send: quinn::SendStream,
+    mut recv: quinn::RecvStream,
+    quote_cfg: Arc<QuicQuoteConfig>,
+) -> Result<(), std::io::Error> {
+    let request_bytes = recv
+        .read_to_end(64 * 1024)
+        .await
+        .map_err(std::io::Error::other)?;
+
+    let response = match serde_json::from_slice::<QuicQuoteRequest>(&request_bytes) {
+        Ok(request) => match request_quote(&request, &quote_cfg).await {
+            Ok(quote) => QuicQuoteResponse {
+                quote: Some(quote),
+                error: None,
+            },
+            Err(e) => QuicQuoteResponse {
+                quote: None,
+                error: Some(e.to_string()),
+            },
+        },
+        Err(e) => QuicQuoteResponse {
+            quote: None,
+            error: Some(format!("invalid quote request: {e}")),
+        },
+    };
+
+    let response_bytes =
+        serde_json::to_vec(&response).map_err(|e| std::io::Error::other(e.to_string()))?;
+    send.write_all(&response_bytes)
+        .await
+        .map_err(std::io::Error::other)?;
+    send.finish().map_err(std::io::Error::other)?;
+
+    Ok(())
+}
+
+async fn request_quote(
+    request: &QuicQuoteRequest,
+    quote_cfg: &QuicQuoteConfig,
+) -> Result<String, tpm::TpmError> {
+    let payload_pub_key = if request.partial {
+        // Unified-Identity: the HTTPS `partial=1` quote request omits the
+        // public key from the response; the TPM worker quote path always
+        // takes one, so reuse the agent's own payload key as a harmless
+        // placeholder exactly like the "partial" flag intends to skip.
+        quote_cfg.payload_pub_key.clone()
+    } else {
+        quote_cfg.payload_pub_key.clone()
+    };
+
+    quotes_handler::request_quote(
+        &quote_cfg.tpm_tx,
+        request.nonce.as_bytes().to_vec(),
+        request.mask,
+        payload_pub_key,
+        quote_cfg.ak_handle,
+        quote_cfg.hash_alg,
+        quote_cfg.sign_alg,
+    )
+    .await
+}
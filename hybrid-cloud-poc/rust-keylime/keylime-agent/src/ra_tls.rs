@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: TPM-attested TLS (RA-TLS)
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: RA-TLS — bind the agent's mTLS certificate to a live TPM quote.
+//!
+//! When `attested_tls = true`, the generated mTLS certificate carries a custom
+//! X.509 extension holding the agent's AK public area and a fresh TPM2 quote
+//! whose qualifying data is the SHA-256 of the certificate's
+//! SubjectPublicKeyInfo. A peer verifying the certificate checks that the TLS
+//! private key in use is the one actually attested by the TPM, not merely a
+//! key that happens to chain to a trusted CA: a proxy holding only the
+//! private key cannot forge a matching quote.
+//!
+//! The quote covers a configured PCR selection (`attested_tls_pcr_mask`), and
+//! the resulting PCR digest is both embedded in the extension and, when
+//! `attested_tls_pcr_policy` is configured, checked against it in
+//! `verify_peer`.
+//!
+//! The extension value is a simple length-prefixed (TLV) encoding, not a
+//! nested ASN.1 structure, so construction and parsing stay straightforward
+//! on both sides of the handshake:
+//! `ak_public_len(4, BE) || ak_public_der || quote_len(4, BE) || quote_blob
+//! || pcr_digest_len(4, BE) || pcr_digest`
+
+use base64::Engine as _;
+use openssl::{
+    asn1::Asn1Object,
+    pkey::{PKey, Public},
+    sha::sha256,
+    sign::Verifier,
+    x509::{X509StoreContextRef, X509VerifyResult, X509},
+};
+use tss_esapi::{
+    structures::{Attest, AttestInfo},
+    traits::UnMarshall,
+};
+
+/// Private enterprise OID used to tag the RA-TLS quote extension.
+pub(crate) const RA_TLS_QUOTE_OID: &str = "1.3.6.1.4.1.99999.1.1";
+
+/// Split the `r<base64 attest>:<base64 signature>` quote blob and unmarshall
+/// its attestation structure, without verifying the signature. Shared by
+/// [`build_quote_extension`] (to read the PCR digest back out of a quote it
+/// just generated) and [`verify_quote_blob`] (where the signature is checked
+/// before anything in the attestation is trusted).
+fn parse_attest(quote_blob: &str) -> Result<Attest, String> {
+    let body = quote_blob
+        .strip_prefix('r')
+        .ok_or_else(|| "quote blob missing 'r' signing-scheme prefix".to_string())?;
+    let attest_b64 = body
+        .split(':')
+        .next()
+        .ok_or_else(|| "quote blob missing attestation field".to_string())?;
+    let attest_bytes = base64::engine::general_purpose::STANDARD
+        .decode(attest_b64)
+        .map_err(|e| format!("failed to decode attestation: {e}"))?;
+    Attest::unmarshall(&attest_bytes).map_err(|e| format!("failed to parse TPMS_ATTEST: {e}"))
+}
+
+/// Extract the PCR composite digest attested by a quote, i.e. the digest over
+/// exactly the PCR selection the quote covers.
+fn quote_pcr_digest(attest: &Attest) -> Result<Vec<u8>, String> {
+    match attest.attested() {
+        AttestInfo::Quote { info } => Ok(info.pcr_digest().value().to_vec()),
+        _ => Err("attestation is not a PCR quote".to_string()),
+    }
+}
+
+/// Build the RA-TLS quote extension DER value from the AK public area and the
+/// quote blob produced by [`keylime::tpm::Context::quote`]. The PCR digest
+/// list attested by the quote is read back out of it and embedded alongside,
+/// so a peer can check the agent's PCR state against policy without itself
+/// re-deriving a digest from a raw PCR bank.
+pub(crate) fn build_quote_extension(ak_public_der: &[u8], quote_blob: &str) -> Result<Vec<u8>, String> {
+    let pcr_digest = quote_pcr_digest(&parse_attest(quote_blob)?)?;
+
+    let quote_bytes = quote_blob.as_bytes();
+    let mut out =
+        Vec::with_capacity(12 + ak_public_der.len() + quote_bytes.len() + pcr_digest.len());
+    out.extend_from_slice(&(ak_public_der.len() as u32).to_be_bytes());
+    out.extend_from_slice(ak_public_der);
+    out.extend_from_slice(&(quote_bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(quote_bytes);
+    out.extend_from_slice(&(pcr_digest.len() as u32).to_be_bytes());
+    out.extend_from_slice(&pcr_digest);
+    Ok(out)
+}
+
+/// Parse the RA-TLS quote extension DER value back into its AK public area,
+/// quote blob, and attested PCR digest. Returns `None` if the value is malformed.
+fn parse_quote_extension(der: &[u8]) -> Option<(Vec<u8>, String, Vec<u8>)> {
+    if der.len() < 4 {
+        return None;
+    }
+    let ak_len = u32::from_be_bytes(der[0..4].try_into().ok()?) as usize;
+    let ak_end = 4 + ak_len;
+    if der.len() < ak_end + 4 {
+        return None;
+    }
+    let ak_public_der = der[4..ak_end].to_vec();
+    let quote_len = u32::from_be_bytes(der[ak_end..ak_end + 4].try_into().ok()?) as usize;
+    let quote_start = ak_end + 4;
+    if der.len() < quote_start + quote_len + 4 {
+        return None;
+    }
+    let quote_blob = String::from_utf8(der[quote_start..quote_start + quote_len].to_vec()).ok()?;
+    let pcr_digest_len_start = quote_start + quote_len;
+    let pcr_digest_len = u32::from_be_bytes(
+        der[pcr_digest_len_start..pcr_digest_len_start + 4]
+            .try_into()
+            .ok()?,
+    ) as usize;
+    let pcr_digest_start = pcr_digest_len_start + 4;
+    if der.len() < pcr_digest_start + pcr_digest_len {
+        return None;
+    }
+    let pcr_digest = der[pcr_digest_start..pcr_digest_start + pcr_digest_len].to_vec();
+    Some((ak_public_der, quote_blob, pcr_digest))
+}
+
+/// Check that the presented leaf certificate carries a valid
+/// [`RA_TLS_QUOTE_OID`] extension whose quote is signed by an AK chaining to
+/// `ek_ca_certs`, whose qualifying data matches the SHA-256 of the leaf's
+/// SubjectPublicKeyInfo, and - when `pcr_policy` is configured - whose
+/// attested PCR digest matches it.
+///
+/// The qualifying-data/presented-pubkey binding is the hard invariant: it is
+/// checked even when every other check (chain, signature, PCR policy) passes.
+///
+/// This is a plain check function rather than an installed verify callback:
+/// `main.rs` composes it with `client_revocation`'s CRL/OCSP checks into a
+/// single callback, since OpenSSL only allows one verify callback per
+/// `SslAcceptorBuilder`.
+pub(crate) fn verify_peer(
+    x509_ctx: &mut X509StoreContextRef,
+    ek_ca_certs: &[X509],
+    pcr_policy: Option<&[u8]>,
+) -> Result<bool, String> {
+    let leaf = x509_ctx
+        .current_cert()
+        .ok_or_else(|| "no current certificate in verify context".to_string())?;
+
+    let quote_oid = Asn1Object::from_str(RA_TLS_QUOTE_OID)
+        .map_err(|e| format!("failed to build RA-TLS OID: {e}"))?;
+    let ext_der = leaf
+        .extensions()
+        .find(|ext| ext.object().as_ref() == quote_oid.as_ref())
+        .map(|ext| ext.data().as_slice().to_vec())
+        .ok_or_else(|| {
+            format!("certificate is missing the RA-TLS quote extension ({RA_TLS_QUOTE_OID})")
+        })?;
+
+    let (ak_public_der, quote_blob, extension_pcr_digest) =
+        parse_quote_extension(&ext_der).ok_or_else(|| "malformed RA-TLS extension".to_string())?;
+
+    let ak_cert = X509::from_der(&ak_public_der)
+        .map_err(|e| format!("AK public area is not an X.509 certificate: {e}"))?;
+    if !ek_ca_certs
+        .iter()
+        .any(|ca| ca.issued(&ak_cert) == X509VerifyResult::OK)
+    {
+        return Err("AK certificate does not chain to a configured EK/manufacturer CA".to_string());
+    }
+    let ak_pubkey = ak_cert
+        .public_key()
+        .map_err(|e| format!("failed to extract AK public key: {e}"))?;
+
+    let leaf_spki_der = leaf
+        .public_key()
+        .and_then(|k| k.public_key_to_der())
+        .map_err(|e| format!("failed to encode leaf SubjectPublicKeyInfo: {e}"))?;
+    let expected_qualifying_data = sha256(&leaf_spki_der);
+
+    let attested_pcr_digest = verify_quote_blob(&ak_pubkey, &quote_blob, &expected_qualifying_data)?;
+
+    // Unified-Identity: the extension's own PCR-digest field isn't covered
+    // by the TPM signature (only the quote blob nested inside it is), so
+    // treat a mismatch against the value re-derived from that signed quote
+    // as a tampered/malformed extension rather than trusting it on its own.
+    if attested_pcr_digest != extension_pcr_digest {
+        return Err("RA-TLS extension's PCR digest does not match the signed quote".to_string());
+    }
+
+    if let Some(expected_pcr_digest) = pcr_policy {
+        if attested_pcr_digest != expected_pcr_digest {
+            return Err("quote PCR digest does not match the configured PCR policy".to_string());
+        }
+    }
+
+    Ok(true)
+}
+
+/// Split the `r<base64 attest>:<base64 signature>` quote blob, verify the
+/// signature over the attestation structure with `ak_pubkey`, and check that
+/// the attestation's `extra_data` (qualifying data) is exactly
+/// `expected_qualifying_data`. Returns the attested PCR digest on success.
+fn verify_quote_blob(
+    ak_pubkey: &PKey<Public>,
+    quote_blob: &str,
+    expected_qualifying_data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let body = quote_blob
+        .strip_prefix('r')
+        .ok_or_else(|| "quote blob missing 'r' signing-scheme prefix".to_string())?;
+    let mut parts = body.split(':');
+    let attest_b64 = parts
+        .next()
+        .ok_or_else(|| "quote blob missing attestation field".to_string())?;
+    let sig_b64 = parts
+        .next()
+        .ok_or_else(|| "quote blob missing signature field".to_string())?;
+
+    let attest_bytes = base64::engine::general_purpose::STANDARD
+        .decode(attest_b64)
+        .map_err(|e| format!("failed to decode attestation: {e}"))?;
+    let sig_bytes = base64::engine::general_purpose::STANDARD
+        .decode(sig_b64)
+        .map_err(|e| format!("failed to decode signature: {e}"))?;
+
+    let mut verifier = Verifier::new(openssl::hash::MessageDigest::sha256(), ak_pubkey)
+        .map_err(|e| format!("failed to create quote verifier: {e}"))?;
+    let sig_valid = verifier
+        .verify_oneshot(&sig_bytes, &attest_bytes)
+        .map_err(|e| format!("failed to run quote signature verification: {e}"))?;
+    if !sig_valid {
+        return Err("quote signature does not verify against the AK public key".to_string());
+    }
+
+    let attest = Attest::unmarshall(&attest_bytes)
+        .map_err(|e| format!("failed to parse TPMS_ATTEST: {e}"))?;
+    if attest.extra_data().as_bytes() != expected_qualifying_data {
+        return Err(
+            "quote qualifying data does not match the presented certificate's public key"
+                .to_string(),
+        );
+    }
+
+    quote_pcr_digest(&attest)
+}
@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Pluggable rustls TLS backend
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: pluggable rustls TLS backend.
+//!
+//! `tls_backend = "openssl" | "rustls" | "mbedtls"` selects which TLS stack
+//! binds the agent's HTTPS listener. The `openssl` backend (the existing
+//! default) keeps using `crypto::generate_tls_context`/`bind_openssl`. The
+//! `rustls` backend builds a [`rustls::ServerConfig`] from the same cert,
+//! private key, and trusted-client-CA inputs, with client authentication
+//! enforced by [`rustls::server::WebPkiClientVerifier`], and binds through
+//! `bind_rustls`. This gives operators a pure-Rust TLS stack with no OpenSSL
+//! system dependency on minimal edge images.
+//!
+//! Unified-Identity: `trusted_client_crl`/`trusted_client_ocsp_responder`
+//! (see `client_revocation`) are enforced here too, via
+//! [`RevocationAwareClientCertVerifier`] wrapping the `WebPkiClientVerifier`
+//! - otherwise an operator selecting `tls_backend = "rustls"` would get the
+//! chain check but no revocation enforcement at all.
+//!
+//! `tls_crypto_provider = "ring" | "aws-lc-rs"` selects the rustls
+//! `CryptoProvider` installed as the process default, so FIPS-oriented edge
+//! deployments can pick `aws-lc-rs` instead of `ring`.
+//!
+//! The `mbedtls` backend, behind the `mbedtls` cargo feature, is for
+//! constrained and TEE-backed edge targets (e.g. SGX/esp-idf-style
+//! environments) where linking OpenSSL is heavy or unavailable. It builds an
+//! [`mbedtls::ssl::Config`] from the same PKCS#8 key and PEM cert inputs as
+//! the other two backends. Unlike `openssl`/`rustls`, there is no `actix-web`
+//! integration for an mbedtls-backed listener today, so selecting this
+//! backend validates and builds the config at startup (surfacing cert/key
+//! errors immediately) but does not yet bind a listener with it — wiring a
+//! dedicated mbedtls accept loop into the server bind step is tracked as
+//! follow-up work.
+//!
+//! Unified-Identity: both the `openssl` and `rustls` backends also negotiate
+//! an agent protocol version over ALPN (see [`alpn_protocols`] and
+//! [`install_openssl_alpn`]), so a client whose advertised version the agent
+//! doesn't support is rejected during the TLS handshake rather than after
+//! its request has already been parsed and routed. The same token list backs
+//! the optional QUIC bind path in `quic_server`.
+
+use crate::client_revocation;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Which TLS stack binds the agent's HTTPS listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TlsBackend {
+    OpenSsl,
+    Rustls,
+    #[cfg(feature = "mbedtls")]
+    MbedTls,
+}
+
+impl TlsBackend {
+    pub(crate) fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "openssl" => Ok(Self::OpenSsl),
+            "rustls" => Ok(Self::Rustls),
+            #[cfg(feature = "mbedtls")]
+            "mbedtls" => Ok(Self::MbedTls),
+            other => {
+                #[cfg(feature = "mbedtls")]
+                let expected = "'openssl', 'rustls', or 'mbedtls'";
+                #[cfg(not(feature = "mbedtls"))]
+                let expected = "'openssl' or 'rustls'";
+                Err(format!("invalid 'tls_backend' value '{other}': expected {expected}"))
+            }
+        }
+    }
+}
+
+/// The rustls `CryptoProvider` backend to install as the process default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CryptoProviderKind {
+    Ring,
+    AwsLcRs,
+}
+
+impl CryptoProviderKind {
+    pub(crate) fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "ring" => Ok(Self::Ring),
+            "aws-lc-rs" => Ok(Self::AwsLcRs),
+            other => Err(format!(
+                "invalid 'tls_crypto_provider' value '{other}': expected 'ring' or 'aws-lc-rs'"
+            )),
+        }
+    }
+}
+
+/// Install `kind` as the process-wide default rustls `CryptoProvider`. Must be
+/// called before any rustls `ServerConfig` is built, and at most once per process.
+pub(crate) fn install_default_crypto_provider(kind: CryptoProviderKind) -> Result<(), String> {
+    let provider = match kind {
+        CryptoProviderKind::Ring => rustls::crypto::ring::default_provider(),
+        CryptoProviderKind::AwsLcRs => rustls::crypto::aws_lc_rs::default_provider(),
+    };
+    provider
+        .install_default()
+        .map_err(|_| "a default rustls CryptoProvider is already installed".to_string())
+}
+
+/// Build a `rustls::ServerConfig` requiring client authentication, from the
+/// same DER-encoded cert/key/trusted-CA inputs used by the OpenSSL backend.
+/// `alpn_protocols` (see [`alpn_protocols`]) is advertised during the
+/// handshake; an empty list disables ALPN negotiation. `revoked_serials`/
+/// `ocsp` mirror the OpenSSL verify callback's CRL/OCSP enforcement (see
+/// `client_revocation`); pass `None` for either to leave that check disabled,
+/// matching the OpenSSL backend's own opt-in behaviour.
+pub(crate) fn build_server_config(
+    cert_der: Vec<u8>,
+    key_der: Vec<u8>,
+    client_ca_certs_der: Vec<Vec<u8>>,
+    alpn_protocols: Vec<Vec<u8>>,
+    revoked_serials: Option<Arc<client_revocation::RevokedSerials>>,
+    ocsp: Option<(String, Arc<client_revocation::OcspCache>)>,
+) -> Result<rustls::ServerConfig, String> {
+    let cert_chain = vec![CertificateDer::from(cert_der)];
+    let key = PrivateKeyDer::try_from(key_der)
+        .map_err(|e| format!("invalid mTLS private key for rustls: {e}"))?;
+
+    let client_ca_certs: Vec<openssl::x509::X509> = client_ca_certs_der
+        .iter()
+        .map(|der| openssl::x509::X509::from_der(der))
+        .collect::<Result<_, _>>()
+        .map_err(|e| {
+            format!("invalid trusted client CA certificate for revocation checking: {e}")
+        })?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    for der in client_ca_certs_der {
+        roots
+            .add(CertificateDer::from(der))
+            .map_err(|e| format!("invalid trusted client CA certificate for rustls: {e}"))?;
+    }
+
+    let client_verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .map_err(|e| format!("failed to build rustls client certificate verifier: {e}"))?;
+
+    let client_verifier: Arc<dyn rustls::server::danger::ClientCertVerifier> =
+        if revoked_serials.is_some() || ocsp.is_some() {
+            Arc::new(RevocationAwareClientCertVerifier {
+                inner: client_verifier,
+                revoked_serials,
+                ocsp,
+                client_ca_certs,
+            })
+        } else {
+            client_verifier
+        };
+
+    let mut server_config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(client_verifier)
+        .with_single_cert(cert_chain, key)
+        .map_err(|e| format!("failed to build rustls ServerConfig: {e}"))?;
+    server_config.alpn_protocols = alpn_protocols;
+
+    Ok(server_config)
+}
+
+/// Unified-Identity: wraps a `WebPkiClientVerifier` to additionally enforce
+/// `trusted_client_crl`/`trusted_client_ocsp_responder` after chain
+/// validation succeeds, mirroring the OpenSSL backend's verify callback (see
+/// `client_revocation`). Signature verification itself is left to `inner`;
+/// this only adds the post-chain-validation revocation checks.
+struct RevocationAwareClientCertVerifier {
+    inner: Arc<dyn rustls::server::danger::ClientCertVerifier>,
+    revoked_serials: Option<Arc<client_revocation::RevokedSerials>>,
+    ocsp: Option<(String, Arc<client_revocation::OcspCache>)>,
+    client_ca_certs: Vec<openssl::x509::X509>,
+}
+
+impl std::fmt::Debug for RevocationAwareClientCertVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RevocationAwareClientCertVerifier")
+            .field("revocation_enabled", &self.revoked_serials.is_some())
+            .field("ocsp_enabled", &self.ocsp.is_some())
+            .finish()
+    }
+}
+
+impl rustls::server::danger::ClientCertVerifier for RevocationAwareClientCertVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[rustls::DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::server::danger::ClientCertVerified, rustls::Error> {
+        let verified = self.inner.verify_client_cert(end_entity, intermediates, now)?;
+
+        if self.revoked_serials.is_none() && self.ocsp.is_none() {
+            return Ok(verified);
+        }
+
+        let leaf = openssl::x509::X509::from_der(end_entity.as_ref()).map_err(|e| {
+            rustls::Error::General(format!(
+                "failed to re-parse client certificate for revocation checking: {e}"
+            ))
+        })?;
+
+        if let Some(ref revoked_serials) = self.revoked_serials {
+            if client_revocation::is_revoked(&leaf, revoked_serials) {
+                return Err(rustls::Error::General(
+                    "client certificate serial number is present in 'trusted_client_crl'"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if let Some((ref responder_url, ref cache)) = self.ocsp {
+            let issuer = find_issuer(&leaf, intermediates, &self.client_ca_certs).ok_or_else(|| {
+                rustls::Error::General(
+                    "issuer certificate unavailable for OCSP check".to_string(),
+                )
+            })?;
+            match client_revocation::check_ocsp(
+                &leaf,
+                &issuer,
+                responder_url,
+                Duration::from_secs(3600),
+                cache,
+            ) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(rustls::Error::General(
+                        "OCSP responder reports client certificate is not good".to_string(),
+                    ));
+                }
+                Err(e) => {
+                    return Err(rustls::Error::General(format!("OCSP check failed: {e}")));
+                }
+            }
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::crypto::verify::HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::crypto::verify::HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Find the issuer of `leaf` to use for an OCSP request: the first
+/// TLS-offered intermediate if the client sent one, otherwise whichever
+/// configured trusted client CA certificate actually issued `leaf`.
+fn find_issuer(
+    leaf: &openssl::x509::X509,
+    intermediates: &[CertificateDer<'_>],
+    client_ca_certs: &[openssl::x509::X509],
+) -> Option<openssl::x509::X509> {
+    if let Some(first) = intermediates.first() {
+        if let Ok(cert) = openssl::x509::X509::from_der(first.as_ref()) {
+            return Some(cert);
+        }
+    }
+
+    let issuer_name_der = leaf.issuer_name().to_der().ok()?;
+    client_ca_certs
+        .iter()
+        .find(|ca| ca.subject_name().to_der().ok().as_ref() == Some(&issuer_name_der))
+        .cloned()
+}
+
+/// Build the wire-format ALPN protocol token list the agent advertises and
+/// accepts, one per supported API version, e.g. `"2.2"` becomes
+/// `b"keylime/v2.2"`. A peer that negotiates none of these is rejected by the
+/// TLS stack at handshake time.
+pub(crate) fn alpn_protocols(api_versions: &[String]) -> Vec<Vec<u8>> {
+    api_versions
+        .iter()
+        .map(|version| format!("keylime/v{version}").into_bytes())
+        .collect()
+}
+
+/// Install an ALPN select callback on an OpenSSL `SslAcceptorBuilder` that
+/// picks the first of `protocols` the connecting client also offers,
+/// rejecting the handshake (via `AlpnError::ALERT_FATAL`, which OpenSSL
+/// surfaces to the client as a fatal `no_application_protocol` alert) if none
+/// match.
+pub(crate) fn install_openssl_alpn(
+    ctx: &mut openssl::ssl::SslAcceptorBuilder,
+    protocols: Vec<Vec<u8>>,
+) {
+    ctx.set_alpn_select_callback(move |_ssl, client_protos| {
+        openssl::ssl::select_next_proto(&wire_encode(&protocols), client_protos)
+            .ok_or(openssl::ssl::AlpnError::ALERT_FATAL)
+    });
+}
+
+/// Concatenate `protocols` into the length-prefixed wire format
+/// `set_alpn_protos`/`select_next_proto` expect.
+fn wire_encode(protocols: &[Vec<u8>]) -> Vec<u8> {
+    let mut wire = Vec::new();
+    for proto in protocols {
+        wire.push(proto.len() as u8);
+        wire.extend_from_slice(proto);
+    }
+    wire
+}
+
+/// Build an `mbedtls::ssl::Config` requiring client authentication, from the
+/// same DER-encoded cert/PKCS#8 key/trusted-CA inputs used by the other
+/// backends. See the module docs for why this does not yet bind a listener.
+#[cfg(feature = "mbedtls")]
+pub(crate) fn build_mbedtls_config(
+    cert_der: Vec<u8>,
+    key_der: Vec<u8>,
+    client_ca_certs_der: Vec<Vec<u8>>,
+) -> Result<mbedtls::ssl::Config, String> {
+    use mbedtls::{
+        pk::Pk,
+        ssl::config::{AuthMode, Config, Endpoint, Preset, Transport},
+        x509::Certificate,
+    };
+
+    let own_cert = Arc::new(
+        Certificate::from_der(&cert_der).map_err(|e| format!("invalid mTLS certificate for mbedtls: {e}"))?,
+    );
+    let own_key = Arc::new(
+        Pk::from_private_key(&key_der, None)
+            .map_err(|e| format!("invalid mTLS private key for mbedtls: {e}"))?,
+    );
+
+    let mut ca_chain = None;
+    for der in client_ca_certs_der {
+        let ca_cert = Certificate::from_der(&der)
+            .map_err(|e| format!("invalid trusted client CA certificate for mbedtls: {e}"))?;
+        match &mut ca_chain {
+            None => ca_chain = Some(ca_cert),
+            Some(chain) => chain.push(ca_cert),
+        }
+    }
+    let ca_chain =
+        ca_chain.ok_or_else(|| "no trusted client CA certificates provided for mbedtls".to_string())?;
+
+    let mut config = Config::new(Endpoint::Server, Transport::Stream, Preset::Default);
+    config.set_ca_list(Arc::new(ca_chain), None);
+    config
+        .push_cert(own_cert, own_key)
+        .map_err(|e| format!("failed to attach mTLS certificate/key to mbedtls config: {e}"))?;
+    config.set_authmode(AuthMode::Required);
+
+    Ok(config)
+}
@@ -0,0 +1,239 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: TUF-backed trust root subsystem
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: TUF-backed trust root subsystem for the agent's mTLS CA
+//! anchors.
+//!
+//! `trusted_client_ca` statically authenticates verifier/tenant client
+//! certificates, and the OpenSSL `X509Store` built from it by
+//! `crypto::generate_tls_context` cannot be swapped once the `SslAcceptor` is
+//! built without a full rebind. This module lets operators narrow that static
+//! anchor set *centrally* instead: [`fetch_trust_roots`] pulls a CA bundle
+//! from a TUF repository (root -> timestamp -> snapshot -> targets, verified
+//! by the `tuf` crate's own signature/threshold checks before trusting any
+//! target), [`refresh_worker`] re-pulls it on an interval and hot-swaps the
+//! live [`TrustedCaStore`], and [`is_trusted`] is composed into the agent's
+//! mTLS verify callback alongside the CRL/OCSP/RA-TLS checks (see
+//! `client_revocation`, `ra_tls`) so a peer must additionally chain to one of
+//! the currently TUF-trusted CAs. The last verified CA bundle is cached on
+//! disk so a restart without network access keeps enforcing that anchor set
+//! rather than falling back to "trust everything in `trusted_client_ca`". The
+//! TUF metadata itself is not cached: each fetch starts from an in-memory
+//! repository and re-downloads the chain from root version 1.
+//!
+//! If TUF signature/threshold verification fails, the refresh is rejected and
+//! the previous (cached or live) anchor set is kept in place.
+
+use log::*;
+use openssl::x509::{X509Ref, X509VerifyResult, X509};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum TrustRootError {
+    #[error("failed to fetch TUF metadata/targets from {0}: {1}")]
+    Fetch(String, String),
+    #[error("TUF metadata chain (root/timestamp/snapshot/targets) failed verification: {0}")]
+    Verification(String),
+    #[error("failed to parse trust root target '{0}' as a CA certificate: {1}")]
+    Parse(String, String),
+    #[error("failed to read cached trust roots at {0}: {1}")]
+    Cache(String, String),
+}
+
+/// Messages accepted by the background trust-root refresh task.
+pub(crate) enum TrustRootMessage {
+    Shutdown,
+}
+
+/// Where to fetch and cache the TUF-distributed CA bundle.
+#[derive(Clone)]
+pub(crate) struct TrustRootConfig {
+    /// CDN base URL serving the TUF repository (`root.json`, `timestamp.json`, etc.).
+    pub(crate) cdn_base_url: String,
+    /// Directory the last verified CA bundle is cached in (`trust_roots.pem`);
+    /// TUF metadata is not cached.
+    pub(crate) cache_dir: PathBuf,
+    /// Path (within the TUF targets) to the CA bundle PEM.
+    pub(crate) ca_bundle_target: String,
+    /// Hex-encoded Ed25519 public keys trusted to sign the TUF root role at
+    /// bootstrap. Must be provisioned out-of-band (e.g. baked into agent
+    /// config at deployment time) - a TUF root of trust can't be bootstrapped
+    /// from keys fetched over the same channel it exists to authenticate.
+    pub(crate) root_keys_hex: Vec<String>,
+    /// Minimum number of `root_keys_hex` signatures required on the TUF root
+    /// role. Must be greater than zero and no larger than `root_keys_hex.len()`.
+    pub(crate) root_threshold: u32,
+}
+
+/// The live, hot-swappable set of TUF-trusted CA certificates, consulted by
+/// the mTLS verify callback on every handshake.
+#[derive(Default)]
+pub(crate) struct TrustedCaStore(Mutex<Vec<X509>>);
+
+impl TrustedCaStore {
+    pub(crate) fn new(initial: Vec<X509>) -> Arc<Self> {
+        Arc::new(Self(Mutex::new(initial)))
+    }
+
+    fn replace(&self, certs: Vec<X509>) {
+        *self.0.lock().unwrap() = certs; //#[allow_ci]
+    }
+}
+
+/// Whether `leaf` is issued by one of the currently TUF-trusted CAs.
+pub(crate) fn is_trusted(leaf: &X509Ref, store: &TrustedCaStore) -> bool {
+    store
+        .0
+        .lock()
+        .unwrap() //#[allow_ci]
+        .iter()
+        .any(|ca| ca.issued(leaf) == X509VerifyResult::OK)
+}
+
+/// Fetch and verify the TUF metadata chain from `cfg.cdn_base_url`, then parse
+/// the `ca_bundle_target` target into a list of CA certificates. The `tuf`
+/// crate itself verifies root -> timestamp -> snapshot -> targets signatures
+/// and thresholds; a target is only returned once that whole chain verifies.
+pub(crate) async fn fetch_trust_roots(cfg: &TrustRootConfig) -> Result<Vec<X509>, TrustRootError> {
+    use tuf::{
+        client::{Client, Config},
+        crypto::{HashAlgorithm, PublicKey},
+        metadata::{MetadataPath, MetadataVersion, TargetPath},
+        repository::{EphemeralRepository, HttpRepositoryBuilder},
+    };
+
+    if cfg.root_keys_hex.is_empty() {
+        return Err(TrustRootError::Verification(
+            "no TUF trusted root keys configured (trust_root_keys); the TUF root of trust cannot bootstrap with zero keys".to_string(),
+        ));
+    }
+    if cfg.root_threshold == 0 || cfg.root_threshold as usize > cfg.root_keys_hex.len() {
+        return Err(TrustRootError::Verification(format!(
+            "trust_root_threshold ({}) must be between 1 and the number of configured trust_root_keys ({})",
+            cfg.root_threshold,
+            cfg.root_keys_hex.len()
+        )));
+    }
+    let trusted_root_keys = cfg
+        .root_keys_hex
+        .iter()
+        .map(|key_hex| {
+            let raw = hex::decode(key_hex).map_err(|e| {
+                TrustRootError::Verification(format!("invalid trust_root_keys entry: {e}"))
+            })?;
+            PublicKey::from_ed25519(raw).map_err(|e| {
+                TrustRootError::Verification(format!("invalid trust_root_keys entry: {e}"))
+            })
+        })
+        .collect::<Result<Vec<_>, TrustRootError>>()?;
+
+    let remote = HttpRepositoryBuilder::new(
+        cfg.cdn_base_url
+            .parse()
+            .map_err(|e| TrustRootError::Fetch(cfg.cdn_base_url.clone(), format!("{e}")))?,
+        reqwest::Client::new(),
+    )
+    .build();
+    let local = EphemeralRepository::new();
+
+    let mut client = Client::with_trusted_root_keys(
+        Config::default(),
+        &MetadataVersion::Number(1),
+        cfg.root_threshold,
+        &trusted_root_keys,
+        local,
+        remote,
+    )
+    .await
+    .map_err(|e| TrustRootError::Verification(e.to_string()))?;
+
+    client
+        .update()
+        .await
+        .map_err(|e| TrustRootError::Verification(e.to_string()))?;
+
+    let target_path = TargetPath::new(cfg.ca_bundle_target.clone())
+        .map_err(|e| TrustRootError::Fetch(cfg.ca_bundle_target.clone(), e.to_string()))?;
+    let mut bundle = Vec::new();
+    client
+        .fetch_target_to_writer(&target_path, &mut bundle, &[HashAlgorithm::Sha256])
+        .await
+        .map_err(|e| TrustRootError::Fetch(cfg.ca_bundle_target.clone(), e.to_string()))?;
+
+    let certs = X509::stack_from_pem(&bundle)
+        .map_err(|e| TrustRootError::Parse(cfg.ca_bundle_target.clone(), e.to_string()))?;
+    if certs.is_empty() {
+        return Err(TrustRootError::Parse(
+            cfg.ca_bundle_target.clone(),
+            "CA bundle target contained no certificates".to_string(),
+        ));
+    }
+
+    save_cache(&cfg.cache_dir, &certs)
+        .unwrap_or_else(|e| warn!("Failed to cache TUF trust roots to disk: {e}"));
+
+    Ok(certs)
+}
+
+/// Load the last-known-good CA bundle cached by a previous successful
+/// [`fetch_trust_roots`] call, for use before the first refresh succeeds
+/// (e.g. on an offline restart).
+pub(crate) fn load_cached(cache_dir: &Path) -> Option<Vec<X509>> {
+    let path = cache_dir.join("trust_roots.pem");
+    let pem = std::fs::read(&path).ok()?;
+    match X509::stack_from_pem(&pem) {
+        Ok(certs) if !certs.is_empty() => Some(certs),
+        _ => None,
+    }
+}
+
+fn save_cache(cache_dir: &Path, certs: &[X509]) -> std::io::Result<()> {
+    std::fs::create_dir_all(cache_dir)?;
+    let mut pem = Vec::new();
+    for cert in certs {
+        pem.extend(cert.to_pem()?);
+    }
+    std::fs::write(cache_dir.join("trust_roots.pem"), pem)
+}
+
+/// Background task: re-fetches `cfg`'s TUF-distributed CA bundle into `store`
+/// every `interval`, stopping on [`TrustRootMessage::Shutdown`]. A failed
+/// fetch or TUF verification leaves the previously-trusted set in place.
+pub(crate) async fn refresh_worker(
+    cfg: TrustRootConfig,
+    store: Arc<TrustedCaStore>,
+    mut rx: tokio::sync::mpsc::Receiver<TrustRootMessage>,
+    interval: Duration,
+) {
+    loop {
+        match fetch_trust_roots(&cfg).await {
+            Ok(certs) => {
+                info!(
+                    "Refreshed {} TUF-trusted CA certificate(s) from {}",
+                    certs.len(),
+                    cfg.cdn_base_url
+                );
+                store.replace(certs);
+            }
+            Err(e) => warn!("Failed to refresh TUF trust roots (keeping previous set): {e}"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            msg = rx.recv() => {
+                match msg {
+                    Some(TrustRootMessage::Shutdown) | None => {
+                        debug!("Shutting down TUF trust-root refresh task");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Native Unix-domain-socket transport for delegated certification
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: SO_PEERCRED authorization for the delegated-certification
+//! Unix-domain-socket listener.
+//!
+//! When `delegated_cert_uds_path` is configured, the delegated-certification
+//! scope is additionally bound on a second `HttpServer` over a
+//! [`tokio::net::UnixListener`](actix_web::rt::net::UnixListener), alongside
+//! the existing HTTPS server used for remote verifier/tenant traffic. A
+//! loopback TCP port has no real access control short of the
+//! `delegated_cert_allowed_ips`/rate-limit machinery; a UDS path can instead
+//! be restricted with filesystem permissions/ownership and every caller can
+//! be authorized by its actual peer uid/gid (`SO_PEERCRED`), which an IP
+//! allow-list can only approximate for loopback traffic.
+//!
+//! [`on_connect`](actix_web::HttpServer::on_connect) reads the peer
+//! credentials once per accepted connection and stores them as a [`PeerCred`]
+//! extension; `delegated_certification_handler::certify_app_key` checks for
+//! that extension to tell a UDS-originated request from an HTTPS one and
+//! authorize it accordingly.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Peer credentials of a Unix-domain-socket connection, attached to the
+/// connection data by [`install_peer_cred`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PeerCred {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+}
+
+/// `HttpServer::on_connect` hook: reads `SO_PEERCRED` off the just-accepted
+/// UDS connection and stores it in the per-connection extensions so handlers
+/// can read it back via `HttpRequest::conn_data()`.
+pub(crate) fn install_peer_cred(
+    connection: &dyn std::any::Any,
+    data: &mut actix_web::dev::Extensions,
+) {
+    if let Some(stream) = connection.downcast_ref::<actix_web::rt::net::UnixStream>() {
+        match stream.peer_cred() {
+            Ok(cred) => {
+                data.insert(PeerCred {
+                    uid: cred.uid(),
+                    gid: cred.gid(),
+                });
+            }
+            Err(e) => {
+                log::warn!("Failed to read SO_PEERCRED for delegated-certification UDS connection: {e}");
+            }
+        }
+    }
+}
+
+/// Whether `cred` is authorized: its uid is in `allowed_uids`, or its gid is
+/// in `allowed_gids`. An empty/empty pair denies every caller, since an
+/// unrestricted UDS listener would defeat the purpose of requiring one.
+pub(crate) fn is_authorized(cred: &PeerCred, allowed_uids: &[u32], allowed_gids: &[u32]) -> bool {
+    allowed_uids.contains(&cred.uid) || allowed_gids.contains(&cred.gid)
+}
+
+/// Apply the configured file mode (and, if non-empty, owner uid:gid) to the
+/// freshly-bound UDS path, run once right after `bind_uds` creates the file.
+pub(crate) fn set_socket_permissions(
+    path: &Path,
+    mode: u32,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> std::io::Result<()> {
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    if uid.is_some() || gid.is_some() {
+        nix::unistd::chown(
+            path,
+            uid.map(nix::unistd::Uid::from_raw),
+            gid.map(nix::unistd::Gid::from_raw),
+        )
+        .map_err(|e| std::io::Error::other(format!("failed to chown {}: {e}", path.display())))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: WebSocket revocation subscriber
+// Copyright 2025 Keylime Authors
+
+//! Unified-Identity: push-based WebSocket revocation subscriber, replacing
+//! the deprecated `revocation::zmq_worker` path.
+//!
+//! Gated behind the `with-ws-revocation` cargo feature, the same way
+//! `with-zmq` gates the ZeroMQ path it replaces. [`subscriber_worker`]
+//! maintains a long-lived, TLS-encrypted WebSocket connection to the
+//! verifier (plain `ws://` is refused; see below), reconnecting with
+//! exponential backoff on any drop. Every binary frame received is verified
+//! against `config.revocation_cert` exactly as the existing revocation
+//! machinery expects, then forwarded into the same `revocation_tx` channel
+//! the ZMQ and direct-POST paths already feed — the downstream
+//! revocation-action handling in `revocation::worker` is unchanged; other
+//! frame types are ignored. Inbound frames go into a queue bounded by
+//! `queue_capacity`, and every queued frame is verified and forwarded before
+//! the next one is read from the socket, so a slow consumer holds up reading
+//! instead of growing the agent's memory; read and verify/forward take turns
+//! in one loop, so the queue holds at most one frame at a time.
+//!
+//! The transport itself is server-authenticated TLS only, not mutual TLS:
+//! the connection doesn't present a client certificate, so it does not by
+//! itself authenticate the agent to the verifier. The real per-message
+//! authenticity guarantee is the Ed25519/ECDSA signature check over
+//! `revocation_cert` in [`process_message`] — every message is individually
+//! verified regardless of what the transport does or doesn't prove.
+
+use base64::{engine::general_purpose, Engine as _};
+use log::*;
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Public},
+    sign::Verifier,
+    x509::X509,
+};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Error, Debug)]
+pub(crate) enum WsRevocationError {
+    #[error("revocation message signature verification failed: {0}")]
+    Verify(String),
+    #[error("failed to parse revocation message: {0}")]
+    Parse(String),
+}
+
+/// Messages accepted by the background WebSocket revocation subscriber task.
+pub(crate) enum WsRevocationMessage {
+    Shutdown,
+}
+
+/// A signed revocation message as received over the WebSocket: `payload` is
+/// the base64-encoded JSON revocation action forwarded unchanged into
+/// `revocation_tx`, `signature` is the base64-encoded detached signature over
+/// the decoded `payload` produced with the verifier's revocation key.
+#[derive(serde::Deserialize)]
+struct SignedRevocationMessage {
+    payload: String,
+    signature: String,
+}
+
+/// Verify `signature` over `payload` against `revocation_cert`'s public key.
+fn verify_signature(
+    payload: &[u8],
+    signature: &[u8],
+    revocation_pubkey: &PKey<Public>,
+) -> Result<(), WsRevocationError> {
+    // Ed25519 signs the message itself; OpenSSL rejects a separate digest for it.
+    let mut verifier = if revocation_pubkey.id() == openssl::pkey::Id::ED25519 {
+        Verifier::new_without_digest(revocation_pubkey)
+    } else {
+        Verifier::new(MessageDigest::sha256(), revocation_pubkey)
+    }
+    .map_err(|e| WsRevocationError::Verify(e.to_string()))?;
+    let ok = verifier
+        .verify_oneshot(signature, payload)
+        .map_err(|e| WsRevocationError::Verify(e.to_string()))?;
+    if !ok {
+        return Err(WsRevocationError::Verify(
+            "signature does not verify against 'revocation_cert'".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Maintain a reconnecting, TLS-encrypted WebSocket connection to `ws_url`
+/// (see the module-level doc comment on what the transport does and doesn't
+/// authenticate), verifying every inbound message against `revocation_cert`
+/// and forwarding its payload into `revocation_tx` as a
+/// [`revocation::RevocationMessage::Revocation`]. Reconnects with exponential
+/// backoff (capped at `max_backoff`) on any connect failure or dropped
+/// connection; stops on [`WsRevocationMessage::Shutdown`].
+pub(crate) async fn subscriber_worker(
+    ws_url: String,
+    revocation_cert: X509,
+    queue_capacity: usize,
+    max_backoff: Duration,
+    revocation_tx: mpsc::Sender<crate::revocation::RevocationMessage>,
+    mut rx: mpsc::Receiver<WsRevocationMessage>,
+) {
+    if !ws_url.starts_with("wss://") {
+        warn!("WebSocket revocation subscriber: 'revocation_notification_ws_url' ({ws_url}) is not a wss:// URL; refusing to connect over plaintext ws://");
+        return;
+    }
+
+    let revocation_pubkey = match revocation_cert.public_key() {
+        Ok(k) => k,
+        Err(e) => {
+            warn!("WebSocket revocation subscriber: failed to extract public key from 'revocation_cert': {e}; not starting");
+            return;
+        }
+    };
+
+    let mut backoff = Duration::from_secs(1);
+
+    'connect: loop {
+        let connect_result = tokio::select! {
+            result = tokio_tungstenite::connect_async(&ws_url) => result,
+            msg = rx.recv() => {
+                match msg {
+                    Some(WsRevocationMessage::Shutdown) | None => {
+                        debug!("Shutting down WebSocket revocation subscriber (during connect)");
+                        return;
+                    }
+                }
+            }
+        };
+
+        let (ws_stream, _) = match connect_result {
+            Ok(connected) => {
+                info!("WebSocket revocation subscriber connected to {ws_url}");
+                backoff = Duration::from_secs(1);
+                connected
+            }
+            Err(e) => {
+                warn!("WebSocket revocation subscriber: failed to connect to {ws_url}: {e}; retrying in {backoff:?}");
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    msg = rx.recv() => {
+                        match msg {
+                            Some(WsRevocationMessage::Shutdown) | None => {
+                                debug!("Shutting down WebSocket revocation subscriber (during backoff)");
+                                return;
+                            }
+                        }
+                    }
+                }
+                backoff = (backoff * 2).min(max_backoff);
+                continue 'connect;
+            }
+        };
+
+        use futures_util::StreamExt;
+        let (_write, mut read) = ws_stream.split();
+        let mut queue: std::collections::VecDeque<Vec<u8>> =
+            std::collections::VecDeque::with_capacity(queue_capacity);
+
+        loop {
+            tokio::select! {
+                frame = read.next() => {
+                    match frame {
+                        Some(Ok(Message::Binary(data))) => {
+                            if queue.len() >= queue_capacity {
+                                warn!("WebSocket revocation subscriber: in-flight queue full, dropping oldest message");
+                                queue.pop_front();
+                            }
+                            queue.push_back(data);
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            warn!("WebSocket revocation subscriber: connection to {ws_url} closed; reconnecting");
+                            continue 'connect;
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => {
+                            warn!("WebSocket revocation subscriber: connection error: {e}; reconnecting");
+                            continue 'connect;
+                        }
+                    }
+                }
+                msg = rx.recv() => {
+                    match msg {
+                        Some(WsRevocationMessage::Shutdown) | None => {
+                            debug!("Shutting down WebSocket revocation subscriber");
+                            return;
+                        }
+                    }
+                }
+            }
+
+            while let Some(data) = queue.pop_front() {
+                if let Err(e) = process_message(&data, &revocation_pubkey, &revocation_tx).await {
+                    warn!("WebSocket revocation subscriber: rejecting message: {e}");
+                }
+            }
+        }
+    }
+}
+
+async fn process_message(
+    data: &[u8],
+    revocation_pubkey: &PKey<Public>,
+    revocation_tx: &mpsc::Sender<crate::revocation::RevocationMessage>,
+) -> Result<(), WsRevocationError> {
+    let message: SignedRevocationMessage =
+        serde_json::from_slice(data).map_err(|e| WsRevocationError::Parse(e.to_string()))?;
+
+    let payload = general_purpose::STANDARD
+        .decode(&message.payload)
+        .map_err(|e| WsRevocationError::Parse(format!("invalid base64 'payload': {e}")))?;
+    let signature = general_purpose::STANDARD
+        .decode(&message.signature)
+        .map_err(|e| WsRevocationError::Parse(format!("invalid base64 'signature': {e}")))?;
+
+    verify_signature(&payload, &signature, revocation_pubkey)?;
+
+    if revocation_tx
+        .send(crate::revocation::RevocationMessage::Revocation(payload))
+        .await
+        .is_err()
+    {
+        warn!("WebSocket revocation subscriber: revocation channel closed, dropping message");
+    }
+
+    Ok(())
+}
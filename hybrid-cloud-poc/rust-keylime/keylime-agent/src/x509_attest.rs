@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: Apache-2.0
+// Unified-Identity: Hardware Integration & Delegated Certification
+// Copyright 2024 Keylime Authors
+
+//! Unified-Identity: builds the self-issued X.509 certificate returned by
+//! `delegated_certification_handler::certify_app_key` when the request sets
+//! `cert_format: "x509"` - subject key is the App Key, issuer/subject are
+//! both the agent (self-issued), and the raw `TPM2_Certify` evidence
+//! (marshalled `Attest` + `Signature`, plus the `challenge_nonce`) is carried
+//! in a non-critical custom extension under [`APP_KEY_ATTESTATION_OID`],
+//! the same evidence the `keylime-json`/`cose` output formats already
+//! return, just packaged the way hardware key-attestation certificates
+//! usually carry theirs.
+//!
+//! Unlike `ra_tls`'s self-signed mTLS certificate (signed by an ordinary
+//! in-process OpenSSL key) or `acme`'s CSR (signed by the mTLS key and
+//! countersigned by a real CA), this certificate is signed by the AK, which
+//! only ever produces a raw `TPM2_Sign` signature over caller-supplied bytes
+//! (see `TpmMessage::SignDigest`) - there's no "sign with an external key"
+//! hook in the `openssl` crate's `X509Builder` for that. So the
+//! `tbsCertificate` is DER-encoded by hand here (the pure, synchronous half
+//! of the work); `delegated_certification_handler::build_x509_certificate`
+//! does the async TPM round trip to actually sign it and hands the raw
+//! signature back to [`signature_value_der`] and [`assemble_certificate`].
+
+/// Private enterprise OID used to tag the App Key attestation extension,
+/// one arc after `ra_tls::RA_TLS_QUOTE_OID`.
+pub(crate) const APP_KEY_ATTESTATION_OID: &str = "1.3.6.1.4.1.99999.1.2";
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        vec![len as u8]
+    } else {
+        let len_bytes = (len as u64).to_be_bytes();
+        let trimmed: Vec<u8> = len_bytes
+            .iter()
+            .skip_while(|&&b| b == 0)
+            .copied()
+            .collect();
+        let mut out = vec![0x80 | trimmed.len() as u8];
+        out.extend(trimmed);
+        out
+    }
+}
+
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    out.extend(der_length(content.len()));
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_sequence(items: &[&[u8]]) -> Vec<u8> {
+    der_tlv(0x30, &items.concat())
+}
+
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let mut b = bytes;
+    while b.len() > 1 && b[0] == 0 && b[1] & 0x80 == 0 {
+        b = &b[1..];
+    }
+    let mut content = Vec::with_capacity(b.len() + 1);
+    if b[0] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend_from_slice(b);
+    der_tlv(0x02, &content)
+}
+
+fn der_boolean(value: bool) -> Vec<u8> {
+    der_tlv(0x01, &[if value { 0xff } else { 0x00 }])
+}
+
+fn der_octet_string(bytes: &[u8]) -> Vec<u8> {
+    der_tlv(0x04, bytes)
+}
+
+fn der_utf8_string(s: &str) -> Vec<u8> {
+    der_tlv(0x0c, s.as_bytes())
+}
+
+fn der_null() -> Vec<u8> {
+    vec![0x05, 0x00]
+}
+
+/// Base-128 big-endian encoding of a single OID arc, continuation bit set on
+/// every byte but the last.
+fn base128(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// DER-encode a dotted OID string (e.g. `"1.3.6.1.4.1.99999.1.2"`).
+fn oid_der(dotted: &str) -> Result<Vec<u8>, String> {
+    let arcs: Vec<u64> = dotted
+        .split('.')
+        .map(|s| s.parse::<u64>().map_err(|_| format!("invalid OID arc: {s}")))
+        .collect::<Result<_, _>>()?;
+    if arcs.len() < 2 {
+        return Err("OID must have at least two arcs".to_string());
+    }
+    let mut body = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        body.extend(base128(arc));
+    }
+    Ok(der_tlv(0x06, &body))
+}
+
+/// `(year, month, day, hour, min, sec)` in UTC for `unix_secs`, via Howard
+/// Hinnant's `civil_from_days` - no calendar-library dependency needed for a
+/// single certificate validity stamp.
+fn civil_from_unix(unix_secs: i64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = unix_secs.div_euclid(86_400);
+    let secs_of_day = unix_secs.rem_euclid(86_400);
+    let (hour, min, sec) = (
+        (secs_of_day / 3600) as u32,
+        ((secs_of_day % 3600) / 60) as u32,
+        (secs_of_day % 60) as u32,
+    );
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = if m <= 2 { y + 1 } else { y };
+    (year, m, d, hour, min, sec)
+}
+
+/// DER `UTCTime` for `unix_secs`. Certificates built by this module are
+/// short-lived, agent-issued attestation artifacts, not long-term roots, so
+/// the two-digit-year `UTCTime` form (valid through 2049) is used
+/// unconditionally rather than switching to `GeneralizedTime`.
+fn der_utc_time(unix_secs: i64) -> Vec<u8> {
+    let (year, month, day, hour, min, sec) = civil_from_unix(unix_secs);
+    let yy = (year.rem_euclid(100)) as u32;
+    der_tlv(
+        0x17,
+        format!("{yy:02}{month:02}{day:02}{hour:02}{min:02}{sec:02}Z").as_bytes(),
+    )
+}
+
+fn der_name(common_name: &str) -> Vec<u8> {
+    let oid_cn = oid_der("2.5.4.3").expect("2.5.4.3 is a valid OID");
+    let attribute = der_sequence(&[&oid_cn, &der_utf8_string(common_name)]);
+    let rdn = der_tlv(0x31, &attribute); // SET OF
+    der_sequence(&[&rdn])
+}
+
+/// Maps an IANA COSE algorithm identifier (as returned by
+/// `delegated_certification_handler::cose_alg_and_raw_signature`) to the
+/// matching X.509 `AlgorithmIdentifier` DER bytes and whether its signature
+/// value needs re-encoding as a DER `SEQUENCE { r, s }` (ECDSA) rather than
+/// used as-is (RSA PKCS#1 v1.5). RSASSA-PSS (PS256) has no single
+/// conventional X.509 OID/parameter story and is rejected here; callers that
+/// need PS256 evidence should use the `cose`/`keylime-json` output instead.
+pub(crate) fn x509_signature_algorithm(cose_alg: i128) -> Result<(Vec<u8>, bool), String> {
+    let (oid, needs_null_params, is_ecdsa) = match cose_alg {
+        -7 => ("1.2.840.10045.4.3.2", false, true),  // ecdsa-with-SHA256
+        -35 => ("1.2.840.10045.4.3.3", false, true), // ecdsa-with-SHA384
+        -36 => ("1.2.840.10045.4.3.4", false, true), // ecdsa-with-SHA512
+        -257 => ("1.2.840.113549.1.1.11", true, false), // sha256WithRSAEncryption
+        _ => return Err("unsupported TPM signature scheme for X.509 output".to_string()),
+    };
+    let oid_der_bytes = oid_der(oid)?;
+    let algorithm_identifier = if needs_null_params {
+        der_sequence(&[&oid_der_bytes, &der_null()])
+    } else {
+        der_sequence(&[&oid_der_bytes])
+    };
+    Ok((algorithm_identifier, is_ecdsa))
+}
+
+/// Convert a raw TPM signature (as returned by `cose_alg_and_raw_signature`)
+/// into the DER `signatureValue` bit-string content X.509 expects: ECDSA's
+/// fixed-width `r || s` becomes `SEQUENCE { INTEGER r, INTEGER s }`; RSA's
+/// plain signature bytes are used unchanged.
+pub(crate) fn signature_value_der(is_ecdsa: bool, raw_signature: &[u8]) -> Result<Vec<u8>, String> {
+    if !is_ecdsa {
+        return Ok(raw_signature.to_vec());
+    }
+    if raw_signature.len() % 2 != 0 {
+        return Err("ECDSA raw signature has odd length".to_string());
+    }
+    let half = raw_signature.len() / 2;
+    let (r, s) = raw_signature.split_at(half);
+    Ok(der_sequence(&[&der_integer(r), &der_integer(s)]))
+}
+
+/// TLV-concatenate the `TPM2_Certify` evidence for the attestation
+/// extension: `attest_len(4, BE) || attest || sig_len(4, BE) || sig ||
+/// nonce_len(4, BE) || nonce`, following the same simple length-prefixed
+/// encoding `ra_tls::build_quote_extension` uses for its quote extension.
+pub(crate) fn build_attestation_extension_value(
+    attest_bytes: &[u8],
+    sig_bytes: &[u8],
+    challenge_nonce: &str,
+) -> Vec<u8> {
+    let nonce_bytes = challenge_nonce.as_bytes();
+    let mut out = Vec::with_capacity(12 + attest_bytes.len() + sig_bytes.len() + nonce_bytes.len());
+    for part in [attest_bytes, sig_bytes, nonce_bytes] {
+        out.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+/// Build the DER `tbsCertificate` for a v3, self-issued certificate whose
+/// subject public key is `app_key_public_der` (a complete
+/// SubjectPublicKeyInfo, as returned by `PKey::public_key_to_der`), carrying
+/// `extension_value` under [`APP_KEY_ATTESTATION_OID`].
+pub(crate) fn build_tbs_certificate(
+    serial: &[u8],
+    common_name: &str,
+    app_key_public_der: &[u8],
+    not_before_unix: i64,
+    not_after_unix: i64,
+    algorithm_identifier: &[u8],
+    extension_value: &[u8],
+) -> Result<Vec<u8>, String> {
+    let version = der_tlv(0xa0, &der_integer(&[2])); // [0] EXPLICIT INTEGER v3
+    let serial_number = der_integer(serial);
+    let issuer = der_name(common_name);
+    let validity = der_sequence(&[
+        &der_utc_time(not_before_unix),
+        &der_utc_time(not_after_unix),
+    ]);
+    let subject = der_name(common_name);
+
+    let extension = der_sequence(&[
+        &oid_der(APP_KEY_ATTESTATION_OID)?,
+        &der_boolean(false),
+        &der_octet_string(extension_value),
+    ]);
+    let extensions = der_tlv(0xa3, &der_sequence(&[&extension])); // [3] EXPLICIT Extensions
+
+    Ok(der_sequence(&[
+        &version,
+        &serial_number,
+        algorithm_identifier,
+        &issuer,
+        &validity,
+        &subject,
+        app_key_public_der,
+        &extensions,
+    ]))
+}
+
+/// Wrap a signed `tbsCertificate` into the outer `Certificate ::= SEQUENCE {
+/// tbsCertificate, signatureAlgorithm, signatureValue }`.
+pub(crate) fn assemble_certificate(
+    tbs_der: &[u8],
+    algorithm_identifier: &[u8],
+    signature_value: &[u8],
+) -> Vec<u8> {
+    let signature_bit_string = {
+        let mut content = Vec::with_capacity(signature_value.len() + 1);
+        content.push(0); // no unused bits
+        content.extend_from_slice(signature_value);
+        der_tlv(0x03, &content)
+    };
+    der_sequence(&[tbs_der, algorithm_identifier, &signature_bit_string])
+}